@@ -0,0 +1,137 @@
+// Post-processing of the game screen, selected by `ScreenFilter`
+
+#import bevy_sprite::mesh2d_types
+#import bevy_sprite::mesh2d_view_bindings
+
+struct ScreenParams {
+    filter: u32,
+    curvature: f32,
+    scanline: f32,
+    grid: f32,
+    blend: f32,
+};
+
+@group(1) @binding(0)
+var<uniform> params: ScreenParams;
+@group(1) @binding(1)
+var screen_texture: texture_2d<f32>;
+@group(1) @binding(2)
+var screen_sampler: sampler;
+@group(1) @binding(3)
+var prev_texture: texture_2d<f32>;
+@group(1) @binding(4)
+var prev_sampler: sampler;
+
+struct FragmentInput {
+    #import bevy_sprite::mesh2d_vertex_output
+};
+
+let FILTER_SCANLINES: u32 = 1u;
+let FILTER_CRT: u32 = 2u;
+let FILTER_LCD_GRID: u32 = 3u;
+let FILTER_XBR: u32 = 4u;
+
+let PI: f32 = 3.14159265;
+
+// Darkest at the boundary of source lines
+fn scanline(y: f32, strength: f32) -> f32 {
+    return 1.0 - strength * (0.5 + 0.5 * cos(2.0 * PI * y));
+}
+
+// Frames are blended for interpolation when `blend` is below 1
+fn texel(p: vec2<i32>) -> vec3<f32> {
+    let size = vec2<i32>(textureDimensions(screen_texture));
+    let p = clamp(p, vec2<i32>(0), size - vec2<i32>(1));
+    let color = textureLoad(screen_texture, p, 0).rgb;
+    if (params.blend >= 1.0) {
+        return color;
+    }
+    return mix(textureLoad(prev_texture, p, 0).rgb, color, params.blend);
+}
+
+// Perceptual distance of colors, weighted in YUV
+fn dist(a: vec3<f32>, b: vec3<f32>) -> f32 {
+    let d = a - b;
+    let y = dot(d, vec3<f32>(0.299, 0.587, 0.114));
+    let u = dot(d, vec3<f32>(-0.169, -0.331, 0.5));
+    let v = dot(d, vec3<f32>(0.5, -0.419, -0.081));
+    return 48.0 * abs(y) + 7.0 * abs(u) + 6.0 * abs(v);
+}
+
+// xBR level 1: when the diagonal edge crossing the corner of the source pixel
+// nearest to `pos` is stronger than the opposite one, the corner is filled with
+// the color of the neighbors along that edge
+fn xbr(pos: vec2<f32>) -> vec3<f32> {
+    let p = vec2<i32>(floor(pos));
+    let f = fract(pos) - vec2<f32>(0.5);
+    let dx = select(-1, 1, f.x >= 0.0);
+    let dy = select(-1, 1, f.y >= 0.0);
+    let ox = vec2<i32>(dx, 0);
+    let oy = vec2<i32>(0, dy);
+
+    let e = texel(p);
+    let b = texel(p - oy);
+    let c = texel(p + ox - oy);
+    let d = texel(p - ox);
+    let ff = texel(p + ox);
+    let g = texel(p - ox + oy);
+    let h = texel(p + oy);
+    let i = texel(p + ox + oy);
+    let f4 = texel(p + ox * 2);
+    let h5 = texel(p + oy * 2);
+    let i4 = texel(p + ox * 2 + oy);
+    let i5 = texel(p + ox + oy * 2);
+
+    let edge = dist(e, c) + dist(e, g) + dist(i, f4) + dist(i, h5) + 4.0 * dist(h, ff);
+    let opposite = dist(h, d) + dist(h, i5) + dist(ff, i4) + dist(ff, b) + 4.0 * dist(e, i);
+    if (edge >= opposite) {
+        return e;
+    }
+
+    // Antialiased along the diagonal line through the middle of the edge neighbors
+    let t = abs(f.x) + abs(f.y) - 0.5;
+    let fill = select(h, ff, dist(e, ff) <= dist(e, h));
+    return mix(e, fill, smoothstep(-0.05, 0.05, t));
+}
+
+@fragment
+fn fragment(in: FragmentInput) -> @location(0) vec4<f32> {
+    let size = vec2<f32>(textureDimensions(screen_texture));
+    var uv = in.uv;
+    var shade = 1.0;
+
+    if (params.filter == FILTER_CRT) {
+        // Barrel distortion and vignette
+        let c = uv * 2.0 - vec2<f32>(1.0);
+        let warped = c * (vec2<f32>(1.0) + params.curvature * c.yx * c.yx);
+        if (any(warped < vec2<f32>(-1.0)) || any(warped > vec2<f32>(1.0))) {
+            return vec4<f32>(0.0, 0.0, 0.0, 1.0);
+        }
+        uv = warped * 0.5 + vec2<f32>(0.5);
+        shade = shade * (1.0 - 0.2 * dot(warped, warped) * 0.5);
+    }
+
+    // Sampled without derivatives, since the control flow above is not uniform
+    var color = textureSampleLevel(screen_texture, screen_sampler, uv, 0.0).rgb;
+    if (params.blend < 1.0) {
+        let prev = textureSampleLevel(prev_texture, prev_sampler, uv, 0.0).rgb;
+        color = mix(prev, color, params.blend);
+    }
+    let pos = uv * size;
+
+    if (params.filter == FILTER_XBR) {
+        color = xbr(pos);
+    }
+
+    if (params.filter == FILTER_SCANLINES || params.filter == FILTER_CRT) {
+        shade = shade * scanline(fract(pos.y), params.scanline);
+    }
+
+    if (params.filter == FILTER_LCD_GRID) {
+        let cell = fract(pos);
+        let edge = min(cell, vec2<f32>(1.0) - cell);
+        shade = shade * mix(1.0 - params.grid, 1.0, smoothstep(0.0, 0.15, min(edge.x, edge.y)));
+    }
+
+    return vec4<f32>(color * shade, 1.0);
+}
@@ -5,6 +5,8 @@ fn main() {
     if target_os == "windows" {
         let mut res = winres::WindowsResource::new();
         res.set_icon("assets/meru.ico");
+        // Loaded by name for the tray icon
+        res.set_icon_with_id("assets/meru.ico", "meru-icon");
         res.compile().unwrap();
     }
 }
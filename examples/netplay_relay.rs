@@ -0,0 +1,119 @@
+//! Relay server for netplay, for players who cannot accept connections.
+//!
+//! The host opens a room and gets its code, and the other player joins with the code.
+//! After that, the server forwards bytes between the two.
+//!
+//! Usage: cargo run --release --example netplay_relay -- [address (default: 0.0.0.0:7846)]
+
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+    net::{Shutdown, TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+const CODE_CHARS: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+const CODE_LEN: usize = 6;
+const MAX_LINE: usize = 256;
+
+// Clients only have this long to say what they want
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+type Rooms = Arc<Mutex<HashMap<String, TcpStream>>>;
+
+fn main() -> std::io::Result<()> {
+    let address = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "0.0.0.0:7846".to_string());
+    let listener = TcpListener::bind(&address)?;
+    println!("Listening on {}", listener.local_addr()?);
+
+    let rooms = Rooms::default();
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                eprintln!("Failed to accept: {err}");
+                continue;
+            }
+        };
+        let rooms = rooms.clone();
+        std::thread::spawn(move || {
+            let peer = stream.peer_addr().ok();
+            if let Err(err) = handle(stream, &rooms) {
+                eprintln!("{peer:?}: {err}");
+            }
+        });
+    }
+    Ok(())
+}
+
+fn handle(mut stream: TcpStream, rooms: &Rooms) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+    let line = read_line(&mut stream)?;
+    stream.set_read_timeout(None)?;
+
+    if line == "HOST" {
+        let code = {
+            let mut rooms = rooms.lock().unwrap();
+            let code = loop {
+                let code = room_code()?;
+                if !rooms.contains_key(&code) {
+                    break code;
+                }
+            };
+            rooms.insert(code.clone(), stream.try_clone()?);
+            code
+        };
+        println!("Opened room {code}");
+        stream.write_all(format!("ROOM {code}\n").as_bytes())
+    } else if let Some(code) = line.strip_prefix("JOIN ") {
+        let host = rooms.lock().unwrap().remove(code);
+        let mut host = if let Some(host) = host {
+            host
+        } else {
+            return stream.write_all(b"ERROR No such room\n");
+        };
+        println!("Joined room {code}");
+
+        host.write_all(b"PAIRED\n")?;
+        stream.write_all(b"PAIRED\n")?;
+
+        let mut host_read = host.try_clone()?;
+        let mut guest_write = stream.try_clone()?;
+        let forward = std::thread::spawn(move || {
+            let _ = std::io::copy(&mut host_read, &mut guest_write);
+            let _ = guest_write.shutdown(Shutdown::Both);
+        });
+        let _ = std::io::copy(&mut stream, &mut host);
+        let _ = host.shutdown(Shutdown::Both);
+        let _ = forward.join();
+        println!("Closed room {code}");
+        Ok(())
+    } else {
+        stream.write_all(b"ERROR Unknown request\n")
+    }
+}
+
+fn read_line(stream: &mut TcpStream) -> std::io::Result<String> {
+    let mut line = vec![];
+    let mut byte = [0];
+    while line.len() < MAX_LINE {
+        if stream.read(&mut byte)? == 0 || byte[0] == b'\n' {
+            break;
+        }
+        line.push(byte[0]);
+    }
+    Ok(String::from_utf8_lossy(&line).trim().to_string())
+}
+
+fn room_code() -> std::io::Result<String> {
+    let mut bytes = [0; CODE_LEN];
+    getrandom::getrandom(&mut bytes)
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err.to_string()))?;
+    Ok(bytes
+        .iter()
+        .map(|b| CODE_CHARS[*b as usize % CODE_CHARS.len()] as char)
+        .collect())
+}
@@ -49,6 +49,42 @@ mod imp {
 
 pub use imp::File;
 
+/// Schema extension telling whether an option is applied only on boot or reset.
+/// Options without this annotation are expected to be applied live by `set_config`.
+pub const REQUIRES_RESET: &str = "x-requires-reset";
+
+/// Schema for options the core reads only on boot or reset.
+///
+/// ```ignore
+/// #[derive(JsonSchema, Serialize, Deserialize)]
+/// struct Config {
+///     #[schemars(schema_with = "meru_interface::config::requires_reset::<Model>")]
+///     model: Model,
+/// }
+/// ```
+pub fn requires_reset<T: JsonSchema>(gen: &mut SchemaGenerator) -> Schema {
+    with_requires_reset::<T>(gen, true)
+}
+
+/// Schema for options applied live, overriding the default of the type (e.g. `File`).
+pub fn applied_live<T: JsonSchema>(gen: &mut SchemaGenerator) -> Schema {
+    with_requires_reset::<T>(gen, false)
+}
+
+fn with_requires_reset<T: JsonSchema>(gen: &mut SchemaGenerator, requires_reset: bool) -> Schema {
+    let mut schema: SchemaObject = T::json_schema(gen).into();
+    schema
+        .extensions
+        .insert(REQUIRES_RESET.to_owned(), requires_reset.into());
+    schema.into()
+}
+
+/// Reads the `REQUIRES_RESET` annotation of a schema
+pub fn schema_requires_reset(schema: &SchemaObject) -> Option<bool> {
+    schema.extensions.get(REQUIRES_RESET)?.as_bool()
+}
+
+/// Files are usually boot ROMs or BIOSes, so they require reset by default.
 impl JsonSchema for File {
     fn schema_name() -> String {
         "File".to_string()
@@ -57,6 +93,9 @@ impl JsonSchema for File {
     fn json_schema(gen: &mut SchemaGenerator) -> Schema {
         let mut schema: SchemaObject = <String>::json_schema(gen).into();
         schema.format = Some("file".to_owned());
+        schema
+            .extensions
+            .insert(REQUIRES_RESET.to_owned(), true.into());
         schema.into()
     }
 }
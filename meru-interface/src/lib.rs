@@ -25,7 +25,17 @@ pub struct CoreInfo {
     pub file_extensions: &'static [&'static str],
 }
 
-#[derive(Default)]
+pub struct FirmwareInfo {
+    pub name: &'static str,
+    pub file_name: &'static str,
+    /// Name of the `File` field in the core config that refers to this firmware
+    pub config_key: &'static str,
+    pub required: bool,
+    /// CRC32 checksums of known-good dumps
+    pub known_crc32: &'static [u32],
+}
+
+#[derive(Default, Clone)]
 pub struct FrameBuffer {
     pub width: usize,
     pub height: usize,
@@ -111,6 +121,49 @@ impl Color {
     }
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Region {
+    Ntsc,
+    Pal,
+}
+
+/// Replaces a byte read by the CPU, as Game Genie devices do.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MemoryPatch {
+    pub addr: u32,
+    pub data: u8,
+    /// Only replaces when the original byte has this value
+    pub compare: Option<u8>,
+}
+
+/// Size of the image seen by a camera peripheral, such as the Game Boy Camera
+pub const CAMERA_WIDTH: usize = 128;
+pub const CAMERA_HEIGHT: usize = 112;
+
+/// Tile or sprite drawn in a frame, identified by the hash of its pixel data
+/// so that the frontend can replace it with a high-resolution texture.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TileDraw {
+    /// Hash of the tile graphics and its palette, which must be stable across runs and versions
+    pub hash: u64,
+    /// Position in the frame buffer. May be partly off screen.
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub flip_h: bool,
+    pub flip_v: bool,
+}
+
+/// Statistics of the last executed frame, for telling slowdowns of the core from the frontend.
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct FrameStats {
+    /// CPU cycles executed in the frame
+    pub cycles: u64,
+    /// Cycles the CPU spent halted or waiting for interrupts, if tracked
+    pub idle_cycles: Option<u64>,
+}
+
 pub struct AudioBuffer {
     pub sample_rate: u32,
     pub channels: u16,
@@ -156,31 +209,96 @@ pub struct KeyConfig {
 
 impl KeyConfig {
     pub fn input(&self, input_state: &impl InputState) -> InputData {
-        let controllers = self
+        let mut ret = InputData::default();
+        self.update_input(input_state, &mut ret);
+        ret
+    }
+
+    /// Updates `input` in place. Key names are copied only when the layout of
+    /// the key config has changed, so nothing is allocated in the steady state.
+    pub fn update_input(&self, input_state: &impl InputState, input: &mut InputData) {
+        input
             .controllers
-            .iter()
-            .map(|keys| {
-                keys.iter()
-                    .map(|(key, assign)| (key.clone(), assign.pressed(input_state)))
-                    .collect()
-            })
-            .collect();
+            .resize_with(self.controllers.len(), Vec::new);
+
+        for (keys, data) in self.controllers.iter().zip(input.controllers.iter_mut()) {
+            let same_layout = keys.len() == data.len()
+                && keys
+                    .iter()
+                    .zip(data.iter())
+                    .all(|((key, _), (name, _))| key == name);
+            if !same_layout {
+                *data = keys.iter().map(|(key, _)| (key.clone(), false)).collect();
+            }
+
+            for ((_, assign), (_, pressed)) in keys.iter().zip(data.iter_mut()) {
+                *pressed = assign.pressed(input_state);
+            }
+        }
+    }
+
+    /// Adds controllers and buttons that exist in `default` but not in `self`,
+    /// keeping existing assignments. Returns the added `(controller index, button name)`s.
+    pub fn add_missing_keys(&mut self, default: &KeyConfig) -> Vec<(usize, String)> {
+        let mut added = vec![];
+
+        for (i, default_keys) in default.controllers.iter().enumerate() {
+            if self.controllers.len() <= i {
+                self.controllers.push(vec![]);
+            }
+            let keys = &mut self.controllers[i];
+
+            for (j, (key, assign)) in default_keys.iter().enumerate() {
+                if keys.iter().all(|(k, _)| k != key) {
+                    keys.insert(j.min(keys.len()), (key.clone(), assign.clone()));
+                    added.push((i, key.clone()));
+                }
+            }
+        }
 
-        InputData { controllers }
+        added
     }
 }
 
+/// Button states of each controller.
+///
+/// Key configs saved by older versions may lack controllers or buttons that a core
+/// added later. Cores should read input through `controller` and `pressed`, which treat
+/// anything missing as not pressed, and should ignore unknown keys instead of panicking.
 #[derive(Default)]
 pub struct InputData {
     pub controllers: Vec<Vec<(String, bool)>>,
 }
 
+impl InputData {
+    /// Buttons of the `index`-th controller. Empty if the controller is not configured.
+    pub fn controller(&self, index: usize) -> &[(String, bool)] {
+        self.controllers
+            .get(index)
+            .map(|keys| keys.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Whether `key` of the `index`-th controller is pressed.
+    /// Missing controllers and keys are treated as released.
+    pub fn pressed(&self, index: usize, key: &str) -> bool {
+        self.controller(index)
+            .iter()
+            .any(|(name, pressed)| name == key && *pressed)
+    }
+}
+
 pub trait EmulatorCore {
     type Error: std::error::Error + Send + Sync + 'static;
     type Config: JsonSchema + Serialize + DeserializeOwned + Default;
 
     fn core_info() -> &'static CoreInfo;
 
+    /// BIOS and other firmware files used by this core.
+    fn firmware_info() -> &'static [FirmwareInfo] {
+        &[]
+    }
+
     fn try_from_file(
         data: &[u8],
         backup: Option<&[u8]>,
@@ -190,6 +308,12 @@ pub trait EmulatorCore {
         Self: Sized;
     fn game_info(&self) -> Vec<(String, String)>;
 
+    /// Returns true when `game_info` has changed since the last call,
+    /// e.g. after detecting the save type. The frontend also refreshes it periodically.
+    fn game_info_updated(&mut self) -> bool {
+        false
+    }
+
     fn set_config(&mut self, config: &Self::Config);
 
     fn exec_frame(&mut self, render_graphics: bool);
@@ -203,6 +327,141 @@ pub trait EmulatorCore {
 
     fn backup(&self) -> Option<Vec<u8>>;
 
+    /// Counter incremented whenever backup RAM is modified.
+    /// Cores that do not track modifications return `None`.
+    fn backup_generation(&self) -> Option<u64> {
+        None
+    }
+
     fn save_state(&self) -> Vec<u8>;
     fn load_state(&mut self, data: &[u8]) -> Result<(), Self::Error>;
+
+    /// Messages to be shown to the user, such as "Saved EEPROM".
+    /// Returned messages are removed from the core.
+    fn take_messages(&mut self) -> Vec<String> {
+        vec![]
+    }
+
+    /// Whether the loaded game can be switched between NTSC and PAL timing.
+    fn supports_region_override(&self) -> bool {
+        false
+    }
+
+    /// Forces NTSC or PAL timing. `None` restores the region detected from the game.
+    /// Cores should change the frame rate and audio sample rate accordingly.
+    fn set_region(&mut self, _region: Option<Region>) {}
+
+    /// Short description of the current game situation (e.g. level, in-game time),
+    /// shown alongside save states.
+    fn status(&self) -> Option<String> {
+        None
+    }
+
+    /// Graphics layers that can be hidden, such as `["BG1", "BG2", "OBJ"]`.
+    /// Used for capturing clean sprites and diagnosing rendering bugs.
+    fn layer_names(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Shows or hides the `index`-th layer of `layer_names`.
+    fn set_layer_enabled(&mut self, _index: usize, _enabled: bool) {}
+
+    /// Whether `read_memory`, `write_memory` and `set_memory_patches` are implemented.
+    /// Used for cheat codes.
+    fn supports_memory_access(&self) -> bool {
+        false
+    }
+
+    /// Reads a byte of the CPU address space without side effects.
+    fn read_memory(&self, _addr: u32) -> u8 {
+        0
+    }
+
+    /// Writes a byte to the CPU address space. Writes to ROM are ignored.
+    fn write_memory(&mut self, _addr: u32, _data: u8) {}
+
+    /// Replaces the bytes read by the CPU. Called with all patches whenever they change.
+    fn set_memory_patches(&mut self, _patches: &[MemoryPatch]) {}
+
+    /// Statistics of the last executed frame, shown in the performance HUD.
+    fn frame_stats(&self) -> Option<FrameStats> {
+        None
+    }
+
+    /// Whether `tile_draws` is implemented. Used for HD texture packs.
+    fn supports_tile_hashes(&self) -> bool {
+        false
+    }
+
+    /// Enables collecting `tile_draws`, which costs hashing every drawn tile.
+    fn set_tile_hashing(&mut self, _enabled: bool) {}
+
+    /// Tiles drawn in the last rendered frame, from back to front.
+    /// Empty unless enabled by `set_tile_hashing`.
+    fn tile_draws(&self) -> &[TileDraw] {
+        &[]
+    }
+
+    /// Whether the loaded game has a camera peripheral, such as the Game Boy Camera.
+    fn supports_camera(&self) -> bool {
+        false
+    }
+
+    /// Sets the image seen by the camera, as `CAMERA_WIDTH * CAMERA_HEIGHT`
+    /// 8-bit grayscale pixels in row-major order. Kept until the next call.
+    fn set_camera_image(&mut self, _image: &[u8]) {}
+
+    /// Whether the loaded game reads cards through an accessory, such as the e-Reader.
+    fn supports_card_scan(&self) -> bool {
+        false
+    }
+
+    /// Scans a card, from the contents of a dot code dump (`.raw`) or a scanned image (`.bmp`).
+    fn scan_card(&mut self, _data: &[u8]) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Whether the loaded game can talk to another console through a link cable,
+    /// such as the serial port of the Game Boy.
+    fn supports_link_cable(&self) -> bool {
+        false
+    }
+
+    /// Bytes sent to the link cable since the last call.
+    fn take_link_output(&mut self) -> Vec<u8> {
+        vec![]
+    }
+
+    /// Bytes received from the other console, which may arrive some frames after they were sent.
+    /// Cores should keep the game waiting in the handshakes of the link protocol until they arrive.
+    fn push_link_input(&mut self, _data: &[u8]) {}
+
+    /// Save types the loaded game can be forced to use, such as `["SRAM", "Flash 128K"]`,
+    /// for games whose save type is detected wrongly. The detected type should be shown in `game_info`.
+    fn save_types(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Forces the `index`-th type of `save_types`. `None` restores the detected type.
+    /// Called right after loading the game, before the first frame.
+    fn set_save_type(&mut self, _index: Option<usize>) {}
+
+    /// Number of controllers the game reads now, when it switches between them while running,
+    /// such as Super Game Boy games asking for multiplayer. `None` if the game does not.
+    /// Cores declare every controller in `default_key_config` and read the extra ones only
+    /// while they are active.
+    fn active_controllers(&self) -> Option<usize> {
+        None
+    }
+
+    /// Whether the loaded game runs both on the original hardware and on an enhanced model,
+    /// such as Game Boy games that also support Game Boy Color.
+    fn is_dual_mode(&self) -> bool {
+        false
+    }
+
+    /// Runs the game on the enhanced model if `true`, or on the original one if `false`,
+    /// from the next reset. `None` restores the model of the core config.
+    /// Kept across `set_config`.
+    fn set_enhanced_model(&mut self, _enhanced: Option<bool>) {}
 }
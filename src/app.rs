@@ -6,18 +6,45 @@ use bevy::{
     window::{PresentMode, WindowMode},
 };
 use bevy_easings::EasingsPlugin;
-use bevy_egui::{EguiContext, EguiPlugin};
+use bevy_egui::{egui, EguiContext, EguiPlugin, EguiSettings};
 use bevy_tiled_camera::TiledCameraPlugin;
+use chrono::{DateTime, Local};
 use log::error;
+use std::collections::VecDeque;
 
 use crate::{
+    audio, audio_pack, camera,
     config::{self, load_config, load_persistent_state},
     core::{self, Emulator, GameScreen},
-    hotkey, menu,
+    file,
+    gamepad::{self, load_controller_db},
+    gb_model, goals, hotkey, library, link,
+    menu::{self, MenuTab},
+    movie, netplay, obs,
+    profile::Profiles,
+    profiler, quick_menu,
     rewinding::{self},
+    session::Sessions,
+    speech, state_carousel, texture_pack,
+    utils::{ease_transform, unbounded_channel, Receiver, Sender},
 };
 
 pub async fn main() {
+    if let Err(err) = file::repair().await {
+        error!("Repairing file system failed: {err}");
+    }
+
+    let config = match load_config().await {
+        Ok(config) => config,
+        Err(err) => {
+            error!("Load config failed: {err}");
+            config::Config::default()
+        }
+    };
+
+    // Controller mappings have to be ready before the gamepad plugin starts
+    load_controller_db(&config);
+
     let window_desc = WindowDescriptor {
         title: "MERU".to_string(),
         resizable: false,
@@ -49,6 +76,7 @@ pub async fn main() {
     app.insert_resource(window_desc)
         .insert_resource(ClearColor(Color::rgb(0.0, 0.0, 0.0)))
         .init_resource::<UiState>()
+        .init_resource::<Sessions>()
         .init_resource::<FullscreenState>()
         .insert_resource(Msaa { samples: 4 })
         .insert_resource(bevy::log::LogSettings {
@@ -63,35 +91,60 @@ pub async fn main() {
         .add_plugin(TiledCameraPlugin)
         .add_plugin(EasingsPlugin)
         .add_plugin(EguiPlugin)
+        .add_plugin(gamepad::GamepadPlugin)
         .add_plugin(hotkey::HotKeyPlugin)
+        .add_plugin(library::LibraryPlugin)
         .add_plugin(menu::MenuPlugin)
         .add_plugin(core::EmulatorPlugin)
         .add_plugin(rewinding::RewindingPlugin)
         .add_plugin(FpsPlugin)
+        .add_plugin(audio::VolumeOsdPlugin)
         .add_plugin(MessagePlugin)
+        .add_plugin(netplay::NetplayPlugin)
+        .add_plugin(link::LinkCablePlugin)
+        .add_plugin(quick_menu::QuickMenuPlugin)
+        .add_plugin(state_carousel::StateCarouselPlugin)
+        .add_plugin(gb_model::GbModelPlugin)
+        .add_plugin(goals::GoalsPlugin)
+        .add_plugin(movie::MoviePlugin)
+        .add_plugin(audio_pack::AudioPackPlugin)
+        .add_plugin(texture_pack::TexturePackPlugin)
+        .add_plugin(camera::CameraPlugin)
+        .add_plugin(obs::ObsPlugin)
+        .add_plugin(speech::SpeechPlugin)
+        .add_plugin(profiler::ProfilerPlugin)
         .add_event::<WindowControlEvent>()
         .add_system(window_control_event)
         .insert_resource(LastClicked(0.0))
         .add_system(process_double_click)
+        .add_system(ui_theme_system)
+        .add_system(msaa_system)
+        .add_system(ui_scale_system)
+        .add_system(window_title_system)
         .add_startup_system(setup)
         .add_startup_stage("single-startup", SystemStage::single_threaded())
         .add_startup_system_to_stage("single-startup", set_window_icon)
         .add_state(AppState::Menu);
 
+    #[cfg(any(target_os = "windows", target_os = "macos"))]
+    app.add_system(capture_protection_system);
+
+    #[cfg(not(target_arch = "wasm32"))]
+    app.add_plugin(crate::tray::TrayPlugin)
+        .add_plugin(crate::os_hotkey::OsHotKeyPlugin);
+
     #[cfg(target_arch = "wasm32")]
     app.add_system(resize_canvas);
 
     let fut = async move {
-        let config = match load_config().await {
-            Ok(config) => config,
-            Err(err) => {
-                error!("Load config failed: {err}");
-                config::Config::default()
-            }
-        };
+        let profiles = Profiles::load().await?;
+        let ask_profile = profiles.ask_on_startup && !profiles.profiles.is_empty();
 
         app.insert_resource(config);
         app.insert_resource(load_persistent_state().await?);
+        app.insert_resource(library::load_library_db().await?);
+        app.insert_resource(menu::ProfileWindow::new(ask_profile));
+        app.insert_resource(profiles);
 
         app.run();
         Ok::<(), anyhow::Error>(())
@@ -101,7 +154,7 @@ pub async fn main() {
 }
 
 #[derive(Component)]
-struct PixelFont;
+pub struct PixelFont;
 
 fn setup(
     mut commands: Commands,
@@ -131,6 +184,117 @@ fn setup(
         .insert(PixelFont);
 }
 
+fn ui_theme_system(
+    config: Res<config::Config>,
+    mut egui_ctx: ResMut<EguiContext>,
+    mut high_contrast: Local<Option<bool>>,
+) {
+    if *high_contrast == Some(config.high_contrast_ui) {
+        return;
+    }
+    *high_contrast = Some(config.high_contrast_ui);
+
+    let visuals = if config.high_contrast_ui {
+        high_contrast_visuals()
+    } else {
+        egui::Visuals::dark()
+    };
+    egui_ctx.ctx_mut().set_visuals(visuals);
+}
+
+fn msaa_system(config: Res<config::Config>, mut msaa: ResMut<Msaa>) {
+    if msaa.samples != config.msaa_samples {
+        msaa.samples = config.msaa_samples;
+    }
+}
+
+fn ui_scale_system(
+    config: Res<config::Config>,
+    windows: Res<Windows>,
+    mut egui_settings: ResMut<EguiSettings>,
+) {
+    let window = if let Some(window) = windows.get_primary() {
+        window
+    } else {
+        return;
+    };
+
+    // bevy_egui multiplies this by the window's scale factor, which changes
+    // when the window moves to a monitor with a different DPI.
+    // Cancel it out when the scale is given manually.
+    let scale_factor = match config.ui_scale {
+        None => 1.0,
+        Some(percent) => percent as f64 / 100.0 / window.scale_factor(),
+    };
+
+    if egui_settings.scale_factor != scale_factor {
+        egui_settings.scale_factor = scale_factor;
+    }
+}
+
+fn window_title_system(
+    config: Res<config::Config>,
+    diagnostics: Res<Diagnostics>,
+    emulator: Option<Res<Emulator>>,
+    mut windows: ResMut<Windows>,
+) {
+    let title = if let Some(emulator) = emulator {
+        let fps = diagnostics
+            .get(FrameTimeDiagnosticsPlugin::FPS)
+            .and_then(|fps| fps.average())
+            .unwrap_or(0.0);
+        config
+            .window_title
+            .replace("{game}", &emulator.game_name)
+            .replace("{system}", emulator.core.core_info().system_name)
+            .replace("{fps}", &format!("{fps:.0}"))
+    } else {
+        "MERU".to_string()
+    };
+
+    let window = if let Some(window) = windows.get_primary_mut() {
+        window
+    } else {
+        return;
+    };
+
+    if window.title() != title {
+        window.set_title(title);
+    }
+}
+
+fn high_contrast_visuals() -> egui::Visuals {
+    use egui::{Color32, Stroke};
+
+    let mut visuals = egui::Visuals::dark();
+    visuals.override_text_color = Some(Color32::WHITE);
+    visuals.faint_bg_color = Color32::from_gray(24);
+    visuals.extreme_bg_color = Color32::BLACK;
+    visuals.hyperlink_color = Color32::from_rgb(0, 255, 255);
+    visuals.selection.bg_fill = Color32::from_rgb(255, 255, 0);
+    visuals.selection.stroke = Stroke::new(2.0, Color32::BLACK);
+
+    let widgets = &mut visuals.widgets;
+    widgets.noninteractive.bg_fill = Color32::BLACK;
+    widgets.noninteractive.bg_stroke = Stroke::new(1.0, Color32::WHITE);
+    widgets.noninteractive.fg_stroke = Stroke::new(1.0, Color32::WHITE);
+    for widget in [
+        &mut widgets.inactive,
+        &mut widgets.hovered,
+        &mut widgets.active,
+    ] {
+        widget.bg_fill = Color32::BLACK;
+        widget.bg_stroke = Stroke::new(2.0, Color32::WHITE);
+        widget.fg_stroke = Stroke::new(2.0, Color32::WHITE);
+    }
+    widgets.hovered.bg_stroke = Stroke::new(2.0, Color32::from_rgb(255, 255, 0));
+    widgets.active.bg_stroke = Stroke::new(3.0, Color32::from_rgb(255, 255, 0));
+    widgets.open.bg_fill = Color32::BLACK;
+    widgets.open.bg_stroke = Stroke::new(2.0, Color32::from_rgb(255, 255, 0));
+
+    visuals
+}
+
 #[cfg(target_os = "windows")]
 fn set_window_icon(windows: NonSend<bevy::winit::WinitWindows>) {
     use winit::window::Icon;
@@ -160,6 +324,67 @@ fn set_window_icon(windows: NonSend<bevy::winit::WinitWindows>) {
 #[cfg(not(target_os = "windows"))]
 fn set_window_icon() {}
 
+pub fn is_capture_protection_supported() -> bool {
+    cfg!(any(target_os = "windows", target_os = "macos"))
+}
+
+#[cfg(any(target_os = "windows", target_os = "macos"))]
+fn capture_protection_system(
+    config: Res<config::Config>,
+    windows: NonSend<bevy::winit::WinitWindows>,
+    mut applied: Local<Option<bool>>,
+) {
+    if *applied == Some(config.capture_protection) {
+        return;
+    }
+
+    let primary = if let Some(window) = windows.get_window(bevy::window::WindowId::primary()) {
+        window
+    } else {
+        return;
+    };
+
+    set_capture_protection(primary, config.capture_protection);
+    *applied = Some(config.capture_protection);
+}
+
+#[cfg(target_os = "windows")]
+fn set_capture_protection(window: &winit::window::Window, enabled: bool) {
+    use winapi::um::winuser::{SetWindowDisplayAffinity, WDA_MONITOR, WDA_NONE};
+    use winit::platform::windows::WindowExtWindows;
+
+    // Hides the window from captures entirely. Not defined in winapi
+    const WDA_EXCLUDEFROMCAPTURE: u32 = 0x11;
+
+    let hwnd = window.hwnd() as winapi::shared::windef::HWND;
+    let ok = unsafe {
+        if enabled {
+            // Windows before 10 version 2004 only supports blacking out the window
+            SetWindowDisplayAffinity(hwnd, WDA_EXCLUDEFROMCAPTURE) != 0
+                || SetWindowDisplayAffinity(hwnd, WDA_MONITOR) != 0
+        } else {
+            SetWindowDisplayAffinity(hwnd, WDA_NONE) != 0
+        }
+    };
+    if !ok {
+        error!("Failed to set the display affinity of the window");
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn set_capture_protection(window: &winit::window::Window, enabled: bool) {
+    use objc::{msg_send, runtime::Object, sel, sel_impl};
+    use winit::platform::macos::WindowExtMacOS;
+
+    // NSWindowSharingNone and NSWindowSharingReadOnly
+    let sharing_type: u64 = if enabled { 0 } else { 1 };
+
+    let ns_window = window.ns_window() as *mut Object;
+    unsafe {
+        let _: () = msg_send![ns_window, setSharingType: sharing_type];
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum AppState {
     Menu,
@@ -170,6 +395,9 @@ pub enum AppState {
 #[derive(Default)]
 pub struct UiState {
     pub state_save_slot: usize,
+    pub paused: bool,
+    pub controllers_swapped: bool,
+    pub in_tray: bool,
 }
 
 #[derive(Component)]
@@ -182,6 +410,7 @@ pub enum WindowControlEvent {
     ToggleFullscreen,
     ChangeScale(usize),
     Restore,
+    MinimizeToTray,
 }
 
 fn window_control_event(
@@ -213,12 +442,17 @@ fn window_control_event(
                         app_state.current(),
                         window,
                         fullscreen_state.0,
-                        config.scaling,
+                        config.video_setting(&emulator.game_hash).scaling,
                     );
                 }
             }
             WindowControlEvent::ChangeScale(scale) => {
-                config.scaling = *scale;
+                // Without a game, the global setting is changed
+                let game_hash = emulator.as_deref().map_or("", |e| e.game_hash.as_str());
+                let mut video = config.video_setting(game_hash);
+                video.scaling = *scale;
+                config.set_video_setting(game_hash, video);
+
                 if running {
                     let window = windows.get_primary_mut().unwrap();
                     restore_window(
@@ -226,20 +460,23 @@ fn window_control_event(
                         app_state.current(),
                         window,
                         fullscreen_state.0,
-                        config.scaling,
+                        *scale,
                     );
                 }
             }
             WindowControlEvent::Restore => {
+                let emulator = emulator.as_deref().unwrap();
                 let window = windows.get_primary_mut().unwrap();
                 restore_window(
-                    emulator.as_deref().unwrap(),
+                    emulator,
                     app_state.current(),
                     window,
                     fullscreen_state.0,
-                    config.scaling,
+                    config.video_setting(&emulator.game_hash).scaling,
                 );
             }
+            // Handled by the tray plugin
+            WindowControlEvent::MinimizeToTray => {}
         }
     }
 }
@@ -309,10 +546,8 @@ fn restore_window(
         (menu::MENU_WIDTH as f32, menu::MENU_HEIGHT as f32)
     } else {
         let scale = scaling as f32;
-        (
-            emulator.core.frame_buffer().width as f32 * scale,
-            emulator.core.frame_buffer().height as f32 * scale,
-        )
+        let (width, height) = emulator.display_size();
+        (width as f32 * scale, height as f32 * scale)
     };
 
     if !fullscreen {
@@ -388,15 +623,71 @@ fn exit_fps_system(
     commands.entity(fps_text_bg.single()).despawn();
 }
 
+// Size of a character of the pixel font in the HUD
+const HUD_CHAR_WIDTH: usize = 6;
+const HUD_LINE_HEIGHT: usize = 16;
+
+// Weight of the latest frame in the smoothed performance stats
+const PERF_STATS_SMOOTHING: f64 = 0.05;
+
+/// Smoothed times in milliseconds, and cycles per frame
+#[derive(Default)]
+struct PerfStats {
+    core_ms: f64,
+    frame_ms: f64,
+    cycles: f64,
+    idle_ratio: Option<f64>,
+}
+
+impl PerfStats {
+    fn update(&mut self, emulator: &Emulator, frame_ms: f64) {
+        let mix = |avg: &mut f64, value: f64| *avg += (value - *avg) * PERF_STATS_SMOOTHING;
+
+        mix(&mut self.core_ms, emulator.core_time.as_secs_f64() * 1000.0);
+        mix(&mut self.frame_ms, frame_ms);
+
+        let stats = emulator.core.frame_stats();
+        match stats {
+            Some(stats) => {
+                mix(&mut self.cycles, stats.cycles as f64);
+                self.idle_ratio = stats.idle_cycles.map(|idle| {
+                    let ratio = idle as f64 / stats.cycles.max(1) as f64;
+                    let avg = self.idle_ratio.unwrap_or(ratio);
+                    avg + (ratio - avg) * PERF_STATS_SMOOTHING
+                });
+            }
+            None => {
+                self.cycles = 0.0;
+                self.idle_ratio = None;
+            }
+        }
+    }
+
+    fn lines(&self) -> Vec<String> {
+        let mut ret = vec![
+            format!("core{:5.1}ms", self.core_ms),
+            format!("all {:5.1}ms", self.frame_ms),
+        ];
+        if self.cycles > 0.0 {
+            ret.push(format!("cyc{:6.2}M", self.cycles / 1e6));
+        }
+        if let Some(idle) = self.idle_ratio {
+            ret.push(format!("idle{:6.1}%", idle * 100.0));
+        }
+        ret
+    }
+}
+
 #[allow(clippy::type_complexity)]
 fn fps_system(
     config: Res<config::Config>,
     diagnostics: Res<Diagnostics>,
     is_turbo: Res<hotkey::IsTurbo>,
     emulator: Option<Res<Emulator>>,
+    mut perf_stats: Local<PerfStats>,
     mut ps: ParamSet<(
         Query<(&mut Text, &mut Visibility, &mut Transform), With<FpsText>>,
-        Query<(&mut Visibility, &mut Transform), With<FpsTextBg>>,
+        Query<(&mut Visibility, &mut Transform, &mut Sprite), With<FpsTextBg>>,
     )>,
 ) {
     let emulator = if let Some(emulator) = emulator {
@@ -405,34 +696,52 @@ fn fps_system(
         return;
     };
 
-    let screen_width = emulator.core.frame_buffer().width;
-    let screen_height = emulator.core.frame_buffer().height;
+    let (screen_width, screen_height) = emulator.display_size();
+
+    let mut lines = vec![];
+
+    if config.show_fps {
+        let fps_diag = diagnostics.get(FrameTimeDiagnosticsPlugin::FPS).unwrap();
+        let fps = fps_diag.average().unwrap_or(0.0)
+            * if is_turbo.0 {
+                emulator.turbo_frames as f64
+            } else {
+                1.0
+            };
+        let fps = format!("{fps:5.02}");
+        lines.push(fps.chars().take(5).collect::<String>());
+    }
+
+    if config.show_perf_stats {
+        let frame_time = diagnostics
+            .get(FrameTimeDiagnosticsPlugin::FRAME_TIME)
+            .unwrap();
+        perf_stats.update(&emulator, frame_time.value().unwrap_or(0.0) * 1000.0);
+        lines.extend(perf_stats.lines());
+    }
+
+    let visible = !lines.is_empty();
+    let width = lines.iter().map(|line| line.len()).max().unwrap_or(0) * HUD_CHAR_WIDTH + 2;
+    let height = lines.len() * HUD_LINE_HEIGHT;
 
     let mut p0 = ps.p0();
     let (mut text, mut visibility, mut transform) = p0.single_mut();
-    visibility.is_visible = config.show_fps;
-    let fps_diag = diagnostics.get(FrameTimeDiagnosticsPlugin::FPS).unwrap();
-    let fps = fps_diag.average().unwrap_or(0.0)
-        * if is_turbo.0 {
-            config.frame_skip_on_turbo as f64
-        } else {
-            1.0
-        };
-    let fps = format!("{fps:5.02}");
-    text.sections[0].value = fps.chars().take(5).collect();
+    visibility.is_visible = visible;
+    text.sections[0].value = lines.join("\n");
 
     *transform = Transform::from_xyz(
-        ((screen_width / 2).max(30) - 30) as _,
+        ((screen_width / 2).max(width) - width + 2) as _,
         (screen_height / 2) as _,
         2.0,
     );
 
     let mut p1 = ps.p1();
-    let (mut visibility, mut transform) = p1.single_mut();
-    visibility.is_visible = config.show_fps;
+    let (mut visibility, mut transform, mut sprite) = p1.single_mut();
+    visibility.is_visible = visible;
+    sprite.custom_size = Some(Vec2::new(width as f32, height as f32));
     *transform = Transform::from_xyz(
-        (screen_width / 2 - 16) as _,
-        (screen_height / 2 - 8) as _,
+        screen_width as f32 / 2.0 - width as f32 / 2.0,
+        screen_height as f32 / 2.0 - height as f32 / 2.0,
         1.0,
     );
 }
@@ -441,13 +750,119 @@ struct MessagePlugin;
 
 impl Plugin for MessagePlugin {
     fn build(&self, app: &mut App) {
-        app.add_system(message_event_system.label("message_event"))
+        // Messages from async tasks are sent through this channel
+        let (s, r) = unbounded_channel::<ShowMessage>();
+
+        app.insert_resource(s)
+            .insert_resource(r)
+            .add_system(async_message_system.before("message_event"))
+            .add_system(message_event_system.label("message_event"))
             .add_system(message_update_system.after("message_event"))
+            .add_system(message_log_system)
+            .init_resource::<MessageLog>()
+            .init_resource::<PendingMenuLink>()
             .add_event::<ShowMessage>();
     }
 }
 
-pub struct ShowMessage(pub String);
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum MessageLevel {
+    Info,
+    Error,
+}
+
+pub struct ShowMessage {
+    pub text: String,
+    pub level: MessageLevel,
+    /// Menu tab to resolve the problem, opened when entering the menu
+    pub link: Option<MenuTab>,
+}
+
+impl ShowMessage {
+    pub fn info(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            level: MessageLevel::Info,
+            link: None,
+        }
+    }
+
+    pub fn error(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            level: MessageLevel::Error,
+            link: None,
+        }
+    }
+
+    pub fn with_link(mut self, tab: MenuTab) -> Self {
+        self.link = Some(tab);
+        self
+    }
+
+    /// Error for a failed save. Links to the storage settings when the storage is full
+    pub fn save_failed(what: &str, err: &anyhow::Error) -> Self {
+        if file::is_quota_exceeded(err) {
+            Self::error(format!("{what} failed: Storage is full")).with_link(MenuTab::Storage)
+        } else {
+            Self::error(format!("{what} failed: {err}"))
+        }
+    }
+}
+
+fn async_message_system(recv: Res<Receiver<ShowMessage>>, mut event: EventWriter<ShowMessage>) {
+    while let Ok(msg) = recv.try_recv() {
+        event.send(msg);
+    }
+}
+
+const MESSAGE_LOG_MAX: usize = 200;
+
+pub const MESSAGE_DURATION_SECS: f64 = 3.0;
+
+pub struct MessageLogEntry {
+    pub time: DateTime<Local>,
+    pub text: String,
+    pub level: MessageLevel,
+    pub link: Option<MenuTab>,
+}
+
+#[derive(Default)]
+pub struct MessageLog {
+    pub entries: VecDeque<MessageLogEntry>,
+}
+
+impl MessageLog {
+    pub fn push(&mut self, text: impl Into<String>, level: MessageLevel, link: Option<MenuTab>) {
+        self.entries.push_back(MessageLogEntry {
+            time: Local::now(),
+            text: text.into(),
+            level,
+            link,
+        });
+        while self.entries.len() > MESSAGE_LOG_MAX {
+            self.entries.pop_front();
+        }
+    }
+}
+
+/// Menu tab linked from the last message, with the time it was shown
+#[derive(Default)]
+pub struct PendingMenuLink(pub Option<(MenuTab, f64)>);
+
+fn message_log_system(
+    time: Res<Time>,
+    mut event: EventReader<ShowMessage>,
+    mut log: ResMut<MessageLog>,
+    mut pending_link: ResMut<PendingMenuLink>,
+) {
+    for msg in event.iter() {
+        log.push(&msg.text, msg.level, msg.link.clone());
+        if let Some(link) = &msg.link {
+            pending_link.0 = Some((link.clone(), time.seconds_since_startup()));
+        }
+    }
+}
 
 #[derive(Component)]
 struct MessageText {
@@ -458,32 +873,35 @@ fn message_event_system(
     mut commands: Commands,
     time: Res<Time>,
     screen: Option<Res<GameScreen>>,
-    images: Res<Assets<Image>>,
+    emulator: Option<Res<Emulator>>,
+    config: Res<config::Config>,
     mut event: EventReader<ShowMessage>,
     pixel_font: Query<&Handle<Font>, With<PixelFont>>,
     mut messages: Query<(Entity, &Transform), With<MessageText>>,
 ) {
-    let image = if let Some(screen) = screen {
-        images.get(&screen.0).unwrap()
-    } else {
-        return;
+    let emulator = match (screen, emulator) {
+        (Some(_), Some(emulator)) => emulator,
+        _ => return,
     };
-    let screen_width = image.size()[0] as f32;
-    let screen_height = image.size()[1] as f32;
+    let (screen_width, screen_height) = emulator.display_size();
+    let (screen_width, screen_height) = (screen_width as f32, screen_height as f32);
 
     let pixel_font = pixel_font.single();
 
-    for ShowMessage(msg) in event.iter() {
-        for (entity, trans) in messages.iter_mut() {
-            use bevy_easings::*;
+    for ShowMessage { text, link, .. } in event.iter() {
+        let msg = match link {
+            Some(link) => format!("{text} [Menu: {}]", link.name()),
+            None => text.clone(),
+        };
 
-            commands.entity(entity).insert(trans.ease_to(
+        for (entity, trans) in messages.iter_mut() {
+            ease_transform(
+                &mut commands.entity(entity),
+                *trans,
                 Transform::from_xyz(0.0, 20.0, 0.0) * *trans,
-                EaseFunction::CubicInOut,
-                EasingType::Once {
-                    duration: std::time::Duration::from_millis(100),
-                },
-            ));
+                100,
+                config.animations,
+            );
         }
 
         commands
@@ -526,7 +944,7 @@ fn message_update_system(
     messages: Query<(Entity, &MessageText), With<MessageText>>,
 ) {
     for (entity, msg) in messages.iter() {
-        if time.seconds_since_startup() - msg.start > 3.0 {
+        if time.seconds_since_startup() - msg.start > MESSAGE_DURATION_SECS {
             commands.entity(entity).despawn_recursive();
         }
     }
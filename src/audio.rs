@@ -0,0 +1,392 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext};
+use log::warn;
+use meru_interface::{AudioBuffer, AudioSample};
+use ringbuf::{Consumer, Producer, RingBuffer};
+use rodio::cpal::traits::{DeviceTrait, HostTrait};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use crate::{
+    config::{AudioConfig, Config, MAX_VOLUME},
+    profiler::profile_scope,
+};
+
+// Night mode compressor parameters
+const COMPRESSOR_THRESHOLD: f32 = 0.125; // -18dBFS
+const COMPRESSOR_RATIO: f32 = 4.0;
+const COMPRESSOR_MAKEUP_GAIN: f32 = 2.0;
+const COMPRESSOR_ATTACK_SECS: f32 = 0.005;
+const COMPRESSOR_RELEASE_SECS: f32 = 0.2;
+
+// Length of fade in/out applied to each chunk to avoid clicks when skipping audio
+const FADE_SAMPLES: usize = 64;
+
+// Largest change of the playback rate by dynamic rate control, inaudible as a pitch change
+const MAX_RATE_ADJUSTMENT: f64 = 0.005;
+// Frames buffered beyond the latency that rate control aims at
+const RATE_CONTROL_MARGIN_FRAMES: usize = 2;
+
+// Seconds to show the volume after it is changed
+const VOLUME_OSD_SECS: f32 = 1.5;
+
+pub struct VolumeOsdPlugin;
+
+impl Plugin for VolumeOsdPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<VolumeOsd>()
+            .add_system(volume_osd_system);
+    }
+}
+
+/// Volume shown on screen for a moment after it is changed by hotkeys
+#[derive(Default)]
+pub struct VolumeOsd {
+    remaining: f32,
+}
+
+impl VolumeOsd {
+    pub fn show(&mut self) {
+        self.remaining = VOLUME_OSD_SECS;
+    }
+}
+
+fn volume_osd_system(
+    mut egui_ctx: ResMut<EguiContext>,
+    config: Res<Config>,
+    time: Res<Time>,
+    mut osd: ResMut<VolumeOsd>,
+) {
+    if osd.remaining <= 0.0 {
+        return;
+    }
+    osd.remaining -= time.delta_seconds();
+
+    let (icon, text) = if config.audio.muted {
+        ("🔇", "Muted".to_string())
+    } else {
+        ("🔊", format!("{}%", config.audio.volume))
+    };
+    let level = if config.audio.muted {
+        0.0
+    } else {
+        config.audio.volume as f32 / MAX_VOLUME as f32
+    };
+
+    egui::Area::new("volume_osd")
+        .anchor(egui::Align2::CENTER_BOTTOM, [0.0, -32.0])
+        .show(egui_ctx.ctx_mut(), |ui| {
+            egui::Frame::popup(ui.style()).show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label(icon);
+                    ui.add(
+                        egui::ProgressBar::new(level)
+                            .desired_width(160.0)
+                            .text(text),
+                    );
+                });
+            });
+        });
+}
+
+pub fn output_device_names() -> Vec<String> {
+    rodio::cpal::default_host()
+        .output_devices()
+        .map(|devices| devices.filter_map(|device| device.name().ok()).collect())
+        .unwrap_or_default()
+}
+
+fn open_output_stream(
+    device_name: Option<&str>,
+) -> (rodio::OutputStream, rodio::OutputStreamHandle) {
+    if let Some(name) = device_name {
+        let device = rodio::cpal::default_host()
+            .output_devices()
+            .ok()
+            .and_then(|mut devices| {
+                devices.find(|device| device.name().ok().as_deref() == Some(name))
+            });
+
+        if let Some(device) = device {
+            match rodio::OutputStream::try_from_device(&device) {
+                Ok(ret) => return ret,
+                Err(err) => warn!("Failed to open audio device `{name}`: {err}"),
+            }
+        } else {
+            warn!("Audio device `{name}` not found. Use default device");
+        }
+    }
+
+    rodio::OutputStream::try_default().expect("No audio output device available")
+}
+
+pub fn setup_audio(world: &mut World) {
+    let device_name = world.resource::<Config>().audio.device.clone();
+    let (stream, stream_handle) = open_output_stream(device_name.as_deref());
+
+    let sink = rodio::Sink::try_new(&stream_handle).expect("Failed to create audio sink");
+
+    world.insert_non_send_resource(stream);
+    world.insert_resource(stream_handle);
+    world.insert_resource(AudioSink::new(sink));
+}
+
+pub struct AudioSink {
+    sink: rodio::Sink,
+    envelope: f32,
+    stream: Option<AudioStream>,
+    samples: Vec<i16>,
+    /// Fraction of a sample position carried over between resampled frames
+    resample_phase: f64,
+    /// Last stereo sample of the previous resampled frame, interpolated with the next one
+    resample_last: Option<[i16; 2]>,
+}
+
+/// Writer side of the source currently played by the sink
+struct AudioStream {
+    sample_rate: u32,
+    channels: u16,
+    producer: Producer<i16>,
+    closed: Arc<AtomicBool>,
+    /// Number of samples in the last appended frame
+    frame_len: usize,
+}
+
+impl Drop for AudioStream {
+    fn drop(&mut self) {
+        self.closed.store(true, Ordering::Relaxed);
+    }
+}
+
+impl AudioSink {
+    fn new(sink: rodio::Sink) -> Self {
+        Self {
+            sink,
+            envelope: 0.0,
+            stream: None,
+            samples: vec![],
+            resample_phase: 0.0,
+            resample_last: None,
+        }
+    }
+
+    pub fn append(&mut self, buffer: &AudioBuffer, config: &AudioConfig) {
+        self.append_inner(buffer, config, false);
+    }
+
+    // Used for fast-forwarding by dropping audio of skipped frames.
+    // Fading each chunk keeps the pitch unchanged without clicks between chunks.
+    pub fn append_faded(&mut self, buffer: &AudioBuffer, config: &AudioConfig) {
+        self.append_inner(buffer, config, true);
+    }
+
+    fn append_inner(&mut self, buffer: &AudioBuffer, config: &AudioConfig, fade: bool) {
+        profile_scope!("audio_append");
+
+        let attack = (-1.0 / (COMPRESSOR_ATTACK_SECS * buffer.sample_rate as f32)).exp();
+        let release = (-1.0 / (COMPRESSOR_RELEASE_SECS * buffer.sample_rate as f32)).exp();
+
+        let mut samples = std::mem::take(&mut self.samples);
+        samples.clear();
+        let len = buffer.samples.len();
+        let fade_len = FADE_SAMPLES.min(len / 2).max(1);
+        for (i, sample) in buffer.samples.iter().enumerate() {
+            let (mut left, mut right) = self.process(sample, config, attack, release);
+            if fade {
+                let gain = (i.min(len - 1 - i) as f32 / fade_len as f32).min(1.0);
+                left = (left as f32 * gain) as i16;
+                right = (right as f32 * gain) as i16;
+            }
+            samples.push(left);
+            samples.push(right);
+        }
+
+        // Stretches or shrinks the frame slightly to keep the buffer near its target,
+        // instead of skipping or repeating whole frames
+        if config.dynamic_rate_control && !fade {
+            let target = (config.latency.max(1) + RATE_CONTROL_MARGIN_FRAMES) as f64;
+            let deviation = ((target - self.len() as f64) / target).clamp(-1.0, 1.0);
+            samples = self.resample(&samples, 1.0 + deviation * MAX_RATE_ADJUSTMENT);
+        } else {
+            self.resample_phase = 0.0;
+            self.resample_last = None;
+        }
+
+        let stream = self.stream(buffer.sample_rate, buffer.channels);
+        stream.frame_len = samples.len().max(1);
+        // Only whole frames are written so that channels stay interleaved on overflow
+        let free = stream.producer.remaining() / 2 * 2;
+        stream
+            .producer
+            .push_slice(&samples[..samples.len().min(free)]);
+
+        self.samples = samples;
+    }
+
+    /// Resamples interleaved stereo `samples` by `ratio` with linear interpolation.
+    /// The last sample of the previous frame comes first, so that the gap between
+    /// frames is interpolated as well.
+    fn resample(&mut self, samples: &[i16], ratio: f64) -> Vec<i16> {
+        let frames = samples.len() / 2;
+        if frames == 0 {
+            return vec![];
+        }
+
+        let last = self.resample_last;
+        let offset = last.is_some() as usize;
+        let sample = |ix: usize, ch: usize| match last {
+            Some(last) if ix == 0 => last[ch] as f64,
+            _ => samples[(ix - offset) * 2 + ch] as f64,
+        };
+
+        let end = (frames + offset - 1) as f64;
+        let step = 1.0 / ratio;
+        let mut ret = Vec::with_capacity((frames as f64 * ratio) as usize * 2 + 2);
+        let mut pos = self.resample_phase;
+        while pos < end {
+            let ix = pos as usize;
+            let t = pos - ix as f64;
+            for ch in 0..2 {
+                let (a, b) = (sample(ix, ch), sample(ix + 1, ch));
+                ret.push((a + (b - a) * t) as i16);
+            }
+            pos += step;
+        }
+        self.resample_phase = pos - end;
+        self.resample_last = Some([samples[frames * 2 - 2], samples[frames * 2 - 1]]);
+        ret
+    }
+
+    /// Returns the stream for the given format,
+    /// replacing the current one when the running core has changed it
+    fn stream(&mut self, sample_rate: u32, channels: u16) -> &mut AudioStream {
+        let reopen = !matches!(&self.stream, Some(stream)
+            if stream.sample_rate == sample_rate && stream.channels == channels);
+
+        if reopen {
+            // Buffered samples of the old stream are played before the new one
+            // One second of stereo samples
+            let (producer, consumer) = RingBuffer::new(sample_rate.max(1) as usize * 2).split();
+            let closed = Arc::new(AtomicBool::new(false));
+            self.sink.append(AudioSource {
+                sample_rate,
+                channels,
+                consumer,
+                closed: closed.clone(),
+            });
+            self.stream = Some(AudioStream {
+                sample_rate,
+                channels,
+                producer,
+                closed,
+                frame_len: 1,
+            });
+        }
+
+        self.stream.as_mut().unwrap()
+    }
+
+    /// Number of frames buffered and not played yet
+    pub fn len(&self) -> usize {
+        self.stream.as_ref().map_or(0, |stream| {
+            (stream.producer.len() + stream.frame_len - 1) / stream.frame_len
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stream
+            .as_ref()
+            .map_or(true, |stream| stream.producer.is_empty())
+    }
+
+    pub fn set_volume(&self, volume: f32) {
+        if self.sink.volume() != volume {
+            self.sink.set_volume(volume);
+        }
+    }
+
+    fn process(
+        &mut self,
+        sample: &AudioSample,
+        config: &AudioConfig,
+        attack: f32,
+        release: f32,
+    ) -> (i16, i16) {
+        let mut left = sample.left as f32 / 32768.0;
+        let mut right = sample.right as f32 / 32768.0;
+
+        if config.mono {
+            let mid = (left + right) / 2.0;
+            left = mid;
+            right = mid;
+        }
+
+        let balance = config.balance as f32 / 100.0;
+        left *= (1.0 - balance).min(1.0);
+        right *= (1.0 + balance).min(1.0);
+
+        if config.night_mode {
+            let level = left.abs().max(right.abs());
+            let coeff = if level > self.envelope {
+                attack
+            } else {
+                release
+            };
+            self.envelope = level + coeff * (self.envelope - level);
+
+            let gain = if self.envelope > COMPRESSOR_THRESHOLD {
+                (COMPRESSOR_THRESHOLD + (self.envelope - COMPRESSOR_THRESHOLD) / COMPRESSOR_RATIO)
+                    / self.envelope
+            } else {
+                1.0
+            } * COMPRESSOR_MAKEUP_GAIN;
+
+            left *= gain;
+            right *= gain;
+        }
+
+        let to_i16 = |x: f32| (x * 32768.0).clamp(i16::MIN as f32, i16::MAX as f32) as i16;
+        (to_i16(left), to_i16(right))
+    }
+}
+
+/// Streaming source fed by the emulator through a lock-free ring buffer.
+/// Plays silence on underrun and ends only when the writer is closed.
+struct AudioSource {
+    sample_rate: u32,
+    channels: u16,
+    consumer: Consumer<i16>,
+    closed: Arc<AtomicBool>,
+}
+
+impl Iterator for AudioSource {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.consumer.pop() {
+            Some(sample) => Some(sample),
+            None if self.closed.load(Ordering::Relaxed) => None,
+            None => Some(0),
+        }
+    }
+}
+
+impl rodio::Source for AudioSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<std::time::Duration> {
+        None
+    }
+}
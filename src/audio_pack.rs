@@ -0,0 +1,192 @@
+use anyhow::Result;
+use bevy::prelude::*;
+use std::{collections::BTreeSet, fs::File, io::BufReader, path::Path};
+
+use crate::{
+    app::{AppState, ShowMessage, UiState},
+    cheats::{parse_conditions, MemoryCondition},
+    config::{AudioPack, Config},
+    core::Emulator,
+    hotkey::IsTurbo,
+};
+
+pub struct AudioPackPlugin;
+
+impl Plugin for AudioPackPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<AudioPackPlayer>()
+            .add_system(audio_pack_system.after("emulator"));
+    }
+}
+
+struct PlayingTrack {
+    index: usize,
+    sink: rodio::Sink,
+    /// Crossfade level from 0.0 to 1.0
+    gain: f32,
+}
+
+/// Plays the tracks of the audio pack of the current game on their own sinks
+#[derive(Default)]
+pub struct AudioPackPlayer {
+    /// Pack of the current game, with the parsed conditions of each track
+    pack: Option<(AudioPack, Vec<Option<Vec<MemoryCondition>>>)>,
+    current: Option<PlayingTrack>,
+    fading_out: Vec<PlayingTrack>,
+    /// Tracks failed to open, not retried until the pack changes
+    failed: BTreeSet<usize>,
+}
+
+impl AudioPackPlayer {
+    /// Gain of the core audio, lowered while a track replaces it
+    pub fn core_gain(&self) -> f32 {
+        match (&self.pack, &self.current) {
+            (Some((pack, _)), Some(track)) if pack.replace_core_audio => 1.0 - track.gain,
+            _ => 1.0,
+        }
+    }
+
+    fn set_pack(&mut self, pack: Option<&AudioPack>) {
+        // Dropping sinks stops them
+        self.current = None;
+        self.fading_out.clear();
+        self.failed.clear();
+        self.pack = pack.map(|pack| {
+            let conditions = pack
+                .tracks
+                .iter()
+                .map(|track| parse_conditions(&track.condition).ok())
+                .collect();
+            (pack.clone(), conditions)
+        });
+    }
+}
+
+fn open_track(
+    stream_handle: &rodio::OutputStreamHandle,
+    dir: &Path,
+    file: &str,
+) -> Result<rodio::Sink> {
+    let file = File::open(dir.join(file))?;
+    let source = rodio::Decoder::new_looped(BufReader::new(file))?;
+    let sink = rodio::Sink::try_new(stream_handle)?;
+    sink.set_volume(0.0);
+    sink.append(source);
+    Ok(sink)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn audio_pack_system(
+    config: Res<Config>,
+    emulator: Option<Res<Emulator>>,
+    stream_handle: Option<Res<rodio::OutputStreamHandle>>,
+    app_state: Res<State<AppState>>,
+    ui_state: Res<UiState>,
+    is_turbo: Res<IsTurbo>,
+    windows: Res<Windows>,
+    time: Res<Time>,
+    mut player: ResMut<AudioPackPlayer>,
+    mut message_event: EventWriter<ShowMessage>,
+) {
+    let (emulator, stream_handle) = match (emulator, stream_handle) {
+        (Some(emulator), Some(stream_handle)) => (emulator, stream_handle),
+        _ => {
+            if player.pack.is_some() {
+                player.set_pack(None);
+            }
+            return;
+        }
+    };
+
+    let pack = config
+        .audio_packs
+        .get(&emulator.game_hash)
+        .filter(|pack| pack.enabled && emulator.core.supports_memory_access());
+    if player.pack.as_ref().map(|(pack, _)| pack) != pack {
+        player.set_pack(pack);
+    }
+
+    let AudioPackPlayer {
+        pack,
+        current,
+        fading_out,
+        failed,
+    } = &mut *player;
+
+    let (pack, conditions) = if let Some(pack) = pack {
+        pack
+    } else {
+        return;
+    };
+
+    let running = app_state.current() == &AppState::Running && !ui_state.paused;
+
+    if running {
+        // The first track whose conditions hold is played
+        let cue = conditions.iter().position(|conditions| {
+            conditions.as_ref().map_or(false, |conditions| {
+                conditions
+                    .iter()
+                    .all(|cond| cond.check(emulator.core.read_memory(cond.addr)))
+            })
+        });
+
+        if cue != current.as_ref().map(|track| track.index) {
+            if let Some(track) = current.take() {
+                fading_out.push(track);
+            }
+
+            if let Some(index) = cue.filter(|index| !failed.contains(index)) {
+                let file = &pack.tracks[index].file;
+                match open_track(&stream_handle, &pack.dir, file) {
+                    Ok(sink) => {
+                        *current = Some(PlayingTrack {
+                            index,
+                            sink,
+                            gain: 0.0,
+                        })
+                    }
+                    Err(err) => {
+                        failed.insert(index);
+                        message_event.send(ShowMessage::error(format!(
+                            "Failed to play `{file}`: {err}"
+                        )));
+                    }
+                }
+            }
+        }
+
+        let step = if pack.crossfade_ms == 0 {
+            1.0
+        } else {
+            time.delta_seconds() * 1000.0 / pack.crossfade_ms as f32
+        };
+        if let Some(track) = current {
+            track.gain = (track.gain + step).min(1.0);
+        }
+        for track in fading_out.iter_mut() {
+            track.gain = (track.gain - step).max(0.0);
+        }
+        fading_out.retain(|track| track.gain > 0.0);
+    }
+
+    let focused = windows
+        .get_primary()
+        .map_or(true, |window| window.is_focused());
+    let volume = if ui_state.in_tray {
+        0.0
+    } else {
+        config
+            .audio
+            .output_volume(emulator.core.core_info().abbrev, is_turbo.0, focused)
+    };
+
+    for track in current.iter().chain(fading_out.iter()) {
+        track.sink.set_volume(track.gain * volume);
+        if running {
+            track.sink.play();
+        } else {
+            track.sink.pause();
+        }
+    }
+}
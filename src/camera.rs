@@ -0,0 +1,292 @@
+//! Input of camera peripherals such as the Game Boy Camera,
+//! from a still image or a webcam, and export of the photos taken with them.
+
+use anyhow::{anyhow, bail, Result};
+use bevy::prelude::*;
+use image::{imageops::FilterType, GrayImage};
+use meru_interface::{CAMERA_HEIGHT, CAMERA_WIDTH};
+use std::{future::Future, io::Cursor};
+
+use crate::{
+    app::{AppState, ShowMessage},
+    config::{CameraConfig, CameraSource, Config},
+    core::Emulator,
+    file::{create_dir_all, write_plain},
+};
+
+#[cfg(all(feature = "webcam", not(target_arch = "wasm32")))]
+use crate::utils::{unbounded_channel, Receiver};
+
+pub struct CameraPlugin;
+
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CameraFeed>().add_system_set(
+            SystemSet::on_update(AppState::Running).with_system(camera_system.before("emulator")),
+        );
+    }
+}
+
+pub fn is_webcam_supported() -> bool {
+    cfg!(all(feature = "webcam", not(target_arch = "wasm32")))
+}
+
+/// Image currently fed to the camera
+#[derive(Default)]
+pub struct CameraFeed {
+    /// Config the feed was opened with
+    config: Option<CameraConfig>,
+    image: Option<Vec<u8>>,
+    #[cfg(all(feature = "webcam", not(target_arch = "wasm32")))]
+    webcam: Option<webcam::Webcam>,
+}
+
+impl CameraFeed {
+    fn open(&mut self, config: &CameraConfig) -> Result<()> {
+        self.config = Some(config.clone());
+        self.image = None;
+        #[cfg(all(feature = "webcam", not(target_arch = "wasm32")))]
+        {
+            self.webcam = None;
+        }
+
+        match config.source {
+            CameraSource::Off => {}
+            CameraSource::Image => {
+                let path = config
+                    .image
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("No image file selected"))?;
+                let image = image::open(path)?.into_luma8();
+                self.image = Some(fit_to_sensor(&image));
+            }
+            CameraSource::Webcam => {
+                cfg_if::cfg_if! {
+                    if #[cfg(all(feature = "webcam", not(target_arch = "wasm32")))] {
+                        self.webcam = Some(webcam::Webcam::open(config.webcam_index));
+                    } else {
+                        bail!("Webcam is not supported in this build");
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn close(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// Crops `image` to the aspect ratio of the camera and scales it down to its size
+fn fit_to_sensor(image: &GrayImage) -> Vec<u8> {
+    let (width, height) = (image.width(), image.height());
+    let (sensor_width, sensor_height) = (CAMERA_WIDTH as u32, CAMERA_HEIGHT as u32);
+
+    let (crop_width, crop_height) = if width * sensor_height > height * sensor_width {
+        (height * sensor_width / sensor_height, height)
+    } else {
+        (width, width * sensor_height / sensor_width)
+    };
+    let cropped = image::imageops::crop_imm(
+        image,
+        (width - crop_width) / 2,
+        (height - crop_height) / 2,
+        crop_width,
+        crop_height,
+    )
+    .to_image();
+
+    image::imageops::resize(&cropped, sensor_width, sensor_height, FilterType::Triangle).into_raw()
+}
+
+fn camera_system(
+    config: Res<Config>,
+    mut emulator: ResMut<Emulator>,
+    mut feed: ResMut<CameraFeed>,
+    mut message_event: EventWriter<ShowMessage>,
+) {
+    let feed = &mut *feed;
+
+    if !emulator.core.supports_camera() {
+        if feed.config.is_some() {
+            feed.close();
+        }
+        return;
+    }
+
+    if feed.config.as_ref() != Some(&config.camera) {
+        if let Err(err) = feed.open(&config.camera) {
+            message_event.send(ShowMessage::error(format!("Camera: {err}")));
+        }
+    }
+
+    #[cfg(all(feature = "webcam", not(target_arch = "wasm32")))]
+    if let Some(webcam) = &feed.webcam {
+        let mut latest = None;
+        while let Ok(event) = webcam.receiver.try_recv() {
+            match event {
+                webcam::WebcamEvent::Frame(image) => latest = Some(fit_to_sensor(&image)),
+                webcam::WebcamEvent::Failed(err) => {
+                    message_event.send(ShowMessage::error(format!("Webcam: {err}")));
+                }
+            }
+        }
+        if latest.is_some() {
+            feed.image = latest;
+        }
+    }
+
+    if let Some(image) = &feed.image {
+        emulator.core.set_camera_image(image);
+    }
+}
+
+#[cfg(all(feature = "webcam", not(target_arch = "wasm32")))]
+mod webcam {
+    use image::{DynamicImage, GrayImage};
+    use nokhwa::{
+        pixel_format::RgbFormat,
+        utils::{CameraIndex, RequestedFormat, RequestedFormatType},
+        Camera,
+    };
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    };
+
+    use super::{unbounded_channel, Receiver};
+
+    const MAX_PENDING_FRAMES: usize = 2;
+
+    pub enum WebcamEvent {
+        Frame(GrayImage),
+        Failed(String),
+    }
+
+    /// Captures frames on a worker thread, since reading a frame blocks until it arrives
+    pub struct Webcam {
+        pub receiver: Receiver<WebcamEvent>,
+        running: Arc<AtomicBool>,
+    }
+
+    impl Webcam {
+        pub fn open(index: u32) -> Self {
+            let (sender, receiver) = unbounded_channel();
+            let running = Arc::new(AtomicBool::new(true));
+
+            let worker_running = running.clone();
+            std::thread::spawn(move || {
+                let format = RequestedFormat::new::<RgbFormat>(
+                    RequestedFormatType::AbsoluteHighestFrameRate,
+                );
+                let camera = Camera::new(CameraIndex::Index(index), format)
+                    .and_then(|mut camera| camera.open_stream().map(|_| camera));
+                let mut camera = match camera {
+                    Ok(camera) => camera,
+                    Err(err) => {
+                        let _ = sender.try_send(WebcamEvent::Failed(err.to_string()));
+                        return;
+                    }
+                };
+
+                while worker_running.load(Ordering::Relaxed) {
+                    match camera
+                        .frame()
+                        .and_then(|frame| frame.decode_image::<RgbFormat>())
+                    {
+                        // Frames are dropped while the game is not running and reading them
+                        Ok(_) if sender.len() >= MAX_PENDING_FRAMES => {}
+                        Ok(image) => {
+                            let image = DynamicImage::ImageRgb8(image).into_luma8();
+                            if sender.try_send(WebcamEvent::Frame(image)).is_err() {
+                                break;
+                            }
+                        }
+                        Err(err) => {
+                            let _ = sender.try_send(WebcamEvent::Failed(err.to_string()));
+                            break;
+                        }
+                    }
+                }
+                let _ = camera.stop_stream();
+            });
+
+            Self { receiver, running }
+        }
+    }
+
+    impl Drop for Webcam {
+        fn drop(&mut self) {
+            self.running.store(false, Ordering::Relaxed);
+        }
+    }
+}
+
+// Game Boy Camera keeps 30 photos in its save RAM, each in a 4KiB block
+const GB_CAMERA_SRAM_SIZE: usize = 0x20000;
+const GB_CAMERA_PHOTO_SLOTS: usize = 30;
+const GB_CAMERA_PHOTO_BASE: usize = 0x2000;
+const GB_CAMERA_PHOTO_STRIDE: usize = 0x1000;
+/// Album number of the photo in each slot, or 0xff if the slot is empty
+const GB_CAMERA_ALBUM_TABLE: usize = 0x11b2;
+const GB_CAMERA_EMPTY_SLOT: u8 = 0xff;
+const GB_SHADES: [u8; 4] = [0xff, 0xaa, 0x55, 0x00];
+
+/// Photos in the save RAM of Game Boy Camera, in the order of the album
+fn gb_camera_photos(sram: &[u8]) -> Result<Vec<GrayImage>> {
+    if sram.len() != GB_CAMERA_SRAM_SIZE {
+        bail!("Save data is not of Game Boy Camera");
+    }
+
+    let mut slots = (0..GB_CAMERA_PHOTO_SLOTS)
+        .map(|slot| (sram[GB_CAMERA_ALBUM_TABLE + slot], slot))
+        .filter(|(number, _)| *number != GB_CAMERA_EMPTY_SLOT)
+        .collect::<Vec<_>>();
+    slots.sort();
+
+    let tiles_per_row = CAMERA_WIDTH / 8;
+    let photos = slots
+        .into_iter()
+        .map(|(_, slot)| {
+            let data = &sram[GB_CAMERA_PHOTO_BASE + slot * GB_CAMERA_PHOTO_STRIDE..];
+            GrayImage::from_fn(CAMERA_WIDTH as u32, CAMERA_HEIGHT as u32, |x, y| {
+                let (x, y) = (x as usize, y as usize);
+                let tile = &data[(y / 8 * tiles_per_row + x / 8) * 16..];
+                let (lo, hi) = (tile[y % 8 * 2], tile[y % 8 * 2 + 1]);
+                let bit = 7 - x % 8;
+                let color = (hi >> bit & 1) << 1 | (lo >> bit & 1);
+                image::Luma([GB_SHADES[color as usize]])
+            })
+        })
+        .collect();
+    Ok(photos)
+}
+
+/// Saves the photos taken in the game as PNG files into the capture directory.
+/// Returns the number of saved photos.
+pub fn export_photos(emulator: &Emulator, config: &Config) -> impl Future<Output = Result<usize>> {
+    let photos = emulator
+        .core
+        .backup()
+        .ok_or_else(|| anyhow!("No save data"))
+        .and_then(|sram| gb_camera_photos(&sram));
+    let capture_dir = config.capture_dir.clone();
+    let game_name = emulator.game_name.clone();
+
+    async move {
+        let photos = photos?;
+        if photos.is_empty() {
+            bail!("No photos taken");
+        }
+
+        create_dir_all(&capture_dir)?;
+        for (i, photo) in photos.iter().enumerate() {
+            let mut data = Cursor::new(vec![]);
+            photo.write_to(&mut data, image::ImageOutputFormat::Png)?;
+            let path = capture_dir.join(format!("{game_name}-photo{:02}.png", i + 1));
+            write_plain(&path, data.into_inner()).await?;
+        }
+        Ok(photos.len())
+    }
+}
@@ -0,0 +1,350 @@
+use anyhow::{anyhow, bail, Result};
+use meru_interface::MemoryPatch;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct Cheat {
+    pub description: String,
+    pub code: String,
+    pub enabled: bool,
+}
+
+/// Byte written to memory every frame
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct CheatWrite {
+    pub addr: u32,
+    pub data: u8,
+    /// Only writes when the current byte has this value
+    pub compare: Option<u8>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CheatOp {
+    Write(CheatWrite),
+    Patch(MemoryPatch),
+}
+
+/// Code formats accepted for each system, shown in the menu
+pub fn supported_formats(abbrev: &str) -> &'static str {
+    match abbrev {
+        "nes" => "Game Genie (6 or 8 letters), raw (AAAA:VV)",
+        "snes" => "Game Genie (XXXX-XXXX), Pro Action Replay (AAAAAAVV), raw (AAAAAA:VV)",
+        "gb" => "Game Genie (XXX-XXX or XXX-XXX-XXX), GameShark (TTVVLLHH), raw (AAAA:VV)",
+        "gba" => "GameShark v1/v2 (XXXXXXXX YYYYYYYY), raw (AAAAAAAA:VV)",
+        _ => "raw (AAAA:VV)",
+    }
+}
+
+/// Parses a code of `abbrev` system.
+/// A code may consist of several lines or `+` separated parts.
+/// Raw codes `AAAA:VV` write `VV` to `AAAA`, and `AAAA:VV?CC` only when the current value is `CC`.
+pub fn parse_code(abbrev: &str, code: &str) -> Result<Vec<CheatOp>> {
+    let mut ret = vec![];
+
+    for part in split_parts(code) {
+        let ops = parse_part(abbrev, &part).map_err(|err| anyhow!("{part}: {err}"))?;
+        ret.extend(ops);
+    }
+
+    if ret.is_empty() {
+        bail!("Empty code");
+    }
+    Ok(ret)
+}
+
+fn split_parts(code: &str) -> impl Iterator<Item = String> + '_ {
+    code.split(|c| c == '\n' || c == '+')
+        .map(|part| part.trim().to_ascii_uppercase())
+        .filter(|part| !part.is_empty())
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// Condition on a byte of memory
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MemoryCondition {
+    pub addr: u32,
+    pub op: CompareOp,
+    pub value: u8,
+}
+
+impl MemoryCondition {
+    pub fn check(&self, data: u8) -> bool {
+        match self.op {
+            CompareOp::Eq => data == self.value,
+            CompareOp::Ne => data != self.value,
+            CompareOp::Lt => data < self.value,
+            CompareOp::Le => data <= self.value,
+            CompareOp::Gt => data > self.value,
+            CompareOp::Ge => data >= self.value,
+        }
+    }
+}
+
+/// Parses conditions written like the compare value of raw codes.
+/// `AAAA?CC` holds when the byte at `AAAA` is `CC`,
+/// and `!`, `<`, `<=`, `>` or `>=` after `?` selects another comparison, as in `AAAA?>=CC`.
+/// All of the lines or `+` separated parts must hold.
+pub fn parse_conditions(s: &str) -> Result<Vec<MemoryCondition>> {
+    let mut ret = vec![];
+
+    for part in split_parts(s) {
+        let cond = parse_condition(&part).map_err(|err| anyhow!("{part}: {err}"))?;
+        ret.push(cond);
+    }
+
+    if ret.is_empty() {
+        bail!("Empty condition");
+    }
+    Ok(ret)
+}
+
+fn parse_condition(part: &str) -> Result<MemoryCondition> {
+    let (addr, value) = part
+        .split_once('?')
+        .ok_or_else(|| anyhow!("Conditions are written as AAAA?CC"))?;
+
+    let (op, value) = [
+        ("<=", CompareOp::Le),
+        (">=", CompareOp::Ge),
+        ("!", CompareOp::Ne),
+        ("<", CompareOp::Lt),
+        (">", CompareOp::Gt),
+    ]
+    .into_iter()
+    .find_map(|(prefix, op)| value.strip_prefix(prefix).map(|value| (op, value)))
+    .unwrap_or((CompareOp::Eq, value));
+
+    Ok(MemoryCondition {
+        addr: hex(addr)?,
+        op,
+        value: hex_byte(value)?,
+    })
+}
+
+fn parse_part(abbrev: &str, part: &str) -> Result<Vec<CheatOp>> {
+    if part.contains(':') {
+        return Ok(vec![CheatOp::Write(parse_raw(part)?)]);
+    }
+
+    Ok(match abbrev {
+        "nes" => vec![CheatOp::Patch(nes_game_genie(part)?)],
+        "snes" if part.contains('-') => vec![CheatOp::Patch(snes_game_genie(part)?)],
+        "snes" => vec![CheatOp::Write(snes_pro_action_replay(part)?)],
+        "gb" if part.contains('-') => vec![CheatOp::Patch(gb_game_genie(part)?)],
+        "gb" => vec![CheatOp::Write(gb_gameshark(part)?)],
+        "gba" => gba_gameshark(part)?
+            .into_iter()
+            .map(CheatOp::Write)
+            .collect(),
+        _ => bail!("Unknown code format"),
+    })
+}
+
+fn hex(s: &str) -> Result<u32> {
+    if s.is_empty() || s.len() > 8 || !s.chars().all(|c| c.is_ascii_hexdigit()) {
+        bail!("Invalid hex number: {s}");
+    }
+    Ok(u32::from_str_radix(s, 16)?)
+}
+
+fn hex_byte(s: &str) -> Result<u8> {
+    if s.len() > 2 {
+        bail!("Invalid byte: {s}");
+    }
+    Ok(hex(s)? as u8)
+}
+
+fn hex_digits(code: &str) -> Result<Vec<u32>> {
+    code.chars()
+        .filter(|&c| c != '-')
+        .map(|c| c.to_digit(16).ok_or_else(|| anyhow!("Invalid digit: {c}")))
+        .collect()
+}
+
+fn parse_raw(part: &str) -> Result<CheatWrite> {
+    let (addr, value) = part.split_once(':').unwrap();
+    let (data, compare) = match value.split_once('?') {
+        Some((data, compare)) => (data, Some(hex_byte(compare)?)),
+        None => (value, None),
+    };
+
+    Ok(CheatWrite {
+        addr: hex(addr)?,
+        data: hex_byte(data)?,
+        compare,
+    })
+}
+
+const NES_GAME_GENIE_LETTERS: &str = "APZLGITYEOXUKSVN";
+
+fn nes_game_genie(code: &str) -> Result<MemoryPatch> {
+    let n = code
+        .chars()
+        .map(|c| {
+            NES_GAME_GENIE_LETTERS
+                .find(c)
+                .map(|i| i as u32)
+                .ok_or_else(|| anyhow!("Invalid letter: {c}"))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    if n.len() != 6 && n.len() != 8 {
+        bail!("Game Genie codes have 6 or 8 letters");
+    }
+
+    let addr = 0x8000
+        | ((n[3] & 7) << 12)
+        | ((n[5] & 7) << 8)
+        | ((n[4] & 8) << 8)
+        | ((n[2] & 7) << 4)
+        | ((n[1] & 8) << 4)
+        | (n[4] & 7)
+        | (n[3] & 8);
+
+    // The bit which completes the data moves to the last letter in 8 letter codes
+    let last = if n.len() == 6 { n[5] } else { n[7] };
+    let data = ((n[1] & 7) << 4) | ((n[0] & 8) << 4) | (n[0] & 7) | (last & 8);
+    let compare =
+        (n.len() == 8).then(|| ((n[7] & 7) << 4) | ((n[6] & 8) << 4) | (n[6] & 7) | (n[5] & 8));
+
+    Ok(MemoryPatch {
+        addr,
+        data: data as u8,
+        compare: compare.map(|c| c as u8),
+    })
+}
+
+const SNES_GAME_GENIE_DIGITS: &str = "DF4709156BC8A23E";
+
+fn snes_game_genie(code: &str) -> Result<MemoryPatch> {
+    let digits = code
+        .chars()
+        .filter(|&c| c != '-')
+        .map(|c| {
+            SNES_GAME_GENIE_DIGITS
+                .find(c)
+                .map(|i| i as u32)
+                .ok_or_else(|| anyhow!("Invalid digit: {c}"))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    if digits.len() != 8 {
+        bail!("Game Genie codes have 8 digits (XXXX-XXXX)");
+    }
+
+    let value = digits.iter().fold(0, |acc, d| acc << 4 | d);
+    let v = value & 0xFFFFFF;
+
+    // Address bits are scrambled
+    let addr = ((v & 0x003C00) << 10)
+        | ((v & 0x00003C) << 14)
+        | ((v & 0xF00000) >> 8)
+        | ((v & 0x000003) << 10)
+        | ((v & 0x00C000) >> 6)
+        | ((v & 0x0F0000) >> 12)
+        | ((v & 0x0003C0) >> 6);
+
+    Ok(MemoryPatch {
+        addr,
+        data: (value >> 24) as u8,
+        compare: None,
+    })
+}
+
+fn snes_pro_action_replay(code: &str) -> Result<CheatWrite> {
+    if code.len() != 8 {
+        bail!("Pro Action Replay codes have 8 digits (AAAAAAVV)");
+    }
+    let value = hex(code)?;
+
+    Ok(CheatWrite {
+        addr: value >> 8,
+        data: value as u8,
+        compare: None,
+    })
+}
+
+fn gb_game_genie(code: &str) -> Result<MemoryPatch> {
+    let d = hex_digits(code)?;
+
+    if d.len() != 6 && d.len() != 9 {
+        bail!("Game Genie codes have 6 or 9 digits (XXX-XXX or XXX-XXX-XXX)");
+    }
+
+    let addr = ((d[5] ^ 0xF) << 12) | (d[2] << 8) | (d[3] << 4) | d[4];
+    let compare = (d.len() == 9).then(|| (((d[6] << 4) | d[8]) as u8).rotate_right(2) ^ 0xBA);
+
+    Ok(MemoryPatch {
+        addr,
+        data: ((d[0] << 4) | d[1]) as u8,
+        compare,
+    })
+}
+
+fn gb_gameshark(code: &str) -> Result<CheatWrite> {
+    if code.len() != 8 {
+        bail!("GameShark codes have 8 digits (TTVVLLHH)");
+    }
+    let value = hex(code)?;
+
+    // Types 8x select the external RAM bank, which is not switched here
+    match value >> 24 {
+        0x01 | 0x80..=0x8F => {}
+        ty => bail!("Unsupported GameShark code type: {ty:02X}"),
+    }
+
+    Ok(CheatWrite {
+        addr: (value & 0xFF) << 8 | (value >> 8) & 0xFF,
+        data: (value >> 16) as u8,
+        compare: None,
+    })
+}
+
+const GBA_GAMESHARK_SEEDS: [u32; 4] = [0x09F4FBBD, 0x9681884A, 0x352027E9, 0xF3DEE5A7];
+
+fn gba_gameshark(code: &str) -> Result<Vec<CheatWrite>> {
+    let words = code.split_whitespace().collect::<Vec<_>>();
+    if words.len() != 2 || words.iter().any(|w| w.len() != 8) {
+        bail!("GameShark codes have two 8 digit words (XXXXXXXX YYYYYYYY)");
+    }
+    let (mut addr, mut value) = (hex(words[0])?, hex(words[1])?);
+
+    // Codes are encrypted with TEA
+    let mut sum = 0xC6EF3720_u32;
+    for _ in 0..32 {
+        value = value.wrapping_sub(
+            ((addr << 4).wrapping_add(GBA_GAMESHARK_SEEDS[2]) ^ addr.wrapping_add(sum))
+                ^ (addr >> 5).wrapping_add(GBA_GAMESHARK_SEEDS[3]),
+        );
+        addr = addr.wrapping_sub(
+            ((value << 4).wrapping_add(GBA_GAMESHARK_SEEDS[0]) ^ value.wrapping_add(sum))
+                ^ (value >> 5).wrapping_add(GBA_GAMESHARK_SEEDS[1]),
+        );
+        sum = sum.wrapping_sub(0x9E3779B9);
+    }
+
+    let width = match addr >> 28 {
+        0 => 1,
+        1 => 2,
+        2 => 4,
+        ty => bail!("Unsupported GameShark code type: {ty}"),
+    };
+    let target = addr & 0x0FFFFFFF;
+
+    Ok((0..width)
+        .map(|i| CheatWrite {
+            addr: target + i,
+            data: (value >> (i * 8)) as u8,
+            compare: None,
+        })
+        .collect())
+}
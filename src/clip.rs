@@ -0,0 +1,124 @@
+use anyhow::{bail, Result};
+use chrono::Local;
+use image::{
+    codecs::gif::{GifEncoder, Repeat},
+    imageops::{resize, FilterType},
+    Delay, Frame, RgbaImage,
+};
+use meru_interface::FrameBuffer;
+use std::{collections::VecDeque, future::Future, path::PathBuf};
+
+use crate::{
+    config::{Config, Overscan},
+    file::{create_dir_all, write_plain},
+};
+
+// GIF frame delays are in 1/100 seconds, so 20fps keeps the timing exact
+const CLIP_FPS: usize = 20;
+const CLIP_FRAME_INTERVAL: usize = 60 / CLIP_FPS;
+
+#[derive(Default)]
+pub struct ClipRecorder {
+    frames: VecDeque<RgbaImage>,
+    /// Core frames run since the last recorded one
+    pending_frames: usize,
+}
+
+impl ClipRecorder {
+    /// Counts `frames` core frames which were run to show `frame_buffer`.
+    /// It is kept once for every `CLIP_FRAME_INTERVAL` frames, so that the clip
+    /// plays at the game speed even when several frames are run per update.
+    pub fn record(
+        &mut self,
+        frames: usize,
+        frame_buffer: &FrameBuffer,
+        overscan: &Overscan,
+        config: &Config,
+    ) {
+        if config.clip_length == 0 {
+            self.frames.clear();
+            self.pending_frames = 0;
+            return;
+        }
+
+        self.pending_frames += frames;
+        if self.pending_frames < CLIP_FRAME_INTERVAL
+            || frame_buffer.width == 0
+            || frame_buffer.height == 0
+        {
+            return;
+        }
+
+        let image = frame_buffer_to_rgba(&overscan.crop(frame_buffer));
+        let max_frames = config.clip_length * CLIP_FPS;
+        let count = (self.pending_frames / CLIP_FRAME_INTERVAL).min(max_frames);
+        self.pending_frames %= CLIP_FRAME_INTERVAL;
+        for _ in 1..count {
+            self.frames.push_back(image.clone());
+        }
+        self.frames.push_back(image);
+        while self.frames.len() > max_frames {
+            self.frames.pop_front();
+        }
+    }
+
+    /// Saves the recorded frames as a GIF file into the capture directory.
+    /// On the web, the file is also downloaded.
+    pub fn save(&self, game_name: &str, config: &Config) -> impl Future<Output = Result<PathBuf>> {
+        let frames = self.frames.iter().cloned().collect::<Vec<_>>();
+        let scale = config.clip_scale.max(1) as u32;
+        let capture_dir = config.capture_dir.clone();
+        let enabled = config.clip_length > 0;
+        let file_name = format!("{game_name}-{}.gif", Local::now().format("%Y%m%d-%H%M%S"));
+
+        async move {
+            if !enabled {
+                bail!("Clip recording is disabled. Set the clip length in the settings");
+            }
+            if frames.is_empty() {
+                bail!("No frames recorded");
+            }
+
+            let data = encode_gif(frames, scale)?;
+
+            #[cfg(target_arch = "wasm32")]
+            crate::utils::download(&file_name, &data)?;
+
+            create_dir_all(&capture_dir)?;
+            let path = capture_dir.join(file_name);
+            write_plain(&path, data).await?;
+            Ok(path)
+        }
+    }
+}
+
+pub fn frame_buffer_to_rgba(frame_buffer: &FrameBuffer) -> RgbaImage {
+    RgbaImage::from_fn(
+        frame_buffer.width as u32,
+        frame_buffer.height as u32,
+        |x, y| {
+            let c = &frame_buffer.buffer[y as usize * frame_buffer.width + x as usize];
+            image::Rgba([c.r, c.g, c.b, 0xff])
+        },
+    )
+}
+
+fn encode_gif(frames: Vec<RgbaImage>, scale: u32) -> Result<Vec<u8>> {
+    let mut data = vec![];
+    {
+        let mut encoder = GifEncoder::new(&mut data);
+        encoder.set_repeat(Repeat::Infinite)?;
+
+        let delay = Delay::from_numer_denom_ms(1000, CLIP_FPS as u32);
+        for frame in frames {
+            let frame = if scale == 1 {
+                frame
+            } else {
+                let (width, height) = (frame.width() * scale, frame.height() * scale);
+                resize(&frame, width, height, FilterType::Nearest)
+            };
+            encoder.encode_frame(Frame::from_parts(frame, 0, 0, delay))?;
+        }
+    }
+    Ok(data)
+}
@@ -1,9 +1,11 @@
 use anyhow::Result;
 use enum_iterator::Sequence;
 use log::{info, warn};
+use meru_interface::FrameBuffer;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::{
+    borrow::Cow,
     collections::{BTreeMap, VecDeque},
     fmt::Display,
     future::Future,
@@ -11,10 +13,14 @@ use std::{
 };
 
 use crate::{
+    cheats::Cheat,
     core::{Emulator, EmulatorCores, EMULATOR_CORES},
-    file::{create_dir_all, read, read_to_string, write},
-    hotkey::HotKeys,
+    file::{create_dir_all, is_state_file, move_save_files, read, read_to_string, write},
+    goals::Goal,
+    hotkey::{HotKey, HotKeys},
     input::KeyConfig,
+    palette::PalettePreset,
+    profile::profile_dir,
 };
 
 #[derive(PartialEq, Eq, Clone, Copy, Debug, Serialize, Deserialize, Sequence)]
@@ -41,6 +47,383 @@ impl Display for SystemKey {
     }
 }
 
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default, Serialize, Deserialize, Sequence)]
+pub enum ScreenFilter {
+    #[default]
+    Nearest,
+    Bilinear,
+    SharpBilinear,
+    Scanlines,
+    Crt,
+    LcdGrid,
+    /// Edge-directed upscaling of pixel art. Heavy on low-end GPUs
+    Xbr,
+}
+
+impl Display for ScreenFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            ScreenFilter::Nearest => "Nearest",
+            ScreenFilter::Bilinear => "Bilinear",
+            ScreenFilter::SharpBilinear => "Sharp Bilinear",
+            ScreenFilter::Scanlines => "Scanlines",
+            ScreenFilter::Crt => "CRT",
+            ScreenFilter::LcdGrid => "LCD Grid",
+            ScreenFilter::Xbr => "xBR",
+        };
+        write!(f, "{s}")
+    }
+}
+
+/// Clockwise rotation of the game screen, for holding devices vertically
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default, Serialize, Deserialize, Sequence)]
+pub enum Rotation {
+    #[default]
+    None,
+    Cw90,
+    Cw180,
+    Cw270,
+}
+
+impl Display for Rotation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Rotation::None => "None",
+            Rotation::Cw90 => "90°",
+            Rotation::Cw180 => "180°",
+            Rotation::Cw270 => "270°",
+        };
+        write!(f, "{s}")
+    }
+}
+
+impl Rotation {
+    pub fn next(self) -> Self {
+        enum_iterator::next_cycle(&self).unwrap()
+    }
+
+    pub fn radians(self) -> f32 {
+        match self {
+            Rotation::None => 0.0,
+            Rotation::Cw90 => std::f32::consts::FRAC_PI_2,
+            Rotation::Cw180 => std::f32::consts::PI,
+            Rotation::Cw270 => std::f32::consts::PI * 1.5,
+        }
+    }
+
+    /// Size of a `(width, height)` screen after rotation
+    pub fn rotate_size<T>(self, (width, height): (T, T)) -> (T, T) {
+        match self {
+            Rotation::None | Rotation::Cw180 => (width, height),
+            Rotation::Cw90 | Rotation::Cw270 => (height, width),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default, Serialize, Deserialize, Sequence)]
+pub enum ButtonLabelStyle {
+    #[default]
+    Auto,
+    Xbox,
+    Nintendo,
+    PlayStation,
+}
+
+impl Display for ButtonLabelStyle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            ButtonLabelStyle::Auto => "Auto Detect",
+            ButtonLabelStyle::Xbox => "Xbox (A south)",
+            ButtonLabelStyle::Nintendo => "Nintendo (A east)",
+            ButtonLabelStyle::PlayStation => "PlayStation",
+        };
+        write!(f, "{s}")
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default, Serialize, Deserialize, Sequence)]
+pub enum StateStorage {
+    #[default]
+    Files,
+    Archive,
+}
+
+impl Display for StateStorage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            StateStorage::Files => "Separate Files",
+            StateStorage::Archive => "One Zip per Game",
+        };
+        write!(f, "{s}")
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default, Serialize, Deserialize, Sequence)]
+pub enum TurboAudio {
+    #[default]
+    Normal,
+    Mute,
+    Reduce,
+    PitchPreserve,
+}
+
+impl Display for TurboAudio {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            TurboAudio::Normal => "Normal",
+            TurboAudio::Mute => "Mute",
+            TurboAudio::Reduce => "Reduce Volume",
+            TurboAudio::PitchPreserve => "Preserve Pitch",
+        };
+        write!(f, "{s}")
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AudioConfig {
+    pub device: Option<String>,
+    pub latency: usize, // frames
+    pub volume: usize,  // percent, up to `MAX_VOLUME`
+    pub muted: bool,
+    pub core_volumes: BTreeMap<String, usize>,
+    pub turbo_audio: TurboAudio,
+    pub mute_in_background: bool,
+    pub mono: bool,
+    pub balance: i32, // -100 (left) ..= 100 (right)
+    pub night_mode: bool,
+    /// Resample audio slightly to follow the display rate, instead of skipping frames
+    pub dynamic_rate_control: bool,
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        Self {
+            device: None,
+            latency: 4,
+            volume: 100,
+            muted: false,
+            core_volumes: BTreeMap::new(),
+            turbo_audio: TurboAudio::default(),
+            mute_in_background: false,
+            mono: false,
+            balance: 0,
+            night_mode: false,
+            dynamic_rate_control: true,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NetplayConfig {
+    /// Port to listen on when hosting
+    pub port: u16,
+    /// Address of the host to join
+    pub address: String,
+    /// Relay server to meet through with a room code, which works behind NAT
+    pub relay_address: String,
+    /// Frames to delay local inputs, which reduces rollbacks
+    pub input_delay: usize,
+    /// Frames to run ahead of the peer with predicted inputs. 0 runs in lockstep.
+    pub rollback_frames: usize,
+}
+
+impl Default for NetplayConfig {
+    fn default() -> Self {
+        Self {
+            port: 7845,
+            address: String::new(),
+            relay_address: String::new(),
+            input_delay: 2,
+            rollback_frames: 8,
+        }
+    }
+}
+
+/// Link cable connection, which is separate from netplay
+#[derive(PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LinkCableConfig {
+    /// Port to listen on when hosting
+    pub port: u16,
+    /// Address of the host to connect to
+    pub address: String,
+}
+
+impl Default for LinkCableConfig {
+    fn default() -> Self {
+        Self {
+            port: 7847,
+            address: String::new(),
+        }
+    }
+}
+
+/// Connection to OBS Studio through obs-websocket, and what to trigger there
+#[derive(PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ObsConfig {
+    pub enabled: bool,
+    /// Host and port of obs-websocket
+    pub address: String,
+    /// Empty when authentication is disabled in OBS
+    pub password: String,
+    /// Start recording when a game is opened
+    pub record_on_game_start: bool,
+    /// Stop recording when the game is closed
+    pub stop_on_game_close: bool,
+    /// Scene switched to while a game is running. Not switched when empty
+    pub running_scene: String,
+    /// Scene switched to while the menu is shown. Not switched when empty
+    pub menu_scene: String,
+}
+
+impl Default for ObsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            address: "localhost:4455".into(),
+            password: String::new(),
+            record_on_game_start: true,
+            stop_on_game_close: true,
+            running_scene: String::new(),
+            menu_scene: String::new(),
+        }
+    }
+}
+
+/// Lines cropped from each edge of the screen
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Overscan {
+    pub top: usize,
+    pub bottom: usize,
+    pub left: usize,
+    pub right: usize,
+}
+
+impl Overscan {
+    /// NES games often show garbage in the top and bottom 8 lines
+    pub fn default_for(abbrev: &str) -> Self {
+        match abbrev {
+            "nes" => Overscan {
+                top: 8,
+                bottom: 8,
+                left: 0,
+                right: 0,
+            },
+            _ => Overscan::default(),
+        }
+    }
+
+    /// Visible area as `(left, top, width, height)`.
+    /// Cropping is ignored when nothing would remain
+    pub fn crop_rect(&self, width: usize, height: usize) -> (usize, usize, usize, usize) {
+        if self.left + self.right >= width || self.top + self.bottom >= height {
+            (0, 0, width, height)
+        } else {
+            (
+                self.left,
+                self.top,
+                width - self.left - self.right,
+                height - self.top - self.bottom,
+            )
+        }
+    }
+
+    pub fn crop_size(&self, width: usize, height: usize) -> (usize, usize) {
+        let (_, _, width, height) = self.crop_rect(width, height);
+        (width, height)
+    }
+
+    /// Copies the visible area only when cropping
+    pub fn crop<'a>(&self, frame_buffer: &'a FrameBuffer) -> Cow<'a, FrameBuffer> {
+        let (left, top, width, height) = self.crop_rect(frame_buffer.width, frame_buffer.height);
+        if (width, height) == (frame_buffer.width, frame_buffer.height) {
+            return Cow::Borrowed(frame_buffer);
+        }
+
+        let mut ret = FrameBuffer::new(width, height);
+        for y in 0..height {
+            let src = (y + top) * frame_buffer.width + left;
+            ret.buffer[y * width..(y + 1) * width]
+                .clone_from_slice(&frame_buffer.buffer[src..src + width]);
+        }
+        Cow::Owned(ret)
+    }
+}
+
+/// OS-wide hotkeys which work while another window is focused.
+/// Keys are written like `ctrl+alt+KeyS`.
+#[derive(PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GlobalHotKeyConfig {
+    pub enabled: bool,
+    pub state_save: String,
+    pub state_load: String,
+    pub pause: String,
+}
+
+impl Default for GlobalHotKeyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            state_save: "ctrl+alt+KeyS".to_string(),
+            state_load: "ctrl+alt+KeyL".to_string(),
+            pause: "ctrl+alt+KeyP".to_string(),
+        }
+    }
+}
+
+impl GlobalHotKeyConfig {
+    pub fn assignments_mut(&mut self) -> [(HotKey, &mut String); 3] {
+        [
+            (HotKey::StateSave, &mut self.state_save),
+            (HotKey::StateLoad, &mut self.state_load),
+            (HotKey::Pause, &mut self.pause),
+        ]
+    }
+
+    pub fn assignments(&self) -> [(HotKey, &str); 3] {
+        [
+            (HotKey::StateSave, &self.state_save),
+            (HotKey::StateLoad, &self.state_load),
+            (HotKey::Pause, &self.pause),
+        ]
+    }
+}
+
+impl AudioConfig {
+    pub fn core_volume(&self, abbrev: &str) -> usize {
+        self.core_volumes.get(abbrev).copied().unwrap_or(100)
+    }
+
+    pub fn set_core_volume(&mut self, abbrev: &str, volume: usize) {
+        self.core_volumes.insert(abbrev.to_string(), volume);
+    }
+
+    pub fn output_volume(&self, abbrev: &str, is_turbo: bool, focused: bool) -> f32 {
+        if self.muted || (!focused && self.mute_in_background) {
+            return 0.0;
+        }
+
+        let volume = (self.volume * self.core_volume(abbrev)) as f32 / (100 * 100) as f32;
+        match self.turbo_audio {
+            TurboAudio::Mute if is_turbo => 0.0,
+            TurboAudio::Reduce if is_turbo => volume * TURBO_REDUCED_VOLUME,
+            _ => volume,
+        }
+    }
+}
+
+const TURBO_REDUCED_VOLUME: f32 = 0.25;
+
+/// Master volume in percent. Above 100% amplifies the sound.
+pub const MAX_VOLUME: usize = 200;
+/// Master volume change per hotkey press, in percent
+pub const VOLUME_STEP: usize = 10;
+
 pub type SystemKeys = KeyConfig<SystemKey>;
 
 impl Default for SystemKeys {
@@ -58,85 +441,524 @@ impl Default for SystemKeys {
     }
 }
 
+/// Screen filter, window scale and frame interpolation, which can be overridden per game
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct VideoSetting {
+    pub screen_filter: ScreenFilter,
+    pub scaling: usize,
+    #[serde(default)]
+    pub frame_interpolation: bool,
+}
+
+/// External soundtrack of a game, whose tracks are played when their memory conditions hold
+#[derive(PartialEq, Eq, Clone, Default, Serialize, Deserialize)]
+pub struct AudioPack {
+    pub enabled: bool,
+    /// Directory of the track files
+    pub dir: PathBuf,
+    /// Mute the core audio while a track is playing, instead of mixing over it
+    pub replace_core_audio: bool,
+    pub crossfade_ms: usize,
+    pub tracks: Vec<AudioPackTrack>,
+}
+
+#[derive(PartialEq, Eq, Clone, Default, Serialize, Deserialize)]
+pub struct AudioPackTrack {
+    /// OGG or FLAC file in the pack directory
+    pub file: String,
+    /// Memory conditions in the syntax of `cheats::parse_conditions`
+    pub condition: String,
+}
+
+pub const MIN_TEXTURE_SCALE: usize = 1;
+pub const MAX_TEXTURE_SCALE: usize = 8;
+
+/// High-resolution replacements of the tiles of a game, by their hashes
+#[derive(PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct TexturePack {
+    pub enabled: bool,
+    /// Directory of `{hash}.png` files
+    pub dir: PathBuf,
+    /// Resolution of the screen relative to the core output. Textures are resized to fit.
+    pub scale: usize,
+    /// Save tiles without replacements into `dump` of `dir`, for making packs
+    pub dump: bool,
+}
+
+impl Default for TexturePack {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            dir: PathBuf::new(),
+            scale: 4,
+            dump: false,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default, Serialize, Deserialize, Sequence)]
+pub enum CameraSource {
+    #[default]
+    Off,
+    Image,
+    Webcam,
+}
+
+impl Display for CameraSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            CameraSource::Off => "Off",
+            CameraSource::Image => "Still Image",
+            CameraSource::Webcam => "Webcam",
+        };
+        write!(f, "{s}")
+    }
+}
+
+/// Image fed to camera peripherals such as the Game Boy Camera
+#[derive(PartialEq, Eq, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CameraConfig {
+    pub source: CameraSource,
+    /// Image file for `CameraSource::Image`
+    pub image: Option<PathBuf>,
+    /// Index of the webcam among the ones found
+    pub webcam_index: u32,
+}
+
+/// Hardware to run Game Boy games that also support Game Boy Color.
+/// Passed to the core with `set_enhanced_model`.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Serialize, Deserialize, Sequence)]
+pub enum GbModel {
+    Dmg,
+    Cgb,
+}
+
+impl Display for GbModel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            GbModel::Dmg => "Game Boy",
+            GbModel::Cgb => "Game Boy Color",
+        };
+        write!(f, "{s}")
+    }
+}
+
 #[derive(PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct Config {
     pub save_dir: PathBuf,
+    #[serde(default = "default_state_dir")]
+    pub state_dir: PathBuf,
+    /// State files of older versions have been moved from `save_dir` to `state_dir`
+    #[serde(default)]
+    pub state_files_migrated: bool,
+    #[serde(default)]
+    pub state_storage: StateStorage,
+    /// Compress save states and states for rewinding with zstd
+    #[serde(default)]
+    pub compress_states: bool,
+    /// Check that save states round-trip exactly after loading a ROM
+    #[serde(default)]
+    pub state_self_test: bool,
+    #[serde(default)]
+    pub rom_dirs: Vec<PathBuf>,
     pub show_fps: bool,
+    /// Show the time spent in the core and its cycles below the FPS
+    #[serde(default)]
+    pub show_perf_stats: bool,
     pub frame_skip_on_turbo: usize,
+    /// Maximum milliseconds spent running frames per update in turbo mode
+    #[serde(default = "default_turbo_time_budget")]
+    pub turbo_time_budget: usize,
+    #[serde(default)]
+    pub core_frame_skips_on_turbo: BTreeMap<String, usize>,
+    /// Per-core overscan cropping. Cores without an entry use `Overscan::default_for`
+    #[serde(default)]
+    pub core_overscans: BTreeMap<String, Overscan>,
     pub scaling: usize,
+    #[serde(default)]
+    pub screen_filter: ScreenFilter,
+    /// Blend consecutive frames on displays with a higher refresh rate than the game
+    #[serde(default)]
+    pub frame_interpolation: bool,
+    /// Video settings of games which override `screen_filter`, `scaling`
+    /// and `frame_interpolation`, by ROM hash
+    #[serde(default)]
+    pub game_video_settings: BTreeMap<String, VideoSetting>,
+    #[serde(default)]
+    pub rotation: Rotation,
+    /// Magnification of the game screen beyond the window fit, in percent
+    #[serde(default = "default_zoom")]
+    pub zoom: usize,
+    #[serde(default)]
+    pub reduce_flashing: bool,
+    /// MSAA sample count: 1 (off), 2, 4 or 8
+    #[serde(default = "default_msaa_samples")]
+    pub msaa_samples: u32,
+    /// Animate the rewinding UI and messages
+    #[serde(default = "default_animations")]
+    pub animations: bool,
+    #[serde(default)]
+    pub high_contrast_ui: bool,
+    /// Read focused menu items and messages aloud
+    #[serde(default)]
+    pub text_to_speech: bool,
+    #[serde(default)]
+    pub simple_mode: bool,
+    #[serde(default)]
+    pub minimize_to_tray: bool,
+    /// Exclude the window from screen capture and streaming
+    #[serde(default)]
+    pub capture_protection: bool,
+    /// Menu scale in percent. Follows the monitor's DPI when `None`
+    #[serde(default)]
+    pub ui_scale: Option<usize>,
+    /// Window title while a game is loaded. `{game}`, `{system}` and `{fps}` are replaced
+    #[serde(default = "default_window_title")]
+    pub window_title: String,
+    /// Enlarge screenshots by the window scale. Screen filters are not applied.
+    #[serde(default, alias = "screenshot_filtered")]
+    pub screenshot_scaled: bool,
+    #[serde(default = "default_capture_dir")]
+    pub capture_dir: PathBuf,
+    #[serde(default = "default_clip_length")]
+    pub clip_length: usize, // seconds
+    #[serde(default = "default_clip_scale")]
+    pub clip_scale: usize,
+    #[serde(default = "default_firmware_dir")]
+    pub firmware_dir: PathBuf,
+    /// SDL_GameControllerDB mapping file
+    #[serde(default)]
+    pub controller_db: Option<PathBuf>,
+    #[serde(default)]
+    pub button_label_style: ButtonLabelStyle,
+    /// Core abbreviations in order of preference for each file extension
+    #[serde(default)]
+    pub core_priority: BTreeMap<String, Vec<String>>,
     pub auto_state_save_rate: usize,   // byte/s
     pub auto_state_save_limit: usize,  // byte
     pub minimum_auto_save_span: usize, // frames
+    /// Memory for the states of hold-to-rewind in bytes. Disabled when 0
+    #[serde(default = "default_hold_rewind_limit")]
+    pub hold_rewind_limit: usize,
+    #[serde(default)]
+    pub clear_backup_on_reload: bool,
+    #[serde(default = "default_backup_snapshots")]
+    pub backup_snapshots: usize,
+    #[serde(default = "default_max_sessions")]
+    pub max_sessions: usize,
+    #[serde(default)]
+    pub audio: AudioConfig,
+    /// Audio packs by ROM hash
+    #[serde(default)]
+    pub audio_packs: BTreeMap<String, AudioPack>,
+    /// Texture packs by ROM hash
+    #[serde(default)]
+    pub texture_packs: BTreeMap<String, TexturePack>,
+    /// Models chosen for dual-mode Game Boy games by ROM hash
+    #[serde(default)]
+    pub gb_models: BTreeMap<String, GbModel>,
+    /// Ask the model when a dual-mode Game Boy game without a chosen model is loaded
+    #[serde(default = "default_prompt_gb_model")]
+    pub prompt_gb_model: bool,
+    #[serde(default)]
+    pub camera: CameraConfig,
+    #[serde(default)]
+    pub netplay: NetplayConfig,
+    #[serde(default)]
+    pub link_cable: LinkCableConfig,
+    #[serde(default)]
+    pub obs: ObsConfig,
     pub hotkeys: HotKeys,
+    #[serde(default)]
+    pub global_hotkeys: GlobalHotKeyConfig,
     pub system_keys: SystemKeys,
+    /// Imported palettes offered in addition to the community palettes
+    #[serde(default)]
+    pub palette_presets: Vec<PalettePreset>,
 
     #[serde(default)]
     core_configs: BTreeMap<String, Value>,
     #[serde(default)]
     key_configs: BTreeMap<String, meru_interface::KeyConfig>,
+    #[serde(skip)]
+    added_keys: BTreeMap<String, Vec<(usize, String)>>,
 }
 
 #[cfg(not(target_arch = "wasm32"))]
 mod dirs {
     use anyhow::{anyhow, Result};
     use directories::ProjectDirs;
+    use std::path::PathBuf;
 
     pub fn project_dirs() -> Result<ProjectDirs> {
         let ret = ProjectDirs::from("", "", "meru")
             .ok_or_else(|| anyhow!("Cannot find project directory"))?;
         Ok(ret)
     }
+
+    fn env_dir(name: &str) -> Option<PathBuf> {
+        std::env::var_os(name)
+            .filter(|s| !s.is_empty())
+            .map(PathBuf::from)
+    }
+
+    fn xdg_dir(name: &str) -> Option<PathBuf> {
+        env_dir(name)
+            .filter(|dir| dir.is_absolute())
+            .map(|dir| dir.join("meru"))
+    }
+
+    pub fn config_dir_override() -> Option<PathBuf> {
+        env_dir("MERU_CONFIG_DIR")
+    }
+
+    pub fn save_dir_override() -> Option<PathBuf> {
+        env_dir("MERU_SAVE_DIR")
+    }
+
+    pub fn state_dir_override() -> Option<PathBuf> {
+        env_dir("MERU_STATE_DIR")
+    }
+
+    pub fn xdg_config_dir() -> Option<PathBuf> {
+        xdg_dir("XDG_CONFIG_HOME")
+    }
+
+    pub fn xdg_data_dir() -> Option<PathBuf> {
+        xdg_dir("XDG_DATA_HOME")
+    }
+
+    pub fn xdg_state_dir() -> Option<PathBuf> {
+        xdg_dir("XDG_STATE_HOME").or_else(xdg_data_dir)
+    }
 }
 
 #[cfg(target_arch = "wasm32")]
 mod dirs {
     use anyhow::{bail, Result};
     use directories::ProjectDirs;
+    use std::path::PathBuf;
 
     pub fn project_dirs() -> Result<ProjectDirs> {
         bail!("wasm does not support project directories")
     }
+
+    pub fn config_dir_override() -> Option<PathBuf> {
+        None
+    }
+
+    pub fn save_dir_override() -> Option<PathBuf> {
+        None
+    }
+
+    pub fn state_dir_override() -> Option<PathBuf> {
+        None
+    }
+
+    pub fn xdg_config_dir() -> Option<PathBuf> {
+        None
+    }
+
+    pub fn xdg_data_dir() -> Option<PathBuf> {
+        None
+    }
+
+    pub fn xdg_state_dir() -> Option<PathBuf> {
+        None
+    }
 }
 
-use dirs::project_dirs;
+use dirs::{
+    config_dir_override, project_dirs, save_dir_override, state_dir_override, xdg_config_dir,
+    xdg_data_dir, xdg_state_dir,
+};
+
+/// Save and state directories of the current profile
+fn default_dirs() -> (PathBuf, PathBuf) {
+    let (save_dir, state_dir) = base_dirs();
+    (profile_dir(save_dir), profile_dir(state_dir))
+}
+
+/// Save and state directories of the current profile overridden by environment variables.
+/// States are stored in the save directory unless the state directory is also overridden.
+fn dir_overrides() -> (Option<PathBuf>, Option<PathBuf>) {
+    let save_dir = save_dir_override();
+    let state_dir = state_dir_override().or_else(|| save_dir.clone());
+    (save_dir.map(profile_dir), state_dir.map(profile_dir))
+}
+
+fn base_dirs() -> (PathBuf, PathBuf) {
+    if let Ok(project_dirs) = project_dirs() {
+        (
+            project_dirs.data_dir().to_owned(),
+            project_dirs
+                .state_dir()
+                .unwrap_or_else(|| project_dirs.data_dir())
+                .to_owned(),
+        )
+    } else if let (Some(data_dir), Some(state_dir)) = (xdg_data_dir(), xdg_state_dir()) {
+        (data_dir, state_dir)
+    } else if cfg!(target_arch = "wasm32") {
+        // IndexedDB has no store for `state`, so states are stored with backups
+        (PathBuf::from("save"), PathBuf::from("save"))
+    } else {
+        warn!("Cannot get project directory. Defaults to `save` and `state`");
+        (PathBuf::from("save"), PathBuf::from("state"))
+    }
+}
+
+fn default_state_dir() -> PathBuf {
+    default_dirs().1
+}
+
+fn default_capture_dir() -> PathBuf {
+    default_dirs().0.join("capture")
+}
+
+// Firmware files are shared by all profiles
+fn default_firmware_dir() -> PathBuf {
+    base_dirs().0.join("firmware")
+}
+
+fn default_turbo_time_budget() -> usize {
+    12
+}
+
+fn default_msaa_samples() -> u32 {
+    4
+}
+
+fn default_animations() -> bool {
+    true
+}
+
+fn default_prompt_gb_model() -> bool {
+    true
+}
+
+pub fn default_window_title() -> String {
+    "MERU — {game} [{system}]".to_string()
+}
+
+pub const MIN_ZOOM: usize = 100;
+pub const MAX_ZOOM: usize = 800;
+
+fn default_zoom() -> usize {
+    MIN_ZOOM
+}
+
+fn default_clip_length() -> usize {
+    0
+}
+
+fn default_clip_scale() -> usize {
+    1
+}
 
 impl Default for Config {
     fn default() -> Self {
-        let (save_dir, state_dir) = if let Ok(project_dirs) = project_dirs() {
-            (
-                project_dirs.data_dir().to_owned(),
-                project_dirs
-                    .state_dir()
-                    .unwrap_or_else(|| project_dirs.data_dir())
-                    .to_owned(),
-            )
-        } else {
-            warn!("Cannot get project directory. Defaults to `save` and `state`");
-            (PathBuf::from("save"), PathBuf::from("state"))
-        };
+        let (save_dir, state_dir) = default_dirs();
+        let (save_override, state_override) = dir_overrides();
+        let save_dir = save_override.unwrap_or(save_dir);
+        let state_dir = state_override.unwrap_or(state_dir);
 
         create_dir_all(&save_dir).unwrap();
         create_dir_all(&state_dir).unwrap();
 
         Self {
             save_dir,
+            state_dir,
+            state_files_migrated: true,
+            state_storage: StateStorage::default(),
+            compress_states: false,
+            state_self_test: false,
+            rom_dirs: vec![],
             show_fps: false,
+            show_perf_stats: false,
             frame_skip_on_turbo: 4,
+            turbo_time_budget: default_turbo_time_budget(),
+            core_frame_skips_on_turbo: BTreeMap::new(),
+            core_overscans: BTreeMap::new(),
             scaling: 2,
+            screen_filter: ScreenFilter::default(),
+            frame_interpolation: false,
+            game_video_settings: BTreeMap::new(),
+            rotation: Rotation::default(),
+            zoom: default_zoom(),
+            reduce_flashing: false,
+            msaa_samples: default_msaa_samples(),
+            animations: default_animations(),
+            high_contrast_ui: false,
+            text_to_speech: false,
+            simple_mode: false,
+            minimize_to_tray: false,
+            capture_protection: false,
+            ui_scale: None,
+            window_title: default_window_title(),
+            screenshot_scaled: false,
+            capture_dir: default_capture_dir(),
+            clip_length: default_clip_length(),
+            clip_scale: default_clip_scale(),
+            firmware_dir: default_firmware_dir(),
+            core_priority: BTreeMap::new(),
+            controller_db: None,
+            button_label_style: ButtonLabelStyle::default(),
             auto_state_save_rate: 128 * 1024,          // 128KB/s
             auto_state_save_limit: 1024 * 1024 * 1024, // 1GB
             minimum_auto_save_span: 60,
+            hold_rewind_limit: default_hold_rewind_limit(),
+            clear_backup_on_reload: false,
+            backup_snapshots: default_backup_snapshots(),
+            max_sessions: default_max_sessions(),
+            audio: AudioConfig::default(),
+            audio_packs: BTreeMap::new(),
+            texture_packs: BTreeMap::new(),
+            gb_models: BTreeMap::new(),
+            prompt_gb_model: default_prompt_gb_model(),
+            camera: CameraConfig::default(),
+            netplay: NetplayConfig::default(),
+            link_cable: LinkCableConfig::default(),
+            obs: ObsConfig::default(),
             system_keys: SystemKeys::default(),
             hotkeys: HotKeys::default(),
+            global_hotkeys: GlobalHotKeyConfig::default(),
+            palette_presets: vec![],
             core_configs: BTreeMap::new(),
             key_configs: BTreeMap::new(),
+            added_keys: BTreeMap::new(),
         }
     }
 }
 
-fn config_dir() -> Result<PathBuf> {
-    let config_dir = if let Ok(project_dirs) = project_dirs() {
+// Off by default, since the state is saved on every frame
+fn default_hold_rewind_limit() -> usize {
+    0
+}
+
+fn default_backup_snapshots() -> usize {
+    5
+}
+
+fn default_max_sessions() -> usize {
+    2
+}
+
+/// Config directory of the current profile
+pub fn config_dir() -> Result<PathBuf> {
+    let config_dir = profile_dir(base_config_dir()?);
+    create_dir_all(&config_dir)?;
+    Ok(config_dir)
+}
+
+/// Config directory shared by all profiles
+pub fn base_config_dir() -> Result<PathBuf> {
+    let config_dir = if let Some(config_dir) = config_dir_override() {
+        config_dir
+    } else if let Ok(project_dirs) = project_dirs() {
         project_dirs.config_dir().to_owned()
+    } else if let Some(config_dir) = xdg_config_dir() {
+        config_dir
     } else {
         warn!("Cannot find project directory. Defaults to `config`");
         Path::new("config").to_owned()
@@ -170,6 +992,74 @@ impl Config {
         self.core_configs.insert(abbrev.to_owned(), value);
     }
 
+    /// Per-core override of `frame_skip_on_turbo`, falling back to the global value
+    pub fn frame_skip_on_turbo(&self, abbrev: &str) -> usize {
+        self.core_frame_skips_on_turbo
+            .get(abbrev)
+            .copied()
+            .unwrap_or(self.frame_skip_on_turbo)
+    }
+
+    pub fn set_core_frame_skip_on_turbo(&mut self, abbrev: &str, frame_skip: Option<usize>) {
+        if let Some(frame_skip) = frame_skip {
+            self.core_frame_skips_on_turbo
+                .insert(abbrev.to_string(), frame_skip);
+        } else {
+            self.core_frame_skips_on_turbo.remove(abbrev);
+        }
+    }
+
+    /// Adds a palette preset, replacing the one with the same name
+    pub fn add_palette_preset(&mut self, preset: PalettePreset) {
+        if let Some(p) = self
+            .palette_presets
+            .iter_mut()
+            .find(|p| p.name == preset.name)
+        {
+            *p = preset;
+        } else {
+            self.palette_presets.push(preset);
+        }
+    }
+
+    /// Video setting of the game with `game_hash`, or the global one if not overridden
+    pub fn video_setting(&self, game_hash: &str) -> VideoSetting {
+        self.game_video_settings
+            .get(game_hash)
+            .copied()
+            .unwrap_or(VideoSetting {
+                screen_filter: self.screen_filter,
+                scaling: self.scaling,
+                frame_interpolation: self.frame_interpolation,
+            })
+    }
+
+    /// Updates the override of the game if exists, otherwise the global setting
+    pub fn set_video_setting(&mut self, game_hash: &str, setting: VideoSetting) {
+        if let Some(game_setting) = self.game_video_settings.get_mut(game_hash) {
+            *game_setting = setting;
+        } else {
+            self.screen_filter = setting.screen_filter;
+            self.scaling = setting.scaling;
+            self.frame_interpolation = setting.frame_interpolation;
+        }
+    }
+
+    pub fn overscan(&self, abbrev: &str) -> Overscan {
+        self.core_overscans
+            .get(abbrev)
+            .copied()
+            .unwrap_or_else(|| Overscan::default_for(abbrev))
+    }
+
+    pub fn set_core_overscan(&mut self, abbrev: &str, overscan: Overscan) {
+        if overscan == Overscan::default_for(abbrev) {
+            self.core_overscans.remove(abbrev);
+        } else {
+            self.core_overscans.insert(abbrev.to_string(), overscan);
+        }
+    }
+
     pub fn key_config(&mut self, abbrev: &str) -> &meru_interface::KeyConfig {
         self.key_configs
             .entry(abbrev.to_string())
@@ -179,13 +1069,56 @@ impl Config {
     pub fn set_key_config(&mut self, abbrev: &str, key_config: meru_interface::KeyConfig) {
         self.key_configs.insert(abbrev.to_string(), key_config);
     }
+
+    /// Key config of the core, or its default if not configured yet
+    pub fn key_config_or_default(&self, abbrev: &str) -> Cow<meru_interface::KeyConfig> {
+        self.key_configs.get(abbrev).map_or_else(
+            || Cow::Owned(Emulator::default_key_config(abbrev)),
+            Cow::Borrowed,
+        )
+    }
+
+    /// Key configs of all cores, including the defaults of cores not configured yet
+    pub fn core_key_configs(&self) -> Vec<(&'static str, Cow<meru_interface::KeyConfig>)> {
+        EMULATOR_CORES
+            .iter()
+            .map(|core| {
+                let abbrev = core.core_info().abbrev;
+                (abbrev, self.key_config_or_default(abbrev))
+            })
+            .collect()
+    }
+
+    /// Merges buttons added to the core's default key config into the user's key config
+    fn merge_default_key_config(&mut self, abbrev: &str) {
+        if let Some(key_config) = self.key_configs.get_mut(abbrev) {
+            let added = key_config.add_missing_keys(&Emulator::default_key_config(abbrev));
+            if !added.is_empty() {
+                info!("Added new buttons to key config of {abbrev}: {added:?}");
+                self.added_keys.insert(abbrev.to_string(), added);
+            }
+        }
+    }
+
+    /// Buttons added to the key config by `merge_default_key_config` in this session
+    pub fn added_keys(&self, abbrev: &str) -> &[(usize, String)] {
+        self.added_keys
+            .get(abbrev)
+            .map(|keys| keys.as_slice())
+            .unwrap_or(&[])
+    }
 }
 
 pub async fn load_config() -> Result<Config> {
     let ret = if let Ok(s) = read_to_string(config_path()?).await {
         let mut config: Config = serde_json::from_str(&s)?;
 
+        config.hotkeys.add_missing_keys(&HotKeys::default());
+        config.system_keys.add_missing_keys(&SystemKeys::default());
+
         for core in EMULATOR_CORES {
+            config.merge_default_key_config(core.core_info().abbrev);
+
             let core_config = config.core_config(core.core_info().abbrev);
             if !core.check_config(core_config) {
                 warn!(
@@ -195,6 +1128,31 @@ pub async fn load_config() -> Result<Config> {
                 config.set_core_config(core.core_info().abbrev, core.default_config());
             }
         }
+
+        // Only the configured directories are migrated, never the overridden ones
+        if !config.state_files_migrated {
+            create_dir_all(&config.state_dir)?;
+            if config.save_dir != config.state_dir {
+                if let Err(err) =
+                    move_save_files(&config.save_dir, &config.state_dir, is_state_file)
+                {
+                    warn!("Failed to migrate state files: {err}");
+                }
+            }
+            config.state_files_migrated = true;
+            config.save().await?;
+        }
+
+        let (save_dir, state_dir) = dir_overrides();
+        if let Some(save_dir) = save_dir {
+            create_dir_all(&save_dir)?;
+            config.save_dir = save_dir;
+        }
+        if let Some(state_dir) = state_dir {
+            config.state_dir = state_dir;
+        }
+        create_dir_all(&config.state_dir)?;
+
         config
     } else {
         Config::default()
@@ -205,6 +1163,21 @@ pub async fn load_config() -> Result<Config> {
 #[derive(Default, Serialize, Deserialize)]
 pub struct PersistentState {
     pub recent: VecDeque<RecentFile>,
+    /// Stored in its own file, since `state.json` is in bincode
+    /// and cannot get new fields without breaking the old ones
+    #[serde(skip)]
+    pub games: GameData,
+}
+
+/// Per-game data stored in `games.json`
+#[derive(Default, Serialize, Deserialize)]
+pub struct GameData {
+    /// Cheat codes by ROM hash
+    #[serde(default)]
+    pub cheats: BTreeMap<String, Vec<Cheat>>,
+    /// Goals by ROM hash
+    #[serde(default)]
+    pub goals: BTreeMap<String, Vec<Goal>>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -223,10 +1196,26 @@ impl PersistentState {
         }
     }
 
+    pub fn cheats(&self, game_hash: &str) -> &[Cheat] {
+        self.games
+            .cheats
+            .get(game_hash)
+            .map_or(&[], |cheats| cheats.as_slice())
+    }
+
+    pub fn goals(&self, game_hash: &str) -> &[Goal] {
+        self.games
+            .goals
+            .get(game_hash)
+            .map_or(&[], |goals| goals.as_slice())
+    }
+
     pub fn save(&self) -> impl Future<Output = Result<()>> {
         let s = bincode::serialize(self).unwrap();
+        let games = serde_json::to_string_pretty(&self.games).unwrap();
         async move {
             write(persistent_state_path().unwrap(), s).await?;
+            write(game_data_path()?, games).await?;
             Ok::<(), anyhow::Error>(())
         }
     }
@@ -238,8 +1227,12 @@ fn persistent_state_path() -> Result<PathBuf> {
     Ok(config_dir.join("state.json"))
 }
 
+fn game_data_path() -> Result<PathBuf> {
+    Ok(config_dir()?.join("games.json"))
+}
+
 pub async fn load_persistent_state() -> Result<PersistentState> {
-    let ret = if let Ok(s) = read(persistent_state_path()?).await {
+    let mut ret: PersistentState = if let Ok(s) = read(persistent_state_path()?).await {
         if let Ok(ret) = bincode::deserialize(&s) {
             ret
         } else {
@@ -248,5 +1241,8 @@ pub async fn load_persistent_state() -> Result<PersistentState> {
     } else {
         Default::default()
     };
+    if let Ok(s) = read_to_string(game_data_path()?).await {
+        ret.games = serde_json::from_str(&s).unwrap_or_default();
+    }
     Ok(ret)
 }
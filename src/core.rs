@@ -1,15 +1,26 @@
 use anyhow::{anyhow, bail, Result};
 use bevy::{
     prelude::*,
-    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+    reflect::TypeUuid,
+    render::{
+        render_resource::{
+            AsBindGroup, Extent3d, ShaderRef, ShaderType, TextureDimension, TextureFormat,
+        },
+        texture::ImageSampler,
+    },
+    sprite::{Material2d, Material2dPlugin, MaterialMesh2dBundle},
 };
 use bevy_tiled_camera::{TiledCamera, TiledCameraBundle};
-use chrono::{DateTime, Local};
-use meru_interface::{AudioBuffer, CoreInfo, EmulatorCore, FrameBuffer, InputData, KeyConfig};
+use chrono::{DateTime, Duration, Local};
+use meru_interface::{
+    AudioBuffer, CoreInfo, EmulatorCore, FirmwareInfo, FrameBuffer, FrameStats, InputData,
+    KeyConfig, MemoryPatch, Region, TileDraw,
+};
 use schemars::{schema::RootSchema, schema_for};
 use serde_json::Value;
 use std::{
-    collections::VecDeque,
+    borrow::Cow,
+    collections::{BTreeSet, VecDeque},
     future::Future,
     io::Cursor,
     marker::PhantomData,
@@ -17,26 +28,50 @@ use std::{
 };
 
 use crate::{
-    app::{AppState, ScreenSprite, WindowControlEvent},
+    app::{AppState, ScreenSprite, ShowMessage, UiState, WindowControlEvent},
     archive::Archive,
-    config::Config,
-    file::{get_state_file_path, load_backup, load_state, modified, save_backup, save_state},
+    audio::{setup_audio, AudioSink},
+    audio_pack::AudioPackPlayer,
+    cheats::{parse_code, Cheat, CheatOp, CheatWrite},
+    clip::{frame_buffer_to_rgba, ClipRecorder},
+    config::{
+        Config, GbModel, Overscan, PersistentState, Rotation, ScreenFilter, TurboAudio,
+        VideoSetting, MAX_ZOOM, MIN_ZOOM,
+    },
+    file::{
+        backup_snapshot_date, compress_state, create_dir_all, delete_backup, load_backup,
+        load_play_session_state, load_play_sessions, load_state, load_state_infos,
+        restore_backup_snapshot, save_backup, save_backup_snapshot, save_play_session, save_state,
+        write_plain, PlaySession,
+    },
     hotkey,
     input::InputState,
+    library::LibraryDb,
+    menu::MenuTab,
+    movie::Movies,
+    netplay::Netplay,
+    profiler::profile_scope,
+    rewind_buffer::RewindBuffer,
     rewinding::AutoSavedState,
-    utils::spawn_local,
+    texture_pack::TextureReplacer,
+    utils::{spawn_local, Sender},
 };
 
+#[cfg(not(any(feature = "nes", feature = "snes", feature = "gb", feature = "gba")))]
+compile_error!("At least one of the core features (nes, snes, gb, gba) must be enabled");
+
 macro_rules! def_emulator_cores {
-    ($( $constr:ident($t:ty) ),* $(,)?) => {
+    ($( $(#[$meta:meta])* $constr:ident($t:ty) ),* $(,)?) => {
         pub enum EmulatorCores {
             $(
+                $(#[$meta])*
                 $constr(PhantomData<$t>),
             )*
         }
 
         pub const EMULATOR_CORES: &[EmulatorCores] = &[
             $(
+                $(#[$meta])*
                 EmulatorCores::$constr(PhantomData),
             )*
         ];
@@ -45,6 +80,7 @@ macro_rules! def_emulator_cores {
             ($enum:ident, $core:ident, $var:ident, $e:expr) => {
                 match $core {
                     $(
+                        $(#[$meta])*
                         $enum::$constr($var) => $e,
                     )*
                 }
@@ -53,11 +89,13 @@ macro_rules! def_emulator_cores {
 
         pub enum EmulatorEnum {
             $(
+                $(#[$meta])*
                 $constr(Box<$t>),
             )*
         }
 
         $(
+            $(#[$meta])*
             impl From<$t> for EmulatorEnum {
                 fn from(core: $t) -> Self {
                     EmulatorEnum::$constr(Box::new(core))
@@ -67,10 +105,15 @@ macro_rules! def_emulator_cores {
     };
 }
 
+// Each core is behind a cargo feature so that e.g. the web build can ship only some of them
 def_emulator_cores!(
+    #[cfg(feature = "nes")]
     Nes(sabicom::Nes),
+    #[cfg(feature = "snes")]
     Snes(super_sabicom::Snes),
+    #[cfg(feature = "gb")]
     GameBoy(tgbr::GameBoy),
+    #[cfg(feature = "gba")]
     GameBoyAdvance(tgba::Agb),
 );
 
@@ -88,6 +131,31 @@ impl EmulatorCores {
         dispatch_enum!(EmulatorCores, self, core, core_info(core))
     }
 
+    /// Cores supporting `ext`, ordered by the user's core priority setting
+    pub fn for_extension(ext: &str, config: &Config) -> Vec<&'static Self> {
+        let mut cores = EMULATOR_CORES
+            .iter()
+            .filter(|core| core.core_info().file_extensions.contains(&ext))
+            .collect::<Vec<_>>();
+
+        if let Some(priority) = config.core_priority.get(ext) {
+            cores.sort_by_key(|core| {
+                priority
+                    .iter()
+                    .position(|abbrev| abbrev == core.core_info().abbrev)
+                    .unwrap_or(usize::MAX)
+            });
+        }
+        cores
+    }
+
+    pub fn firmware_info(&self) -> &'static [FirmwareInfo] {
+        fn firmware_info<T: EmulatorCore>(_: &PhantomData<T>) -> &'static [FirmwareInfo] {
+            T::firmware_info()
+        }
+        dispatch_enum!(EmulatorCores, self, core, firmware_info(core))
+    }
+
     pub fn default_config(&self) -> Value {
         fn default_config<T: EmulatorCore>(_: &PhantomData<T>) -> Value {
             serde_json::to_value(T::Config::default()).unwrap()
@@ -116,6 +184,7 @@ async fn make_core_from_data<T: EmulatorCore + Into<EmulatorEnum>>(
     ext: &str,
     data: &[u8],
     config: &Config,
+    with_backup: bool,
 ) -> Option<Result<EmulatorEnum>> {
     let core_info = <T as EmulatorCore>::core_info();
     if !core_info.file_extensions.contains(&ext) {
@@ -123,7 +192,11 @@ async fn make_core_from_data<T: EmulatorCore + Into<EmulatorEnum>>(
     }
 
     let fut = async {
-        let backup = load_backup(core_info.abbrev, name, &config.save_dir).await?;
+        let backup = if with_backup {
+            load_backup(core_info.abbrev, name, &config.save_dir).await?
+        } else {
+            None
+        };
         let config = serde_json::from_value(config.core_config(T::core_info().abbrev))?;
         let core = T::try_from_file(data, backup.as_deref(), &config)?;
         Ok(core.into())
@@ -139,13 +212,19 @@ impl EmulatorEnum {
             .any(|core| core.core_info().file_extensions.contains(&ext))
     }
 
-    pub async fn try_new(name: &str, ext: &str, data: &[u8], config: &Config) -> Result<Self> {
-        for core in EMULATOR_CORES {
+    pub async fn try_new(
+        name: &str,
+        ext: &str,
+        data: &[u8],
+        config: &Config,
+        with_backup: bool,
+    ) -> Result<Self> {
+        for core in EmulatorCores::for_extension(ext, config) {
             if let Some(ret) = dispatch_enum!(
                 EmulatorCores,
                 core,
                 core,
-                make_core_from_data(core, name, ext, data, config).await
+                make_core_from_data(core, name, ext, data, config, with_backup).await
             ) {
                 return ret;
             }
@@ -164,10 +243,128 @@ impl EmulatorEnum {
         dispatch_enum!(EmulatorEnum, self, core, core.game_info())
     }
 
+    pub fn game_info_updated(&mut self) -> bool {
+        dispatch_enum!(EmulatorEnum, self, core, core.game_info_updated())
+    }
+
     pub fn backup(&self) -> Option<Vec<u8>> {
         dispatch_enum!(EmulatorEnum, self, core, core.backup())
     }
 
+    pub fn backup_generation(&self) -> Option<u64> {
+        dispatch_enum!(EmulatorEnum, self, core, core.backup_generation())
+    }
+
+    pub fn take_messages(&mut self) -> Vec<String> {
+        dispatch_enum!(EmulatorEnum, self, core, core.take_messages())
+    }
+
+    pub fn status(&self) -> Option<String> {
+        dispatch_enum!(EmulatorEnum, self, core, core.status())
+    }
+
+    pub fn supports_region_override(&self) -> bool {
+        dispatch_enum!(EmulatorEnum, self, core, core.supports_region_override())
+    }
+
+    pub fn set_region(&mut self, region: Option<Region>) {
+        dispatch_enum!(EmulatorEnum, self, core, core.set_region(region));
+    }
+
+    pub fn layer_names(&self) -> &'static [&'static str] {
+        dispatch_enum!(EmulatorEnum, self, core, core.layer_names())
+    }
+
+    pub fn set_layer_enabled(&mut self, index: usize, enabled: bool) {
+        dispatch_enum!(
+            EmulatorEnum,
+            self,
+            core,
+            core.set_layer_enabled(index, enabled)
+        );
+    }
+
+    pub fn supports_memory_access(&self) -> bool {
+        dispatch_enum!(EmulatorEnum, self, core, core.supports_memory_access())
+    }
+
+    pub fn read_memory(&self, addr: u32) -> u8 {
+        dispatch_enum!(EmulatorEnum, self, core, core.read_memory(addr))
+    }
+
+    pub fn write_memory(&mut self, addr: u32, data: u8) {
+        dispatch_enum!(EmulatorEnum, self, core, core.write_memory(addr, data));
+    }
+
+    pub fn set_memory_patches(&mut self, patches: &[MemoryPatch]) {
+        dispatch_enum!(EmulatorEnum, self, core, core.set_memory_patches(patches));
+    }
+
+    pub fn frame_stats(&self) -> Option<FrameStats> {
+        dispatch_enum!(EmulatorEnum, self, core, core.frame_stats())
+    }
+
+    pub fn supports_tile_hashes(&self) -> bool {
+        dispatch_enum!(EmulatorEnum, self, core, core.supports_tile_hashes())
+    }
+
+    pub fn set_tile_hashing(&mut self, enabled: bool) {
+        dispatch_enum!(EmulatorEnum, self, core, core.set_tile_hashing(enabled));
+    }
+
+    pub fn tile_draws(&self) -> &[TileDraw] {
+        dispatch_enum!(EmulatorEnum, self, core, core.tile_draws())
+    }
+
+    pub fn supports_camera(&self) -> bool {
+        dispatch_enum!(EmulatorEnum, self, core, core.supports_camera())
+    }
+
+    pub fn set_camera_image(&mut self, image: &[u8]) {
+        dispatch_enum!(EmulatorEnum, self, core, core.set_camera_image(image));
+    }
+
+    pub fn supports_card_scan(&self) -> bool {
+        dispatch_enum!(EmulatorEnum, self, core, core.supports_card_scan())
+    }
+
+    pub fn scan_card(&mut self, data: &[u8]) -> Result<()> {
+        dispatch_enum!(EmulatorEnum, self, core, core.scan_card(data)?);
+        Ok(())
+    }
+
+    pub fn supports_link_cable(&self) -> bool {
+        dispatch_enum!(EmulatorEnum, self, core, core.supports_link_cable())
+    }
+
+    pub fn take_link_output(&mut self) -> Vec<u8> {
+        dispatch_enum!(EmulatorEnum, self, core, core.take_link_output())
+    }
+
+    pub fn push_link_input(&mut self, data: &[u8]) {
+        dispatch_enum!(EmulatorEnum, self, core, core.push_link_input(data));
+    }
+
+    pub fn save_types(&self) -> &'static [&'static str] {
+        dispatch_enum!(EmulatorEnum, self, core, core.save_types())
+    }
+
+    pub fn set_save_type(&mut self, index: Option<usize>) {
+        dispatch_enum!(EmulatorEnum, self, core, core.set_save_type(index));
+    }
+
+    pub fn active_controllers(&self) -> Option<usize> {
+        dispatch_enum!(EmulatorEnum, self, core, core.active_controllers())
+    }
+
+    pub fn is_dual_mode(&self) -> bool {
+        dispatch_enum!(EmulatorEnum, self, core, core.is_dual_mode())
+    }
+
+    pub fn set_enhanced_model(&mut self, enhanced: Option<bool>) {
+        dispatch_enum!(EmulatorEnum, self, core, core.set_enhanced_model(enhanced));
+    }
+
     pub fn set_config(&mut self, core_config: &Value) {
         fn set_config<T: EmulatorCore>(core: &mut T, config: &Value) {
             core.set_config(&serde_json::from_value::<T::Config>(config.clone()).unwrap());
@@ -185,6 +382,7 @@ impl EmulatorEnum {
     }
 
     pub fn exec_frame(&mut self, render_graphics: bool) {
+        profile_scope!("exec_frame");
         dispatch_enum!(EmulatorEnum, self, core, core.exec_frame(render_graphics));
     }
 
@@ -201,6 +399,7 @@ impl EmulatorEnum {
     }
 
     pub fn save_state(&self) -> Vec<u8> {
+        profile_scope!("save_state");
         dispatch_enum!(EmulatorEnum, self, core, core.save_state())
     }
 
@@ -213,26 +412,103 @@ impl EmulatorEnum {
 pub struct Emulator {
     pub core: EmulatorEnum,
     pub game_name: String,
+    /// CRC32 of the ROM, which identifies the game in per-game settings
+    pub game_hash: String,
+    pub rom_path: PathBuf,
+    /// File name in the archive when the ROM is loaded from an archive
+    pub archive_entry: Option<String>,
     pub auto_saved_states: VecDeque<AutoSavedState>,
+    /// States of every frame for rewinding while the hotkey is held
+    pub rewind_buffer: RewindBuffer,
+    pub clip_recorder: ClipRecorder,
     pub state_files: Vec<Option<StateFile>>,
+    pub backup_snapshots: Vec<Option<DateTime<Local>>>,
+    last_backup: Option<Vec<u8>>,
     total_auto_saved_size: usize,
     prev_auto_saved_frame: usize,
     prev_backup_saved_frame: usize,
+    backup_generation: u64,
+    backup_changed_frame: Option<usize>,
+    backup_discarded: bool,
     save_dir: PathBuf,
+    state_dir: PathBuf,
     frames: usize,
+    pub game_info: Vec<(String, String)>,
+    prev_game_info_frame: usize,
+    session_start: DateTime<Local>,
+    pub play_sessions: Vec<PlaySession>,
+    pub overscan: Overscan,
+    pub rotation: Rotation,
+    /// Indices of layers hidden from the debug toolbar
+    hidden_layers: BTreeSet<usize>,
+    /// Enabled cheat codes writing memory every frame
+    cheat_writes: Vec<CheatWrite>,
+    /// Enabled cheat codes patching memory reads
+    cheat_patches: Vec<MemoryPatch>,
+    /// Cheats are kept but not applied, during netplay and movies
+    cheats_suspended: bool,
+    /// Frames executed in the last turbo update
+    pub turbo_frames: usize,
+    /// Time spent in the core during the last update
+    pub core_time: std::time::Duration,
+    core_config: Value,
+    /// Core config at the last boot or reset.
+    /// Options that are applied only on reset keep this value until then.
+    boot_config: Value,
+    /// State before core config changes from the menu, to revert them
+    config_checkpoint: Option<ConfigCheckpoint>,
+    /// The core panicked after a config change. Stopped until the change is reverted.
+    pub core_failed: bool,
+    /// Game Boy game which also runs on Game Boy Color
+    pub dual_mode_cart: bool,
+    /// Model of the game overriding the one of the core config
+    gb_model: Option<GbModel>,
+    /// Controllers the game read at the last frame, to tell the user when it changes
+    active_controllers: Option<usize>,
+}
+
+struct ConfigCheckpoint {
+    core_config: Value,
+    boot_config: Value,
+    state: Vec<u8>,
+    /// Frame of the last config change
+    changed_frame: usize,
 }
 
 pub struct StateFile {
     pub modified: DateTime<Local>,
+    pub context: Option<String>,
+    /// Screen at the time of saving, only for states saved in this session
+    pub thumbnail: Option<image::RgbaImage>,
 }
 
 impl Drop for Emulator {
     fn drop(&mut self) {
         let fut = self.save_backup();
-        spawn_local(async { fut.await.unwrap() });
+        let session = self.end_play_session();
+        spawn_local(async {
+            fut.await.unwrap();
+            session.await.unwrap();
+        });
     }
 }
 
+const MAX_PLAY_SESSIONS: usize = 20;
+
+// Keep the state before a config change until playing for a while without problems
+const CONFIG_CHECKPOINT_FRAMES: usize = 60 * 60;
+
+// Sessions shorter than this are not recorded
+const MIN_PLAY_SESSION_FRAMES: usize = 60 * 60;
+
+const BACKUP_SNAPSHOT_INTERVAL_MINUTES: i64 = 10;
+
+// Wait for a while after backup RAM is modified, since games write saves across several frames
+const BACKUP_SAVE_DELAY_FRAMES: usize = 60;
+const BACKUP_SAVE_INTERVAL_FRAMES: usize = 60 * 60;
+
+const GAME_INFO_REFRESH_FRAMES: usize = 60;
+
 pub const ARCHIVE_EXTENSIONS: &[&str] = &["zip", "7z", "rar"];
 
 fn is_archive_file(path: &Path) -> bool {
@@ -242,7 +518,27 @@ fn is_archive_file(path: &Path) -> bool {
     })
 }
 
-async fn try_make_emulator(path: &Path, data: &[u8], config: &Config) -> Result<Emulator> {
+fn is_rom_entry(path: &Path) -> bool {
+    // Skip metadata added by macOS archivers
+    if path.components().any(|c| c.as_os_str() == "__MACOSX")
+        || path
+            .file_name()
+            .map_or(true, |name| name.to_string_lossy().starts_with('.'))
+    {
+        return false;
+    }
+
+    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    EmulatorEnum::exist_supported_core(ext)
+}
+
+async fn try_make_emulator(
+    rom_path: &Path,
+    path: &Path,
+    data: &[u8],
+    config: &Config,
+    with_backup: bool,
+) -> Result<Emulator> {
     let ext = path
         .extension()
         .ok_or_else(|| anyhow!("Cannot detect file type"))?
@@ -253,33 +549,100 @@ async fn try_make_emulator(path: &Path, data: &[u8], config: &Config) -> Result<
         .ok_or_else(|| anyhow!("Invalid file name"))?
         .to_string_lossy();
 
-    let core = EmulatorEnum::try_new(&name, &ext, data, config).await?;
+    let game_hash = format!("{:08X}", crc32fast::hash(data));
+    let mut core = EmulatorEnum::try_new(&name, &ext, data, config, with_backup).await?;
+
+    let dual_mode_cart = core.is_dual_mode();
+    let gb_model = config
+        .gb_models
+        .get(&game_hash)
+        .copied()
+        .filter(|_| dual_mode_cart);
+    if let Some(model) = gb_model {
+        core.set_enhanced_model(Some(model == GbModel::Cgb));
+        core.reset();
+    }
 
-    let mut state_files = vec![];
+    let state_files = load_state_infos(
+        core.core_info().abbrev,
+        &name,
+        10,
+        &config.state_dir,
+        config.state_storage,
+    )
+    .await?
+    .into_iter()
+    .map(|info| {
+        info.map(|(modified, context)| StateFile {
+            modified,
+            context,
+            thumbnail: None,
+        })
+    })
+    .collect::<Vec<_>>();
 
-    for i in 0..10 {
-        let state_file_path =
-            get_state_file_path(core.core_info().abbrev, &name, i, &config.save_dir)?;
-        let state_file = modified(&state_file_path)
-            .await
-            .map(|modified| StateFile { modified })
-            .ok();
-        state_files.push(state_file);
+    let mut backup_snapshots = vec![];
+
+    for i in 0..config.backup_snapshots {
+        let date =
+            backup_snapshot_date(core.core_info().abbrev, &name, i, &config.save_dir).await?;
+        backup_snapshots.push(date);
     }
 
+    let play_sessions =
+        load_play_sessions(core.core_info().abbrev, &name, &config.state_dir).await?;
+
+    let last_backup = core.backup();
+    let backup_generation = core.backup_generation().unwrap_or(0);
+    let game_info = core.game_info();
+    let core_config = config.core_config(core.core_info().abbrev);
+    let overscan = config.overscan(core.core_info().abbrev);
+
     Ok(Emulator {
         core,
         game_name: name.to_string(),
+        game_hash,
+        rom_path: rom_path.to_owned(),
+        archive_entry: (rom_path != path).then(|| path.display().to_string()),
         auto_saved_states: VecDeque::new(),
+        rewind_buffer: RewindBuffer::default(),
+        clip_recorder: ClipRecorder::default(),
         state_files,
+        backup_snapshots,
+        last_backup,
         total_auto_saved_size: 0,
         prev_auto_saved_frame: 0,
         prev_backup_saved_frame: 0,
+        backup_generation,
+        backup_changed_frame: None,
+        backup_discarded: false,
         save_dir: config.save_dir.clone(),
+        state_dir: config.state_dir.clone(),
         frames: 0,
+        game_info,
+        prev_game_info_frame: 0,
+        session_start: Local::now(),
+        play_sessions,
+        overscan,
+        rotation: config.rotation,
+        hidden_layers: BTreeSet::new(),
+        cheat_writes: vec![],
+        cheat_patches: vec![],
+        cheats_suspended: false,
+        turbo_frames: 1,
+        core_time: std::time::Duration::ZERO,
+        boot_config: core_config.clone(),
+        core_config,
+        config_checkpoint: None,
+        core_failed: false,
+        dual_mode_cart,
+        gb_model,
+        active_controllers: None,
     })
 }
 
+pub const GB_ABBREV: &str = "gb";
+
 impl Emulator {
     pub fn core_infos() -> Vec<&'static CoreInfo> {
         let mut ret = vec![];
@@ -302,20 +665,40 @@ impl Emulator {
     }
 
     pub async fn try_new_from_bytes(path: &Path, data: Vec<u8>, config: &Config) -> Result<Self> {
+        Self::try_new_from_bytes_inner(path, data, config, true).await
+    }
+
+    async fn try_new_from_bytes_inner(
+        path: &Path,
+        data: Vec<u8>,
+        config: &Config,
+        with_backup: bool,
+    ) -> Result<Self> {
         if is_archive_file(path) {
             let data = Cursor::new(data);
             let mut archive = Archive::new(data)?;
 
             let mut ret = anyhow!("File does not contain a supported file");
 
-            for file in archive.file_names()? {
-                let path = Path::new(&file);
-                let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
-                if !EmulatorEnum::exist_supported_core(ext) {
-                    continue;
-                }
+            let mut files = archive
+                .file_names()?
+                .into_iter()
+                .filter(|file| is_rom_entry(Path::new(file)))
+                .collect::<Vec<_>>();
+
+            // Prefer the entry named after the archive, since archives often contain
+            // other versions (hacks, translations) or unrelated files
+            let archive_stem = path.file_stem().map(|s| s.to_string_lossy().to_lowercase());
+            files.sort_by_key(|file| {
+                Path::new(file)
+                    .file_stem()
+                    .map(|s| s.to_string_lossy().to_lowercase())
+                    != archive_stem
+            });
+
+            for file in files {
                 let data = archive.uncompress_file(&file)?;
-                match try_make_emulator(Path::new(&file), &data, config).await {
+                match try_make_emulator(path, Path::new(&file), &data, config, with_backup).await {
                     Ok(ret) => return Ok(ret),
                     Err(e) => ret = e,
                 }
@@ -327,23 +710,270 @@ impl Emulator {
             if !EmulatorEnum::exist_supported_core(ext) {
                 bail!("No supported core for {}", path.display());
             }
-            try_make_emulator(path, &data, config).await
+            try_make_emulator(path, path, &data, config, with_backup).await
+        }
+    }
+
+    pub fn reload(&self, config: &Config, with_backup: bool) -> impl Future<Output = Result<Self>> {
+        let path = self.rom_path.clone();
+        let config = config.clone();
+
+        async move {
+            cfg_if::cfg_if! {
+                if #[cfg(target_arch = "wasm32")] {
+                    bail!("Reloading ROM is not supported on this platform: {}", path.display())
+                } else {
+                    let data = std::fs::read(&path)?;
+                    Self::try_new_from_bytes_inner(&path, data, &config, with_backup).await
+                }
+            }
         }
     }
 
     pub fn reset(&mut self) {
         self.core.reset();
+        self.boot_config = self.core_config.clone();
+    }
+
+    /// Applies `core_config`. The state before the first of successive changes is kept,
+    /// so that the changes can be reverted when they break the game.
+    pub fn set_core_config(&mut self, core_config: &Value) {
+        if core_config == &self.core_config {
+            return;
+        }
+
+        match &mut self.config_checkpoint {
+            Some(checkpoint) => checkpoint.changed_frame = self.frames,
+            None => {
+                self.config_checkpoint = Some(ConfigCheckpoint {
+                    core_config: self.core_config.clone(),
+                    boot_config: self.boot_config.clone(),
+                    state: self.core.save_state(),
+                    changed_frame: self.frames,
+                });
+            }
+        }
+
+        self.core.set_config(core_config);
+        self.core_config = core_config.clone();
+    }
+
+    pub fn gb_model(&self) -> Option<GbModel> {
+        self.gb_model
+    }
+
+    /// Runs the game on `model`, or the model of the core config if `None`.
+    /// The game is reset when the model changes.
+    pub fn set_gb_model(&mut self, model: Option<GbModel>) {
+        if !self.dual_mode_cart || model == self.gb_model {
+            return;
+        }
+        self.gb_model = model;
+        self.core
+            .set_enhanced_model(model.map(|model| model == GbModel::Cgb));
+        self.reset();
+    }
+
+    pub fn has_config_checkpoint(&self) -> bool {
+        self.config_checkpoint.is_some()
+    }
+
+    /// Restores the config and the state before the config changes.
+    /// Returns the restored core config.
+    pub fn revert_core_config(&mut self) -> Result<Value> {
+        let checkpoint = self
+            .config_checkpoint
+            .take()
+            .ok_or_else(|| anyhow!("No config change to revert"))?;
+
+        self.core.set_config(&checkpoint.core_config);
+        self.core.load_state(&checkpoint.state)?;
+        self.core_config = checkpoint.core_config;
+        self.boot_config = checkpoint.boot_config;
+        self.core_failed = false;
+        Ok(self.core_config.clone())
+    }
+
+    fn expire_config_checkpoint(&mut self) {
+        if let Some(checkpoint) = &self.config_checkpoint {
+            if checkpoint.changed_frame + CONFIG_CHECKPOINT_FRAMES < self.frames {
+                self.config_checkpoint = None;
+            }
+        }
+    }
+
+    pub fn boot_config(&self) -> &Value {
+        &self.boot_config
+    }
+
+    pub fn is_layer_enabled(&self, index: usize) -> bool {
+        !self.hidden_layers.contains(&index)
+    }
+
+    pub fn set_layer_enabled(&mut self, index: usize, enabled: bool) {
+        if enabled {
+            self.hidden_layers.remove(&index);
+        } else {
+            self.hidden_layers.insert(index);
+        }
+        self.core.set_layer_enabled(index, enabled);
+    }
+
+    /// Replaces the active cheat codes with the enabled ones of `cheats`.
+    /// Codes which fail to parse are ignored.
+    pub fn set_cheats(&mut self, cheats: &[Cheat]) {
+        let abbrev = self.core.core_info().abbrev;
+
+        let mut writes = vec![];
+        let mut patches = vec![];
+        for cheat in cheats.iter().filter(|cheat| cheat.enabled) {
+            match parse_code(abbrev, &cheat.code) {
+                Ok(ops) => {
+                    for op in ops {
+                        match op {
+                            CheatOp::Write(write) => writes.push(write),
+                            CheatOp::Patch(patch) => patches.push(patch),
+                        }
+                    }
+                }
+                Err(err) => warn!("Ignoring cheat `{}`: {err}", cheat.description),
+            }
+        }
+
+        self.cheat_writes = writes;
+        if !self.cheats_suspended {
+            self.core.set_memory_patches(&patches);
+        }
+        self.cheat_patches = patches;
+    }
+
+    pub fn cheats_suspended(&self) -> bool {
+        self.cheats_suspended
+    }
+
+    /// Stops applying the cheat codes while `suspended`, keeping them to be resumed
+    pub fn suspend_cheats(&mut self, suspended: bool) {
+        if suspended == self.cheats_suspended {
+            return;
+        }
+        self.cheats_suspended = suspended;
+        if suspended {
+            self.core.set_memory_patches(&[]);
+        } else {
+            self.core.set_memory_patches(&self.cheat_patches);
+        }
+    }
+
+    fn apply_cheats(&mut self) {
+        if self.cheats_suspended {
+            return;
+        }
+        for write in &self.cheat_writes {
+            if let Some(compare) = write.compare {
+                if self.core.read_memory(write.addr) != compare {
+                    continue;
+                }
+            }
+            self.core.write_memory(write.addr, write.data);
+        }
+    }
+
+    /// Frame buffer with overscan cropped
+    pub fn screen(&self) -> Cow<'_, FrameBuffer> {
+        self.overscan.crop(self.core.frame_buffer())
+    }
+
+    pub fn screen_size(&self) -> (usize, usize) {
+        let fb = self.core.frame_buffer();
+        self.overscan.crop_size(fb.width, fb.height)
+    }
+
+    /// Screen size on the window, after rotation
+    pub fn display_size(&self) -> (usize, usize) {
+        self.rotation.rotate_size(self.screen_size())
+    }
+
+    /// Entry of `state_files` for a state saved just now
+    pub fn current_state_file(&self) -> StateFile {
+        StateFile {
+            modified: Local::now(),
+            context: self.core.status(),
+            thumbnail: Some(frame_buffer_to_rgba(&self.screen())),
+        }
+    }
+
+    pub fn screenshot(&self, config: &Config) -> image::RgbaImage {
+        use image::imageops::{resize, FilterType};
+
+        let raw = frame_buffer_to_rgba(&self.screen());
+
+        if !config.screenshot_scaled {
+            return raw;
+        }
+
+        // Screen filters are shaders on the screen sprite, which are not run for screenshots
+        let scale = config.video_setting(&self.game_hash).scaling.max(1) as u32;
+        resize(
+            &raw,
+            raw.width() * scale,
+            raw.height() * scale,
+            FilterType::Nearest,
+        )
+    }
+
+    pub fn refresh_game_info(&mut self) {
+        if self.core.game_info_updated()
+            || self.prev_game_info_frame + GAME_INFO_REFRESH_FRAMES <= self.frames
+        {
+            self.game_info = self.core.game_info();
+            self.prev_game_info_frame = self.frames;
+        }
+    }
+
+    pub fn backup_save_needed(&mut self) -> bool {
+        if let Some(generation) = self.core.backup_generation() {
+            if generation != self.backup_generation {
+                self.backup_generation = generation;
+                self.backup_changed_frame = Some(self.frames);
+            }
+            self.backup_changed_frame.map_or(false, |frame| {
+                frame + BACKUP_SAVE_DELAY_FRAMES <= self.frames
+            })
+        } else {
+            self.prev_backup_saved_frame + BACKUP_SAVE_INTERVAL_FRAMES <= self.frames
+        }
     }
 
     pub fn save_backup(&mut self) -> impl Future<Output = Result<()>> {
         self.prev_backup_saved_frame = self.frames;
+        self.backup_changed_frame = None;
+
+        let backup = if self.backup_discarded {
+            None
+        } else {
+            self.core.backup()
+        };
+
+        // Skip writing when nothing changed since the last save
+        let backup = backup.filter(|ram| self.last_backup.as_ref() != Some(ram));
+        let snapshot = match (&backup, &self.last_backup) {
+            (Some(cur), Some(prev)) if cur != prev => self.next_backup_snapshot(prev),
+            _ => None,
+        };
+
+        if backup.is_some() {
+            self.last_backup = backup.clone();
+        }
 
-        let backup = self.core.backup();
         let abbrev = self.core.core_info().abbrev.to_string();
         let game_name = self.game_name.clone();
         let save_dir = self.save_dir.clone();
 
         async move {
+            if let Some((index, ram)) = snapshot {
+                save_backup_snapshot(&abbrev, &game_name, index, &ram, &save_dir).await?;
+            }
+
             if let Some(ram) = backup {
                 save_backup(&abbrev, &game_name, &ram, &save_dir).await
             } else {
@@ -352,25 +982,170 @@ impl Emulator {
         }
     }
 
-    pub fn push_auto_save(&mut self) {
+    fn next_backup_snapshot(&mut self, ram: &[u8]) -> Option<(usize, Vec<u8>)> {
+        let now = Local::now();
+
+        let recently_saved = self
+            .backup_snapshots
+            .iter()
+            .flatten()
+            .any(|date| now - *date < Duration::minutes(BACKUP_SNAPSHOT_INTERVAL_MINUTES));
+        if recently_saved {
+            return None;
+        }
+
+        let index = (0..self.backup_snapshots.len()).min_by_key(|&i| self.backup_snapshots[i])?;
+        self.backup_snapshots[index] = Some(now);
+        Some((index, ram.to_vec()))
+    }
+
+    pub fn restore_backup_snapshot(&self, index: usize) -> impl Future<Output = Result<()>> {
+        let abbrev = self.core.core_info().abbrev.to_string();
+        let game_name = self.game_name.clone();
+        let save_dir = self.save_dir.clone();
+
+        async move { restore_backup_snapshot(&abbrev, &game_name, index, &save_dir).await }
+    }
+
+    pub fn delete_backup(&self) -> impl Future<Output = Result<()>> {
+        let abbrev = self.core.core_info().abbrev.to_string();
+        let game_name = self.game_name.clone();
+        let save_dir = self.save_dir.clone();
+
+        async move { delete_backup(&abbrev, &game_name, &save_dir).await }
+    }
+
+    pub fn set_save_dir(&mut self, save_dir: &Path) {
+        self.save_dir = save_dir.to_owned();
+    }
+
+    pub fn set_state_dir(&mut self, state_dir: &Path) {
+        self.state_dir = state_dir.to_owned();
+    }
+
+    fn end_play_session(&mut self) -> impl Future<Output = Result<()>> {
+        let data = (self.frames >= MIN_PLAY_SESSION_FRAMES).then(|| self.core.save_state());
+        let session = PlaySession {
+            start: self.session_start,
+            end: Local::now(),
+        };
+        let abbrev = self.core.core_info().abbrev.to_string();
+        let game_name = self.game_name.clone();
+        let state_dir = self.state_dir.clone();
+
+        async move {
+            if let Some(data) = data {
+                save_play_session(
+                    &abbrev,
+                    &game_name,
+                    session,
+                    &data,
+                    MAX_PLAY_SESSIONS,
+                    &state_dir,
+                )
+                .await?;
+            }
+            Ok(())
+        }
+    }
+
+    pub fn load_play_session(
+        &self,
+        session: &PlaySession,
+    ) -> impl Future<Output = Result<Vec<u8>>> {
+        let abbrev = self.core.core_info().abbrev.to_string();
+        let game_name = self.game_name.clone();
+        let state_dir = self.state_dir.clone();
+        let start = session.start;
+
+        async move { load_play_session_state(&abbrev, &game_name, &start, &state_dir).await }
+    }
+
+    /// Keeps the current state for rewinding while the hotkey is held
+    fn push_rewind_state(&mut self, config: &Config) {
+        if config.hold_rewind_limit > 0 {
+            let state = self.core.save_state();
+            self.rewind_buffer.push(state, config.hold_rewind_limit);
+        } else if !self.rewind_buffer.is_empty() {
+            self.rewind_buffer.clear();
+        }
+    }
+
+    pub fn discard_backup(&mut self) {
+        self.backup_discarded = true;
+    }
+
+    pub fn push_auto_save(&mut self, config: &Config) {
         let saved_state = AutoSavedState {
-            data: self.core.save_state(),
-            thumbnail: frame_buffer_to_image(self.core.frame_buffer()),
+            data: compress_state(self.core.save_state(), config.compress_states),
+            thumbnail: frame_buffer_to_image(self.core.frame_buffer(), &self.overscan),
+            context: self.core.status(),
         };
         self.auto_saved_states.push_back(saved_state);
     }
 
+    /// Passes the screen after `frames` core frames to the clip recorder
+    fn record_clip(&mut self, frames: usize, config: &Config) {
+        self.clip_recorder
+            .record(frames, self.core.frame_buffer(), &self.overscan, config);
+    }
+
+    pub fn save_clip(&self, config: &Config) -> impl Future<Output = Result<PathBuf>> {
+        self.clip_recorder.save(&self.game_name, config)
+    }
+
+    /// Saves the current screen as a PNG file into the capture directory.
+    /// On wasm, the file is also downloaded.
+    pub fn save_screenshot(&self, config: &Config) -> impl Future<Output = Result<PathBuf>> {
+        let image = self.screenshot(config);
+        let capture_dir = config.capture_dir.clone();
+        let file_name = format!(
+            "{}-{}.png",
+            self.game_name,
+            Local::now().format("%Y%m%d-%H%M%S")
+        );
+
+        async move {
+            let mut data = Cursor::new(vec![]);
+            image.write_to(&mut data, image::ImageOutputFormat::Png)?;
+            let data = data.into_inner();
+
+            #[cfg(target_arch = "wasm32")]
+            crate::utils::download(&file_name, &data)?;
+
+            create_dir_all(&capture_dir)?;
+            let path = capture_dir.join(file_name);
+            write_plain(&path, data).await?;
+            Ok(path)
+        }
+    }
+
     pub fn save_state_slot(
         &self,
         slot: usize,
         config: &Config,
     ) -> impl Future<Output = Result<()>> {
         let data = self.core.save_state();
+        let context = self.core.status();
         let abbrev = self.core.core_info().abbrev.to_string();
         let game_name = self.game_name.clone();
-        let save_dir = config.save_dir.clone();
+        let state_dir = config.state_dir.clone();
+        let state_storage = config.state_storage;
+        let compress = config.compress_states;
 
-        async move { save_state(&abbrev, &game_name, slot, &data, &save_dir).await }
+        async move {
+            save_state(
+                &abbrev,
+                &game_name,
+                slot,
+                &data,
+                context.as_deref(),
+                &state_dir,
+                state_storage,
+                compress,
+            )
+            .await
+        }
     }
 
     pub fn load_state_slot(
@@ -380,10 +1155,11 @@ impl Emulator {
     ) -> impl Future<Output = Result<Vec<u8>>> {
         let abbrev = self.core.core_info().abbrev.to_string();
         let game_name = self.game_name.clone();
-        let save_dir = config.save_dir.clone();
+        let state_dir = config.state_dir.clone();
+        let state_storage = config.state_storage;
 
         async move {
-            let data = load_state(&abbrev, &game_name, slot, &save_dir).await?;
+            let data = load_state(&abbrev, &game_name, slot, &state_dir, state_storage).await?;
             Ok(data)
         }
     }
@@ -391,13 +1167,67 @@ impl Emulator {
     pub fn load_state_data(&mut self, data: &[u8]) -> Result<()> {
         self.core.load_state(data)
     }
+
+    /// Checks that loading a state restores the core exactly,
+    /// by running the same frames before and after loading the state.
+    /// The state before the test is restored afterwards.
+    /// Cores index their controllers, so the input has every button of `key_config` released.
+    pub fn state_self_test(&mut self, key_config: &KeyConfig) -> Result<()> {
+        let state = self.core.save_state();
+        let input = key_config.input(&ReleasedInput);
+        let result = self.state_round_trip(&state, &input);
+        self.core.load_state(&state)?;
+        result
+    }
+
+    fn state_round_trip(&mut self, state: &[u8], input: &InputData) -> Result<()> {
+        let run = |core: &mut EmulatorEnum| {
+            core.set_input(input);
+            for _ in 0..SELF_TEST_FRAMES {
+                core.exec_frame(false);
+            }
+            crc32fast::hash(&core.save_state())
+        };
+
+        let expected = run(&mut self.core);
+
+        // States themselves may differ by clock data, so only where they lead is compared
+        self.core.load_state(state)?;
+        if run(&mut self.core) != expected {
+            bail!("Frames after loading a state differ");
+        }
+        Ok(())
+    }
 }
 
+/// Input with no button pressed
+struct ReleasedInput;
+
+impl meru_interface::InputState for ReleasedInput {
+    fn pressed(&self, _key: &meru_interface::SingleKey) -> bool {
+        false
+    }
+
+    fn just_pressed(&self, _key: &meru_interface::SingleKey) -> bool {
+        false
+    }
+}
+
+/// Frames run by the state self-test, short enough not to delay the start of the game
+const SELF_TEST_FRAMES: usize = 10;
+
 pub struct EmulatorPlugin;
 
 impl Plugin for EmulatorPlugin {
     fn build(&self, app: &mut App) {
-        app.init_resource::<InputData>()
+        app.world.resource_mut::<Assets<Shader>>().set_untracked(
+            SCREEN_SHADER_HANDLE,
+            Shader::from_wgsl(include_str!("../assets/shaders/screen.wgsl")),
+        );
+
+        app.add_plugin(Material2dPlugin::<ScreenMaterial>::default())
+            .init_resource::<InputData>()
+            .init_resource::<ScreenPan>()
             .add_system_set(
                 SystemSet::on_update(AppState::Running)
                     .with_system(emulator_input_system.label("input")),
@@ -413,12 +1243,102 @@ impl Plugin for EmulatorPlugin {
             )
             .add_system_set(
                 SystemSet::on_update(AppState::Running)
-                    .with_system(emulator_system)
-                    .after("input"),
+                    .with_system(emulator_system.label("emulator").after("input"))
+                    .with_system(core_message_system.after("emulator"))
+                    .with_system(screen_transform_system.after("emulator"))
+                    .with_system(frame_interpolation_system.after("emulator")),
             )
-            .add_system_set(
-                SystemSet::on_exit(AppState::Running).with_system(exit_emulator_system),
-            );
+            .add_system_set(SystemSet::on_exit(AppState::Running).with_system(exit_emulator_system))
+            .add_system(apply_game_settings_system.label("game_settings"))
+            .add_system(state_self_test_system.after("game_settings"));
+    }
+}
+
+/// Why resetting the game or loading a state is refused now, since it would desync
+/// the netplay peer or the movie. `None` if it is allowed.
+pub fn state_change_blocker(netplay: &Netplay, movies: &Movies) -> Option<&'static str> {
+    if netplay.is_playing() {
+        Some("during netplay")
+    } else if movies.is_active() {
+        Some("during movie recording or playback")
+    } else {
+        None
+    }
+}
+
+// Apply per-game settings whenever a new emulator instance is created (open, reload, etc.)
+// or another game is switched in from suspended sessions
+fn apply_game_settings_system(
+    emulator: Option<ResMut<Emulator>>,
+    library_db: Res<LibraryDb>,
+    persistent_state: Res<PersistentState>,
+    mut last_rom_path: Local<PathBuf>,
+) {
+    let mut emulator = if let Some(emulator) = emulator {
+        emulator
+    } else {
+        return;
+    };
+
+    if !emulator.is_added() && *last_rom_path == emulator.rom_path {
+        return;
+    }
+    *last_rom_path = emulator.rom_path.clone();
+
+    if let Some(region) = library_db.game(&emulator.rom_path).and_then(|r| r.region) {
+        if emulator.core.supports_region_override() {
+            emulator.core.set_region(Some(region));
+        }
+    }
+
+    let save_type = library_db
+        .game(&emulator.rom_path)
+        .and_then(|r| r.save_type.as_ref())
+        .and_then(|save_type| {
+            emulator
+                .core
+                .save_types()
+                .iter()
+                .position(|t| t == save_type)
+        });
+    if save_type.is_some() {
+        emulator.core.set_save_type(save_type);
+    }
+
+    let cheats = persistent_state.cheats(&emulator.game_hash);
+    emulator.set_cheats(cheats);
+}
+
+// Warn when states of the core do not replay deterministically,
+// which breaks rewinding, movies and netplay
+fn state_self_test_system(
+    config: Res<Config>,
+    emulator: Option<ResMut<Emulator>>,
+    mut message_event: EventWriter<ShowMessage>,
+    mut last_rom_path: Local<PathBuf>,
+) {
+    let mut emulator = if let Some(emulator) = emulator {
+        emulator
+    } else {
+        return;
+    };
+
+    if !emulator.is_added() && *last_rom_path == emulator.rom_path {
+        return;
+    }
+    *last_rom_path = emulator.rom_path.clone();
+
+    if !config.state_self_test {
+        return;
+    }
+
+    let key_config = config.key_config_or_default(emulator.core.core_info().abbrev);
+    if let Err(err) = emulator.state_self_test(&key_config) {
+        let core_name = emulator.core.core_info().system_name;
+        message_event.send(ShowMessage::error(format!(
+            "Save states of {core_name} are not deterministic: {err}. \
+             Rewind, movies and netplay may not work correctly"
+        )));
     }
 }
 
@@ -429,67 +1349,130 @@ pub fn emulator_input_system(
     input_gamepad_button: Res<Input<GamepadButton>>,
     input_gamepad_axis: Res<Axis<GamepadAxis>>,
     mut input: ResMut<InputData>,
+    ui_state: Res<UiState>,
 ) {
-    *input = config
+    config
         .key_config(emulator.core.core_info().abbrev)
-        .input(&InputState::new(
-            &input_keycode,
-            &input_gamepad_button,
-            &input_gamepad_axis,
-        ));
+        .update_input(
+            &InputState::new(&input_keycode, &input_gamepad_button, &input_gamepad_axis),
+            &mut input,
+        );
+
+    if ui_state.controllers_swapped {
+        swap_controllers(&mut input);
+    }
 }
 
-fn setup_audio(world: &mut World) {
-    let (stream, stream_handle) =
-        rodio::OutputStream::try_default().expect("No audio output device available");
+// Exchange the inputs of player 1 and player 2.
+// Buttons are matched by name, and by position when the other controller lacks the name
+// (e.g. the microphone on a Famicom second controller).
+fn swap_controllers(input: &mut InputData) {
+    if input.controllers.len() < 2 {
+        return;
+    }
+
+    let pick = |keys: &[(String, bool)], name: &str, ix: usize| {
+        keys.iter()
+            .find(|(key, _)| key == name)
+            .or_else(|| keys.get(ix))
+            .map_or(false, |(_, pressed)| *pressed)
+    };
+
+    // Only the pressed states are copied, not the key names
+    let (c0, rest) = input.controllers.split_at_mut(1);
+    let (c0, c1) = (&mut c0[0], &mut rest[0]);
+
+    let pressed0 = c0
+        .iter()
+        .enumerate()
+        .map(|(ix, (name, _))| pick(c1, name, ix))
+        .collect::<Vec<_>>();
+    for (ix, (name, pressed)) in c1.iter_mut().enumerate() {
+        *pressed = pick(c0, name, ix);
+    }
+    for ((_, pressed), new) in c0.iter_mut().zip(pressed0) {
+        *pressed = new;
+    }
+}
 
-    let sink = rodio::Sink::try_new(&stream_handle).expect("Failed to create audio sink");
+pub struct GameScreen(pub Handle<Image>);
 
-    world.insert_non_send_resource(stream);
-    world.insert_resource(stream_handle);
-    world.insert_resource(AudioSink::new(sink));
+/// Previous frame and the timing of frames, for blending frames
+/// on displays with a higher refresh rate than the game
+struct FrameInterpolation {
+    prev_screen: Handle<Image>,
+    last_frame: bevy::utils::Instant,
+    /// Time between the last two frames, in seconds
+    interval: f32,
 }
 
-struct AudioSink {
-    sink: rodio::Sink,
+// Longest frame interval to interpolate over, so that a stall shows the latest frame
+const MAX_INTERPOLATION_INTERVAL: f32 = 0.1;
+
+const SCREEN_SHADER_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 0x5587de58abb07b1e);
+
+/// Material of the game screen, which applies the shader of `ScreenFilter`
+#[derive(AsBindGroup, TypeUuid, Clone)]
+#[uuid = "5b1db3bd-e7be-4a00-a1be-89b32facdd74"]
+pub struct ScreenMaterial {
+    #[uniform(0)]
+    params: ScreenParams,
+    #[texture(1)]
+    #[sampler(2)]
+    texture: Handle<Image>,
+    #[texture(3)]
+    #[sampler(4)]
+    prev_texture: Handle<Image>,
 }
 
-impl AudioSink {
-    fn new(sink: rodio::Sink) -> Self {
-        Self { sink }
-    }
+#[derive(ShaderType, Clone, Copy)]
+struct ScreenParams {
+    filter: u32,
+    curvature: f32,
+    scanline: f32,
+    grid: f32,
+    /// Weight of the current frame over the previous one
+    blend: f32,
+}
 
-    fn append(&self, buffer: &AudioBuffer) {
-        let mut samples = Vec::with_capacity(buffer.samples.len() * buffer.channels as usize);
-        for sample in &buffer.samples {
-            samples.push(sample.left);
-            samples.push(sample.right);
+impl ScreenParams {
+    fn new(filter: ScreenFilter) -> Self {
+        Self {
+            filter: match filter {
+                ScreenFilter::Nearest | ScreenFilter::Bilinear | ScreenFilter::SharpBilinear => 0,
+                ScreenFilter::Scanlines => 1,
+                ScreenFilter::Crt => 2,
+                ScreenFilter::LcdGrid => 3,
+                ScreenFilter::Xbr => 4,
+            },
+            curvature: 0.08,
+            scanline: 0.35,
+            grid: 0.4,
+            blend: 1.0,
         }
-        self.sink.append(AudioSource {
-            sample_rate: buffer.sample_rate,
-            channels: buffer.channels,
-            data: samples,
-            cursor: 0,
-        });
     }
+}
 
-    fn len(&self) -> usize {
-        self.sink.len()
+impl Material2d for ScreenMaterial {
+    fn fragment_shader() -> ShaderRef {
+        SCREEN_SHADER_HANDLE.typed().into()
     }
 }
 
-pub struct GameScreen(pub Handle<Image>);
-
 fn setup_emulator_system(
     #[cfg(not(target_arch = "wasm32"))] mut windows: ResMut<Windows>,
     mut commands: Commands,
+    config: Res<Config>,
     emulator: Res<Emulator>,
     mut images: ResMut<Assets<Image>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ScreenMaterial>>,
     mut event: EventWriter<WindowControlEvent>,
 ) {
-    let width = emulator.core.frame_buffer().width.max(1) as u32;
-    let height = emulator.core.frame_buffer().height.max(1) as u32;
-    let img = Image::new(
+    let (width, height) = emulator.screen_size();
+    let (width, height) = (width.max(1) as u32, height.max(1) as u32);
+    let mut img = Image::new(
         Extent3d {
             width,
             height,
@@ -499,16 +1482,30 @@ fn setup_emulator_system(
         vec![0; (width * height * 4) as usize],
         TextureFormat::Rgba8UnormSrgb,
     );
+    let screen_filter = config.video_setting(&emulator.game_hash).screen_filter;
+    img.sampler_descriptor = screen_sampler(screen_filter);
 
+    let prev_texture = images.add(img.clone());
     let texture = images.add(img);
     commands
-        .spawn_bundle(SpriteBundle {
-            texture: texture.clone(),
+        .spawn_bundle(MaterialMesh2dBundle {
+            mesh: meshes.add(Mesh::from(shape::Quad::new(Vec2::ONE))).into(),
+            material: materials.add(ScreenMaterial {
+                params: ScreenParams::new(screen_filter),
+                texture: texture.clone(),
+                prev_texture: prev_texture.clone(),
+            }),
+            transform: Transform::from_scale(Vec3::new(width as f32, height as f32, 1.0)),
             ..Default::default()
         })
         .insert(ScreenSprite);
 
     commands.insert_resource(GameScreen(texture));
+    commands.insert_resource(FrameInterpolation {
+        prev_screen: prev_texture,
+        last_frame: bevy::utils::Instant::now(),
+        interval: 0.0,
+    });
 
     #[cfg(not(target_arch = "wasm32"))]
     {
@@ -555,41 +1552,25 @@ fn exit_emulator_system(
     commands.entity(screen_entity.single()).despawn();
 }
 
-struct AudioSource {
-    sample_rate: u32,
-    channels: u16,
-    data: Vec<i16>,
-    cursor: usize,
-}
-
-impl Iterator for AudioSource {
-    type Item = i16;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.cursor >= self.data.len() {
-            return None;
-        }
-        let sample = self.data[self.cursor];
-        self.cursor += 1;
-        Some(sample as i16)
-    }
-}
-
-impl rodio::Source for AudioSource {
-    fn current_frame_len(&self) -> Option<usize> {
-        None
-    }
-
-    fn channels(&self) -> u16 {
-        self.channels
-    }
-
-    fn sample_rate(&self) -> u32 {
-        self.sample_rate
+fn core_message_system(
+    mut emulator: ResMut<Emulator>,
+    mut message_event: EventWriter<ShowMessage>,
+) {
+    for msg in emulator.core.take_messages() {
+        message_event.send(ShowMessage::info(msg));
     }
 
-    fn total_duration(&self) -> Option<std::time::Duration> {
-        None
+    let active_controllers = emulator.core.active_controllers();
+    if active_controllers != emulator.active_controllers {
+        // Games start with one controller, which is not worth telling
+        let prev = std::mem::replace(&mut emulator.active_controllers, active_controllers);
+        if let Some(n) = active_controllers.filter(|&n| n > 1 || prev.is_some()) {
+            message_event.send(ShowMessage::info(if n > 1 {
+                format!("The game uses {n} controllers")
+            } else {
+                "The game uses 1 controller".to_string()
+            }));
+        }
     }
 }
 
@@ -597,90 +1578,341 @@ impl rodio::Source for AudioSource {
 fn emulator_system(
     mut commands: Commands,
     screen: Res<GameScreen>,
+    screen_sprite: Query<&Handle<ScreenMaterial>, With<ScreenSprite>>,
+    mut screen_filter: Local<Option<ScreenFilter>>,
+    mut flash_filter: Local<FlashFilter>,
     camera: Query<(Entity, &TiledCamera)>,
     config: Res<Config>,
     mut emulator: ResMut<Emulator>,
-    mut images: ResMut<Assets<Image>>,
+    (mut images, mut materials, mut interpolation): (
+        ResMut<Assets<Image>>,
+        ResMut<Assets<ScreenMaterial>>,
+        ResMut<FrameInterpolation>,
+    ),
     input: Res<InputData>,
-    audio_sink: Res<AudioSink>,
-    is_turbo: Res<hotkey::IsTurbo>,
+    mut audio_sink: ResMut<AudioSink>,
+    (is_turbo, is_rewinding): (Res<hotkey::IsTurbo>, Res<hotkey::IsRewinding>),
+    windows: Res<Windows>,
+    (ui_state, mut netplay, mut movies, audio_pack, texture_replacer): (
+        Res<UiState>,
+        ResMut<Netplay>,
+        ResMut<Movies>,
+        Res<AudioPackPlayer>,
+        Res<TextureReplacer>,
+    ),
+    message_send: Res<Sender<ShowMessage>>,
 ) {
-    let min_audio_frames = 4;
+    let min_audio_frames = config.audio.latency.max(1);
+
+    let focused = windows
+        .get_primary()
+        .map_or(true, |window| window.is_focused());
+    audio_sink.set_volume(if ui_state.in_tray {
+        0.0
+    } else {
+        config
+            .audio
+            .output_volume(emulator.core.core_info().abbrev, is_turbo.0, focused)
+            * audio_pack.core_gain()
+    });
+
+    if ui_state.paused || emulator.core_failed {
+        return;
+    }
+
+    let video = config.video_setting(&emulator.game_hash);
+    let scale = texture_replacer
+        .scale()
+        .unwrap_or_else(|| screen_prescale(&video));
 
     emulator.core.set_input(&*input);
 
-    if emulator.prev_backup_saved_frame + 60 * 60 <= emulator.frames {
+    emulator.refresh_game_info();
+
+    if emulator.backup_save_needed() {
         let fut = emulator.save_backup();
-        spawn_local(async move { fut.await.unwrap() });
+        let message_send = message_send.clone();
+        spawn_local(async move {
+            if let Err(err) = fut.await {
+                let msg = ShowMessage::save_failed("Saving backup RAM", &err);
+                message_send.send(msg).await.unwrap();
+            }
+        });
     }
 
-    if !is_turbo.0 {
-        if audio_sink.len() >= min_audio_frames + 4 {
-            // execution too fast. wait 1 frame.
-            return;
+    // Rate control absorbs small drift, so frames are only skipped or added on large ones
+    let (max_audio_frames, catch_up_frames) = if config.audio.dynamic_rate_control {
+        (min_audio_frames * 2 + 8, 1)
+    } else {
+        (min_audio_frames + 4, min_audio_frames)
+    };
+
+    if !is_turbo.0 && audio_sink.len() >= max_audio_frames {
+        // execution too fast. wait 1 frame.
+        return;
+    }
+
+    if video.frame_interpolation {
+        // Keep the frame on screen to blend from it to the next one
+        let prev = images.get(&screen.0).unwrap().clone();
+        images.set_untracked(&interpolation.prev_screen, prev);
+
+        let now = bevy::utils::Instant::now();
+        interpolation.interval = (now - interpolation.last_frame).as_secs_f32();
+        interpolation.last_frame = now;
+    }
+
+    // Cheats on one side would desync the peer, and movies do not record them
+    emulator.suspend_cheats(netplay.is_playing() || movies.is_active());
+    emulator.apply_cheats();
+
+    let mut core_time = std::time::Duration::ZERO;
+
+    if netplay.is_playing() {
+        // Frames advance in lockstep with the peer, without turbo or catching up audio
+        let start = bevy::utils::Instant::now();
+        let result = netplay.exec_frame(&mut emulator, &input, &mut audio_sink, &config);
+        core_time = start.elapsed();
+        match result {
+            Ok(true) => {}
+            Ok(false) => return,
+            Err(err) => {
+                let msg = ShowMessage::error(format!("Netplay: {err}"));
+                message_send.try_send(msg).unwrap();
+                return;
+            }
         }
+        emulator.frames += 1;
+        emulator.record_clip(1, &config);
+
+        let image = images.get_mut(&screen.0).unwrap();
+        copy_frame_buffer(
+            image,
+            emulator.core.frame_buffer(),
+            &emulator.overscan,
+            scale,
+        );
+    } else if movies.is_active() {
+        // Movies advance one frame per update, so that playback is deterministic
+        let input = if let Some(input) = movies.next_input(&input) {
+            input
+        } else {
+            let msg = ShowMessage::info("Movie playback finished");
+            message_send.try_send(msg).unwrap();
+            return;
+        };
 
-        let mut exec_frame = |audio_sink: &AudioSink, render_graphics| {
-            emulator.core.exec_frame(render_graphics);
-            emulator.frames += 1;
+        emulator.core.set_input(&input);
+        let start = bevy::utils::Instant::now();
+        emulator.core.exec_frame(true);
+        core_time = start.elapsed();
+        emulator.frames += 1;
+        audio_sink.append(emulator.core.audio_buffer(), &config.audio);
+        emulator.record_clip(1, &config);
 
-            // FIXME
-            let elapsed = emulator.frames as f64 / 60.0;
-            let need_more = emulator.total_auto_saved_size
-                < (elapsed * config.auto_state_save_rate as f64).floor() as usize;
-            let enough_span =
-                emulator.prev_auto_saved_frame + config.minimum_auto_save_span < emulator.frames;
+        let image = images.get_mut(&screen.0).unwrap();
+        copy_frame_buffer(
+            image,
+            emulator.core.frame_buffer(),
+            &emulator.overscan,
+            scale,
+        );
+    } else if is_rewinding.0 {
+        // Steps back one frame per update without sound. The frame after the state
+        // is run to show it, and the state is loaded again to resume from it.
+        let emulator = &mut *emulator;
+        let state = if let Some(state) = emulator.rewind_buffer.pop() {
+            state
+        } else {
+            return;
+        };
 
-            if need_more && enough_span {
-                let saved_state = AutoSavedState {
-                    data: emulator.core.save_state(),
-                    thumbnail: frame_buffer_to_image(emulator.core.frame_buffer()),
+        let start = bevy::utils::Instant::now();
+        let result = emulator.core.load_state(state).and_then(|_| {
+            emulator.core.exec_frame(true);
+            emulator.core.load_state(state)
+        });
+        core_time = start.elapsed();
+        if let Err(err) = result {
+            emulator.rewind_buffer.clear();
+            let msg = ShowMessage::error(format!("Failed to rewind: {err}"));
+            message_send.try_send(msg).unwrap();
+            return;
+        }
+
+        let image = images.get_mut(&screen.0).unwrap();
+        copy_frame_buffer(
+            image,
+            emulator.core.frame_buffer(),
+            &emulator.overscan,
+            scale,
+        );
+    } else {
+        // A core may panic after a config change, such as a different boot ROM.
+        // Catch it to offer reverting the change instead of crashing.
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            if !is_turbo.0 {
+                let mut exec_frame = |audio_sink: &mut AudioSink, render_graphics| {
+                    let start = bevy::utils::Instant::now();
+                    emulator.core.exec_frame(render_graphics);
+                    core_time += start.elapsed();
+                    emulator.frames += 1;
+
+                    // FIXME
+                    let elapsed = emulator.frames as f64 / 60.0;
+                    let need_more = emulator.total_auto_saved_size
+                        < (elapsed * config.auto_state_save_rate as f64).floor() as usize;
+                    let enough_span = emulator.prev_auto_saved_frame
+                        + config.minimum_auto_save_span
+                        < emulator.frames;
+
+                    if need_more && enough_span {
+                        let saved_state = AutoSavedState {
+                            data: compress_state(
+                                emulator.core.save_state(),
+                                config.compress_states,
+                            ),
+                            thumbnail: frame_buffer_to_image(
+                                emulator.core.frame_buffer(),
+                                &emulator.overscan,
+                            ),
+                            context: emulator.core.status(),
+                        };
+
+                        let state_size = saved_state.size();
+                        emulator.total_auto_saved_size += state_size;
+                        emulator.prev_auto_saved_frame = emulator.frames;
+
+                        emulator.auto_saved_states.push_back(saved_state);
+                        if emulator.auto_saved_states.len() * state_size
+                            > config.auto_state_save_limit
+                        {
+                            emulator.auto_saved_states.pop_front();
+                        }
+                    }
+                    audio_sink.append(emulator.core.audio_buffer(), &config.audio);
+                    emulator.push_rewind_state(&config);
+                    emulator.record_clip(1, &config);
                 };
 
-                let state_size = saved_state.size();
-                emulator.total_auto_saved_size += state_size;
-                emulator.prev_auto_saved_frame = emulator.frames;
+                exec_frame(&mut audio_sink, true);
 
-                emulator.auto_saved_states.push_back(saved_state);
-                if emulator.auto_saved_states.len() * state_size > config.auto_state_save_limit {
-                    emulator.auto_saved_states.pop_front();
+                // execution too slow. run frames for supply enough audio samples.
+                while audio_sink.len() < catch_up_frames {
+                    exec_frame(&mut audio_sink, false);
                 }
+
+                // Update texture
+                let image = images.get_mut(&screen.0).unwrap();
+                copy_frame_buffer(
+                    image,
+                    emulator.core.frame_buffer(),
+                    &emulator.overscan,
+                    scale,
+                );
+            } else {
+                let frame_skip = config.frame_skip_on_turbo(emulator.core.core_info().abbrev);
+                let budget = std::time::Duration::from_millis(config.turbo_time_budget as u64);
+                let start = bevy::utils::Instant::now();
+                let mut turbo_frames = 0;
+
+                for i in 0..frame_skip {
+                    // Keep the UI responsive on slow machines by limiting the time spent per update
+                    if i > 0 && start.elapsed() >= budget {
+                        break;
+                    }
+                    emulator.core.exec_frame(i == 0);
+                    turbo_frames += 1;
+                    if audio_sink.len() < min_audio_frames {
+                        match config.audio.turbo_audio {
+                            // Keep only one frame of audio per displayed frame
+                            TurboAudio::PitchPreserve => {
+                                if i == 0 {
+                                    audio_sink
+                                        .append_faded(emulator.core.audio_buffer(), &config.audio);
+                                }
+                            }
+                            _ => audio_sink.append(emulator.core.audio_buffer(), &config.audio),
+                        }
+                    }
+                }
+                // Update texture
+                let image = images.get_mut(&screen.0).unwrap();
+                copy_frame_buffer(
+                    image,
+                    emulator.core.frame_buffer(),
+                    &emulator.overscan,
+                    scale,
+                );
+
+                // Only the displayed frame is kept, so a rewind step skips back
+                // as many frames as turbo runs per update
+                emulator.push_rewind_state(&config);
+                emulator.record_clip(turbo_frames, &config);
+
+                emulator.turbo_frames = turbo_frames;
+                emulator.frames += 1;
+                core_time = start.elapsed();
             }
-            audio_sink.append(emulator.core.audio_buffer());
-        };
+        }));
 
-        exec_frame(audio_sink.as_ref(), true);
+        if let Err(panic) = result {
+            if !emulator.has_config_checkpoint() {
+                std::panic::resume_unwind(panic);
+            }
 
-        // execution too slow. run frames for supply enough audio samples.
-        while audio_sink.len() < min_audio_frames {
-            exec_frame(audio_sink.as_ref(), false);
+            emulator.core_failed = true;
+            let abbrev = emulator.core.core_info().abbrev.to_string();
+            let msg = ShowMessage::error("The core stopped after the config change")
+                .with_link(MenuTab::CoreSetting(abbrev));
+            message_send.try_send(msg).unwrap();
+            return;
         }
+    }
+
+    emulator.expire_config_checkpoint();
+    emulator.core_time = core_time;
+
+    if texture_replacer.scale().is_some() {
+        let image = images.get_mut(&screen.0).unwrap();
+        texture_replacer.draw_tiles(
+            image,
+            emulator.core.frame_buffer(),
+            &emulator.overscan,
+            emulator.core.tile_draws(),
+        );
+    }
 
-        // Update texture
-        let fb = emulator.core.frame_buffer();
+    if config.reduce_flashing {
         let image = images.get_mut(&screen.0).unwrap();
-        copy_frame_buffer(image, fb);
+        flash_filter.apply(&mut image.data);
     } else {
-        for i in 0..config.frame_skip_on_turbo {
-            emulator.core.exec_frame(i == 0);
-            if audio_sink.len() < min_audio_frames {
-                audio_sink.append(emulator.core.audio_buffer());
-            }
-        }
-        // Update texture
-        let fb = emulator.core.frame_buffer();
+        flash_filter.clear();
+    }
+
+    if *screen_filter != Some(video.screen_filter) {
         let image = images.get_mut(&screen.0).unwrap();
-        copy_frame_buffer(image, fb);
-        emulator.frames += 1;
+        image.sampler_descriptor = screen_sampler(video.screen_filter);
+        *screen_filter = Some(video.screen_filter);
     }
 
     {
         let camera = camera.single();
-        let image = images.get(&screen.0).unwrap();
-        let image_size = image.size();
-        let width = image_size[0] as u32;
-        let height = image_size[1] as u32;
+        let (width, height) = emulator.screen_size();
+        let (width, height) = (width.max(1) as u32, height.max(1) as u32);
+
+        for material in screen_sprite.iter() {
+            // Materials are not rebound when their texture is updated,
+            // so mark the material as modified to refer to the new frame
+            if let Some(material) = materials.get_mut(material) {
+                let params = ScreenParams::new(video.screen_filter);
+                if material.params.filter != params.filter {
+                    material.params = params;
+                }
+            }
+        }
 
+        let (width, height) = emulator.rotation.rotate_size((width, height));
         if (camera.1.tile_count.x, camera.1.tile_count.y) != (width, height) {
             commands.entity(camera.0).despawn();
             commands.spawn_bundle(
@@ -690,9 +1922,95 @@ fn emulator_system(
     }
 }
 
-fn frame_buffer_to_image(frame_buffer: &FrameBuffer) -> Image {
-    let width = frame_buffer.width;
-    let height = frame_buffer.height;
+fn frame_interpolation_system(
+    config: Res<Config>,
+    emulator: Res<Emulator>,
+    ui_state: Res<UiState>,
+    interpolation: Res<FrameInterpolation>,
+    screen_sprite: Query<&Handle<ScreenMaterial>, With<ScreenSprite>>,
+    mut materials: ResMut<Assets<ScreenMaterial>>,
+) {
+    let video = config.video_setting(&emulator.game_hash);
+
+    // Shows the previous frame just after a frame is ready, reaching it in one frame interval.
+    // This adds a frame of latency.
+    let interval = interpolation.interval;
+    let blend = if !video.frame_interpolation
+        || ui_state.paused
+        || interval <= 0.0
+        || interval > MAX_INTERPOLATION_INTERVAL
+    {
+        1.0
+    } else {
+        (interpolation.last_frame.elapsed().as_secs_f32() / interval).min(1.0)
+    };
+
+    for material in screen_sprite.iter() {
+        let needs_update = materials
+            .get(material)
+            .map_or(false, |material| material.params.blend != blend);
+        if needs_update {
+            materials.get_mut(material).unwrap().params.blend = blend;
+        }
+    }
+}
+
+/// Offset of the zoomed view from the center of the screen, in screen pixels
+#[derive(Default)]
+pub struct ScreenPan(pub Vec2);
+
+const PAN_DEAD_ZONE: f32 = 0.2;
+
+fn screen_transform_system(
+    config: Res<Config>,
+    emulator: Res<Emulator>,
+    time: Res<Time>,
+    gamepads: Res<Gamepads>,
+    gamepad_axis: Res<Axis<GamepadAxis>>,
+    mut pan: ResMut<ScreenPan>,
+    mut screen_sprite: Query<&mut Transform, With<ScreenSprite>>,
+) {
+    let (width, height) = emulator.screen_size();
+    let (width, height) = (width.max(1) as f32, height.max(1) as f32);
+    let zoom = config.zoom.clamp(MIN_ZOOM, MAX_ZOOM) as f32 / 100.0;
+
+    if zoom > 1.0 {
+        let mut stick = Vec2::ZERO;
+        for gamepad in gamepads.iter() {
+            let axis = |axis_type| {
+                let value = gamepad_axis
+                    .get(GamepadAxis::new(*gamepad, axis_type))
+                    .unwrap_or(0.0);
+                if value.abs() < PAN_DEAD_ZONE {
+                    0.0
+                } else {
+                    value
+                }
+            };
+            stick += Vec2::new(
+                axis(GamepadAxisType::RightStickX),
+                axis(GamepadAxisType::RightStickY),
+            );
+        }
+
+        // Crossing the whole screen takes about a second at full tilt
+        let (display_width, display_height) = emulator.display_size();
+        let display = Vec2::new(display_width as f32, display_height as f32);
+        let limit = display * (zoom - 1.0) / 2.0;
+        pan.0 = (pan.0 + stick * display * time.delta_seconds()).clamp(-limit, limit);
+    } else {
+        pan.0 = Vec2::ZERO;
+    }
+
+    for mut transform in screen_sprite.iter_mut() {
+        transform.scale = Vec3::new(width * zoom, height * zoom, 1.0);
+        transform.rotation = Quat::from_rotation_z(-emulator.rotation.radians());
+        transform.translation = (-pan.0).extend(transform.translation.z);
+    }
+}
+
+fn frame_buffer_to_image(frame_buffer: &FrameBuffer, overscan: &Overscan) -> Image {
+    let (width, height) = overscan.crop_size(frame_buffer.width, frame_buffer.height);
 
     let mut image = Image::new_fill(
         Extent3d {
@@ -704,17 +2022,86 @@ fn frame_buffer_to_image(frame_buffer: &FrameBuffer) -> Image {
         &[0, 0, 0, 0],
         TextureFormat::Rgba8UnormSrgb,
     );
-    copy_frame_buffer(&mut image, frame_buffer);
+    copy_frame_buffer(&mut image, frame_buffer, overscan, 1);
     image
 }
 
-fn copy_frame_buffer(image: &mut Image, frame_buffer: &FrameBuffer) {
+// Maximum change of average luminance per frame when reducing flashing
+const MAX_LUMA_DELTA: f32 = 0.05;
+
+#[derive(Default)]
+struct FlashFilter {
+    prev: Vec<u8>,
+}
+
+impl FlashFilter {
+    fn apply(&mut self, data: &mut [u8]) {
+        if self.prev.len() == data.len() {
+            let delta = (average_luma(data) - average_luma(&self.prev)).abs();
+            if delta > MAX_LUMA_DELTA {
+                let t = MAX_LUMA_DELTA / delta;
+                for (cur, prev) in data.iter_mut().zip(self.prev.iter()) {
+                    *cur = (*prev as f32 + (*cur as f32 - *prev as f32) * t).round() as u8;
+                }
+            }
+        }
+        self.prev.clear();
+        self.prev.extend_from_slice(data);
+    }
+
+    fn clear(&mut self) {
+        self.prev.clear();
+    }
+}
+
+fn average_luma(data: &[u8]) -> f32 {
+    let pixels = (data.len() / 4).max(1);
+    let sum = data
+        .chunks_exact(4)
+        .map(|p| 0.299 * p[0] as f32 + 0.587 * p[1] as f32 + 0.114 * p[2] as f32)
+        .sum::<f32>();
+    sum / pixels as f32 / 255.0
+}
+
+fn screen_sampler(filter: ScreenFilter) -> ImageSampler {
+    match filter {
+        ScreenFilter::Nearest
+        | ScreenFilter::Scanlines
+        | ScreenFilter::LcdGrid
+        | ScreenFilter::Xbr => ImageSampler::nearest(),
+        ScreenFilter::Bilinear | ScreenFilter::SharpBilinear | ScreenFilter::Crt => {
+            ImageSampler::linear()
+        }
+    }
+}
+
+// Sharp bilinear upscales the frame by an integer factor with nearest neighbor,
+// so the linear sampler only blurs the remaining fractional scaling.
+fn screen_prescale(video: &VideoSetting) -> usize {
+    match video.screen_filter {
+        ScreenFilter::SharpBilinear => video.scaling.max(1),
+        _ => 1,
+    }
+}
+
+/// Writes the visible area of the frame directly into the texture,
+/// so that no intermediate buffer is allocated per frame
+fn copy_frame_buffer(
+    image: &mut Image,
+    frame_buffer: &FrameBuffer,
+    overscan: &Overscan,
+    scale: usize,
+) {
+    profile_scope!("copy_frame_buffer");
+
     if frame_buffer.width == 0 || frame_buffer.height == 0 {
         return;
     }
 
-    let width = frame_buffer.width;
-    let height = frame_buffer.height;
+    let (left, top, src_width, src_height) =
+        overscan.crop_rect(frame_buffer.width, frame_buffer.height);
+    let width = src_width * scale;
+    let height = src_height * scale;
 
     let image_size = image.size();
     if (image_size[0] as usize, image_size[1] as usize) != (width, height) {
@@ -728,10 +2115,11 @@ fn copy_frame_buffer(image: &mut Image, frame_buffer: &FrameBuffer) {
     let data = &mut image.data;
 
     for y in 0..height {
+        let row = (top + y / scale) * frame_buffer.width + left;
         for x in 0..width {
             let ix = y * width + x;
             let pixel = &mut data[ix * 4..ix * 4 + 4];
-            let c = &frame_buffer.buffer[ix];
+            let c = &frame_buffer.buffer[row + x / scale];
             pixel[0] = c.r;
             pixel[1] = c.g;
             pixel[2] = c.b;
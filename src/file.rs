@@ -1,7 +1,18 @@
-use anyhow::{bail, Result};
+use anyhow::{anyhow, bail, Result};
 use chrono::prelude::*;
 use log::info;
-use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    io::{Cursor, Read, Write},
+    path::{Component, Path, PathBuf},
+};
+use zip::{write::FileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::{
+    config::StateStorage,
+    profile::{open_file, seal_file},
+};
 
 #[derive(thiserror::Error, Debug)]
 pub enum FileSystemError {
@@ -11,6 +22,8 @@ pub enum FileSystemError {
     PersistError(#[from] tempfile::PersistError),
     #[error("File not found")]
     FileNotFound,
+    #[error("Storage quota exceeded")]
+    QuotaExceeded,
 
     #[error("{0}")]
     SerdeError(#[from] serde_json::Error),
@@ -25,7 +38,7 @@ mod filesystem {
     use super::FileSystemError;
     use chrono::prelude::*;
     use std::fs;
-    use std::path::Path;
+    use std::path::{Path, PathBuf};
 
     pub fn create_dir_all(dir: impl AsRef<Path>) -> Result<(), FileSystemError> {
         fs::create_dir_all(dir)?;
@@ -52,9 +65,36 @@ mod filesystem {
         Ok(ret)
     }
 
+    pub async fn remove(path: impl AsRef<Path>) -> Result<(), FileSystemError> {
+        fs::remove_file(path)?;
+        Ok(())
+    }
+
     pub async fn modified(path: impl AsRef<Path>) -> Result<DateTime<Local>, FileSystemError> {
         Ok(fs::metadata(path)?.modified()?.into())
     }
+
+    /// Files directly under `dir` with their sizes
+    pub async fn list_files(dir: &Path) -> Result<Vec<(PathBuf, usize)>, FileSystemError> {
+        if !dir.is_dir() {
+            return Ok(vec![]);
+        }
+
+        let mut ret = vec![];
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            if metadata.is_file() {
+                ret.push((entry.path(), metadata.len() as usize));
+            }
+        }
+        Ok(ret)
+    }
+
+    /// Writes are atomic on native file systems, so there is nothing to repair
+    pub async fn repair() -> Result<(), FileSystemError> {
+        Ok(())
+    }
 }
 
 #[cfg(target_arch = "wasm32")]
@@ -65,15 +105,17 @@ mod filesystem {
     use js_sys::Uint8Array;
     use log::info;
     use serde::{Deserialize, Serialize};
-    use std::path::Path;
+    use std::collections::BTreeSet;
+    use std::path::{Path, PathBuf};
     use std::time::SystemTime;
     use wasm_bindgen::{prelude::*, JsCast};
     use web_sys::DomException;
 
+    const STORES: &[&str] = &["save", "config", "data"];
+
     async fn open_db() -> Result<IdbDatabase, DomException> {
         let mut db_req: OpenDbRequest = IdbDatabase::open_u32("meru", 1)?;
         db_req.set_on_upgrade_needed(Some(|evt: &IdbVersionChangeEvent| -> Result<(), JsValue> {
-            const STORES: &[&str] = &["save", "config", "data"];
             for store in STORES {
                 if let None = evt.db().object_store_names().find(|n| n == store) {
                     evt.db().create_object_store(store)?;
@@ -86,6 +128,14 @@ mod filesystem {
         Ok(db)
     }
 
+    fn dom_error(err: DomException) -> FileSystemError {
+        if err.name() == "QuotaExceededError" {
+            FileSystemError::QuotaExceeded
+        } else {
+            FileSystemError::DomException
+        }
+    }
+
     // parse path to (store name, file_name)
     fn parse_path(path: &Path) -> (String, String) {
         let mut it = path.iter();
@@ -142,22 +192,28 @@ mod filesystem {
             .object_store(&store_name)
             .map_err(|_| FileSystemError::DomException)?;
 
-        store
-            .put_key_val_owned(&file_name, &Uint8Array::from(data.as_ref()))
-            .map_err(|_| FileSystemError::DomException)?;
+        let metadata = JsValue::from_serde(&Metadata {
+            modified: Utc::now().into(),
+        })?;
 
-        store
-            .put_key_val_owned(
-                &format!("{file_name}.metadata"),
-                &JsValue::from_serde(&Metadata {
-                    modified: Utc::now().into(),
-                })?,
-            )
-            .map_err(|_| FileSystemError::DomException)?;
+        // Data and metadata are written in a single transaction.
+        // It is aborted when either put fails, so they never get out of sync.
+        let result = async {
+            store
+                .put_key_val_owned(&file_name, &Uint8Array::from(data.as_ref()))?
+                .await?;
+            store
+                .put_key_val_owned(&format!("{file_name}.metadata"), &metadata)?
+                .await
+        }
+        .await;
 
-        tx.await
-            .into_result()
-            .map_err(|_| FileSystemError::DomException)?;
+        if let Err(err) = result {
+            let _ = tx.abort();
+            Err(dom_error(err))?;
+        }
+
+        tx.await.into_result().map_err(dom_error)?;
 
         Ok(())
     }
@@ -199,6 +255,38 @@ mod filesystem {
         Ok(array.to_vec())
     }
 
+    pub async fn remove(path: impl AsRef<Path>) -> Result<(), FileSystemError> {
+        info!("fs: remove: {}", path.as_ref().display());
+
+        let (store_name, file_name) = parse_path(path.as_ref());
+
+        let db = open_db().await.map_err(|_| FileSystemError::DomException)?;
+
+        let tx: IdbTransaction = db
+            .transaction_on_one_with_mode(&store_name, IdbTransactionMode::Readwrite)
+            .map_err(|_| FileSystemError::DomException)?;
+        let store: IdbObjectStore = tx
+            .object_store(&store_name)
+            .map_err(|_| FileSystemError::DomException)?;
+
+        let result = async {
+            store.delete_owned(&file_name)?.await?;
+            store.delete_owned(&format!("{file_name}.metadata"))?.await
+        }
+        .await;
+
+        if result.is_err() {
+            let _ = tx.abort();
+            Err(FileSystemError::DomException)?;
+        }
+
+        tx.await
+            .into_result()
+            .map_err(|_| FileSystemError::DomException)?;
+
+        Ok(())
+    }
+
     pub async fn modified(
         path: impl AsRef<Path>,
     ) -> anyhow::Result<DateTime<Local>, FileSystemError> {
@@ -235,9 +323,280 @@ mod filesystem {
 
         Ok(metadata.modified.into())
     }
+
+    /// Files directly under `dir` with their sizes
+    pub async fn list_files(dir: &Path) -> Result<Vec<(PathBuf, usize)>, FileSystemError> {
+        let mut it = dir.iter();
+        let store_name = it.next().unwrap().to_str().unwrap().to_string();
+        let prefix = it.as_path().to_str().unwrap().to_string();
+
+        let db = open_db().await.map_err(|_| FileSystemError::DomException)?;
+
+        let tx: IdbTransaction = db
+            .transaction_on_one_with_mode(&store_name, IdbTransactionMode::Readonly)
+            .map_err(|_| FileSystemError::DomException)?;
+        let store: IdbObjectStore = tx
+            .object_store(&store_name)
+            .map_err(|_| FileSystemError::DomException)?;
+
+        let keys = store
+            .get_all_keys()
+            .map_err(|_| FileSystemError::DomException)?
+            .await
+            .map_err(|_| FileSystemError::DomException)?;
+
+        let mut ret = vec![];
+        for key in keys.iter().filter_map(|key| key.as_string()) {
+            let file_name = match key.strip_prefix(&format!("{prefix}/")) {
+                Some(file_name) if !file_name.contains('/') => file_name,
+                _ => continue,
+            };
+            if file_name.ends_with(".metadata") {
+                continue;
+            }
+
+            let size = store
+                .get_owned(key.as_str())
+                .map_err(|_| FileSystemError::DomException)?
+                .await
+                .map_err(|_| FileSystemError::DomException)?
+                .and_then(|value| value.dyn_into::<Uint8Array>().ok())
+                .map_or(0, |array| array.length() as usize);
+
+            ret.push((dir.join(file_name), size));
+        }
+
+        tx.await
+            .into_result()
+            .map_err(|_| FileSystemError::DomException)?;
+
+        Ok(ret)
+    }
+
+    /// Fixes entries left inconsistent by interrupted writes of older versions.
+    /// Metadata without data is removed, and data without metadata is stamped with the current time.
+    pub async fn repair() -> Result<(), FileSystemError> {
+        let db = open_db().await.map_err(|_| FileSystemError::DomException)?;
+
+        let metadata = JsValue::from_serde(&Metadata {
+            modified: Utc::now().into(),
+        })?;
+
+        for store_name in STORES {
+            let tx: IdbTransaction = db
+                .transaction_on_one_with_mode(store_name, IdbTransactionMode::Readwrite)
+                .map_err(|_| FileSystemError::DomException)?;
+            let store: IdbObjectStore = tx
+                .object_store(store_name)
+                .map_err(|_| FileSystemError::DomException)?;
+
+            let keys = store
+                .get_all_keys()
+                .map_err(|_| FileSystemError::DomException)?
+                .await
+                .map_err(|_| FileSystemError::DomException)?
+                .iter()
+                .filter_map(|key| key.as_string())
+                .collect::<BTreeSet<_>>();
+
+            for key in &keys {
+                if let Some(file_name) = key.strip_suffix(".metadata") {
+                    if !keys.contains(file_name) {
+                        info!("fs: repair: remove orphaned metadata: {store_name}/{file_name}");
+                        store
+                            .delete_owned(key)
+                            .map_err(|_| FileSystemError::DomException)?;
+                    }
+                } else if !keys.contains(&format!("{key}.metadata")) {
+                    info!("fs: repair: add missing metadata: {store_name}/{key}");
+                    store
+                        .put_key_val_owned(&format!("{key}.metadata"), &metadata)
+                        .map_err(|_| FileSystemError::DomException)?;
+                }
+            }
+
+            tx.await
+                .into_result()
+                .map_err(|_| FileSystemError::DomException)?;
+        }
+
+        Ok(())
+    }
+}
+
+pub use filesystem::{create_dir_all, exists, list_files, modified, remove, repair};
+
+/// Writes a file, encrypted if it is in the directory of an encrypted profile
+pub async fn write(path: impl AsRef<Path>, data: impl AsRef<[u8]>) -> Result<()> {
+    let path = path.as_ref();
+    filesystem::write(path, seal_file(path, data.as_ref())?).await?;
+    Ok(())
+}
+
+/// Writes a file unencrypted, for captures that are opened with other apps
+pub async fn write_plain(path: impl AsRef<Path>, data: impl AsRef<[u8]>) -> Result<()> {
+    filesystem::write(path, data).await?;
+    Ok(())
+}
+
+pub async fn read(path: impl AsRef<Path>) -> Result<Vec<u8>> {
+    let path = path.as_ref();
+    open_file(path, filesystem::read(path).await?)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn move_save_files(from: &Path, to: &Path, pred: impl Fn(&str) -> bool) -> Result<()> {
+    use std::fs;
+
+    if from == to || !from.is_dir() {
+        return Ok(());
+    }
+
+    for core_dir in fs::read_dir(from)?.flatten() {
+        if !core_dir.path().is_dir() {
+            continue;
+        }
+
+        let dest_dir = to.join(core_dir.file_name());
+
+        for file in fs::read_dir(core_dir.path())?.flatten() {
+            let file_name = file.file_name().to_string_lossy().to_string();
+            if !file.path().is_file() || !pred(&file_name) {
+                continue;
+            }
+
+            create_dir_all(&dest_dir)?;
+            let dest = dest_dir.join(&file_name);
+            if dest.exists() {
+                continue;
+            }
+
+            info!("Moving `{}` to `{}`", file.path().display(), dest.display());
+            if fs::rename(file.path(), &dest).is_err() {
+                fs::copy(file.path(), &dest)?;
+                fs::remove_file(file.path())?;
+            }
+        }
+
+        // Remove per-core directory if it becomes empty
+        if fs::read_dir(core_dir.path())?.next().is_none() {
+            fs::remove_dir(core_dir.path())?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn move_save_files(_from: &Path, _to: &Path, _pred: impl Fn(&str) -> bool) -> Result<()> {
+    Ok(())
+}
+
+pub fn is_backup_file(file_name: &str) -> bool {
+    file_name.ends_with(".sav") || file_name.contains(".sav.")
+}
+
+pub fn is_state_file(file_name: &str) -> bool {
+    file_name.ends_with(".state")
+        || file_name.ends_with(".state.txt")
+        || file_name.ends_with(".states.zip")
+        || file_name.ends_with(".sessions.json")
+        || file_name.ends_with(".movie")
+}
+
+/// Save data and states of a game
+pub struct GameStorage {
+    pub abbrev: String,
+    pub name: String,
+    pub backup_size: usize,
+    pub state_size: usize,
+    pub files: Vec<PathBuf>,
 }
 
-pub use filesystem::*;
+/// Game name of a file in a save directory
+fn game_name_of(file_name: &str) -> Option<&str> {
+    let strip_slot = |s: &str| s.rsplit_once('-').map(|(name, _)| name);
+
+    if let Some(name) = file_name.strip_suffix(".state.txt") {
+        strip_slot(name)
+    } else if let Some(name) = file_name.strip_suffix(".state") {
+        match name.rfind(".session-") {
+            Some(pos) => Some(&name[..pos]),
+            None => strip_slot(name),
+        }
+    } else if let Some(name) = file_name.strip_suffix(".states.zip") {
+        Some(name)
+    } else if let Some(name) = file_name.strip_suffix(".sessions.json") {
+        Some(name)
+    } else if let Some(name) = file_name.strip_suffix(".movie") {
+        name.rfind(".movie-").map(|pos| &name[..pos])
+    } else if let Some(name) = file_name.strip_suffix(".sav") {
+        Some(name)
+    } else {
+        file_name.rfind(".sav.").map(|pos| &file_name[..pos])
+    }
+}
+
+/// Storage used by each game, in the order of core and game name
+pub async fn storage_usage(
+    abbrevs: &[&str],
+    save_dir: &Path,
+    state_dir: &Path,
+) -> Result<Vec<GameStorage>> {
+    let mut games = BTreeMap::<(String, String), GameStorage>::new();
+
+    for abbrev in abbrevs {
+        let mut dirs = vec![save_dir.join(abbrev)];
+        if state_dir != save_dir {
+            dirs.push(state_dir.join(abbrev));
+        }
+
+        for dir in dirs {
+            for (path, size) in list_files(&dir).await? {
+                let file_name = path.file_name().unwrap().to_string_lossy().to_string();
+                let name = if let Some(name) = game_name_of(&file_name) {
+                    name.to_string()
+                } else {
+                    continue;
+                };
+
+                let game = games
+                    .entry((abbrev.to_string(), name.clone()))
+                    .or_insert_with(|| GameStorage {
+                        abbrev: abbrev.to_string(),
+                        name,
+                        backup_size: 0,
+                        state_size: 0,
+                        files: vec![],
+                    });
+
+                if is_backup_file(&file_name) {
+                    game.backup_size += size;
+                } else {
+                    game.state_size += size;
+                }
+                game.files.push(path);
+            }
+        }
+    }
+
+    Ok(games.into_values().collect())
+}
+
+pub async fn delete_files(files: &[PathBuf]) -> Result<()> {
+    for file in files {
+        info!("Deleting `{}`", file.display());
+        remove(file).await?;
+    }
+    Ok(())
+}
+
+pub fn is_quota_exceeded(err: &anyhow::Error) -> bool {
+    matches!(
+        err.downcast_ref::<FileSystemError>(),
+        Some(FileSystemError::QuotaExceeded)
+    )
+}
 
 pub async fn read_to_string(path: impl AsRef<Path>) -> Result<String> {
     info!("fs: read_to_string: {}", path.as_ref().display());
@@ -262,13 +621,59 @@ fn get_backup_file_path(core_abbrev: &str, name: &str, save_dir: &Path) -> Resul
     Ok(get_save_dir(core_abbrev, save_dir)?.join(format!("{name}.sav")))
 }
 
-pub fn get_state_file_path(
+fn get_backup_snapshot_path(
     core_abbrev: &str,
     name: &str,
-    slot: usize,
+    index: usize,
     save_dir: &Path,
 ) -> Result<PathBuf> {
-    Ok(get_save_dir(core_abbrev, save_dir)?.join(format!("{name}-{slot}.state")))
+    Ok(get_save_dir(core_abbrev, save_dir)?.join(format!("{name}.sav.{index}")))
+}
+
+fn get_state_file_path(
+    core_abbrev: &str,
+    name: &str,
+    slot: usize,
+    state_dir: &Path,
+) -> Result<PathBuf> {
+    Ok(get_save_dir(core_abbrev, state_dir)?.join(format!("{name}-{slot}.state")))
+}
+
+fn get_state_context_path(
+    core_abbrev: &str,
+    name: &str,
+    slot: usize,
+    state_dir: &Path,
+) -> Result<PathBuf> {
+    Ok(get_save_dir(core_abbrev, state_dir)?.join(format!("{name}-{slot}.state.txt")))
+}
+
+fn get_play_session_index_path(core_abbrev: &str, name: &str, state_dir: &Path) -> Result<PathBuf> {
+    Ok(get_save_dir(core_abbrev, state_dir)?.join(format!("{name}.sessions.json")))
+}
+
+fn get_play_session_state_path(
+    core_abbrev: &str,
+    name: &str,
+    start: &DateTime<Local>,
+    state_dir: &Path,
+) -> Result<PathBuf> {
+    let start = start.format("%Y%m%d%H%M%S");
+    Ok(get_save_dir(core_abbrev, state_dir)?.join(format!("{name}.session-{start}.state")))
+}
+
+fn get_movie_path(
+    core_abbrev: &str,
+    name: &str,
+    start: &DateTime<Local>,
+    state_dir: &Path,
+) -> Result<PathBuf> {
+    let start = start.format("%Y%m%d%H%M%S");
+    Ok(get_save_dir(core_abbrev, state_dir)?.join(format!("{name}.movie-{start}.movie")))
+}
+
+fn get_state_archive_path(core_abbrev: &str, name: &str, state_dir: &Path) -> Result<PathBuf> {
+    Ok(get_save_dir(core_abbrev, state_dir)?.join(format!("{name}.states.zip")))
 }
 
 pub async fn load_backup(
@@ -299,41 +704,518 @@ pub async fn save_backup(core_abbrev: &str, name: &str, ram: &[u8], save_dir: &P
     Ok(())
 }
 
-pub async fn save_state(
+pub async fn save_backup_snapshot(
     core_abbrev: &str,
     name: &str,
-    slot: usize,
-    data: &[u8],
+    index: usize,
+    ram: &[u8],
     save_dir: &Path,
 ) -> Result<()> {
-    write(
-        &get_state_file_path(core_abbrev, name, slot, save_dir)?,
-        data,
-    )
-    .await?;
+    let path = get_backup_snapshot_path(core_abbrev, name, index, save_dir)?;
+    info!("Saving backup RAM snapshot: `{}`", path.display());
+    write(&path, ram).await?;
     Ok(())
 }
 
-pub async fn load_state(
+pub async fn restore_backup_snapshot(
     core_abbrev: &str,
     name: &str,
-    slot: usize,
+    index: usize,
     save_dir: &Path,
-) -> Result<Vec<u8>> {
-    let ret = read(get_state_file_path(core_abbrev, name, slot, save_dir)?).await?;
-    Ok(ret)
+) -> Result<()> {
+    let path = get_backup_snapshot_path(core_abbrev, name, index, save_dir)?;
+    info!("Restoring backup RAM snapshot: `{}`", path.display());
+    let ram = read(&path).await?;
+    write(get_backup_file_path(core_abbrev, name, save_dir)?, ram).await?;
+    Ok(())
 }
 
-pub async fn state_date(
+pub async fn backup_snapshot_date(
     core_abbrev: &str,
     name: &str,
-    slot: usize,
+    index: usize,
     save_dir: &Path,
 ) -> Result<Option<DateTime<Local>>> {
-    let path = get_state_file_path(core_abbrev, name, slot, save_dir)?;
+    let path = get_backup_snapshot_path(core_abbrev, name, index, save_dir)?;
     if let Ok(date) = modified(&path).await {
         Ok(Some(date))
     } else {
         Ok(None)
     }
 }
+
+pub async fn delete_backup(core_abbrev: &str, name: &str, save_dir: &Path) -> Result<()> {
+    let path = get_backup_file_path(core_abbrev, name, save_dir)?;
+
+    if exists(&path).await? {
+        info!("Deleting backup RAM file: `{}`", path.display());
+        remove(&path).await?;
+    }
+    Ok(())
+}
+
+/// Magic number at the start of zstd frames, which tells compressed states from raw ones
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+#[cfg(not(target_arch = "wasm32"))]
+const STATE_COMPRESSION_LEVEL: i32 = 3;
+
+/// Compresses `data` with zstd when `compress` is set and it is supported on the platform
+pub fn compress_state(data: Vec<u8>, compress: bool) -> Vec<u8> {
+    if !compress || cfg!(target_arch = "wasm32") {
+        return data;
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    match zstd::bulk::compress(&data, STATE_COMPRESSION_LEVEL) {
+        Ok(compressed) => return compressed,
+        Err(err) => log::warn!("Failed to compress state: {err}"),
+    }
+    data
+}
+
+/// Decompresses a state compressed by `compress_state`. Raw states are returned as is.
+pub fn decompress_state(data: &[u8]) -> Result<Vec<u8>> {
+    if !data.starts_with(&ZSTD_MAGIC) {
+        return Ok(data.to_vec());
+    }
+
+    cfg_if::cfg_if! {
+        if #[cfg(not(target_arch = "wasm32"))] {
+            Ok(zstd::stream::decode_all(data)?)
+        } else {
+            bail!("Compressed states are not supported on this platform")
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn save_state(
+    core_abbrev: &str,
+    name: &str,
+    slot: usize,
+    data: &[u8],
+    context: Option<&str>,
+    state_dir: &Path,
+    storage: StateStorage,
+    compress: bool,
+) -> Result<()> {
+    let data = &compress_state(data.to_vec(), compress);
+    let state_path = get_state_file_path(core_abbrev, name, slot, state_dir)?;
+    let context_path = get_state_context_path(core_abbrev, name, slot, state_dir)?;
+    let archive_path = get_state_archive_path(core_abbrev, name, state_dir)?;
+
+    match storage {
+        StateStorage::Files => {
+            write(&state_path, data).await?;
+            if let Some(context) = context {
+                write(&context_path, context).await?;
+            } else if exists(&context_path).await? {
+                remove(&context_path).await?;
+            }
+
+            // Drop the stale copy in the archive, if any
+            if exists(&archive_path).await? {
+                let (state_entry, context_entry) = state_entry_names(slot);
+                update_state_archive(&archive_path, &[(state_entry, None), (context_entry, None)])
+                    .await?;
+            }
+        }
+        StateStorage::Archive => {
+            let (state_entry, context_entry) = state_entry_names(slot);
+            update_state_archive(
+                &archive_path,
+                &[
+                    (state_entry, Some(data)),
+                    (context_entry, context.map(|s| s.as_bytes())),
+                ],
+            )
+            .await?;
+
+            // Drop the stale loose files, if any
+            for path in [&state_path, &context_path] {
+                if exists(path).await? {
+                    remove(path).await?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+pub async fn load_state(
+    core_abbrev: &str,
+    name: &str,
+    slot: usize,
+    state_dir: &Path,
+    storage: StateStorage,
+) -> Result<Vec<u8>> {
+    let (state_entry, _) = state_entry_names(slot);
+    let state_path = get_state_file_path(core_abbrev, name, slot, state_dir)?;
+    let archive_path = get_state_archive_path(core_abbrev, name, state_dir)?;
+
+    if storage == StateStorage::Files && exists(&state_path).await? {
+        return decompress_state(&read(&state_path).await?);
+    }
+    if let Some(mut archive) = read_state_archive(&archive_path).await? {
+        if let Some(data) = read_archive_entry(&mut archive, &state_entry) {
+            return decompress_state(&data);
+        }
+    }
+    decompress_state(&read(&state_path).await?)
+}
+
+/// Dates and contexts of the states in slots `0..slots`.
+/// The state archive is read only once for all slots.
+pub async fn load_state_infos(
+    core_abbrev: &str,
+    name: &str,
+    slots: usize,
+    state_dir: &Path,
+    storage: StateStorage,
+) -> Result<Vec<Option<(DateTime<Local>, Option<String>)>>> {
+    let archive_path = get_state_archive_path(core_abbrev, name, state_dir)?;
+    let mut archive = read_state_archive(&archive_path).await?;
+
+    let mut ret = vec![];
+    for slot in 0..slots {
+        let (state_entry, context_entry) = state_entry_names(slot);
+        let state_path = get_state_file_path(core_abbrev, name, slot, state_dir)?;
+        let context_path = get_state_context_path(core_abbrev, name, slot, state_dir)?;
+
+        // States saved with the other storage are still visible until overwritten
+        let file_date = modified(&state_path).await.ok();
+        let archive_date = archive
+            .as_mut()
+            .and_then(|archive| archive_entry_date(archive, &state_entry));
+        let date = match storage {
+            StateStorage::Files => file_date.or(archive_date),
+            StateStorage::Archive => archive_date.or(file_date),
+        };
+        let date = match date {
+            Some(date) => date,
+            None => {
+                ret.push(None);
+                continue;
+            }
+        };
+
+        let archive_context = archive
+            .as_mut()
+            .and_then(|archive| read_archive_entry(archive, &context_entry))
+            .and_then(|data| String::from_utf8(data).ok());
+        let context = match storage {
+            StateStorage::Files => match read_to_string(&context_path).await.ok() {
+                Some(context) => Some(context),
+                None => archive_context,
+            },
+            StateStorage::Archive => match archive_context {
+                Some(context) => Some(context),
+                None => read_to_string(&context_path).await.ok(),
+            },
+        };
+        ret.push(Some((date, context)));
+    }
+    Ok(ret)
+}
+
+fn archive_entry_date(
+    archive: &mut ZipArchive<Cursor<Vec<u8>>>,
+    name: &str,
+) -> Option<DateTime<Local>> {
+    let date = archive.by_name(name).ok()?.last_modified();
+    let date = NaiveDate::from_ymd_opt(date.year() as i32, date.month() as u32, date.day() as u32)?
+        .and_hms_opt(
+            date.hour() as u32,
+            date.minute() as u32,
+            date.second() as u32,
+        )?;
+    Local.from_local_datetime(&date).earliest()
+}
+
+fn state_entry_names(slot: usize) -> (String, String) {
+    (format!("{slot}.state"), format!("{slot}.state.txt"))
+}
+
+async fn read_state_archive(path: &Path) -> Result<Option<ZipArchive<Cursor<Vec<u8>>>>> {
+    if !exists(path).await? {
+        return Ok(None);
+    }
+    let data = read(path).await?;
+    Ok(Some(ZipArchive::new(Cursor::new(data))?))
+}
+
+fn read_archive_entry(archive: &mut ZipArchive<Cursor<Vec<u8>>>, name: &str) -> Option<Vec<u8>> {
+    let mut file = archive.by_name(name).ok()?;
+    let mut data = vec![];
+    file.read_to_end(&mut data).ok()?;
+    Some(data)
+}
+
+/// Rewrites the state archive, replacing the given entries.
+/// Entries with `None` are removed from the archive.
+async fn update_state_archive(path: &Path, entries: &[(String, Option<&[u8]>)]) -> Result<()> {
+    let mut writer = ZipWriter::new(Cursor::new(vec![]));
+
+    if let Some(mut archive) = read_state_archive(path).await? {
+        for i in 0..archive.len() {
+            let file = archive.by_index_raw(i)?;
+            if entries.iter().any(|(name, _)| name == file.name()) {
+                continue;
+            }
+            writer.raw_copy_file(file)?;
+        }
+    }
+
+    let now = Local::now();
+    let last_modified = zip::DateTime::from_date_and_time(
+        now.year() as u16,
+        now.month() as u8,
+        now.day() as u8,
+        now.hour() as u8,
+        now.minute() as u8,
+        now.second() as u8,
+    )
+    .unwrap_or_default();
+    let options = FileOptions::default()
+        .compression_method(CompressionMethod::Deflated)
+        .last_modified_time(last_modified);
+
+    for (name, data) in entries {
+        if let Some(data) = data {
+            writer.start_file(name, options)?;
+            writer.write_all(data)?;
+        }
+    }
+
+    let data = writer.finish()?.into_inner();
+    info!("Writing state archive: `{}`", path.display());
+    write(path, data).await?;
+    Ok(())
+}
+
+/// A play session of a game, with the state at its end
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PlaySession {
+    pub start: DateTime<Local>,
+    pub end: DateTime<Local>,
+}
+
+pub async fn load_play_sessions(
+    core_abbrev: &str,
+    name: &str,
+    state_dir: &Path,
+) -> Result<Vec<PlaySession>> {
+    let path = get_play_session_index_path(core_abbrev, name, state_dir)?;
+    Ok(if exists(&path).await? {
+        serde_json::from_str(&read_to_string(&path).await?)?
+    } else {
+        vec![]
+    })
+}
+
+/// Records a play session and its end state, keeping the latest `max_sessions` ones
+pub async fn save_play_session(
+    core_abbrev: &str,
+    name: &str,
+    session: PlaySession,
+    data: &[u8],
+    max_sessions: usize,
+    state_dir: &Path,
+) -> Result<()> {
+    let path = get_play_session_state_path(core_abbrev, name, &session.start, state_dir)?;
+    info!("Saving play session state: `{}`", path.display());
+    write(&path, data).await?;
+
+    let mut sessions = load_play_sessions(core_abbrev, name, state_dir).await?;
+    sessions.push(session);
+    while sessions.len() > max_sessions {
+        let old = sessions.remove(0);
+        let path = get_play_session_state_path(core_abbrev, name, &old.start, state_dir)?;
+        if exists(&path).await? {
+            remove(&path).await?;
+        }
+    }
+
+    let index_path = get_play_session_index_path(core_abbrev, name, state_dir)?;
+    write(&index_path, serde_json::to_string_pretty(&sessions)?).await?;
+    Ok(())
+}
+
+pub async fn load_play_session_state(
+    core_abbrev: &str,
+    name: &str,
+    start: &DateTime<Local>,
+    state_dir: &Path,
+) -> Result<Vec<u8>> {
+    let path = get_play_session_state_path(core_abbrev, name, start, state_dir)?;
+    Ok(read(&path).await?)
+}
+
+pub async fn save_movie(
+    core_abbrev: &str,
+    name: &str,
+    start: &DateTime<Local>,
+    data: &[u8],
+    state_dir: &Path,
+) -> Result<()> {
+    let path = get_movie_path(core_abbrev, name, start, state_dir)?;
+    info!("Saving movie: `{}`", path.display());
+    write(&path, data).await?;
+    Ok(())
+}
+
+pub async fn load_movie(
+    core_abbrev: &str,
+    name: &str,
+    start: &DateTime<Local>,
+    state_dir: &Path,
+) -> Result<Vec<u8>> {
+    let path = get_movie_path(core_abbrev, name, start, state_dir)?;
+    Ok(read(&path).await?)
+}
+
+pub async fn delete_movie(
+    core_abbrev: &str,
+    name: &str,
+    start: &DateTime<Local>,
+    state_dir: &Path,
+) -> Result<()> {
+    let path = get_movie_path(core_abbrev, name, start, state_dir)?;
+    info!("Deleting movie: `{}`", path.display());
+    remove(&path).await?;
+    Ok(())
+}
+
+/// Start times of the recorded movies of a game, newest first
+pub async fn list_movies(
+    core_abbrev: &str,
+    name: &str,
+    state_dir: &Path,
+) -> Result<Vec<DateTime<Local>>> {
+    let prefix = format!("{name}.movie-");
+    let mut ret = vec![];
+
+    for (path, _) in list_files(&get_save_dir(core_abbrev, state_dir)?).await? {
+        let file_name = path.file_name().unwrap().to_string_lossy().to_string();
+        let start = file_name
+            .strip_prefix(&prefix)
+            .and_then(|s| s.strip_suffix(".movie"))
+            .and_then(|s| NaiveDateTime::parse_from_str(s, "%Y%m%d%H%M%S").ok())
+            .and_then(|start| Local.from_local_datetime(&start).single());
+        if let Some(start) = start {
+            ret.push(start);
+        }
+    }
+
+    ret.sort_unstable_by(|a, b| b.cmp(a));
+    Ok(ret)
+}
+
+/// Directories bundled by `export_all`, with their names in the archive
+fn data_dirs<'a>(
+    config_dir: &'a Path,
+    save_dir: &'a Path,
+    state_dir: &'a Path,
+) -> Vec<(&'static str, &'a Path)> {
+    let mut ret = vec![("config", config_dir), ("save", save_dir)];
+    if state_dir != save_dir {
+        ret.push(("state", state_dir));
+    }
+    ret
+}
+
+/// Bundles config, save data and states of all cores into a zip archive
+pub async fn export_all(
+    abbrevs: &[&str],
+    config_dir: &Path,
+    save_dir: &Path,
+    state_dir: &Path,
+) -> Result<Vec<u8>> {
+    let mut writer = ZipWriter::new(Cursor::new(vec![]));
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    for (prefix, dir) in data_dirs(config_dir, save_dir, state_dir) {
+        let subdirs = if prefix == "config" {
+            vec![(prefix.to_string(), dir.to_owned())]
+        } else {
+            abbrevs
+                .iter()
+                .map(|abbrev| (format!("{prefix}/{abbrev}"), dir.join(abbrev)))
+                .collect()
+        };
+
+        for (name, dir) in subdirs {
+            for (path, _) in list_files(&dir).await? {
+                let file_name = path.file_name().unwrap().to_string_lossy();
+                writer.start_file(format!("{name}/{file_name}"), options)?;
+                writer.write_all(&read(&path).await?)?;
+            }
+        }
+    }
+
+    Ok(writer.finish()?.into_inner())
+}
+
+/// Restores files from an archive made by `export_all`, overwriting existing ones.
+/// Returns the number of restored files.
+pub async fn import_all(
+    data: &[u8],
+    config_dir: &Path,
+    save_dir: &Path,
+    state_dir: &Path,
+) -> Result<usize> {
+    let mut archive = ZipArchive::new(Cursor::new(data))?;
+
+    // States exported from a separate state directory are restored to `state_dir`
+    let mut dirs = data_dirs(config_dir, save_dir, state_dir);
+    dirs.push(("state", state_dir));
+
+    let mut files = vec![];
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        if file.is_dir() {
+            continue;
+        }
+
+        // Every component has to be a plain file name, also when a Windows path
+        // is read on other platforms
+        let name = file.name().to_string();
+        let components = file
+            .enclosed_name()
+            .and_then(|path| {
+                path.components()
+                    .map(|c| match c {
+                        Component::Normal(c) => c.to_str().filter(|c| !c.contains(['\\', ':'])),
+                        _ => None,
+                    })
+                    .collect::<Option<Vec<_>>>()
+            })
+            .ok_or_else(|| anyhow!("Invalid entry in archive: `{name}`"))?;
+        let path = match components.as_slice() {
+            ["config", file_name] => config_dir.join(file_name),
+            [prefix, abbrev, file_name] if *prefix != "config" => {
+                match dirs.iter().find(|(p, _)| p == prefix) {
+                    Some((_, dir)) => dir.join(abbrev).join(file_name),
+                    None => bail!("Unexpected entry in archive: `{name}`"),
+                }
+            }
+            _ => bail!("Unexpected entry in archive: `{name}`"),
+        };
+
+        let mut buf = vec![];
+        file.read_to_end(&mut buf)?;
+        files.push((path, buf));
+    }
+
+    // Nothing is written unless the whole archive is valid
+    for (path, data) in &files {
+        if let Some(dir) = path.parent() {
+            create_dir_all(dir)?;
+        }
+        info!("Importing `{}`", path.display());
+        write(path, data).await?;
+    }
+
+    Ok(files.len())
+}
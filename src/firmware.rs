@@ -0,0 +1,88 @@
+use anyhow::Result;
+use meru_interface::FirmwareInfo;
+use std::path::{Path, PathBuf};
+
+use crate::{
+    core::EMULATOR_CORES,
+    file::{create_dir_all, exists, read, write},
+};
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum FirmwareStatus {
+    Missing,
+    Verified,
+    UnknownHash,
+}
+
+impl std::fmt::Display for FirmwareStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            FirmwareStatus::Missing => "✖ Missing",
+            FirmwareStatus::Verified => "✔ Verified",
+            FirmwareStatus::UnknownHash => "⚠ Unknown hash",
+        };
+        write!(f, "{s}")
+    }
+}
+
+pub struct FirmwareEntry {
+    pub abbrev: &'static str,
+    pub system_name: &'static str,
+    pub info: &'static FirmwareInfo,
+    pub status: FirmwareStatus,
+}
+
+pub fn firmware_path(abbrev: &str, info: &FirmwareInfo, firmware_dir: &Path) -> PathBuf {
+    firmware_dir.join(abbrev).join(info.file_name)
+}
+
+pub fn verify_firmware(info: &FirmwareInfo, data: &[u8]) -> bool {
+    info.known_crc32.contains(&crc32fast::hash(data))
+}
+
+async fn firmware_status(
+    abbrev: &str,
+    info: &FirmwareInfo,
+    firmware_dir: &Path,
+) -> Result<FirmwareStatus> {
+    let path = firmware_path(abbrev, info, firmware_dir);
+    if !exists(&path).await? {
+        return Ok(FirmwareStatus::Missing);
+    }
+
+    let data = read(&path).await?;
+    Ok(if verify_firmware(info, &data) {
+        FirmwareStatus::Verified
+    } else {
+        FirmwareStatus::UnknownHash
+    })
+}
+
+pub async fn check_firmwares(firmware_dir: &Path) -> Result<Vec<FirmwareEntry>> {
+    let mut ret = vec![];
+    for core in EMULATOR_CORES.iter() {
+        let core_info = core.core_info();
+        for info in core.firmware_info() {
+            let status = firmware_status(core_info.abbrev, info, firmware_dir).await?;
+            ret.push(FirmwareEntry {
+                abbrev: core_info.abbrev,
+                system_name: core_info.system_name,
+                info,
+                status,
+            });
+        }
+    }
+    Ok(ret)
+}
+
+pub async fn install_firmware(
+    abbrev: &str,
+    info: &FirmwareInfo,
+    data: &[u8],
+    firmware_dir: &Path,
+) -> Result<PathBuf> {
+    let path = firmware_path(abbrev, info, firmware_dir);
+    create_dir_all(path.parent().unwrap())?;
+    write(&path, data).await?;
+    Ok(path)
+}
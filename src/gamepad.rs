@@ -0,0 +1,200 @@
+use bevy::prelude::*;
+use gilrs::Gilrs;
+use log::{info, warn};
+use meru_interface::{GamepadButton, GamepadButtonType, MultiKey, SingleKey};
+use std::collections::BTreeMap;
+
+use crate::config::{ButtonLabelStyle, Config};
+
+pub struct GamepadPlugin;
+
+impl Plugin for GamepadPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GamepadLayouts>()
+            .add_system(gamepad_layout_system);
+    }
+}
+
+/// Loads the SDL_GameControllerDB mapping file specified in the config.
+/// This must be called before gilrs is initialized, since gilrs reads mappings
+/// from `SDL_GAMECONTROLLERCONFIG` only on startup.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn load_controller_db(config: &Config) {
+    const ENV_NAME: &str = "SDL_GAMECONTROLLERCONFIG";
+
+    let path = if let Some(path) = &config.controller_db {
+        path
+    } else {
+        return;
+    };
+
+    match std::fs::read_to_string(path) {
+        Ok(mappings) => {
+            // Keep mappings given by the user through the environment variable
+            let mappings = match std::env::var(ENV_NAME) {
+                Ok(env_mappings) => format!("{mappings}\n{env_mappings}"),
+                Err(_) => mappings,
+            };
+            std::env::set_var(ENV_NAME, mappings);
+            info!("Loaded controller mappings: {}", path.display());
+        }
+        Err(err) => warn!(
+            "Failed to load controller mappings `{}`: {err}",
+            path.display()
+        ),
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn load_controller_db(_config: &Config) {}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum ButtonLayout {
+    #[default]
+    Xbox,
+    PlayStation,
+    Nintendo,
+}
+
+impl ButtonLayout {
+    fn detect(name: &str) -> Self {
+        let name = name.to_lowercase();
+        if [
+            "playstation",
+            "dualshock",
+            "dualsense",
+            "sony",
+            "ps3",
+            "ps4",
+            "ps5",
+        ]
+        .iter()
+        .any(|s| name.contains(s))
+        {
+            ButtonLayout::PlayStation
+        } else if ["nintendo", "switch", "pro controller", "joy-con", "8bitdo"]
+            .iter()
+            .any(|s| name.contains(s))
+        {
+            ButtonLayout::Nintendo
+        } else {
+            ButtonLayout::Xbox
+        }
+    }
+
+    pub fn button_label(&self, button_type: GamepadButtonType) -> &'static str {
+        use ButtonLayout::*;
+        use GamepadButtonType::*;
+
+        match (self, button_type) {
+            (Xbox, South) => "A",
+            (Xbox, East) => "B",
+            (Xbox, North) => "Y",
+            (Xbox, West) => "X",
+            (Xbox, LeftTrigger) => "LB",
+            (Xbox, LeftTrigger2) => "LT",
+            (Xbox, RightTrigger) => "RB",
+            (Xbox, RightTrigger2) => "RT",
+            (Xbox, Select) => "View",
+            (Xbox, Start) => "Menu",
+            (Xbox, Mode) => "Guide",
+
+            (PlayStation, South) => "✕",
+            (PlayStation, East) => "○",
+            (PlayStation, North) => "△",
+            (PlayStation, West) => "□",
+            (PlayStation, LeftTrigger) => "L1",
+            (PlayStation, LeftTrigger2) => "L2",
+            (PlayStation, RightTrigger) => "R1",
+            (PlayStation, RightTrigger2) => "R2",
+            (PlayStation, Select) => "Share",
+            (PlayStation, Start) => "Options",
+            (PlayStation, Mode) => "PS",
+            (PlayStation, LeftThumb) => "L3",
+            (PlayStation, RightThumb) => "R3",
+
+            (Nintendo, South) => "B",
+            (Nintendo, East) => "A",
+            (Nintendo, North) => "X",
+            (Nintendo, West) => "Y",
+            (Nintendo, LeftTrigger) => "L",
+            (Nintendo, LeftTrigger2) => "ZL",
+            (Nintendo, RightTrigger) => "R",
+            (Nintendo, RightTrigger2) => "ZR",
+            (Nintendo, Select) => "-",
+            (Nintendo, Start) => "+",
+            (Nintendo, Mode) => "Home",
+
+            (_, C) => "C",
+            (_, Z) => "Z",
+            (_, LeftThumb) => "LS",
+            (_, RightThumb) => "RS",
+            (_, DPadUp) => "↑",
+            (_, DPadDown) => "↓",
+            (_, DPadLeft) => "←",
+            (_, DPadRight) => "→",
+        }
+    }
+}
+
+/// Button layout of each connected gamepad, detected from its name
+#[derive(Default)]
+pub struct GamepadLayouts {
+    detected: BTreeMap<usize, ButtonLayout>,
+    style: ButtonLabelStyle,
+}
+
+impl GamepadLayouts {
+    pub fn layout(&self, gamepad_id: usize) -> ButtonLayout {
+        match self.style {
+            ButtonLabelStyle::Auto => self.detected.get(&gamepad_id).copied().unwrap_or_default(),
+            ButtonLabelStyle::Xbox => ButtonLayout::Xbox,
+            ButtonLabelStyle::Nintendo => ButtonLayout::Nintendo,
+            ButtonLabelStyle::PlayStation => ButtonLayout::PlayStation,
+        }
+    }
+
+    pub fn button_label(&self, button: &GamepadButton) -> String {
+        let label = self
+            .layout(button.gamepad.id)
+            .button_label(button.button_type);
+        format!("Pad{}.{label}", button.gamepad.id)
+    }
+
+    pub fn multi_key_label(&self, multi_key: &MultiKey) -> String {
+        multi_key
+            .0
+            .iter()
+            .map(|single_key| match single_key {
+                SingleKey::GamepadButton(button) => self.button_label(button),
+                _ => single_key.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("+")
+    }
+}
+
+fn gamepad_layout_system(
+    config: Res<Config>,
+    gilrs: Option<NonSend<Gilrs>>,
+    mut layouts: ResMut<GamepadLayouts>,
+) {
+    if layouts.style != config.button_label_style {
+        layouts.style = config.button_label_style;
+    }
+
+    let gilrs = if let Some(gilrs) = gilrs {
+        gilrs
+    } else {
+        return;
+    };
+
+    let new_layouts = gilrs
+        .gamepads()
+        .map(|(id, gamepad)| (usize::from(id), ButtonLayout::detect(gamepad.name())))
+        .collect::<BTreeMap<_, _>>();
+
+    if layouts.detected != new_layouts {
+        layouts.detected = new_layouts;
+    }
+}
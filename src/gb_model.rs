@@ -0,0 +1,152 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext};
+use enum_iterator::{all, next_cycle, previous_cycle};
+
+use crate::{
+    app::{AppState, ShowMessage, UiState},
+    config::{Config, GbModel},
+    core::Emulator,
+    utils::spawn_local,
+};
+
+pub struct GbModelPlugin;
+
+impl Plugin for GbModelPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GbModelPrompt>()
+            .add_system_set(
+                SystemSet::on_update(AppState::Running)
+                    .with_system(gb_model_prompt_system.after("game_settings")),
+            )
+            .add_system_set(
+                SystemSet::on_exit(AppState::Running).with_system(close_gb_model_prompt_system),
+            );
+    }
+}
+
+/// Dialog asking the model to run a dual-mode Game Boy game on.
+/// The game is paused while it is open.
+pub struct GbModelPrompt {
+    open: bool,
+    selected: GbModel,
+    remember: bool,
+    was_paused: bool,
+    /// Model chosen without remembering it, kept for reloading the same game
+    last_choice: Option<(String, GbModel)>,
+}
+
+impl Default for GbModelPrompt {
+    fn default() -> Self {
+        Self {
+            open: false,
+            selected: GbModel::Cgb,
+            remember: true,
+            was_paused: false,
+            last_choice: None,
+        }
+    }
+}
+
+impl GbModelPrompt {
+    fn show(&mut self, ui_state: &mut UiState) {
+        if !self.open {
+            self.open = true;
+            self.was_paused = ui_state.paused;
+            ui_state.paused = true;
+        }
+    }
+
+    fn close(&mut self, ui_state: &mut UiState) {
+        if self.open {
+            self.open = false;
+            ui_state.paused = self.was_paused;
+        }
+    }
+}
+
+fn close_gb_model_prompt_system(mut prompt: ResMut<GbModelPrompt>, mut ui_state: ResMut<UiState>) {
+    prompt.close(&mut ui_state);
+}
+
+#[allow(clippy::too_many_arguments)]
+fn gb_model_prompt_system(
+    mut egui_ctx: ResMut<EguiContext>,
+    mut prompt: ResMut<GbModelPrompt>,
+    mut ui_state: ResMut<UiState>,
+    mut config: ResMut<Config>,
+    mut emulator: ResMut<Emulator>,
+    input_keycode: Res<Input<KeyCode>>,
+    input_gamepad_button: Res<Input<GamepadButton>>,
+    mut message_event: EventWriter<ShowMessage>,
+) {
+    // Games switched in from suspended sessions keep their model
+    if emulator.is_added() && emulator.dual_mode_cart && emulator.gb_model().is_none() {
+        let last_choice = prompt
+            .last_choice
+            .as_ref()
+            .filter(|(game_hash, _)| game_hash == &emulator.game_hash)
+            .map(|(_, model)| *model);
+
+        if let Some(model) = last_choice {
+            emulator.set_gb_model(Some(model));
+        } else if config.prompt_gb_model {
+            prompt.show(&mut ui_state);
+        }
+    }
+
+    if !prompt.open {
+        return;
+    }
+
+    let pressed = |key: KeyCode, button: GamepadButtonType| {
+        input_keycode.just_pressed(key)
+            || input_gamepad_button
+                .get_just_pressed()
+                .any(|pressed| pressed.button_type == button)
+    };
+
+    if pressed(KeyCode::Left, GamepadButtonType::DPadLeft) {
+        prompt.selected = previous_cycle(&prompt.selected).unwrap_or(GbModel::Dmg);
+    }
+    if pressed(KeyCode::Right, GamepadButtonType::DPadRight) {
+        prompt.selected = next_cycle(&prompt.selected).unwrap_or(GbModel::Dmg);
+    }
+    let mut confirmed = pressed(KeyCode::Return, GamepadButtonType::East);
+
+    egui::Window::new("Game Boy Model")
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+        .show(egui_ctx.ctx_mut(), |ui| {
+            ui.label("This game also runs on Game Boy Color. Which model do you want to use?");
+
+            ui.horizontal(|ui| {
+                for model in all::<GbModel>() {
+                    ui.selectable_value(&mut prompt.selected, model, model.to_string());
+                }
+            });
+
+            ui.checkbox(&mut prompt.remember, "Remember for this game");
+
+            if ui.button("Start").clicked() {
+                confirmed = true;
+            }
+        });
+
+    if !confirmed {
+        return;
+    }
+
+    let model = prompt.selected;
+    emulator.set_gb_model(Some(model));
+    prompt.last_choice = Some((emulator.game_hash.clone(), model));
+    prompt.close(&mut ui_state);
+
+    if prompt.remember {
+        config.gb_models.insert(emulator.game_hash.clone(), model);
+        let config = config.clone();
+        spawn_local(async move { config.save().await.unwrap() });
+    }
+
+    message_event.send(ShowMessage::info(format!("Running on {model}")));
+}
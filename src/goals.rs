@@ -0,0 +1,95 @@
+use bevy::prelude::*;
+use chrono::DateTime;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app::{AppState, ShowMessage},
+    cheats::{parse_conditions, MemoryCondition},
+    config::PersistentState,
+    core::Emulator,
+    utils::spawn_local,
+};
+
+pub struct GoalsPlugin;
+
+impl Plugin for GoalsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(
+            SystemSet::on_update(AppState::Running).with_system(goal_system.after("emulator")),
+        );
+    }
+}
+
+/// Memory condition of a game defined by the user, notified once when it holds
+#[derive(Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct Goal {
+    pub name: String,
+    /// Conditions in the syntax of `parse_conditions`
+    pub condition: String,
+    pub reached: Option<DateTime<chrono::Local>>,
+}
+
+#[derive(Default)]
+struct GoalWatch {
+    game_hash: String,
+    /// Indices and conditions of unreached goals,
+    /// with whether the condition has been seen unmet
+    goals: Vec<(usize, Vec<MemoryCondition>, bool)>,
+}
+
+fn goal_system(
+    emulator: Res<Emulator>,
+    mut persistent_state: ResMut<PersistentState>,
+    mut watch: Local<GoalWatch>,
+    mut message_event: EventWriter<ShowMessage>,
+) {
+    if emulator.core_failed || !emulator.core.supports_memory_access() {
+        return;
+    }
+
+    if persistent_state.is_changed() || watch.game_hash != emulator.game_hash {
+        watch.game_hash = emulator.game_hash.clone();
+        watch.goals = persistent_state
+            .goals(&emulator.game_hash)
+            .iter()
+            .enumerate()
+            .filter(|(_, goal)| goal.reached.is_none())
+            .filter_map(|(ix, goal)| {
+                let conditions = parse_conditions(&goal.condition).ok()?;
+                Some((ix, conditions, false))
+            })
+            .collect();
+    }
+
+    // Goals have to be unmet once before reaching them,
+    // so that uninitialized memory or loading a state does not count
+    let mut reached = vec![];
+    for (ix, conditions, armed) in watch.goals.iter_mut() {
+        let met = conditions
+            .iter()
+            .all(|cond| cond.check(emulator.core.read_memory(cond.addr)));
+        if !met {
+            *armed = true;
+        } else if *armed {
+            reached.push(*ix);
+        }
+    }
+
+    if reached.is_empty() {
+        return;
+    }
+
+    let now = chrono::Local::now();
+    if let Some(goals) = persistent_state.games.goals.get_mut(&emulator.game_hash) {
+        for ix in reached {
+            goals[ix].reached = Some(now);
+            message_event.send(ShowMessage::info(format!(
+                "Goal reached: {}",
+                goals[ix].name
+            )));
+        }
+    }
+
+    let fut = persistent_state.save();
+    spawn_local(async move { fut.await.unwrap() });
+}
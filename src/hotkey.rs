@@ -1,26 +1,45 @@
 use bevy::prelude::*;
 use either::Either;
 use enum_iterator::{all, Sequence};
+use meru_interface::{MultiKey, SingleKey};
 use serde::{Deserialize, Serialize};
-use std::fmt::Display;
+use std::{fmt::Display, path::PathBuf};
 use Either::{Left, Right};
 
 use crate::{
     app::{AppState, ShowMessage, UiState, WindowControlEvent},
-    config::Config,
-    core::Emulator,
+    audio::VolumeOsd,
+    config::{Config, MAX_VOLUME, MAX_ZOOM, MIN_ZOOM, VOLUME_STEP},
+    core::{state_change_blocker, Emulator},
     input::{InputState, KeyConfig},
+    movie::Movies,
+    netplay::Netplay,
+    quick_menu::QuickMenu,
+    state_carousel::StateCarousel,
     utils::{spawn_local, unbounded_channel, Receiver, Sender},
 };
 
+/// Zoom change per hotkey press, in percent
+const ZOOM_STEP: usize = 50;
+
+/// Keys suggested for hotkeys conflicting with core buttons, in order of preference
+const SPARE_KEYS: &[meru_interface::KeyCode] = {
+    use meru_interface::KeyCode::*;
+    &[
+        F3, F4, F6, F7, F8, Insert, Home, End, Delete, PageUp, PageDown, F13, F14, F15, F16,
+    ]
+};
+
 pub struct HotKeyPlugin;
 
 impl Plugin for HotKeyPlugin {
     fn build(&self, app: &mut App) {
         let (s, r) = unbounded_channel::<Either<HotKey, HotKeyCont>>();
-        app.add_system(check_hotkey)
+        app.add_event::<TriggerHotKey>()
+            .add_system(check_hotkey)
             .add_system(process_hotkey)
             .insert_resource(IsTurbo(false))
+            .insert_resource(IsRewinding(false))
             .insert_resource(s)
             .insert_resource(r);
     }
@@ -29,36 +48,75 @@ impl Plugin for HotKeyPlugin {
 #[derive(PartialEq, Eq, Clone, Copy, Debug, Serialize, Deserialize, Sequence)]
 pub enum HotKey {
     Reset,
+    ReloadRom,
     Turbo,
     StateSave,
     StateLoad,
     NextSlot,
     PrevSlot,
     Rewind,
+    HoldRewind,
     Menu,
+    Pause,
     FullScreen,
     ScaleUp,
     ScaleDown,
+    SaveClip,
+    Screenshot,
+    SwapControllers,
+    MinimizeToTray,
+    Rotate,
+    NetplayStatus,
+    QuickMenu,
+    StateCarousel,
+    ZoomIn,
+    ZoomOut,
+    MovieRecord,
+    MoviePlay,
+    Mute,
+    VolumeUp,
+    VolumeDown,
 }
 
 enum HotKeyCont {
     StateLoadDone(anyhow::Result<Vec<u8>>),
+    ReloadRomDone(anyhow::Result<Emulator>),
+    SaveClipDone(anyhow::Result<PathBuf>),
+    ScreenshotDone(anyhow::Result<PathBuf>),
 }
 
 impl Display for HotKey {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let s = match self {
             HotKey::Reset => "Reset",
+            HotKey::ReloadRom => "Reload ROM",
             HotKey::Turbo => "Turbo",
             HotKey::StateSave => "State Save",
             HotKey::StateLoad => "State Load",
             HotKey::NextSlot => "State Slot Next",
             HotKey::PrevSlot => "State Slot Prev",
             HotKey::Rewind => "Start Rewindng",
+            HotKey::HoldRewind => "Rewind While Held",
             HotKey::Menu => "Enter/Leave Menu",
+            HotKey::Pause => "Pause/Resume",
             HotKey::FullScreen => "Fullsceen",
             HotKey::ScaleUp => "Window Scale +",
             HotKey::ScaleDown => "Window Scale -",
+            HotKey::SaveClip => "Save Clip",
+            HotKey::Screenshot => "Screenshot",
+            HotKey::SwapControllers => "Swap Controllers",
+            HotKey::MinimizeToTray => "Minimize to Tray",
+            HotKey::Rotate => "Rotate Screen",
+            HotKey::NetplayStatus => "Netplay Status",
+            HotKey::QuickMenu => "Quick Settings",
+            HotKey::StateCarousel => "State Slots",
+            HotKey::ZoomIn => "Zoom +",
+            HotKey::ZoomOut => "Zoom -",
+            HotKey::MovieRecord => "Start/Stop Movie Recording",
+            HotKey::MoviePlay => "Play/Stop Latest Movie",
+            HotKey::Mute => "Mute/Unmute",
+            HotKey::VolumeUp => "Volume +",
+            HotKey::VolumeDown => "Volume -",
         };
         write!(f, "{s}")
     }
@@ -72,6 +130,7 @@ impl Default for HotKeys {
         use HotKey::*;
         Self(vec![
             (Reset, all![keycode!(LControl), keycode!(R)]),
+            (ReloadRom, all![keycode!(LControl), keycode!(F5)]),
             (Turbo, any![keycode!(Tab), pad_button!(0, LeftTrigger2)]),
             (StateSave, all![keycode!(LControl), keycode!(S)]),
             (StateLoad, all![keycode!(LControl), keycode!(L)]),
@@ -84,19 +143,166 @@ impl Default for HotKeys {
                     all![pad_button!(0, LeftTrigger2), pad_button!(0, RightTrigger2)]
                 ],
             ),
-            (Menu, keycode!(Escape)),
+            (
+                HoldRewind,
+                any![
+                    keycode!(Grave),
+                    all![pad_button!(0, Select), pad_button!(0, LeftTrigger)]
+                ],
+            ),
+            (
+                Menu,
+                any![
+                    keycode!(Escape),
+                    all![pad_button!(0, Select), pad_button!(0, Start)],
+                    all![pad_button!(1, Select), pad_button!(1, Start)]
+                ],
+            ),
+            (
+                Pause,
+                any![keycode!(Pause), pad_button!(0, Mode), pad_button!(1, Mode)],
+            ),
             (FullScreen, all![keycode!(RAlt), keycode!(Return)]),
             (
                 ScaleUp,
                 all![keycode!(LControl), any![keycode!(Plus), keycode!(Equals)]],
             ),
             (ScaleDown, all![keycode!(LControl), keycode!(Minus)]),
+            (SaveClip, all![keycode!(LControl), keycode!(G)]),
+            (Screenshot, keycode!(F12)),
+            (SwapControllers, all![keycode!(LControl), keycode!(W)]),
+            (MinimizeToTray, all![keycode!(LControl), keycode!(T)]),
+            (Rotate, all![keycode!(LControl), keycode!(O)]),
+            (NetplayStatus, all![keycode!(LControl), keycode!(K)]),
+            (
+                QuickMenu,
+                any![
+                    keycode!(F1),
+                    all![pad_button!(0, Select), pad_button!(0, RightTrigger2)]
+                ],
+            ),
+            (
+                StateCarousel,
+                any![
+                    keycode!(F2),
+                    all![pad_button!(0, Select), pad_button!(0, RightTrigger)]
+                ],
+            ),
+            (ZoomIn, all![keycode!(LControl), keycode!(PageUp)]),
+            (ZoomOut, all![keycode!(LControl), keycode!(PageDown)]),
+            (MovieRecord, all![keycode!(LControl), keycode!(M)]),
+            // Not a superset of the recording chord, which would fire along with it
+            (MoviePlay, all![keycode!(LAlt), keycode!(M)]),
+            (Mute, keycode!(F9)),
+            (VolumeDown, keycode!(F10)),
+            (VolumeUp, keycode!(F11)),
         ])
     }
 }
 
+/// Hotkey assignment of a single key which is also a button of a core,
+/// so pressing the button in game triggers the hotkey
+pub struct HotKeyConflict {
+    pub hotkey: HotKey,
+    pub key: MultiKey,
+    pub core: &'static str,
+    pub button: String,
+    /// Unused key to assign instead, if any is left
+    pub suggestion: Option<MultiKey>,
+}
+
+/// Finds hotkeys conflicting with core buttons, for all cores or only `core`
+pub fn hotkey_conflicts(config: &Config, core: Option<&str>) -> Vec<HotKeyConflict> {
+    let key_configs = config.core_key_configs();
+    let core_buttons = key_configs
+        .iter()
+        .flat_map(|(abbrev, key_config)| {
+            key_config.controllers.iter().flat_map(move |controller| {
+                controller.iter().flat_map(move |(button, assign)| {
+                    assign
+                        .0
+                        .iter()
+                        .flat_map(|multi_key| multi_key.0.iter())
+                        .map(move |key| (*abbrev, button, key))
+                })
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut used = core_buttons
+        .iter()
+        .map(|(_, _, key)| (*key).clone())
+        .chain(
+            config
+                .hotkeys
+                .0
+                .iter()
+                .flat_map(|(_, assign)| assign.0.iter())
+                .flat_map(|multi_key| multi_key.0.iter().cloned()),
+        )
+        .collect::<Vec<_>>();
+
+    let mut ret = vec![];
+    for (hotkey, assign) in &config.hotkeys.0 {
+        // Chords are assigned on purpose, e.g. Select + Start for the menu
+        for multi_key in assign.0.iter().filter(|multi_key| multi_key.0.len() == 1) {
+            let conflict = core_buttons.iter().find(|(abbrev, _, key)| {
+                core.map_or(true, |core| core == *abbrev) && *key == &multi_key.0[0]
+            });
+            let (abbrev, button, _) = if let Some(conflict) = conflict {
+                conflict
+            } else {
+                continue;
+            };
+
+            let suggestion = if let SingleKey::KeyCode(_) = &multi_key.0[0] {
+                SPARE_KEYS
+                    .iter()
+                    .map(|key_code| SingleKey::KeyCode(*key_code))
+                    .find(|key| !used.contains(key))
+            } else {
+                None
+            };
+            if let Some(key) = &suggestion {
+                used.push(key.clone());
+            }
+
+            ret.push(HotKeyConflict {
+                hotkey: *hotkey,
+                key: multi_key.clone(),
+                core: *abbrev,
+                button: button.to_string(),
+                suggestion: suggestion.map(|key| MultiKey(vec![key])),
+            });
+        }
+    }
+    ret
+}
+
+/// Replaces the conflicting key with the suggested one
+pub fn apply_suggestion(hotkeys: &mut HotKeys, conflict: &HotKeyConflict) {
+    let suggestion = if let Some(suggestion) = &conflict.suggestion {
+        suggestion
+    } else {
+        return;
+    };
+    if let Some(assign) = hotkeys.key_assign_mut(&conflict.hotkey) {
+        for multi_key in assign.0.iter_mut() {
+            if multi_key == &conflict.key {
+                *multi_key = suggestion.clone();
+            }
+        }
+    }
+}
+
 pub struct IsTurbo(pub bool);
 
+/// The hold-to-rewind hotkey is held
+pub struct IsRewinding(pub bool);
+
+/// Triggers a hotkey from outside of the key config, e.g. OS-wide hotkeys
+pub struct TriggerHotKey(pub HotKey);
+
 fn check_hotkey(
     config: Res<Config>,
     input_keycode: Res<Input<KeyCode>>,
@@ -104,7 +310,13 @@ fn check_hotkey(
     input_gamepad_axis: Res<Axis<GamepadAxis>>,
     writer: Res<Sender<Either<HotKey, HotKeyCont>>>,
     mut is_turbo: ResMut<IsTurbo>,
+    mut is_rewinding: ResMut<IsRewinding>,
+    mut trigger: EventReader<TriggerHotKey>,
 ) {
+    for TriggerHotKey(hotkey) in trigger.iter() {
+        writer.try_send(Left(*hotkey)).unwrap();
+    }
+
     let input_state = InputState::new(&input_keycode, &input_gamepad_button, &input_gamepad_axis);
 
     for hotkey in all::<HotKey>() {
@@ -113,14 +325,13 @@ fn check_hotkey(
         }
     }
 
-    is_turbo.0 = config.hotkeys.pressed(
-        &HotKey::Turbo,
-        &InputState::new(&input_keycode, &input_gamepad_button, &input_gamepad_axis),
-    );
+    is_turbo.0 = config.hotkeys.pressed(&HotKey::Turbo, &input_state);
+    is_rewinding.0 = config.hotkeys.pressed(&HotKey::HoldRewind, &input_state);
 }
 
 #[allow(clippy::too_many_arguments)]
 fn process_hotkey(
+    mut commands: Commands,
     mut config: ResMut<Config>,
     recv: Res<Receiver<Either<HotKey, HotKeyCont>>>,
     send: Res<Sender<Either<HotKey, HotKeyCont>>>,
@@ -129,22 +340,123 @@ fn process_hotkey(
     mut ui_state: ResMut<UiState>,
     mut window_control_event: EventWriter<WindowControlEvent>,
     mut message_event: EventWriter<ShowMessage>,
+    message_send: Res<Sender<ShowMessage>>,
+    (mut netplay, mut quick_menu, mut state_carousel, mut movies, mut volume_osd): (
+        ResMut<Netplay>,
+        ResMut<QuickMenu>,
+        ResMut<StateCarousel>,
+        ResMut<Movies>,
+        ResMut<VolumeOsd>,
+    ),
 ) {
     while let Ok(hotkey) = recv.try_recv() {
+        // These would desync the peer or the movie
+        if let Left(
+            key @ (HotKey::Reset | HotKey::ReloadRom | HotKey::StateLoad | HotKey::Rewind),
+        ) = &hotkey
+        {
+            if let Some(reason) = state_change_blocker(&netplay, &movies) {
+                message_event.send(ShowMessage::info(format!(
+                    "{key} is not available {reason}"
+                )));
+                continue;
+            }
+        }
+
         match hotkey {
+            Left(HotKey::MovieRecord) => {
+                if movies.is_recording() {
+                    movies.stop(&config);
+                } else if netplay.is_active() {
+                    message_event
+                        .send(ShowMessage::info("Movies are not available during netplay"));
+                } else if let Some(emulator) = &emulator {
+                    movies.start_recording(emulator, &config);
+                    message_event.send(ShowMessage::info("Movie recording started"));
+                }
+            }
+            Left(HotKey::MoviePlay) => {
+                if movies.is_playing() {
+                    movies.stop(&config);
+                    message_event.send(ShowMessage::info("Movie playback stopped"));
+                } else if netplay.is_active() {
+                    message_event
+                        .send(ShowMessage::info("Movies are not available during netplay"));
+                } else if let Some(emulator) = &emulator {
+                    movies.play(emulator, None, &config);
+                }
+            }
+            Left(HotKey::NetplayStatus) => {
+                netplay.show_overlay = !netplay.show_overlay;
+            }
+            Left(HotKey::QuickMenu) => {
+                if app_state.current() == &AppState::Running {
+                    quick_menu.toggle(&mut ui_state);
+                }
+            }
+            Left(HotKey::StateCarousel) => {
+                if let (AppState::Running, Some(emulator)) = (app_state.current(), &emulator) {
+                    state_carousel.toggle(&mut ui_state, emulator.state_files.len());
+                }
+            }
             Left(HotKey::Reset) => {
                 if let Some(emulator) = &mut emulator {
                     emulator.reset();
-                    message_event.send(ShowMessage("Reset machine".to_string()));
+                    message_event.send(ShowMessage::info("Reset machine".to_string()));
+                }
+            }
+            Left(HotKey::ReloadRom) => {
+                if let Some(emulator) = &mut emulator {
+                    let send = send.clone();
+
+                    // The new emulator reads the save file, so in-game saves are flushed first
+                    let save = emulator.save_backup();
+                    let fut = emulator.reload(config.as_ref(), !config.clear_backup_on_reload);
+
+                    spawn_local(async move {
+                        let result = match save.await {
+                            Ok(()) => fut.await,
+                            Err(err) => Err(err),
+                        };
+                        send.send(Right(HotKeyCont::ReloadRomDone(result)))
+                            .await
+                            .unwrap();
+                    });
                 }
             }
+            Right(HotKeyCont::ReloadRomDone(result)) => match result {
+                Ok(new_emulator) => {
+                    // Backup RAM of the old one would overwrite the save of the new one on drop
+                    if let Some(emulator) = &mut emulator {
+                        emulator.discard_backup();
+                    }
+                    commands.insert_resource(new_emulator);
+                    message_event.send(ShowMessage::info("ROM reloaded".to_string()));
+                }
+                Err(err) => {
+                    message_event.send(ShowMessage::error(format!("Failed to reload ROM: {err}")));
+                }
+            },
             Left(HotKey::StateSave) => {
-                if let Some(emulator) = &emulator {
+                if let Some(emulator) = &mut emulator {
                     let fut = emulator.save_state_slot(ui_state.state_save_slot, config.as_ref());
 
-                    spawn_local(async move { fut.await.unwrap() });
+                    let message_send = message_send.clone();
+                    spawn_local(async move {
+                        if let Err(err) = fut.await {
+                            let msg = ShowMessage::save_failed("Saving state", &err);
+                            message_send.send(msg).await.unwrap();
+                        }
+                    });
+
+                    let state_file = emulator.current_state_file();
 
-                    message_event.send(ShowMessage(format!(
+                    if let Some(slot_file) = emulator.state_files.get_mut(ui_state.state_save_slot)
+                    {
+                        *slot_file = Some(state_file);
+                    }
+
+                    message_event.send(ShowMessage::info(format!(
                         "State saved: #{}",
                         ui_state.state_save_slot
                     )));
@@ -169,10 +481,11 @@ fn process_hotkey(
                     match data {
                         Ok(data) => {
                             if let Err(err) = emulator.load_state_data(&data) {
-                                message_event
-                                    .send(ShowMessage(format!("Failed to load state: {err:?}")));
+                                message_event.send(ShowMessage::error(format!(
+                                    "Failed to load state: {err:?}"
+                                )));
                             } else {
-                                message_event.send(ShowMessage(format!(
+                                message_event.send(ShowMessage::info(format!(
                                     "State loaded: #{}",
                                     ui_state.state_save_slot
                                 )));
@@ -180,21 +493,21 @@ fn process_hotkey(
                         }
                         Err(err) => {
                             message_event
-                                .send(ShowMessage(format!("Failed to load state: {err:?}")));
+                                .send(ShowMessage::error(format!("Failed to load state: {err:?}")));
                         }
                     }
                 }
             }
             Left(HotKey::NextSlot) => {
                 ui_state.state_save_slot += 1;
-                message_event.send(ShowMessage(format!(
+                message_event.send(ShowMessage::info(format!(
                     "State slot changed: #{}",
                     ui_state.state_save_slot
                 )));
             }
             Left(HotKey::PrevSlot) => {
                 ui_state.state_save_slot = ui_state.state_save_slot.saturating_sub(1);
-                message_event.send(ShowMessage(format!(
+                message_event.send(ShowMessage::info(format!(
                     "State slot changed: #{}",
                     ui_state.state_save_slot
                 )));
@@ -202,7 +515,7 @@ fn process_hotkey(
             Left(HotKey::Rewind) => {
                 if app_state.current() == &AppState::Running {
                     let emulator = emulator.as_mut().unwrap();
-                    emulator.push_auto_save();
+                    emulator.push_auto_save(&config);
                     app_state.push(AppState::Rewinding).unwrap();
                 }
             }
@@ -213,19 +526,120 @@ fn process_hotkey(
                     app_state.set(AppState::Running).unwrap();
                 }
             }
+            Left(HotKey::Pause) => {
+                if app_state.current() == &AppState::Running {
+                    ui_state.paused = !ui_state.paused;
+                    message_event.send(ShowMessage::info(if ui_state.paused {
+                        "Paused"
+                    } else {
+                        "Resumed"
+                    }));
+                }
+            }
+            Left(HotKey::SwapControllers) => {
+                ui_state.controllers_swapped = !ui_state.controllers_swapped;
+                message_event.send(ShowMessage::info(if ui_state.controllers_swapped {
+                    "Controllers swapped"
+                } else {
+                    "Controllers restored"
+                }));
+            }
+            Left(HotKey::MinimizeToTray) => {
+                window_control_event.send(WindowControlEvent::MinimizeToTray);
+            }
             Left(HotKey::FullScreen) => {
                 window_control_event.send(WindowControlEvent::ToggleFullscreen);
             }
             Left(HotKey::ScaleUp) => {
-                config.scaling += 1;
+                let game_hash = emulator.as_ref().map_or("", |e| e.game_hash.as_str());
+                let scaling = config.video_setting(game_hash).scaling;
+                window_control_event.send(WindowControlEvent::ChangeScale(scaling + 1));
+            }
+            Left(HotKey::Rotate) => {
+                config.rotation = config.rotation.next();
+                if let Some(emulator) = &mut emulator {
+                    emulator.rotation = config.rotation;
+                }
                 window_control_event.send(WindowControlEvent::Restore);
+                message_event.send(ShowMessage::info(format!("Rotation: {}", config.rotation)));
+            }
+            Left(HotKey::ZoomIn) => {
+                config.zoom = (config.zoom + ZOOM_STEP).min(MAX_ZOOM);
+                message_event.send(ShowMessage::info(format!("Zoom: {}%", config.zoom)));
+            }
+            Left(HotKey::ZoomOut) => {
+                config.zoom = config.zoom.saturating_sub(ZOOM_STEP).max(MIN_ZOOM);
+                message_event.send(ShowMessage::info(format!("Zoom: {}%", config.zoom)));
             }
             Left(HotKey::ScaleDown) => {
-                config.scaling = (config.scaling - 1).max(1);
-                window_control_event.send(WindowControlEvent::Restore);
+                let game_hash = emulator.as_ref().map_or("", |e| e.game_hash.as_str());
+                let scaling = config.video_setting(game_hash).scaling;
+                window_control_event.send(WindowControlEvent::ChangeScale((scaling - 1).max(1)));
             }
+            Left(HotKey::SaveClip) => {
+                if let Some(emulator) = &emulator {
+                    let send = send.clone();
+
+                    let fut = emulator.save_clip(config.as_ref());
+
+                    spawn_local(async move {
+                        let result = fut.await;
+                        send.send(Right(HotKeyCont::SaveClipDone(result)))
+                            .await
+                            .unwrap();
+                    });
+                }
+            }
+            Right(HotKeyCont::SaveClipDone(result)) => match result {
+                Ok(path) => {
+                    message_event
+                        .send(ShowMessage::info(format!("Clip saved: {}", path.display())));
+                }
+                Err(err) => {
+                    message_event.send(ShowMessage::error(format!("Failed to save clip: {err}")));
+                }
+            },
+            Left(HotKey::Screenshot) => {
+                if let Some(emulator) = &emulator {
+                    let send = send.clone();
+
+                    let fut = emulator.save_screenshot(config.as_ref());
 
-            Left(HotKey::Turbo) => {}
+                    spawn_local(async move {
+                        let result = fut.await;
+                        send.send(Right(HotKeyCont::ScreenshotDone(result)))
+                            .await
+                            .unwrap();
+                    });
+                }
+            }
+            Right(HotKeyCont::ScreenshotDone(result)) => match result {
+                Ok(path) => {
+                    message_event.send(ShowMessage::info(format!(
+                        "Screenshot saved: {}",
+                        path.display()
+                    )));
+                }
+                Err(err) => {
+                    message_event.send(ShowMessage::save_failed("Saving screenshot", &err));
+                }
+            },
+
+            Left(HotKey::Mute) => {
+                config.audio.muted = !config.audio.muted;
+                volume_osd.show();
+            }
+            Left(HotKey::VolumeUp) => {
+                config.audio.volume = (config.audio.volume + VOLUME_STEP).min(MAX_VOLUME);
+                config.audio.muted = false;
+                volume_osd.show();
+            }
+            Left(HotKey::VolumeDown) => {
+                config.audio.volume = config.audio.volume.saturating_sub(VOLUME_STEP);
+                config.audio.muted = false;
+                volume_osd.show();
+            }
+            Left(HotKey::Turbo | HotKey::HoldRewind) => {}
         }
     }
 }
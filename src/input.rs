@@ -377,6 +377,14 @@ impl<Key: PartialEq + Clone> KeyConfig<Key> {
         self.0.iter_mut().find(|(h, _)| h == key).map(|(_, k)| k)
     }
 
+    pub fn add_missing_keys(&mut self, default: &Self) {
+        for (key, assign) in &default.0 {
+            if self.key_assign(key).is_none() {
+                self.0.push((key.clone(), assign.clone()));
+            }
+        }
+    }
+
     pub fn insert_keycode(&mut self, key: &Key, key_code: meru_interface::KeyCode) {
         if let Some(key_assign) = self.key_assign_mut(key) {
             key_assign.insert_keycode(key_code);
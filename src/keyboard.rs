@@ -0,0 +1,184 @@
+use bevy_egui::egui;
+use enum_iterator::all;
+
+use crate::{
+    config::{SystemKey, SystemKeys},
+    input::InputState,
+};
+
+const CHAR_ROWS: &[&str] = &["1234567890", "qwertyuiop", "asdfghjkl-", "zxcvbnm_.,"];
+
+#[derive(Clone, Copy)]
+enum Key {
+    Char(char),
+    Shift,
+    Space,
+    Backspace,
+    Done,
+}
+
+impl Key {
+    fn label(&self, shift: bool) -> String {
+        match self {
+            Key::Char(c) if shift => c.to_ascii_uppercase().to_string(),
+            Key::Char(c) => c.to_string(),
+            Key::Shift => "⇧ Shift".to_string(),
+            Key::Space => "Space".to_string(),
+            Key::Backspace => "⌫".to_string(),
+            Key::Done => "Done".to_string(),
+        }
+    }
+}
+
+fn key_rows() -> Vec<Vec<Key>> {
+    let mut rows = CHAR_ROWS
+        .iter()
+        .map(|row| row.chars().map(Key::Char).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    rows.push(vec![Key::Shift, Key::Space, Key::Backspace, Key::Done]);
+    rows
+}
+
+#[derive(Default)]
+pub struct OnScreenKeyboard {
+    target: Option<egui::Id>,
+    cursor: (usize, usize),
+    shift: bool,
+    pressed: Vec<SystemKey>,
+    gamepad_used: bool,
+}
+
+impl OnScreenKeyboard {
+    pub fn update_input(
+        &mut self,
+        system_keys: &SystemKeys,
+        input_state: &InputState<'_>,
+        gamepad_used: bool,
+    ) {
+        self.pressed = all::<SystemKey>()
+            .filter(|key| system_keys.just_pressed(key, input_state))
+            .collect();
+        self.gamepad_used = gamepad_used;
+    }
+
+    pub fn text_edit_singleline(&mut self, ui: &mut egui::Ui, text: &mut String) -> egui::Response {
+        self.text_edit(ui, text, |text| egui::TextEdit::singleline(text))
+    }
+
+    /// Adds the text edit made by `make`, with a button to type into it on screen
+    pub fn text_edit(
+        &mut self,
+        ui: &mut egui::Ui,
+        text: &mut String,
+        make: impl FnOnce(&mut String) -> egui::TextEdit<'_>,
+    ) -> egui::Response {
+        ui.horizontal(|ui| self.text_edit_with_button(ui, text, make))
+            .inner
+    }
+
+    fn text_edit_with_button(
+        &mut self,
+        ui: &mut egui::Ui,
+        text: &mut String,
+        make: impl FnOnce(&mut String) -> egui::TextEdit<'_>,
+    ) -> egui::Response {
+        let resp = ui.add(make(text));
+
+        let is_target = self.target == Some(resp.id);
+        let mut open = is_target;
+        if ui
+            .selectable_label(is_target, "⌨")
+            .on_hover_text("On-screen keyboard")
+            .clicked()
+        {
+            open = !open;
+        }
+        // Gamepad users cannot type, so bring up the keyboard as soon as the field gets focus
+        if resp.has_focus() && self.gamepad_used {
+            open = true;
+        }
+
+        if open {
+            if !is_target {
+                self.target = Some(resp.id);
+                self.cursor = (0, 0);
+                self.pressed.clear();
+            }
+            self.show(ui.ctx(), text);
+            if self.target.is_none() {
+                resp.surrender_focus();
+            }
+        } else if is_target {
+            self.target = None;
+        }
+
+        resp
+    }
+
+    fn show(&mut self, ctx: &egui::Context, text: &mut String) {
+        let rows = key_rows();
+        self.navigate(&rows);
+
+        let mut pressed_key = None;
+
+        egui::Window::new("Keyboard")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_BOTTOM, [0.0, -20.0])
+            .show(ctx, |ui| {
+                egui::Grid::new("on_screen_keyboard").show(ui, |ui| {
+                    for (y, row) in rows.iter().enumerate() {
+                        for (x, key) in row.iter().enumerate() {
+                            if ui
+                                .selectable_label(self.cursor == (y, x), key.label(self.shift))
+                                .clicked()
+                            {
+                                self.cursor = (y, x);
+                                pressed_key = Some(*key);
+                            }
+                        }
+                        ui.end_row();
+                    }
+                });
+            });
+
+        if self.pressed.contains(&SystemKey::Ok) {
+            pressed_key = Some(rows[self.cursor.0][self.cursor.1]);
+        }
+        if self.pressed.contains(&SystemKey::Cancel) {
+            self.target = None;
+        }
+
+        match pressed_key {
+            Some(Key::Char(c)) => {
+                text.push(if self.shift {
+                    c.to_ascii_uppercase()
+                } else {
+                    c
+                });
+            }
+            Some(Key::Shift) => self.shift = !self.shift,
+            Some(Key::Space) => text.push(' '),
+            Some(Key::Backspace) => {
+                text.pop();
+            }
+            Some(Key::Done) => self.target = None,
+            None => {}
+        }
+    }
+
+    fn navigate(&mut self, rows: &[Vec<Key>]) {
+        let (mut y, mut x) = self.cursor;
+        for key in &self.pressed {
+            match key {
+                SystemKey::Up => y = (y + rows.len() - 1) % rows.len(),
+                SystemKey::Down => y = (y + 1) % rows.len(),
+                SystemKey::Left => x = (x + rows[y].len() - 1) % rows[y].len(),
+                SystemKey::Right => x = (x + 1) % rows[y].len(),
+                SystemKey::Ok | SystemKey::Cancel => {}
+            }
+            x = x.min(rows[y].len() - 1);
+        }
+        self.cursor = (y, x);
+    }
+}
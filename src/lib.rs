@@ -1,10 +1,38 @@
 pub mod app;
 pub mod archive;
+pub mod audio;
+pub mod audio_pack;
+pub mod camera;
+pub mod cheats;
+pub mod clip;
 pub mod config;
 pub mod core;
 pub mod file;
+pub mod firmware;
+pub mod gamepad;
+pub mod gb_model;
+pub mod goals;
 pub mod hotkey;
 pub mod input;
+pub mod keyboard;
+pub mod library;
+pub mod link;
 pub mod menu;
+pub mod movie;
+pub mod netplay;
+pub mod obs;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod os_hotkey;
+pub mod palette;
+pub mod profile;
+pub mod profiler;
+pub mod quick_menu;
+pub mod rewind_buffer;
 pub mod rewinding;
+pub mod session;
+pub mod speech;
+pub mod state_carousel;
+pub mod texture_pack;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod tray;
 pub mod utils;
@@ -0,0 +1,481 @@
+use anyhow::Result;
+use bevy::prelude::*;
+use chrono::{DateTime, Local};
+use meru_interface::Region;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    future::Future,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::SystemTime,
+};
+
+use crate::{
+    config::config_dir,
+    file::{read_to_string, write},
+};
+
+#[cfg(not(target_arch = "wasm32"))]
+use {
+    crate::{
+        config::Config,
+        utils::{spawn_local, unbounded_channel, Receiver, Sender},
+    },
+    bevy::tasks::AsyncComputeTaskPool,
+};
+
+const RECENTLY_ADDED_NUM: usize = 10;
+
+#[cfg(not(target_arch = "wasm32"))]
+const CHECK_INTERVAL: f64 = 3.0;
+
+// Number of files hashed by one task
+#[cfg(not(target_arch = "wasm32"))]
+const HASH_CHUNK_SIZE: usize = 16;
+
+pub struct LibraryPlugin;
+
+impl Plugin for LibraryPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Library>();
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let (s, r) = unbounded_channel::<ScanEvent>();
+            app.insert_resource(s)
+                .insert_resource(r)
+                .add_system(library_scan_system);
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct LibraryEntry {
+    pub path: PathBuf,
+    pub added: DateTime<Local>,
+    pub modified: SystemTime,
+    pub crc32: u32,
+}
+
+impl LibraryEntry {
+    pub fn name(&self) -> String {
+        self.path
+            .file_name()
+            .map_or_else(String::new, |r| r.to_string_lossy().to_string())
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+enum ScanEvent {
+    /// Files unchanged since the last scan, the scanned directories
+    /// and the number of files to be hashed
+    Found {
+        entries: Vec<LibraryEntry>,
+        dirs: DirTimes,
+        to_hash: usize,
+    },
+    Hashed(Vec<LibraryEntry>),
+    Done,
+}
+
+/// Directories with their modification times, which change when files are
+/// added, removed or renamed in them. `None` if the directory does not exist.
+type DirTimes = BTreeMap<PathBuf, Option<SystemTime>>;
+
+struct ScanState {
+    hashed: usize,
+    total: usize,
+    dirs: DirTimes,
+    cancel: Arc<AtomicBool>,
+}
+
+#[derive(Default)]
+pub struct Library {
+    pub entries: Vec<LibraryEntry>,
+    rom_dirs: Vec<PathBuf>,
+    /// Directories of the last completed scan
+    dirs: DirTimes,
+    /// Time of the last scan or check of `dirs`. `None` to scan again.
+    last_check: Option<f64>,
+    scan: Option<ScanState>,
+    auto_scan_paused: bool,
+}
+
+impl Library {
+    pub fn is_scanning(&self) -> bool {
+        self.scan.is_some()
+    }
+
+    /// Returns `(hashed, total)` while new files are being hashed
+    pub fn scan_progress(&self) -> Option<(usize, usize)> {
+        self.scan
+            .as_ref()
+            .filter(|scan| scan.total > 0)
+            .map(|scan| (scan.hashed, scan.total))
+    }
+
+    /// Cancels the running scan. Periodic rescans are paused until `rescan` is called.
+    pub fn cancel_scan(&mut self) {
+        if let Some(scan) = &self.scan {
+            scan.cancel.store(true, Ordering::Relaxed);
+        }
+        self.auto_scan_paused = true;
+    }
+
+    pub fn rescan(&mut self) {
+        self.last_check = None;
+        self.auto_scan_paused = false;
+    }
+
+    pub fn recently_added(&self) -> Vec<&LibraryEntry> {
+        let mut ret = self.entries.iter().collect::<Vec<_>>();
+        ret.sort_by(|a, b| b.added.cmp(&a.added));
+        ret.truncate(RECENTLY_ADDED_NUM);
+        ret
+    }
+}
+
+#[derive(PartialEq, Eq, Clone)]
+pub enum LibraryFilter {
+    All,
+    Favorites,
+    Collection(String),
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct LibraryDb {
+    #[serde(default)]
+    pub collections: Vec<String>,
+    #[serde(default)]
+    pub games: BTreeMap<PathBuf, GameRecord>,
+}
+
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub struct GameRecord {
+    #[serde(default)]
+    pub favorite: bool,
+    #[serde(default)]
+    pub collections: BTreeSet<String>,
+    #[serde(default)]
+    pub region: Option<Region>,
+    /// Forced save type, one of the core's `save_types`
+    #[serde(default)]
+    pub save_type: Option<String>,
+    #[serde(default)]
+    pub notes: String,
+    #[serde(default)]
+    pub tags: BTreeSet<String>,
+}
+
+impl GameRecord {
+    fn is_empty(&self) -> bool {
+        !self.favorite
+            && self.collections.is_empty()
+            && self.region.is_none()
+            && self.save_type.is_none()
+            && self.notes.is_empty()
+            && self.tags.is_empty()
+    }
+}
+
+impl LibraryDb {
+    pub fn game(&self, path: &Path) -> Option<&GameRecord> {
+        self.games.get(path)
+    }
+
+    pub fn update_game(&mut self, path: &Path, f: impl FnOnce(&mut GameRecord)) {
+        let record = self.games.entry(path.to_owned()).or_default();
+        f(record);
+        if record.is_empty() {
+            self.games.remove(path);
+        }
+    }
+
+    pub fn is_favorite(&self, path: &Path) -> bool {
+        self.game(path).map_or(false, |r| r.favorite)
+    }
+
+    pub fn matches(&self, path: &Path, filter: &LibraryFilter) -> bool {
+        match filter {
+            LibraryFilter::All => true,
+            LibraryFilter::Favorites => self.is_favorite(path),
+            LibraryFilter::Collection(name) => self
+                .game(path)
+                .map_or(false, |r| r.collections.contains(name)),
+        }
+    }
+
+    /// Case-insensitive search over the file name, notes and tags of a game
+    pub fn matches_query(&self, entry: &LibraryEntry, query: &str) -> bool {
+        let query = query.trim().to_lowercase();
+        if query.is_empty() {
+            return true;
+        }
+
+        let contains = |s: &str| s.to_lowercase().contains(&query);
+
+        contains(&entry.name())
+            || self.game(&entry.path).map_or(false, |r| {
+                contains(&r.notes) || r.tags.iter().any(|tag| contains(tag))
+            })
+    }
+
+    pub fn add_collection(&mut self, name: &str) {
+        if !self.collections.iter().any(|r| r == name) {
+            self.collections.push(name.to_string());
+        }
+    }
+
+    pub fn remove_collection(&mut self, name: &str) {
+        self.collections.retain(|r| r != name);
+        let paths = self.games.keys().cloned().collect::<Vec<_>>();
+        for path in paths {
+            self.update_game(&path, |r| {
+                r.collections.remove(name);
+            });
+        }
+    }
+
+    pub fn save(&self) -> impl Future<Output = Result<()>> {
+        let s = serde_json::to_string_pretty(self).unwrap();
+        async move {
+            write(library_db_path()?, s).await?;
+            Ok(())
+        }
+    }
+}
+
+fn library_db_path() -> Result<PathBuf> {
+    Ok(config_dir()?.join("library.json"))
+}
+
+pub async fn load_library_db() -> Result<LibraryDb> {
+    let ret = if let Ok(s) = read_to_string(library_db_path()?).await {
+        serde_json::from_str(&s).unwrap_or_default()
+    } else {
+        Default::default()
+    };
+    Ok(ret)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn library_scan_system(
+    time: Res<Time>,
+    config: Res<Config>,
+    mut library: ResMut<Library>,
+    send: Res<Sender<ScanEvent>>,
+    recv: Res<Receiver<ScanEvent>>,
+) {
+    while let Ok(event) = recv.try_recv() {
+        match event {
+            ScanEvent::Found {
+                entries,
+                dirs,
+                to_hash,
+            } => {
+                library.entries = entries;
+                if let Some(scan) = &mut library.scan {
+                    scan.total = to_hash;
+                    scan.dirs = dirs;
+                }
+            }
+            ScanEvent::Hashed(entries) => {
+                if let Some(scan) = &mut library.scan {
+                    scan.hashed += entries.len();
+                }
+                library.entries.extend(entries);
+            }
+            ScanEvent::Done => {
+                if let Some(scan) = library.scan.take() {
+                    if scan.cancel.load(Ordering::Relaxed) {
+                        // Files left unhashed are found by the next scan
+                        library.last_check = None;
+                    } else {
+                        library.dirs = scan.dirs;
+                    }
+                }
+            }
+        }
+    }
+
+    let dirs_changed = library.rom_dirs != config.rom_dirs;
+
+    if let Some(scan) = &library.scan {
+        // Restart the scan with the new directories
+        if dirs_changed {
+            scan.cancel.store(true, Ordering::Relaxed);
+        }
+        return;
+    }
+
+    let now = time.seconds_since_startup();
+    let need_check = !library.auto_scan_paused
+        && library
+            .last_check
+            .map_or(true, |last_check| now - last_check >= CHECK_INTERVAL);
+
+    if !dirs_changed && !need_check {
+        return;
+    }
+
+    // Walking the whole tree is only needed when some directory has changed
+    let need_scan = dirs_changed || library.last_check.is_none() || dirs_modified(&library.dirs);
+    library.last_check = Some(now);
+    if !need_scan {
+        return;
+    }
+
+    library.rom_dirs = config.rom_dirs.clone();
+    library.auto_scan_paused = false;
+
+    let cancel = Arc::new(AtomicBool::new(false));
+    library.scan = Some(ScanState {
+        hashed: 0,
+        total: 0,
+        dirs: DirTimes::new(),
+        cancel: cancel.clone(),
+    });
+
+    let rom_dirs = config.rom_dirs.clone();
+    let known = library
+        .entries
+        .iter()
+        .map(|entry| (entry.path.clone(), entry.clone()))
+        .collect::<BTreeMap<_, _>>();
+    let send = send.clone();
+
+    spawn_local(async move {
+        let mut files = vec![];
+        let mut dirs = DirTimes::new();
+        for dir in &rom_dirs {
+            scan_dir(dir, &mut files, &mut dirs);
+        }
+
+        // Only hash files which are new or modified since the last scan
+        let mut entries = vec![];
+        let mut to_hash = vec![];
+        for file in files {
+            match known.get(&file.path) {
+                Some(entry) if entry.modified == file.modified => entries.push(entry.clone()),
+                _ => to_hash.push(file),
+            }
+        }
+
+        send.send(ScanEvent::Found {
+            entries,
+            dirs,
+            to_hash: to_hash.len(),
+        })
+        .await
+        .unwrap();
+
+        let pool = AsyncComputeTaskPool::get();
+        let tasks = to_hash
+            .chunks(HASH_CHUNK_SIZE)
+            .map(|chunk| {
+                let chunk = chunk.to_vec();
+                let cancel = cancel.clone();
+                let send = send.clone();
+                pool.spawn(async move {
+                    let mut entries = vec![];
+                    for file in chunk {
+                        if cancel.load(Ordering::Relaxed) {
+                            break;
+                        }
+                        if let Ok(data) = std::fs::read(&file.path) {
+                            entries.push(LibraryEntry {
+                                path: file.path,
+                                added: file.added,
+                                modified: file.modified,
+                                crc32: crc32fast::hash(&data),
+                            });
+                        }
+                    }
+                    send.send(ScanEvent::Hashed(entries)).await.unwrap();
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for task in tasks {
+            task.await;
+        }
+
+        send.send(ScanEvent::Done).await.unwrap();
+    });
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Clone)]
+struct ScannedFile {
+    path: PathBuf,
+    added: DateTime<Local>,
+    modified: SystemTime,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn scan_dir(dir: &Path, files: &mut Vec<ScannedFile>, dirs: &mut DirTimes) {
+    // Taken before reading, so that files added while reading are found by the next check
+    dirs.insert(dir.to_owned(), dir_modified(dir));
+
+    let read_dir = match std::fs::read_dir(dir) {
+        Ok(read_dir) => read_dir,
+        Err(_) => return,
+    };
+
+    for entry in read_dir.flatten() {
+        let path = entry.path();
+
+        if path.is_dir() {
+            scan_dir(&path, files, dirs);
+            continue;
+        }
+
+        if !is_rom_file(&path) {
+            continue;
+        }
+
+        let metadata = if let Ok(metadata) = entry.metadata() {
+            metadata
+        } else {
+            continue;
+        };
+
+        let modified = metadata.modified();
+        let added = metadata.created().or_else(|_| metadata.modified());
+
+        if let (Ok(added), Ok(modified)) = (added, modified) {
+            files.push(ScannedFile {
+                path,
+                added: added.into(),
+                modified,
+            });
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn dir_modified(dir: &Path) -> Option<SystemTime> {
+    std::fs::metadata(dir)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// Whether files are added, removed or renamed in `dirs` since they were scanned.
+/// ROMs overwritten in place are not noticed until the library is rescanned from the menu.
+#[cfg(not(target_arch = "wasm32"))]
+fn dirs_modified(dirs: &DirTimes) -> bool {
+    dirs.iter()
+        .any(|(dir, modified)| dir_modified(dir) != *modified)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn is_rom_file(path: &Path) -> bool {
+    use crate::core::{EmulatorEnum, ARCHIVE_EXTENSIONS};
+
+    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    EmulatorEnum::exist_supported_core(ext) || ARCHIVE_EXTENSIONS.contains(&ext)
+}
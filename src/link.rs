@@ -0,0 +1,236 @@
+use anyhow::{bail, Result};
+use bevy::prelude::*;
+use std::{
+    io::{ErrorKind, Read, Write},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+use crate::{
+    app::{AppState, ShowMessage},
+    core::Emulator,
+    utils::{unbounded_channel, Receiver},
+};
+
+const LINK_VERSION: u32 = 1;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+const MAX_HELLO_LEN: usize = 256;
+
+pub struct LinkCablePlugin;
+
+impl Plugin for LinkCablePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LinkCable>()
+            .add_system_set(SystemSet::on_update(AppState::Running).with_system(link_cable_system));
+    }
+}
+
+/// Link cable between two instances over TCP, apart from netplay.
+/// Bytes sent to the link cable by the core are forwarded to the other side as they are,
+/// and the core handles the latency in the handshakes of the link protocol.
+/// Both sides may run different games of the same system, such as for trading.
+#[derive(Default)]
+pub struct LinkCable {
+    status: Status,
+    last_error: Option<String>,
+}
+
+#[derive(Default)]
+enum Status {
+    #[default]
+    Disconnected,
+    Listening(TcpListener),
+    Connecting(Receiver<std::io::Result<TcpStream>>),
+    Handshaking(Connection),
+    Connected(Connection),
+}
+
+struct Connection {
+    stream: TcpStream,
+    read_buf: Vec<u8>,
+    write_buf: Vec<u8>,
+}
+
+impl Connection {
+    /// Sends the version and the system, which the other side checks before linking
+    fn new(stream: TcpStream, abbrev: &str) -> Result<Self> {
+        stream.set_nonblocking(true)?;
+        stream.set_nodelay(true)?;
+        Ok(Self {
+            stream,
+            read_buf: vec![],
+            write_buf: format!("MERU-LINK {LINK_VERSION} {abbrev}\n").into_bytes(),
+        })
+    }
+
+    /// Writes buffered bytes and reads received ones into `read_buf` without blocking
+    fn poll(&mut self) -> Result<()> {
+        while !self.write_buf.is_empty() {
+            match self.stream.write(&self.write_buf) {
+                Ok(0) => bail!("Connection closed"),
+                Ok(n) => {
+                    self.write_buf.drain(..n);
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        let mut buf = [0; 4096];
+        loop {
+            match self.stream.read(&mut buf) {
+                Ok(0) => bail!("Connection closed by the other side"),
+                Ok(n) => self.read_buf.extend_from_slice(&buf[..n]),
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the hello line of the other side. Returns whether it has arrived.
+    fn check_hello(&mut self, abbrev: &str) -> Result<bool> {
+        let pos = match self.read_buf.iter().position(|&b| b == b'\n') {
+            Some(pos) => pos,
+            None if self.read_buf.len() > MAX_HELLO_LEN => bail!("Not a link cable"),
+            None => return Ok(false),
+        };
+
+        let line = String::from_utf8_lossy(&self.read_buf[..pos]).into_owned();
+        // Bytes after the line are already link data
+        self.read_buf.drain(..=pos);
+
+        let mut words = line.split(' ');
+        if words.next() != Some("MERU-LINK") {
+            bail!("Not a link cable");
+        }
+        if words.next() != Some(&LINK_VERSION.to_string()) {
+            bail!("Incompatible version");
+        }
+        let other = words.next().unwrap_or_default();
+        if other != abbrev {
+            bail!("The other side is running a game of another system ({other})");
+        }
+        Ok(true)
+    }
+}
+
+impl LinkCable {
+    pub fn host(&mut self, port: u16) -> Result<()> {
+        let listener = TcpListener::bind(("0.0.0.0", port))?;
+        listener.set_nonblocking(true)?;
+        self.status = Status::Listening(listener);
+        self.last_error = None;
+        Ok(())
+    }
+
+    pub fn join(&mut self, address: &str) {
+        let address = address.to_string();
+        let (s, r) = unbounded_channel();
+
+        std::thread::spawn(move || {
+            let result = address
+                .to_socket_addrs()
+                .and_then(|mut addrs| {
+                    addrs.next().ok_or_else(|| {
+                        std::io::Error::new(ErrorKind::InvalidInput, "Invalid address")
+                    })
+                })
+                .and_then(|addr| TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT));
+            let _ = s.try_send(result);
+        });
+
+        self.status = Status::Connecting(r);
+        self.last_error = None;
+    }
+
+    pub fn disconnect(&mut self) {
+        self.status = Status::Disconnected;
+    }
+
+    /// Disconnects and keeps `err` to show in the status
+    pub fn fail(&mut self, err: &anyhow::Error) {
+        self.status = Status::Disconnected;
+        self.last_error = Some(err.to_string());
+    }
+
+    pub fn is_active(&self) -> bool {
+        !matches!(self.status, Status::Disconnected)
+    }
+
+    pub fn status_text(&self) -> String {
+        match &self.status {
+            Status::Disconnected => match &self.last_error {
+                Some(err) => format!("Disconnected: {err}"),
+                None => "Disconnected".into(),
+            },
+            Status::Listening(listener) => match listener.local_addr() {
+                Ok(addr) => format!("Waiting for the other side on port {}", addr.port()),
+                Err(_) => "Waiting for the other side".into(),
+            },
+            Status::Connecting(_) => "Connecting...".into(),
+            Status::Handshaking(_) => "Starting...".into(),
+            Status::Connected(_) => "Connected".into(),
+        }
+    }
+
+    fn update(&mut self, emulator: &mut Emulator) -> Result<Option<String>> {
+        let abbrev = emulator.core.core_info().abbrev;
+
+        let (status, msg) = match std::mem::take(&mut self.status) {
+            Status::Listening(listener) => match listener.accept() {
+                Ok((stream, _)) => (Status::Handshaking(Connection::new(stream, abbrev)?), None),
+                Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                    (Status::Listening(listener), None)
+                }
+                Err(err) => return Err(err.into()),
+            },
+            Status::Connecting(recv) => match recv.try_recv() {
+                Ok(result) => (Status::Handshaking(Connection::new(result?, abbrev)?), None),
+                Err(_) => (Status::Connecting(recv), None),
+            },
+            Status::Handshaking(mut conn) => {
+                conn.poll()?;
+                if conn.check_hello(abbrev)? {
+                    (Status::Connected(conn), Some("Link cable connected".into()))
+                } else {
+                    (Status::Handshaking(conn), None)
+                }
+            }
+            Status::Connected(mut conn) => {
+                if emulator.core.supports_link_cable() {
+                    conn.write_buf.extend(emulator.core.take_link_output());
+                    conn.poll()?;
+                    emulator.core.push_link_input(&conn.read_buf);
+                } else {
+                    conn.poll()?;
+                }
+                conn.read_buf.clear();
+                (Status::Connected(conn), None)
+            }
+            Status::Disconnected => (Status::Disconnected, None),
+        };
+
+        self.status = status;
+        Ok(msg)
+    }
+}
+
+fn link_cable_system(
+    mut link: ResMut<LinkCable>,
+    mut emulator: ResMut<Emulator>,
+    mut message_event: EventWriter<ShowMessage>,
+) {
+    match link.update(&mut emulator) {
+        Ok(Some(msg)) => message_event.send(ShowMessage::info(msg)),
+        Ok(None) => {}
+        Err(err) => {
+            link.fail(&err);
+            message_event.send(ShowMessage::error(format!("Link cable: {err}")));
+        }
+    }
+}
@@ -1,25 +1,61 @@
 use bevy::prelude::*;
 use bevy_egui::{egui, EguiContext};
 use cfg_if::cfg_if;
-use chrono::Utc;
+use chrono::{DateTime, Local};
 use enum_iterator::all;
-use meru_interface::{File, MultiKey, SingleKey};
+use meru_interface::{
+    config::schema_requires_reset, File, FirmwareInfo, FrameBuffer, MultiKey, Region, SingleKey,
+};
 use schemars::{
     schema::{InstanceType, RootSchema, Schema, SchemaObject, SingleOrVec},
     visit::{visit_schema, Visitor},
 };
 use serde_json::{json, Value};
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, BTreeSet},
     path::{Path, PathBuf},
 };
 
 use crate::{
-    app::{AppState, FullscreenState, ShowMessage, WindowControlEvent},
-    config::{Config, PersistentState, RecentFile, SystemKey, SystemKeys},
-    core::{Emulator, StateFile, ARCHIVE_EXTENSIONS, EMULATOR_CORES},
-    hotkey::{HotKey, HotKeys},
-    input::ConvertInput,
+    app::{
+        is_capture_protection_supported, AppState, FullscreenState, MessageLevel, MessageLog,
+        PendingMenuLink, ShowMessage, WindowControlEvent, MESSAGE_DURATION_SECS,
+    },
+    audio::output_device_names,
+    camera,
+    cheats::{parse_code, parse_conditions, supported_formats, Cheat},
+    config::{
+        config_dir, default_window_title, AudioPack, AudioPackTrack, ButtonLabelStyle,
+        CameraSource, Config, GbModel, Overscan, PersistentState, RecentFile, Rotation,
+        ScreenFilter, StateStorage, SystemKey, SystemKeys, TurboAudio, MAX_TEXTURE_SCALE,
+        MAX_VOLUME, MAX_ZOOM, MIN_TEXTURE_SCALE, MIN_ZOOM,
+    },
+    core::{
+        state_change_blocker, Emulator, EmulatorCores, StateFile, ARCHIVE_EXTENSIONS,
+        EMULATOR_CORES, GB_ABBREV,
+    },
+    file::{
+        delete_files, export_all, import_all, is_backup_file, is_quota_exceeded, is_state_file,
+        move_save_files, storage_usage, GameStorage,
+    },
+    firmware::{check_firmwares, install_firmware, verify_firmware, FirmwareEntry},
+    gamepad::GamepadLayouts,
+    goals::Goal,
+    hotkey::{apply_suggestion, hotkey_conflicts, HotKey, HotKeys},
+    input::{ConvertInput, InputState},
+    keyboard::OnScreenKeyboard,
+    library::{Library, LibraryDb, LibraryFilter},
+    link::LinkCable,
+    movie::Movies,
+    netplay::Netplay,
+    obs::{self, Obs, ObsStatus},
+    palette::{community_presets, export_palette, import_palette, recolor_frame, PalettePreset},
+    profile::{
+        current_profile, is_valid_profile_name, load_profile_data, set_current_profile, Profile,
+        Profiles, DEFAULT_PROFILE,
+    },
+    session::Sessions,
+    speech, texture_pack,
     utils::{spawn_local, unbounded_channel, Receiver, Sender},
 };
 
@@ -34,7 +70,8 @@ impl Plugin for MenuPlugin {
             .add_system_set(
                 SystemSet::on_update(AppState::Menu)
                     .with_system(menu_system)
-                    .with_system(menu_event_system),
+                    .with_system(menu_event_system)
+                    .with_system(profile_window_system),
             )
             .add_system_set(SystemSet::on_exit(AppState::Menu).with_system(menu_exit))
             .add_event::<MenuEvent>();
@@ -52,11 +89,49 @@ pub enum MenuEvent {
     },
     StateSaved {
         slot: usize,
+        result: anyhow::Result<StateFile>,
     },
     StateLoaded {
         slot: usize,
         data: anyhow::Result<Vec<u8>>,
     },
+    PlaySessionLoaded {
+        end: DateTime<Local>,
+        data: anyhow::Result<Vec<u8>>,
+    },
+    AddRomDir(PathBuf),
+    SetSaveDir(PathBuf),
+    SetStateDir(PathBuf),
+    SetCaptureDir(PathBuf),
+    SetAudioPackDir(PathBuf),
+    SetTexturePackDir(PathBuf),
+    SetCameraImage(PathBuf),
+    CameraPhotosExported(anyhow::Result<usize>),
+    /// File name and contents of a card to scan
+    ScanCard(String, Vec<u8>),
+    SetControllerDb(Option<PathBuf>),
+    DeleteBackup,
+    BackupDeleted(anyhow::Result<Emulator>),
+    RestoreBackup(usize),
+    BackupRestored(anyhow::Result<Emulator>),
+    FirmwareChecked(anyhow::Result<Vec<FirmwareEntry>>),
+    FirmwareInstalled {
+        abbrev: &'static str,
+        info: &'static FirmwareInfo,
+        data: Vec<u8>,
+        result: anyhow::Result<PathBuf>,
+    },
+    PaletteImported(anyhow::Result<PalettePreset>),
+    PaletteExported(anyhow::Result<PathBuf>),
+    StorageChecked(anyhow::Result<Vec<GameStorage>>),
+    StorageDeleted(anyhow::Result<()>),
+    /// Location of the exported archive, or `None` when cancelled
+    DataExported(anyhow::Result<Option<String>>),
+    DataImported(anyhow::Result<(usize, (Config, PersistentState, LibraryDb))>),
+    ProfileLoaded {
+        previous: String,
+        result: anyhow::Result<(Config, PersistentState, LibraryDb)>,
+    },
 }
 
 struct ConfigValue {
@@ -79,12 +154,15 @@ impl ConfigChannel {
 struct MenuError {
     title: String,
     message: String,
+    link: Option<MenuTab>,
 }
 
 fn setup_menu_system(
     mut commands: Commands,
     #[cfg(not(target_arch = "wasm32"))] mut windows: ResMut<Windows>,
     fullscreen_state: Res<FullscreenState>,
+    time: Res<Time>,
+    mut pending_link: ResMut<PendingMenuLink>,
 ) {
     if !fullscreen_state.0 {
         #[cfg(not(target_arch = "wasm32"))]
@@ -94,7 +172,16 @@ fn setup_menu_system(
         }
     }
 
-    commands.insert_resource(MenuState::default());
+    let mut menu_state = MenuState::default();
+
+    // Open the tab linked from a message still on screen
+    if let Some((tab, shown_at)) = pending_link.0.take() {
+        if time.seconds_since_startup() - shown_at <= MESSAGE_DURATION_SECS {
+            menu_state.open_tab(tab);
+        }
+    }
+
+    commands.insert_resource(menu_state);
     commands.insert_resource(None as Option<MenuError>);
 
     let (s, r) = unbounded_channel::<MenuEvent>();
@@ -119,12 +206,35 @@ fn menu_event_system(
     mut persistent_state: ResMut<PersistentState>,
     mut menu_error: ResMut<Option<MenuError>>,
     mut message_event: EventWriter<ShowMessage>,
-    config: Res<Config>,
+    mut config: ResMut<Config>,
+    mut menu_state: ResMut<MenuState>,
+    mut sessions: ResMut<Sessions>,
+    (netplay, movies): (Res<Netplay>, Res<Movies>),
 ) {
+    // Requests from the menu made before netplay or a movie started are refused too
+    let blocker = state_change_blocker(&netplay, &movies);
+
     while let Ok(event) = recv.try_recv() {
         match event {
             MenuEvent::OpenRomFile { path, data } => {
-                let config = config.clone();
+                if menu_state.loading.is_some() {
+                    continue;
+                }
+
+                // Resume the suspended session instead of loading the same game twice
+                if let (Some(index), Some(emulator)) =
+                    (sessions.find(&path), emulator.as_deref_mut())
+                {
+                    sessions.switch(index, emulator);
+                    app_state.set(AppState::Running).unwrap();
+                    continue;
+                }
+                menu_state.loading = Some(
+                    path.file_name()
+                        .map_or_else(String::new, |name| name.to_string_lossy().to_string()),
+                );
+
+                let config = config.as_ref().clone();
                 let send = send.clone();
 
                 let recent = RecentFile {
@@ -144,35 +254,93 @@ fn menu_event_system(
                     fut.await.unwrap();
                 });
             }
-            MenuEvent::OpenRomDone { recent, result } => match result {
-                Ok(emulator) => {
-                    commands.insert_resource(emulator);
+            MenuEvent::OpenRomDone { recent, result } => {
+                menu_state.loading = None;
+
+                match result {
+                    Ok(new_emulator) => {
+                        match emulator.as_deref_mut() {
+                            Some(emulator)
+                                if config.max_sessions > 0
+                                    && emulator.rom_path != new_emulator.rom_path =>
+                            {
+                                let prev = std::mem::replace(emulator, new_emulator);
+                                sessions.suspend(prev, config.max_sessions);
+                            }
+                            _ => commands.insert_resource(new_emulator),
+                        }
 
-                    persistent_state.add_recent(recent);
-                    let fut = persistent_state.save();
-                    spawn_local(async move {
-                        fut.await.unwrap();
-                    });
-                    app_state.set(AppState::Running).unwrap();
+                        persistent_state.add_recent(recent);
+                        let fut = persistent_state.save();
+                        spawn_local(async move {
+                            fut.await.unwrap();
+                        });
+                        app_state.set(AppState::Running).unwrap();
+                    }
+                    Err(err) => {
+                        let message = err.to_string();
+                        *menu_error.as_mut() = Some(MenuError {
+                            title: "Failed to open ROM".into(),
+                            link: firmware_error_link(&message),
+                            message,
+                        });
+                    }
+                }
+            }
+            MenuEvent::StateSaved { slot, result } => match result {
+                Ok(state_file) => {
+                    if let Some(emulator) = emulator.as_deref_mut() {
+                        emulator.state_files[slot] = Some(state_file);
+                    }
+                    menu_state.storage = None;
+                    message_event.send(ShowMessage::info(format!("State saved: #{slot}")));
                 }
                 Err(err) => {
+                    let quota_exceeded = is_quota_exceeded(&err);
                     *menu_error.as_mut() = Some(MenuError {
-                        title: "Failed to open ROM".into(),
-                        message: err.to_string(),
+                        title: "Failed to save state".into(),
+                        message: if quota_exceeded {
+                            "Storage is full. Delete save data or states you no longer need.".into()
+                        } else {
+                            err.to_string()
+                        },
+                        link: quota_exceeded.then_some(MenuTab::Storage),
                     });
                 }
             },
-            MenuEvent::StateSaved { slot } => {
-                if let Some(emulator) = emulator.as_deref_mut() {
-                    let state_file = StateFile {
-                        modified: Utc::now().into(),
-                    };
-                    emulator.state_files[slot] = Some(state_file);
+            MenuEvent::PlaySessionLoaded { end, data } => {
+                let f = || -> anyhow::Result<()> {
+                    if let Some(reason) = blocker {
+                        anyhow::bail!("Not available {reason}");
+                    }
+                    let data = data?;
+                    let emulator = emulator
+                        .as_deref_mut()
+                        .ok_or_else(|| anyhow::anyhow!("No emulator instance"))?;
+                    emulator.load_state_data(&data)?;
+                    Ok(())
+                };
+
+                let end = end.format("%Y/%m/%d %H:%M");
+                match f() {
+                    Ok(_) => {
+                        message_event.send(ShowMessage::info(format!(
+                            "Resumed from the session ended at {end}"
+                        )));
+                        app_state.set(AppState::Running).unwrap();
+                    }
+                    Err(e) => {
+                        message_event.send(ShowMessage::error(format!(
+                            "Failed to resume the session ended at {end}: {e}"
+                        )));
+                    }
                 }
-                message_event.send(ShowMessage(format!("State saved: #{slot}")));
             }
             MenuEvent::StateLoaded { slot, data } => {
                 let f = || -> anyhow::Result<()> {
+                    if let Some(reason) = blocker {
+                        anyhow::bail!("Not available {reason}");
+                    }
                     let data = data?;
                     let emulator = emulator
                         .as_deref_mut()
@@ -183,31 +351,438 @@ fn menu_event_system(
 
                 match f() {
                     Ok(_) => {
-                        message_event.send(ShowMessage(format!("State loaded: #{slot}")));
+                        message_event.send(ShowMessage::info(format!("State loaded: #{slot}")));
                     }
                     Err(e) => {
-                        message_event.send(ShowMessage(format!(
+                        message_event.send(ShowMessage::error(format!(
                             "Failed to load state from slot #{slot}: {e}"
                         )));
                     }
                 }
                 app_state.set(AppState::Running).unwrap();
             }
+            MenuEvent::DeleteBackup => {
+                if let Some(emulator) = emulator.as_deref() {
+                    let delete = emulator.delete_backup();
+                    let reload = emulator.reload(config.as_ref(), false);
+                    let send = send.clone();
+
+                    spawn_local(async move {
+                        let result = match delete.await {
+                            Ok(()) => reload.await,
+                            Err(err) => Err(err),
+                        };
+                        send.send(MenuEvent::BackupDeleted(result)).await.unwrap();
+                    });
+                }
+            }
+            MenuEvent::BackupDeleted(result) => match result {
+                Ok(new_emulator) => {
+                    if let Some(emulator) = emulator.as_deref_mut() {
+                        emulator.discard_backup();
+                    }
+                    commands.insert_resource(new_emulator);
+                    message_event.send(ShowMessage::info("Save data deleted".to_string()));
+                }
+                Err(err) => {
+                    *menu_error.as_mut() = Some(MenuError {
+                        title: "Failed to delete save data".into(),
+                        message: err.to_string(),
+                        link: None,
+                    });
+                }
+            },
+            MenuEvent::RestoreBackup(index) => {
+                if let Some(reason) = blocker {
+                    message_event.send(ShowMessage::error(format!(
+                        "Restoring save data is not available {reason}"
+                    )));
+                } else if let Some(emulator) = emulator.as_deref() {
+                    let restore = emulator.restore_backup_snapshot(index);
+                    let reload = emulator.reload(config.as_ref(), true);
+                    let send = send.clone();
+
+                    spawn_local(async move {
+                        let result = match restore.await {
+                            Ok(()) => reload.await,
+                            Err(err) => Err(err),
+                        };
+                        send.send(MenuEvent::BackupRestored(result)).await.unwrap();
+                    });
+                }
+            }
+            MenuEvent::BackupRestored(result) => match result {
+                Ok(new_emulator) => {
+                    if let Some(emulator) = emulator.as_deref_mut() {
+                        emulator.discard_backup();
+                    }
+                    commands.insert_resource(new_emulator);
+                    message_event.send(ShowMessage::info("Save data restored".to_string()));
+                }
+                Err(err) => {
+                    *menu_error.as_mut() = Some(MenuError {
+                        title: "Failed to restore save data".into(),
+                        message: err.to_string(),
+                        link: None,
+                    });
+                }
+            },
+            MenuEvent::SetSaveDir(dir) => {
+                if let Err(err) = move_save_files(&config.save_dir, &dir, is_backup_file) {
+                    *menu_error.as_mut() = Some(MenuError {
+                        title: "Failed to move save files".into(),
+                        message: err.to_string(),
+                        link: None,
+                    });
+                    continue;
+                }
+
+                if let Some(emulator) = emulator.as_deref_mut() {
+                    emulator.set_save_dir(&dir);
+                }
+                config.save_dir = dir;
+
+                let config = config.clone();
+                spawn_local(async move { config.save().await.unwrap() });
+            }
+            MenuEvent::SetStateDir(dir) => {
+                if let Err(err) = move_save_files(&config.state_dir, &dir, is_state_file) {
+                    *menu_error.as_mut() = Some(MenuError {
+                        title: "Failed to move state files".into(),
+                        message: err.to_string(),
+                        link: None,
+                    });
+                    continue;
+                }
+
+                if let Some(emulator) = emulator.as_deref_mut() {
+                    emulator.set_state_dir(&dir);
+                }
+                config.state_dir = dir;
+
+                let config = config.clone();
+                spawn_local(async move { config.save().await.unwrap() });
+            }
+            MenuEvent::SetCaptureDir(dir) => {
+                config.capture_dir = dir;
+
+                let config = config.clone();
+                spawn_local(async move { config.save().await.unwrap() });
+            }
+            MenuEvent::SetAudioPackDir(dir) => {
+                if let Some(emulator) = emulator.as_deref() {
+                    if let Some(pack) = config.audio_packs.get_mut(&emulator.game_hash) {
+                        pack.dir = dir;
+                    }
+
+                    let config = config.clone();
+                    spawn_local(async move { config.save().await.unwrap() });
+                }
+            }
+            MenuEvent::SetTexturePackDir(dir) => {
+                if let Some(emulator) = emulator.as_deref() {
+                    if let Some(pack) = config.texture_packs.get_mut(&emulator.game_hash) {
+                        pack.dir = dir;
+                    }
+
+                    let config = config.clone();
+                    spawn_local(async move { config.save().await.unwrap() });
+                }
+            }
+            MenuEvent::SetCameraImage(path) => {
+                config.camera.image = Some(path);
+
+                let config = config.clone();
+                spawn_local(async move { config.save().await.unwrap() });
+            }
+            MenuEvent::CameraPhotosExported(result) => match result {
+                Ok(count) => {
+                    message_event.send(ShowMessage::info(format!(
+                        "Exported {count} photos to {}",
+                        config.capture_dir.display()
+                    )));
+                }
+                Err(err) => {
+                    *menu_error.as_mut() = Some(MenuError {
+                        title: "Failed to export photos".into(),
+                        message: err.to_string(),
+                        link: None,
+                    });
+                }
+            },
+            MenuEvent::ScanCard(name, data) => {
+                if let Some(emulator) = emulator.as_deref_mut() {
+                    match emulator.core.scan_card(&data) {
+                        Ok(()) => message_event.send(ShowMessage::info(format!("Scanned {name}"))),
+                        Err(err) => {
+                            *menu_error.as_mut() = Some(MenuError {
+                                title: "Failed to scan card".into(),
+                                message: err.to_string(),
+                                link: None,
+                            });
+                        }
+                    }
+                }
+            }
+            MenuEvent::SetControllerDb(path) => {
+                config.controller_db = path;
+
+                let config = config.clone();
+                spawn_local(async move { config.save().await.unwrap() });
+
+                message_event.send(ShowMessage::info(
+                    "Controller mappings will be applied after restart",
+                ));
+            }
+            MenuEvent::AddRomDir(dir) => {
+                if !config.rom_dirs.contains(&dir) {
+                    config.rom_dirs.push(dir);
+
+                    let config = config.clone();
+                    spawn_local(async move { config.save().await.unwrap() });
+                }
+            }
+            MenuEvent::PaletteImported(result) => match result {
+                Ok(preset) => {
+                    message_event.send(ShowMessage::info(format!(
+                        "Imported palette: {}",
+                        preset.name
+                    )));
+                    config.add_palette_preset(preset);
+
+                    let config = config.clone();
+                    spawn_local(async move { config.save().await.unwrap() });
+                }
+                Err(err) => {
+                    *menu_error.as_mut() = Some(MenuError {
+                        title: "Failed to import palette".into(),
+                        message: err.to_string(),
+                        link: None,
+                    });
+                }
+            },
+            MenuEvent::PaletteExported(result) => match result {
+                Ok(path) => {
+                    message_event.send(ShowMessage::info(format!(
+                        "Exported palette: {}",
+                        path.display()
+                    )));
+                }
+                Err(err) => {
+                    *menu_error.as_mut() = Some(MenuError {
+                        title: "Failed to export palette".into(),
+                        message: err.to_string(),
+                        link: None,
+                    });
+                }
+            },
+            MenuEvent::StorageChecked(result) => match result {
+                Ok(games) => menu_state.storage = Some(games),
+                Err(err) => {
+                    *menu_error.as_mut() = Some(MenuError {
+                        title: "Failed to check storage".into(),
+                        message: err.to_string(),
+                        link: None,
+                    });
+                }
+            },
+            MenuEvent::DataExported(result) => match result {
+                Ok(Some(location)) => {
+                    message_event.send(ShowMessage::info(format!("Exported all data: {location}")));
+                }
+                Ok(None) => {}
+                Err(err) => {
+                    *menu_error.as_mut() = Some(MenuError {
+                        title: "Failed to export data".into(),
+                        message: err.to_string(),
+                        link: None,
+                    });
+                }
+            },
+            MenuEvent::DataImported(result) => match result {
+                Ok((files, (mut new_config, new_persistent_state, new_library_db))) => {
+                    // Files were imported to the current directories
+                    new_config.save_dir = config.save_dir.clone();
+                    new_config.state_dir = config.state_dir.clone();
+                    *config = new_config;
+                    let config = config.clone();
+                    spawn_local(async move { config.save().await.unwrap() });
+                    *persistent_state = new_persistent_state;
+                    commands.insert_resource(new_library_db);
+                    menu_state.storage = None;
+                    message_event.send(ShowMessage::info(format!("Imported {files} files")));
+                }
+                Err(err) => {
+                    *menu_error.as_mut() = Some(MenuError {
+                        title: "Failed to import data".into(),
+                        message: err.to_string(),
+                        link: None,
+                    });
+                }
+            },
+            MenuEvent::ProfileLoaded { previous, result } => match result {
+                Ok((new_config, new_persistent_state, new_library_db)) => {
+                    // Dropping the emulator saves its backup to the previous profile
+                    commands.remove_resource::<Emulator>();
+                    sessions.games.clear();
+                    *config = new_config;
+                    *persistent_state = new_persistent_state;
+                    commands.insert_resource(new_library_db);
+                    menu_state.storage = None;
+                    menu_state.firmware = None;
+                    menu_state.tab = MenuTab::File;
+                    message_event.send(ShowMessage::info(format!(
+                        "Switched to profile: {}",
+                        current_profile()
+                    )));
+                }
+                Err(err) => {
+                    set_current_profile(&previous);
+                    *menu_error.as_mut() = Some(MenuError {
+                        title: "Failed to load profile".into(),
+                        message: err.to_string(),
+                        link: None,
+                    });
+                }
+            },
+            MenuEvent::StorageDeleted(result) => {
+                menu_state.storage = None;
+                if let Err(err) = result {
+                    *menu_error.as_mut() = Some(MenuError {
+                        title: "Failed to delete files".into(),
+                        message: err.to_string(),
+                        link: None,
+                    });
+                }
+            }
+            MenuEvent::FirmwareChecked(result) => match result {
+                Ok(entries) => menu_state.firmware = Some(entries),
+                Err(err) => {
+                    *menu_error.as_mut() = Some(MenuError {
+                        title: "Failed to check firmware".into(),
+                        message: err.to_string(),
+                        link: None,
+                    });
+                }
+            },
+            MenuEvent::FirmwareInstalled {
+                abbrev,
+                info,
+                data,
+                result,
+            } => match result {
+                Ok(path) => {
+                    let verified = verify_firmware(info, &data);
+
+                    let mut core_config = config.core_config(abbrev);
+                    core_config[info.config_key] = serde_json::to_value(File::new(path, data))
+                        .expect("Failed to serialize file");
+
+                    if let Some(emulator) = emulator.as_deref_mut() {
+                        if emulator.core.core_info().abbrev == abbrev {
+                            emulator.set_core_config(&core_config);
+                        }
+                    }
+                    config.set_core_config(abbrev, core_config);
+
+                    let config = config.clone();
+                    spawn_local(async move { config.save().await.unwrap() });
+
+                    menu_state.firmware = None;
+
+                    message_event.send(if verified {
+                        ShowMessage::info(format!("Installed {}", info.name))
+                    } else {
+                        ShowMessage::error(format!(
+                            "Installed {}, but its hash does not match any known dump",
+                            info.name
+                        ))
+                    });
+                }
+                Err(err) => {
+                    *menu_error.as_mut() = Some(MenuError {
+                        title: "Failed to install firmware".into(),
+                        message: err.to_string(),
+                        link: None,
+                    });
+                }
+            },
         }
     }
 }
 
 #[derive(PartialEq, Eq, Clone)]
-enum MenuTab {
+pub enum MenuTab {
     File,
+    Library,
     State,
     GameInfo,
+    Cheats,
+    Goals,
+    Movies,
+    AudioPack,
+    TexturePack,
     GeneralSetting,
     CoreSetting(String),
     ControllerSetting(String),
     Graphics,
+    Audio,
+    Firmware,
+    Sessions,
+    Storage,
+    Netplay,
+    Integrations,
+    InputTest,
     HotKey,
     SystemKey,
+    Messages,
+}
+
+impl MenuTab {
+    pub fn name(&self) -> String {
+        match self {
+            MenuTab::File => "File".into(),
+            MenuTab::Library => "Library".into(),
+            MenuTab::State => "State Save/Load".into(),
+            MenuTab::GameInfo => "Game Info".into(),
+            MenuTab::Cheats => "Cheats".into(),
+            MenuTab::Goals => "Goals".into(),
+            MenuTab::Movies => "Movies".into(),
+            MenuTab::AudioPack => "Audio Pack".into(),
+            MenuTab::TexturePack => "Texture Pack".into(),
+            MenuTab::GeneralSetting => "General Setting".into(),
+            MenuTab::CoreSetting(abbrev) => format!("{abbrev} Setting"),
+            MenuTab::ControllerSetting(abbrev) => format!("{abbrev} Controller Setting"),
+            MenuTab::Graphics => "Graphics".into(),
+            MenuTab::Audio => "Audio".into(),
+            MenuTab::Firmware => "BIOS / Firmware".into(),
+            MenuTab::Sessions => "Sessions".into(),
+            MenuTab::Storage => "Storage".into(),
+            MenuTab::Netplay => "Netplay".into(),
+            MenuTab::Integrations => "Integrations".into(),
+            MenuTab::InputTest => "Controller Test".into(),
+            MenuTab::HotKey => "Hotkey".into(),
+            MenuTab::SystemKey => "System Key".into(),
+            MenuTab::Messages => "Messages".into(),
+        }
+    }
+
+    /// Tabs hidden in simple mode
+    fn is_advanced(&self) -> bool {
+        use MenuTab::*;
+        matches!(
+            self,
+            GeneralSetting
+                | CoreSetting(_)
+                | ControllerSetting(_)
+                | Firmware
+                | Integrations
+                | InputTest
+                | HotKey
+                | SystemKey
+                | Messages
+        )
+    }
 }
 
 #[derive(PartialEq, Eq)]
@@ -225,6 +800,26 @@ struct MenuState {
     constructing_hotkey: Option<Vec<SingleKey>>,
     system_key_tab: ControllerTab,
     system_key_ix: usize,
+    library_filter: LibraryFilter,
+    new_collection_name: String,
+    library_query: String,
+    new_tag: String,
+    new_cheat: Cheat,
+    new_goal: Goal,
+    confirm_delete_backup: bool,
+    confirm_reset_cores: Option<Vec<String>>,
+    keyboard: OnScreenKeyboard,
+    audio_devices: Option<Vec<String>>,
+    firmware: Option<Vec<FirmwareEntry>>,
+    storage: Option<Vec<GameStorage>>,
+    input_test_pad: usize,
+    palette_edit: Option<PaletteEdit>,
+    palette_preview: Option<egui::TextureHandle>,
+    state_thumbnails: StateThumbnails,
+    /// Show advanced tabs in simple mode
+    show_advanced: bool,
+    /// Name of the ROM being loaded
+    loading: Option<String>,
 }
 
 impl Default for MenuState {
@@ -238,13 +833,58 @@ impl Default for MenuState {
             constructing_hotkey: None,
             system_key_tab: ControllerTab::Keyboard,
             system_key_ix: 0,
+            library_filter: LibraryFilter::All,
+            new_collection_name: String::new(),
+            library_query: String::new(),
+            new_tag: String::new(),
+            new_cheat: Cheat::default(),
+            new_goal: Goal::default(),
+            confirm_delete_backup: false,
+            confirm_reset_cores: None,
+            keyboard: OnScreenKeyboard::default(),
+            audio_devices: None,
+            firmware: None,
+            storage: None,
+            input_test_pad: 0,
+            palette_edit: None,
+            palette_preview: None,
+            state_thumbnails: StateThumbnails::new(),
+            show_advanced: false,
+            loading: None,
         }
     }
 }
 
+fn button_label(ui: &mut egui::Ui, name: &str, added: bool) {
+    if added {
+        ui.label(format!("{name} 🆕"))
+            .on_hover_text("Newly added button. Its default assignment has been applied");
+    } else {
+        ui.label(name);
+    }
+}
+
 impl MenuState {
-    fn tab_selector(&mut self, ui: &mut egui::Ui, emulator_loaded: bool) {
+    fn open_tab(&mut self, tab: MenuTab) {
+        if tab.is_advanced() {
+            self.show_advanced = true;
+        }
+        self.tab = tab;
+    }
+
+    fn tab_selector(
+        &mut self,
+        ui: &mut egui::Ui,
+        emulator_loaded: bool,
+        has_sessions: bool,
+        simple_mode: bool,
+    ) {
         ui.selectable_value(&mut self.tab, MenuTab::File, "📁 File");
+        ui.selectable_value(&mut self.tab, MenuTab::Library, "📚 Library");
+
+        ui.add_enabled_ui(has_sessions, |ui| {
+            ui.selectable_value(&mut self.tab, MenuTab::Sessions, "🗂 Sessions");
+        });
 
         ui.add_enabled_ui(emulator_loaded, |ui| {
             ui.selectable_value(&mut self.tab, MenuTab::State, "💾 State Save/Load");
@@ -254,30 +894,225 @@ impl MenuState {
             ui.selectable_value(&mut self.tab, MenuTab::GameInfo, "ℹ Game Info");
         });
 
-        ui.selectable_value(&mut self.tab, MenuTab::GeneralSetting, "🔧 General Setting");
-        ui.selectable_value(&mut self.tab, MenuTab::Graphics, "🖼 Graphics");
+        ui.add_enabled_ui(emulator_loaded, |ui| {
+            ui.selectable_value(&mut self.tab, MenuTab::Cheats, "🎲 Cheats");
+        });
 
-        ui.collapsing("⚙ Core Setting", |ui| {
-            for core_info in Emulator::core_infos() {
-                ui.selectable_value(
-                    &mut self.tab,
-                    MenuTab::CoreSetting(core_info.abbrev.into()),
-                    core_info.system_name,
-                );
-            }
+        ui.add_enabled_ui(emulator_loaded, |ui| {
+            ui.selectable_value(&mut self.tab, MenuTab::Goals, "🏆 Goals");
         });
-        ui.collapsing("🎮 Controller Setting", |ui| {
-            for core_info in Emulator::core_infos() {
-                ui.selectable_value(
-                    &mut self.tab,
-                    MenuTab::ControllerSetting(core_info.abbrev.into()),
-                    core_info.system_name,
-                );
-            }
+
+        ui.add_enabled_ui(emulator_loaded, |ui| {
+            ui.selectable_value(&mut self.tab, MenuTab::Movies, "🎬 Movies");
         });
 
+        ui.selectable_value(&mut self.tab, MenuTab::Graphics, "🖼 Graphics");
+        ui.selectable_value(&mut self.tab, MenuTab::Audio, "🔊 Audio");
+
+        ui.add_enabled_ui(emulator_loaded, |ui| {
+            ui.selectable_value(&mut self.tab, MenuTab::AudioPack, "🎵 Audio Pack");
+        });
+
+        ui.add_enabled_ui(emulator_loaded, |ui| {
+            ui.selectable_value(&mut self.tab, MenuTab::TexturePack, "🧩 Texture Pack");
+        });
+
+        ui.selectable_value(&mut self.tab, MenuTab::Storage, "🗄 Storage");
+        ui.selectable_value(&mut self.tab, MenuTab::Netplay, "🌐 Netplay");
+
+        if simple_mode {
+            ui.separator();
+            if ui
+                .selectable_label(self.show_advanced, "🔓 Advanced")
+                .clicked()
+            {
+                self.show_advanced = !self.show_advanced;
+            }
+
+            if !self.show_advanced {
+                if self.tab.is_advanced() {
+                    self.tab = MenuTab::File;
+                }
+                return;
+            }
+        }
+
+        ui.selectable_value(&mut self.tab, MenuTab::GeneralSetting, "🔧 General Setting");
+        ui.selectable_value(&mut self.tab, MenuTab::Firmware, "🔑 BIOS / Firmware");
+
+        ui.collapsing("⚙ Core Setting", |ui| {
+            for core_info in Emulator::core_infos() {
+                ui.selectable_value(
+                    &mut self.tab,
+                    MenuTab::CoreSetting(core_info.abbrev.into()),
+                    core_info.system_name,
+                );
+            }
+        });
+        ui.collapsing("🎮 Controller Setting", |ui| {
+            for core_info in Emulator::core_infos() {
+                ui.selectable_value(
+                    &mut self.tab,
+                    MenuTab::ControllerSetting(core_info.abbrev.into()),
+                    core_info.system_name,
+                );
+            }
+        });
+
+        ui.selectable_value(&mut self.tab, MenuTab::InputTest, "🕹 Controller Test");
         ui.selectable_value(&mut self.tab, MenuTab::HotKey, "⌨ Hotkey");
         ui.selectable_value(&mut self.tab, MenuTab::SystemKey, "💻 System Key");
+        ui.selectable_value(&mut self.tab, MenuTab::Integrations, "🔌 Integrations");
+        ui.selectable_value(&mut self.tab, MenuTab::Messages, "🔔 Messages");
+    }
+
+    fn tab_library(
+        &mut self,
+        ui: &mut egui::Ui,
+        library: &mut Library,
+        library_db: &mut LibraryDb,
+        menu_event: &Sender<MenuEvent>,
+        menu_error: &mut Option<MenuError>,
+    ) {
+        ui.heading("Library");
+
+        #[cfg(not(target_arch = "wasm32"))]
+        ui.horizontal(|ui| {
+            if library.is_scanning() {
+                if let Some((hashed, total)) = library.scan_progress() {
+                    ui.add(
+                        egui::ProgressBar::new(hashed as f32 / total as f32)
+                            .text(format!("Scanning... {hashed}/{total}"))
+                            .desired_width(200.0),
+                    );
+                } else {
+                    ui.spinner();
+                    ui.label("Scanning...");
+                }
+                if ui.button("Cancel").clicked() {
+                    library.cancel_scan();
+                }
+            } else {
+                ui.label(format!("{} games", library.entries.len()));
+                if ui.button("Rescan").clicked() {
+                    library.rescan();
+                }
+            }
+        });
+
+        let mut changed = false;
+
+        ui.horizontal_wrapped(|ui| {
+            ui.selectable_value(&mut self.library_filter, LibraryFilter::All, "All");
+            ui.selectable_value(
+                &mut self.library_filter,
+                LibraryFilter::Favorites,
+                "★ Favorites",
+            );
+            for name in &library_db.collections {
+                ui.selectable_value(
+                    &mut self.library_filter,
+                    LibraryFilter::Collection(name.clone()),
+                    name,
+                );
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("New collection:");
+            self.keyboard
+                .text_edit_singleline(ui, &mut self.new_collection_name);
+            let name = self.new_collection_name.trim().to_string();
+            if ui
+                .add_enabled(!name.is_empty(), egui::Button::new("Create"))
+                .clicked()
+            {
+                library_db.add_collection(&name);
+                self.new_collection_name.clear();
+                changed = true;
+            }
+
+            if let LibraryFilter::Collection(name) = self.library_filter.clone() {
+                if ui.button(format!("Delete `{name}`")).clicked() {
+                    library_db.remove_collection(&name);
+                    self.library_filter = LibraryFilter::All;
+                    changed = true;
+                }
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Search:");
+            self.keyboard
+                .text_edit_singleline(ui, &mut self.library_query)
+                .on_hover_text("Matches file names, notes and tags");
+            if ui.button("✖").clicked() {
+                self.library_query.clear();
+            }
+        });
+
+        ui.separator();
+
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            egui::Grid::new("library")
+                .num_columns(3)
+                .spacing([20.0, 4.0])
+                .striped(true)
+                .show(ui, |ui| {
+                    for entry in &library.entries {
+                        if !library_db.matches(&entry.path, &self.library_filter)
+                            || !library_db.matches_query(entry, &self.library_query)
+                        {
+                            continue;
+                        }
+
+                        let favorite = library_db.is_favorite(&entry.path);
+                        if ui
+                            .selectable_label(favorite, if favorite { "★" } else { "☆" })
+                            .on_hover_text("Add to favorites")
+                            .clicked()
+                        {
+                            library_db.update_game(&entry.path, |r| r.favorite = !favorite);
+                            changed = true;
+                        }
+
+                        if ui
+                            .button(entry.name())
+                            .on_hover_text(format!("CRC32: {:08X}", entry.crc32))
+                            .clicked()
+                        {
+                            open_rom_path(&entry.path, menu_event, menu_error);
+                        }
+
+                        ui.add_enabled_ui(!library_db.collections.is_empty(), |ui| {
+                            ui.menu_button("Collections", |ui| {
+                                for name in library_db.collections.clone() {
+                                    let mut contained = library_db
+                                        .game(&entry.path)
+                                        .map_or(false, |r| r.collections.contains(&name));
+                                    if ui.checkbox(&mut contained, &name).changed() {
+                                        library_db.update_game(&entry.path, |r| {
+                                            if contained {
+                                                r.collections.insert(name.clone());
+                                            } else {
+                                                r.collections.remove(&name);
+                                            }
+                                        });
+                                        changed = true;
+                                    }
+                                }
+                            });
+                        });
+
+                        ui.end_row();
+                    }
+                });
+        });
+
+        if changed {
+            let fut = library_db.save();
+            spawn_local(async move { fut.await.unwrap() });
+        }
     }
 
     fn tab_controller(
@@ -287,8 +1122,16 @@ impl MenuState {
         core: &str,
         key_code_input: &Input<KeyCode>,
         gamepad_button_input: &Input<GamepadButton>,
+        gamepad_layouts: &GamepadLayouts,
     ) {
         let mut key_config = config.key_config(core).clone();
+        let added_keys = config.added_keys(core).to_vec();
+        let controller_ix = self.controller_ix;
+        let is_added = |name: &str| {
+            added_keys
+                .iter()
+                .any(|(i, key)| *i == controller_ix && key == name)
+        };
 
         if self.controller_ix >= key_config.controllers.len() {
             self.controller_ix = 0;
@@ -340,7 +1183,7 @@ impl MenuState {
                             .enumerate()
                         {
                             let ix = ix + 1;
-                            ui.label(name.clone());
+                            button_label(ui, name, is_added(name));
                             let assign_str = assign
                                 .extract_keycode()
                                 .map_or_else(|| "".to_string(), |k| format!("{k:?}"));
@@ -365,11 +1208,12 @@ impl MenuState {
                             .enumerate()
                         {
                             let ix = ix + 1;
-                            ui.label(name.clone());
+                            button_label(ui, name, is_added(name));
 
-                            let assign_str = assign
-                                .extract_gamepad()
-                                .map_or_else(|| "".to_string(), |k| k.to_string());
+                            let assign_str = assign.extract_gamepad().map_or_else(
+                                || "".to_string(),
+                                |k| gamepad_layouts.button_label(&k),
+                            );
 
                             ui.selectable_value(&mut self.controller_button_ix, ix, assign_str)
                                 .on_hover_text("Click and press the button you want to assign");
@@ -400,6 +1244,8 @@ impl MenuState {
             self.controller_button_ix = 0;
             config.set_key_config(core, default_key_config);
         }
+
+        hotkey_conflict_setting(ui, config, gamepad_layouts, Some(core));
     }
 
     fn tab_hotkey(
@@ -408,6 +1254,7 @@ impl MenuState {
         config: &mut Config,
         key_code_input: &Input<KeyCode>,
         gamepad_button_input: &Input<GamepadButton>,
+        gamepad_layouts: &GamepadLayouts,
     ) {
         let grid = |ui: &mut egui::Ui| {
             ui.label("HotKey");
@@ -469,12 +1316,12 @@ impl MenuState {
                             }
 
                             if let Some(mk) = &self.constructing_hotkey {
-                                MultiKey(mk.clone()).to_string()
+                                gamepad_layouts.multi_key_label(&MultiKey(mk.clone()))
                             } else {
-                                key_assign.0[i].to_string()
+                                gamepad_layouts.multi_key_label(&key_assign.0[i])
                             }
                         } else {
-                            key_assign.0[i].to_string()
+                            gamepad_layouts.multi_key_label(&key_assign.0[i])
                         };
 
                         if ui
@@ -498,7 +1345,7 @@ impl MenuState {
                         }
 
                         if let Some(mk) = &self.constructing_hotkey {
-                            MultiKey(mk.clone()).to_string()
+                            gamepad_layouts.multi_key_label(&MultiKey(mk.clone()))
                         } else {
                             "...".to_string()
                         }
@@ -524,6 +1371,14 @@ impl MenuState {
         if ui.button("Reset to default").clicked() {
             config.hotkeys = HotKeys::default();
         }
+
+        hotkey_conflict_setting(ui, config, gamepad_layouts, None);
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            ui.separator();
+            global_hotkey_setting(ui, config, &mut self.keyboard);
+        }
     }
 
     fn tab_system_key(
@@ -532,6 +1387,7 @@ impl MenuState {
         config: &mut Config,
         key_code_input: &Input<KeyCode>,
         gamepad_button_input: &Input<GamepadButton>,
+        gamepad_layouts: &GamepadLayouts,
     ) {
         ui.horizontal(|ui| {
             let mut resp = ui.selectable_value(
@@ -600,9 +1456,10 @@ impl MenuState {
 
                             let assign = config.system_keys.key_assign_mut(&key);
 
-                            let assign_str = assign
-                                .and_then(|r| r.extract_gamepad())
-                                .map_or_else(|| "".to_string(), |k| k.to_string());
+                            let assign_str = assign.and_then(|r| r.extract_gamepad()).map_or_else(
+                                || "".to_string(),
+                                |k| gamepad_layouts.button_label(&k),
+                            );
 
                             ui.selectable_value(&mut self.system_key_ix, ix, assign_str)
                                 .on_hover_text("Click and type the key you want to assign");
@@ -637,22 +1494,62 @@ impl MenuState {
 #[allow(clippy::too_many_arguments)]
 fn menu_system(
     mut config: ResMut<Config>,
-    persistent_state: Res<PersistentState>,
+    mut persistent_state: ResMut<PersistentState>,
+    (
+        mut library,
+        mut library_db,
+        mut message_log,
+        gamepad_layouts,
+        gamepads,
+        mut sessions,
+        mut profile_window,
+        mut netplay,
+        mut link_cable,
+        mut movies,
+        mut obs,
+    ): (
+        ResMut<Library>,
+        ResMut<LibraryDb>,
+        ResMut<MessageLog>,
+        Res<GamepadLayouts>,
+        Res<Gamepads>,
+        ResMut<Sessions>,
+        ResMut<ProfileWindow>,
+        ResMut<Netplay>,
+        ResMut<LinkCable>,
+        ResMut<Movies>,
+        ResMut<Obs>,
+    ),
     mut egui_ctx: ResMut<EguiContext>,
     mut app_state: ResMut<State<AppState>>,
     mut menu_state: ResMut<MenuState>,
     mut emulator: Option<ResMut<Emulator>>,
     menu_event: Res<Sender<MenuEvent>>,
     config_channel: Res<ConfigChannel>,
-    mut window_control_event: EventWriter<WindowControlEvent>,
+    (mut window_control_event, mut message_event): (
+        EventWriter<WindowControlEvent>,
+        EventWriter<ShowMessage>,
+    ),
     mut menu_error: ResMut<Option<MenuError>>,
     key_code_input: Res<Input<KeyCode>>,
     gamepad_button_input: Res<Input<GamepadButton>>,
+    gamepad_axis_input: Res<Axis<GamepadAxis>>,
     fullscreen_state: Res<FullscreenState>,
 ) {
+    if menu_error.is_changed() {
+        if let Some(error) = menu_error.as_ref() {
+            message_log.push(
+                format!("{}: {}", error.title, error.message),
+                MessageLevel::Error,
+                error.link.clone(),
+            );
+        }
+    }
+
     if let Some(error) = menu_error.as_ref() {
         let mut open = true;
         let mut clicked = false;
+        let mut open_link = false;
         egui::Window::new(&error.title)
             .open(&mut open)
             .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
@@ -661,13 +1558,26 @@ fn menu_system(
 
                 ui.with_layout(layout, |ui| {
                     ui.label(&error.message);
-                    if ui.button("OK").clicked() {
-                        clicked = true;
-                    }
+                    ui.horizontal(|ui| {
+                        if let Some(link) = &error.link {
+                            if ui.button(format!("Open {}", link.name())).clicked() {
+                                open_link = true;
+                            }
+                        }
+                        if ui.button("OK").clicked() {
+                            clicked = true;
+                        }
+                    });
                 });
             });
 
-        if !open || clicked {
+        if open_link {
+            if let Some(link) = error.link.clone() {
+                menu_state.open_tab(link);
+            }
+        }
+
+        if !open || clicked || open_link {
             *menu_error.as_mut() = None;
         }
     }
@@ -675,7 +1585,7 @@ fn menu_system(
     while let Ok(config_value) = config_channel.receiver.try_recv() {
         if let Some(emulator) = emulator.as_deref_mut() {
             if emulator.core.core_info().abbrev == config_value.abbrev {
-                emulator.core.set_config(&config_value.value);
+                emulator.set_core_config(&config_value.value);
             }
         }
 
@@ -685,9 +1595,32 @@ fn menu_system(
         spawn_local(async move { config.save().await.unwrap() });
     }
 
+    let input_state = InputState::new(&key_code_input, &gamepad_button_input, &gamepad_axis_input);
+    let gamepad_used = gamepad_button_input.get_just_pressed().next().is_some();
+    menu_state
+        .keyboard
+        .update_input(&config.system_keys, &input_state, gamepad_used);
+
     let old_config = config.clone();
 
+    if let Some(name) = &menu_state.loading {
+        egui::Window::new("Loading")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(egui_ctx.ctx_mut(), |ui| {
+                ui.horizontal(|ui| {
+                    ui.spinner();
+                    ui.label(format!("Loading {name}..."));
+                });
+            });
+    }
+    let loading = menu_state.loading.is_some();
+
     egui::CentralPanel::default().show(egui_ctx.ctx_mut(), |ui| {
+        // Prevent opening another file while loading
+        ui.set_enabled(!loading);
+
         let width = ui.available_width();
 
         let frame = egui::Frame::default();
@@ -697,10 +1630,25 @@ fn menu_system(
             ui.set_width(width / 4.0);
 
             ui.with_layout(egui::Layout::top_down_justified(egui::Align::Min), |ui| {
-                menu_state.tab_selector(ui, emulator.is_some());
+                menu_state.tab_selector(
+                    ui,
+                    emulator.is_some(),
+                    !sessions.games.is_empty(),
+                    config.simple_mode,
+                );
+
+                ui.separator();
+                if ui
+                    .selectable_label(profile_window.open, format!("👤 {}", current_profile()))
+                    .clicked()
+                {
+                    profile_window.toggle();
+                }
             });
         });
 
+        let menu_state = menu_state.as_mut();
+        let blocker = state_change_blocker(&netplay, &movies);
         egui::CentralPanel::default().show_inside(ui, |ui| match menu_state.tab.clone() {
             MenuTab::File => {
                 tab_file(
@@ -708,25 +1656,114 @@ fn menu_system(
                     emulator.as_ref().map(|r| r.as_ref()),
                     app_state.as_mut(),
                     persistent_state.as_ref(),
+                    library.as_ref(),
+                    menu_event.as_ref(),
+                    menu_error.as_mut(),
+                );
+            }
+            MenuTab::Library => {
+                menu_state.tab_library(
+                    ui,
+                    library.as_mut(),
+                    library_db.as_mut(),
                     menu_event.as_ref(),
                     menu_error.as_mut(),
                 );
             }
             MenuTab::State => {
                 if let Some(emulator) = emulator.as_deref_mut() {
-                    tab_state(ui, emulator, config.as_ref(), &menu_event);
+                    tab_state(
+                        ui,
+                        emulator,
+                        config.as_ref(),
+                        &mut menu_state.state_thumbnails,
+                        blocker,
+                        &menu_event,
+                    );
                 }
             }
             MenuTab::GameInfo => {
+                if let Some(emulator) = emulator.as_deref_mut() {
+                    tab_game_info(
+                        ui,
+                        emulator,
+                        library_db.as_mut(),
+                        &mut menu_state.new_tag,
+                        &mut menu_state.confirm_delete_backup,
+                        &mut menu_state.keyboard,
+                        blocker,
+                        &menu_event,
+                    );
+                }
+            }
+            MenuTab::Cheats => {
+                if let Some(emulator) = emulator.as_deref_mut() {
+                    tab_cheats(
+                        ui,
+                        emulator,
+                        persistent_state.as_mut(),
+                        &mut menu_state.new_cheat,
+                        &mut menu_state.keyboard,
+                    );
+                }
+            }
+            MenuTab::Goals => {
+                if let Some(emulator) = emulator.as_deref() {
+                    tab_goals(
+                        ui,
+                        emulator,
+                        persistent_state.as_mut(),
+                        &mut menu_state.new_goal,
+                        &mut menu_state.keyboard,
+                    );
+                }
+            }
+            MenuTab::Movies => {
                 if let Some(emulator) = emulator.as_deref() {
-                    tab_game_info(ui, emulator);
+                    tab_movies(
+                        ui,
+                        emulator,
+                        &mut movies,
+                        netplay.is_active(),
+                        config.as_ref(),
+                        app_state.as_mut(),
+                    );
+                }
+            }
+            MenuTab::AudioPack => {
+                if let Some(emulator) = emulator.as_deref() {
+                    tab_audio_pack(
+                        ui,
+                        emulator,
+                        config.as_mut(),
+                        &mut menu_state.keyboard,
+                        &menu_event,
+                    );
+                }
+            }
+            MenuTab::TexturePack => {
+                if let Some(emulator) = emulator.as_deref() {
+                    tab_texture_pack(ui, emulator, config.as_mut(), &menu_event);
                 }
             }
             MenuTab::GeneralSetting => {
                 ui.heading("General Settings");
                 ui.with_layout(egui::Layout::top_down_justified(egui::Align::Min), |ui| {
                     ui.group(|ui| {
-                        tab_general_setting(ui, &mut config);
+                        tab_general_setting(
+                            ui,
+                            &mut config,
+                            &mut menu_state.keyboard,
+                            &menu_event,
+                        );
+                    });
+                    ui.group(|ui| {
+                        ui.label("Accessibility");
+                        ui.add_enabled_ui(speech::is_supported(), |ui| {
+                            ui.checkbox(&mut config.text_to_speech, "Text-to-speech")
+                                .on_hover_text("Read focused menu items and messages aloud")
+                                .on_disabled_hover_text("Not supported in this build");
+                        });
                     });
                 });
             }
@@ -735,6 +1772,120 @@ fn menu_system(
                 ui.with_layout(egui::Layout::top_down_justified(egui::Align::Min), |ui| {
                     ui.group(|ui| {
                         ui.checkbox(&mut config.show_fps, "Display FPS");
+                        ui.checkbox(&mut config.show_perf_stats, "Display performance stats")
+                            .on_hover_text("Time spent in the core and in the whole frame, and cycles executed by the core. Shows whether slowdowns come from the core or the frontend");
+                        ui.checkbox(&mut config.reduce_flashing, "Reduce flashing");
+                        ui.checkbox(&mut config.high_contrast_ui, "High contrast menu theme");
+                        ui.checkbox(&mut config.animations, "Animations");
+
+                        ui.horizontal(|ui| {
+                            ui.label("Anti-aliasing (MSAA):");
+
+                            let label = |samples: u32| match samples {
+                                1 => "Off".to_string(),
+                                n => format!("{n}x"),
+                            };
+                            egui::ComboBox::from_id_source("msaa_samples")
+                                .selected_text(label(config.msaa_samples))
+                                .show_ui(ui, |ui| {
+                                    // WebGL2 supports only 4x
+                                    let options: &[u32] = if cfg!(target_arch = "wasm32") {
+                                        &[1, 4]
+                                    } else {
+                                        &[1, 2, 4, 8]
+                                    };
+                                    for &samples in options {
+                                        ui.selectable_value(
+                                            &mut config.msaa_samples,
+                                            samples,
+                                            label(samples),
+                                        );
+                                    }
+                                });
+                        });
+
+                        // Without a game, the global setting is edited
+                        let game_hash = emulator
+                            .as_deref()
+                            .map_or(String::new(), |emulator| emulator.game_hash.clone());
+
+                        if let Some(emulator) = emulator.as_deref() {
+                            let mut per_game = config.game_video_settings.contains_key(&game_hash);
+                            if ui
+                                .checkbox(
+                                    &mut per_game,
+                                    format!(
+                                        "Use separate screen settings for {}",
+                                        emulator.game_name
+                                    ),
+                                )
+                                .changed()
+                            {
+                                if per_game {
+                                    let video = config.video_setting(&game_hash);
+                                    config.game_video_settings.insert(game_hash.clone(), video);
+                                } else {
+                                    config.game_video_settings.remove(&game_hash);
+                                }
+                            }
+                        }
+
+                        let mut video = config.video_setting(&game_hash);
+
+                        ui.horizontal(|ui| {
+                            ui.label("Screen Filter:");
+
+                            egui::ComboBox::from_id_source("screen_filter")
+                                .selected_text(video.screen_filter.to_string())
+                                .show_ui(ui, |ui| {
+                                    for filter in all::<ScreenFilter>() {
+                                        ui.selectable_value(
+                                            &mut video.screen_filter,
+                                            filter,
+                                            filter.to_string(),
+                                        );
+                                    }
+                                });
+                        });
+
+                        if video.screen_filter == ScreenFilter::Xbr {
+                            ui.colored_label(
+                                egui::Color32::YELLOW,
+                                "⚠ xBR samples many pixels per output pixel and may drop frames on slow GPUs, especially at high scaling",
+                            );
+                        }
+
+                        ui.checkbox(&mut video.frame_interpolation, "Frame Interpolation")
+                            .on_hover_text("Blend consecutive frames for smooth motion on 120 Hz or faster displays. Adds a frame of latency");
+
+                        ui.horizontal(|ui| {
+                            ui.label("Rotation:");
+
+                            egui::ComboBox::from_id_source("rotation")
+                                .selected_text(config.rotation.to_string())
+                                .show_ui(ui, |ui| {
+                                    for rotation in all::<Rotation>() {
+                                        ui.selectable_value(
+                                            &mut config.rotation,
+                                            rotation,
+                                            rotation.to_string(),
+                                        );
+                                    }
+                                });
+                        })
+                        .response
+                        .on_hover_text("For playing on vertically held devices");
+
+                        ui.horizontal(|ui| {
+                            ui.label("Zoom:");
+                            ui.add(
+                                egui::Slider::new(&mut config.zoom, MIN_ZOOM..=MAX_ZOOM)
+                                    .step_by(10.0)
+                                    .suffix("%"),
+                            );
+                        })
+                        .response
+                        .on_hover_text("Magnify the screen. Pan with the right stick");
 
                         let mut fullscreen = fullscreen_state.0;
                         if ui.checkbox(&mut fullscreen, "Full Screen").changed() {
@@ -746,16 +1897,70 @@ fn menu_system(
                             ui.label("Window Scale:");
 
                             if ui
-                                .add(egui::Slider::new(&mut config.scaling, 1..=8))
+                                .add(egui::Slider::new(&mut video.scaling, 1..=8))
                                 .changed()
                             {
                                 window_control_event
-                                    .send(WindowControlEvent::ChangeScale(config.scaling));
+                                    .send(WindowControlEvent::ChangeScale(video.scaling));
                             }
                         });
+
+                        if video != config.video_setting(&game_hash) {
+                            config.set_video_setting(&game_hash, video);
+                        }
+                    });
+                });
+            }
+            MenuTab::Audio => {
+                ui.heading("Audio Settings");
+                ui.with_layout(egui::Layout::top_down_justified(egui::Align::Min), |ui| {
+                    ui.group(|ui| {
+                        tab_audio_setting(ui, &mut config, &mut menu_state.audio_devices);
+                    });
+                });
+            }
+            MenuTab::Storage => {
+                ui.heading("Storage");
+                tab_storage(
+                    ui,
+                    config.as_ref(),
+                    emulator.as_deref(),
+                    &mut menu_state.storage,
+                    &menu_event,
+                );
+            }
+            MenuTab::Netplay => {
+                ui.heading("Netplay");
+                tab_netplay(
+                    ui,
+                    &mut netplay,
+                    config.as_mut(),
+                    emulator.as_deref(),
+                    &mut menu_state.keyboard,
+                );
+
+                ui.separator();
+                ui.heading("Link Cable");
+                tab_link_cable(
+                    ui,
+                    &mut link_cable,
+                    config.as_mut(),
+                    emulator.as_deref(),
+                    &mut menu_state.keyboard,
+                );
+            }
+            MenuTab::Integrations => {
+                ui.heading("Integrations");
+                ui.with_layout(egui::Layout::top_down_justified(egui::Align::Min), |ui| {
+                    ui.group(|ui| {
+                        tab_obs(ui, &mut obs, config.as_mut(), &mut menu_state.keyboard);
                     });
                 });
             }
+            MenuTab::Firmware => {
+                ui.heading("BIOS / Firmware");
+                tab_firmware(ui, config.as_ref(), &mut menu_state.firmware, &menu_event);
+            }
             MenuTab::CoreSetting(core) => {
                 let core_info = Emulator::core_infos()
                     .into_iter()
@@ -763,12 +1968,145 @@ fn menu_system(
                     .unwrap();
 
                 ui.heading(format!("{} Settings", core_info.system_name));
+
+                let boot_config = emulator
+                    .as_deref()
+                    .filter(|emulator| emulator.core.core_info().abbrev == core_info.abbrev)
+                    .map(|emulator| emulator.boot_config().clone());
+
+                if let Some(emulator) = emulator.as_deref_mut().filter(|emulator| {
+                    emulator.core.core_info().abbrev == core_info.abbrev
+                        && emulator.has_config_checkpoint()
+                }) {
+                    ui.horizontal(|ui| {
+                        if emulator.core_failed {
+                            ui.colored_label(
+                                egui::Color32::RED,
+                                "The core stopped after the config change",
+                            );
+                        } else {
+                            ui.label("The game state before the config change is kept for a while");
+                        }
+                        let revert = ui
+                            .add_enabled(
+                                blocker.is_none(),
+                                egui::Button::new("Revert config and restore"),
+                            )
+                            .on_disabled_hover_text(format!(
+                                "Not available {}",
+                                blocker.unwrap_or_default()
+                            ));
+                        if revert.clicked() {
+                            match emulator.revert_core_config() {
+                                Ok(core_config) => {
+                                    config.set_core_config(core_info.abbrev, core_config);
+                                    message_event.send(ShowMessage::info(
+                                        "Restored the state before the config change",
+                                    ));
+                                }
+                                Err(err) => message_event.send(ShowMessage::error(format!(
+                                    "Failed to restore state: {err}"
+                                ))),
+                            }
+                        }
+                    });
+                }
+
+                let mut pending_reset = vec![];
                 ui.with_layout(egui::Layout::top_down_justified(egui::Align::Min), |ui| {
                     ui.group(|ui| {
                         let core_config = config.core_config(core_info.abbrev);
-                        core_config_ui(ui, core_info.abbrev, core_config, &config_channel.sender);
+                        pending_reset = core_config_ui(
+                            ui,
+                            core_info.abbrev,
+                            core_config,
+                            boot_config,
+                            &mut menu_state.palette_edit,
+                            community_presets()
+                                .chain(config.palette_presets.iter().cloned())
+                                .collect(),
+                            &menu_event,
+                            &config_channel.sender,
+                        );
+                    });
+
+                    let state = &mut *menu_state;
+                    if let Some(edit) = &state.palette_edit {
+                        let frame_buffer = emulator
+                            .as_deref()
+                            .filter(|emulator| emulator.core.core_info().abbrev == core_info.abbrev)
+                            .map(|emulator| emulator.screen());
+                        palette_preview(
+                            ui,
+                            edit,
+                            frame_buffer.as_deref(),
+                            &mut state.palette_preview,
+                        );
+                    }
+
+                    ui.group(|ui| {
+                        core_frontend_setting(ui, &mut config, core_info.abbrev);
+                    });
+
+                    if core_info.abbrev == GB_ABBREV {
+                        ui.group(|ui| {
+                            gb_model_setting(
+                                ui,
+                                &mut config,
+                                emulator.as_deref_mut(),
+                                blocker,
+                            );
+                        });
+                        ui.group(|ui| {
+                            camera_setting(ui, &mut config, emulator.as_deref(), &menu_event);
+                        });
+                    }
+
+                    let card_reader = emulator.as_deref().map_or(false, |emulator| {
+                        emulator.core.core_info().abbrev == core_info.abbrev
+                            && emulator.core.supports_card_scan()
                     });
+                    if card_reader {
+                        ui.group(|ui| {
+                            card_reader_setting(ui, &menu_event);
+                        });
+                    }
                 });
+
+                if !pending_reset.is_empty() {
+                    ui.horizontal(|ui| {
+                        ui.colored_label(
+                            egui::Color32::YELLOW,
+                            format!(
+                                "Takes effect after the core is reset: {}",
+                                pending_reset.join(", ")
+                            ),
+                        );
+                        let reset = ui
+                            .add_enabled(
+                                blocker.is_none(),
+                                egui::Button::new("Reset core to apply"),
+                            )
+                            .on_disabled_hover_text(format!(
+                                "Not available {}",
+                                blocker.unwrap_or_default()
+                            ));
+                        if reset.clicked() {
+                            if let Some(emulator) = emulator.as_deref_mut() {
+                                emulator.reset();
+                                message_event.send(ShowMessage::info("Reset machine".to_string()));
+                            }
+                        }
+                    });
+                }
+
+                reset_core_config_ui(
+                    ui,
+                    config.as_ref(),
+                    core_info.abbrev,
+                    &mut menu_state.confirm_reset_cores,
+                    &config_channel.sender,
+                );
             }
             MenuTab::ControllerSetting(core) => {
                 let core_info = Emulator::core_infos()
@@ -783,15 +2121,34 @@ fn menu_system(
                     &core,
                     key_code_input.as_ref(),
                     gamepad_button_input.as_ref(),
+                    gamepad_layouts.as_ref(),
                 );
             }
-            MenuTab::HotKey => {
-                ui.heading("Hotkey Settings");
-                menu_state.tab_hotkey(
+            MenuTab::Sessions => {
+                ui.heading("Sessions");
+                if let Some(emulator) = emulator.as_deref_mut() {
+                    tab_sessions(ui, emulator, sessions.as_mut(), app_state.as_mut());
+                }
+            }
+            MenuTab::InputTest => {
+                ui.heading("Controller Test");
+                tab_input_test(
+                    ui,
+                    &mut menu_state.input_test_pad,
+                    gamepads.as_ref(),
+                    gamepad_button_input.as_ref(),
+                    gamepad_axis_input.as_ref(),
+                    gamepad_layouts.as_ref(),
+                );
+            }
+            MenuTab::HotKey => {
+                ui.heading("Hotkey Settings");
+                menu_state.tab_hotkey(
                     ui,
                     config.as_mut(),
                     key_code_input.as_ref(),
                     gamepad_button_input.as_ref(),
+                    gamepad_layouts.as_ref(),
                 );
             }
             MenuTab::SystemKey => {
@@ -801,16 +2158,25 @@ fn menu_system(
                     config.as_mut(),
                     key_code_input.as_ref(),
                     gamepad_button_input.as_ref(),
+                    gamepad_layouts.as_ref(),
                 );
             }
+            MenuTab::Messages => {
+                ui.heading("Messages");
+                if let Some(link) = tab_messages(ui, &mut message_log) {
+                    menu_state.open_tab(link);
+                }
+            }
         });
     });
 
     if &old_config != config.as_ref() {
         if let Some(emulator) = emulator.as_deref_mut() {
-            emulator
-                .core
-                .set_config(&config.core_config(emulator.core.core_info().abbrev));
+            let abbrev = emulator.core.core_info().abbrev;
+            let core_config = config.core_config(abbrev);
+            emulator.set_core_config(&core_config);
+            emulator.overscan = config.overscan(abbrev);
+            emulator.rotation = config.rotation;
         }
 
         let config = config.clone();
@@ -820,6 +2186,15 @@ fn menu_system(
     }
 }
 
+/// Cores report missing BIOS only as an error message
+fn firmware_error_link(message: &str) -> Option<MenuTab> {
+    let message = message.to_lowercase();
+    ["bios", "boot rom", "firmware"]
+        .iter()
+        .any(|word| message.contains(word))
+        .then_some(MenuTab::Firmware)
+}
+
 fn file_dialog_filters() -> Vec<(String, Vec<String>)> {
     let mut ret = vec![("All files".into(), vec!["*".to_string()])];
 
@@ -886,6 +2261,7 @@ fn tab_file(
     emulator: Option<&Emulator>,
     app_state: &mut State<AppState>,
     persistent_state: &PersistentState,
+    library: &Library,
     menu_event: &Sender<MenuEvent>,
     #[allow(unused_variables)] menu_error: &mut Option<MenuError>,
 ) {
@@ -947,6 +2323,7 @@ fn tab_file(
                             *menu_error = Some(MenuError {
                                 title: "Failed to open ROM".into(),
                                 message: err.to_string(),
+                                link: None,
                             });
                             continue;
                         }
@@ -963,6 +2340,18 @@ fn tab_file(
                     .unwrap();
             }
         }
+
+        let recently_added = library.recently_added();
+        if !recently_added.is_empty() {
+            ui.separator();
+            ui.label("Recently Added");
+
+            for entry in recently_added {
+                if ui.button(entry.name()).clicked() {
+                    open_rom_path(&entry.path, menu_event, menu_error);
+                }
+            }
+        }
     };
 
     egui::ScrollArea::vertical().show(ui, |ui| {
@@ -970,10 +2359,73 @@ fn tab_file(
     });
 }
 
+fn open_rom_path(path: &Path, menu_event: &Sender<MenuEvent>, menu_error: &mut Option<MenuError>) {
+    match std::fs::read(path) {
+        Ok(data) => {
+            let path = path.to_owned();
+            menu_event
+                .try_send(MenuEvent::OpenRomFile { path, data })
+                .unwrap();
+        }
+        Err(err) => {
+            *menu_error = Some(MenuError {
+                title: "Failed to open ROM".into(),
+                message: err.to_string(),
+                link: None,
+            });
+        }
+    }
+}
+
+/// Thumbnails of state slots, uploaded once per saved state
+pub type StateThumbnails = BTreeMap<usize, (DateTime<Local>, egui::TextureHandle)>;
+
+pub fn state_thumbnail(
+    ctx: &egui::Context,
+    thumbnails: &mut StateThumbnails,
+    slot: usize,
+    state_file: Option<&StateFile>,
+) -> Option<egui::TextureHandle> {
+    let state_file = if let Some(state_file) = state_file {
+        state_file
+    } else {
+        thumbnails.remove(&slot);
+        return None;
+    };
+
+    let image = if let Some(image) = &state_file.thumbnail {
+        image
+    } else {
+        thumbnails.remove(&slot);
+        return None;
+    };
+
+    if let Some((modified, texture)) = thumbnails.get(&slot) {
+        if *modified == state_file.modified {
+            return Some(texture.clone());
+        }
+    }
+
+    let image = egui::ColorImage::from_rgba_unmultiplied(
+        [image.width() as usize, image.height() as usize],
+        image.as_raw(),
+    );
+    // Nearest neighbor keeps pixels sharp at integer scales
+    let texture = ctx.load_texture(
+        format!("state-thumbnail-{slot}"),
+        image,
+        egui::TextureFilter::Nearest,
+    );
+    thumbnails.insert(slot, (state_file.modified, texture.clone()));
+    Some(texture)
+}
+
 fn tab_state(
     ui: &mut egui::Ui,
     emulator: &mut Emulator,
     config: &Config,
+    thumbnails: &mut StateThumbnails,
+    blocker: Option<&str>,
     menu_event: &Sender<MenuEvent>,
 ) {
     ui.heading("State Save / Load");
@@ -982,19 +2434,37 @@ fn tab_state(
         for i in 0..10 {
             ui.label(format!("{}", i));
 
+            let thumbnail =
+                state_thumbnail(ui.ctx(), thumbnails, i, emulator.state_files[i].as_ref());
+            if let Some(texture) = thumbnail {
+                let size = texture.size_vec2();
+                ui.image(&texture, size / 4.0).on_hover_ui(|ui| {
+                    ui.image(&texture, size * 2.0);
+                });
+            } else {
+                ui.label("");
+            }
+
             if ui.button("Save").clicked() {
                 let menu_event = menu_event.clone();
                 let fut = emulator.save_state_slot(i, config);
+                let state_file = emulator.current_state_file();
                 spawn_local(async move {
-                    fut.await.unwrap();
+                    let result = fut.await.map(|_| state_file);
                     menu_event
-                        .send(MenuEvent::StateSaved { slot: i })
+                        .send(MenuEvent::StateSaved { slot: i, result })
                         .await
                         .unwrap();
                 });
             }
             ui.add_enabled_ui(emulator.state_files[i].is_some(), |ui| {
-                if ui.button("Load").clicked() {
+                let load = ui
+                    .add_enabled(blocker.is_none(), egui::Button::new("Load"))
+                    .on_disabled_hover_text(format!(
+                        "Not available {}",
+                        blocker.unwrap_or_default()
+                    ));
+                if load.clicked() {
                     let menu_event = menu_event.clone();
                     let fut = emulator.load_state_slot(i, config);
                     spawn_local(async move {
@@ -1011,6 +2481,12 @@ fn tab_state(
                 || "---".to_string(),
                 |state_file| state_file.modified.format("%Y/%m/%d %H:%M:%S").to_string(),
             ));
+            ui.label(
+                emulator.state_files[i]
+                    .as_ref()
+                    .and_then(|state_file| state_file.context.as_deref())
+                    .unwrap_or(""),
+            );
             ui.end_row();
         }
     };
@@ -1020,7 +2496,7 @@ fn tab_state(
             ui.label("Slot");
 
             egui::Grid::new("state_save")
-                .num_columns(4)
+                .num_columns(6)
                 .spacing([40.0, 4.0])
                 .striped(true)
                 .show(ui, grid);
@@ -1028,157 +2504,2143 @@ fn tab_state(
     });
 }
 
-fn tab_game_info(ui: &mut egui::Ui, emulator: &Emulator) {
-    let info = emulator.core.game_info();
+fn tab_sessions(
+    ui: &mut egui::Ui,
+    emulator: &mut Emulator,
+    sessions: &mut Sessions,
+    app_state: &mut State<AppState>,
+) {
+    ui.label(format!("Running `{}`", emulator.game_name));
 
-    ui.heading("Game Info");
+    ui.separator();
 
-    egui::Grid::new("key_config")
-        .num_columns(2)
-        .spacing([40.0, 4.0])
+    if sessions.games.is_empty() {
+        ui.label("No suspended games");
+        return;
+    }
+
+    let mut switched = None;
+    let mut closed = None;
+
+    egui::Grid::new("sessions")
+        .num_columns(3)
+        .spacing([20.0, 4.0])
         .striped(true)
         .show(ui, |ui| {
-            for (key, value) in info {
-                ui.label(key);
-                ui.label(value);
+            for (i, game) in sessions.games.iter().enumerate() {
+                ui.label(&game.game_name);
+                if ui.button("Switch").clicked() {
+                    switched = Some(i);
+                }
+                if ui
+                    .button("Close")
+                    .on_hover_text("Save data is written and the game is unloaded")
+                    .clicked()
+                {
+                    closed = Some(i);
+                }
                 ui.end_row();
             }
         });
+
+    if let Some(i) = switched {
+        sessions.switch(i, emulator);
+        app_state.set(AppState::Running).unwrap();
+    } else if let Some(i) = closed {
+        sessions.close(i);
+    }
 }
 
-fn tab_general_setting(ui: &mut egui::Ui, config: &mut ResMut<Config>) {
-    ui.horizontal(|ui| {
-        ui.label("Frame skip on turbo:");
+/// Toggles for graphics layers, for cores that support hiding them
+fn layer_toolbar(ui: &mut egui::Ui, emulator: &mut Emulator) {
+    let layer_names = emulator.core.layer_names();
+    if layer_names.is_empty() {
+        return;
+    }
 
-        ui.add(egui::Slider::new(&mut config.frame_skip_on_turbo, 1..=10));
+    ui.horizontal(|ui| {
+        ui.label("Layers:");
+        for (i, name) in layer_names.iter().enumerate() {
+            let enabled = emulator.is_layer_enabled(i);
+            if ui
+                .selectable_label(enabled, *name)
+                .on_hover_text("Show/hide this layer")
+                .clicked()
+            {
+                emulator.set_layer_enabled(i, !enabled);
+            }
+        }
+        if ui.button("Show all").clicked() {
+            for i in 0..layer_names.len() {
+                emulator.set_layer_enabled(i, true);
+            }
+        }
     });
+}
 
-    ui.separator();
+fn tab_cheats(
+    ui: &mut egui::Ui,
+    emulator: &mut Emulator,
+    persistent_state: &mut PersistentState,
+    new_cheat: &mut Cheat,
+    keyboard: &mut OnScreenKeyboard,
+) {
+    ui.heading("Cheats");
 
-    #[cfg(not(target_arch = "wasm32"))]
-    {
-        ui.label("TODO: Save directory");
+    if !emulator.core.supports_memory_access() {
+        ui.label("This core does not support cheats");
+        return;
+    }
 
-        // let mut save_dir = Some(config.save_dir.clone());
-        // if file_field(ui, "Save file directory:", &mut save_dir, &[], false) {
-        //     config.save_dir = save_dir.unwrap();
-        // }
-        // ui.separator();
+    if emulator.cheats_suspended() {
+        ui.colored_label(
+            egui::Color32::YELLOW,
+            "Cheats are not applied during netplay or movie recording and playback",
+        );
     }
 
-    ui.label("Rewinding:");
+    let abbrev = emulator.core.core_info().abbrev;
+    let mut cheats = persistent_state.cheats(&emulator.game_hash).to_vec();
+    let mut remove = None;
 
-    ui.horizontal(|ui| {
-        ui.label("Memory budget for rewinding:");
-        let mut rate_in_kb = config.auto_state_save_rate / 1024;
-        ui.add(
-            egui::Slider::new(&mut rate_in_kb, 0..=8192)
-                .logarithmic(true)
-                .suffix("KiB/s"),
-        );
-        config.auto_state_save_rate = rate_in_kb * 1024;
-    });
+    egui::Grid::new("cheats")
+        .num_columns(3)
+        .striped(true)
+        .show(ui, |ui| {
+            for (ix, cheat) in cheats.iter_mut().enumerate() {
+                ui.checkbox(&mut cheat.enabled, &cheat.description);
+                ui.monospace(cheat.code.replace('\n', " + "));
+                if ui.small_button("✖").on_hover_text("Remove").clicked() {
+                    remove = Some(ix);
+                }
+                ui.end_row();
+            }
+        });
 
-    ui.horizontal(|ui| {
-        ui.label("Maximum memory amount for rewinding:");
-        let mut amount_in_mb = config.auto_state_save_limit / (1024 * 1024);
-        ui.add(
-            egui::Slider::new(&mut amount_in_mb, 0..=8192)
-                .logarithmic(true)
-                .suffix("MiB"),
-        );
-        config.auto_state_save_limit = amount_in_mb * 1024 * 1024;
-    });
+    if cheats.is_empty() {
+        ui.label("No cheats for this game");
+    }
 
-    ui.horizontal(|ui| {
-        ui.label("Minimum auto save span:");
-        ui.add(
-            egui::Slider::new(&mut config.minimum_auto_save_span, 1..=300)
-                .logarithmic(true)
-                .suffix("Frames"),
-        );
+    if let Some(ix) = remove {
+        cheats.remove(ix);
+    }
+
+    ui.separator();
+
+    egui::Grid::new("new_cheat").num_columns(2).show(ui, |ui| {
+        ui.label("Description:");
+        keyboard.text_edit_singleline(ui, &mut new_cheat.description);
+        ui.end_row();
+
+        ui.label("Code:");
+        keyboard.text_edit(ui, &mut new_cheat.code, |text| {
+            egui::TextEdit::multiline(text).desired_rows(2)
+        });
+        ui.end_row();
     });
+    ui.small(format!("Formats: {}", supported_formats(abbrev)));
 
-    // FIXME: reset auto save timing state when changed rewinding setting
-}
+    let parsed = parse_code(abbrev, &new_cheat.code);
+    if let Err(err) = &parsed {
+        if !new_cheat.code.trim().is_empty() {
+            ui.colored_label(egui::Color32::RED, err.to_string());
+        }
+    }
 
-pub struct FileFieldResult {
-    file_sent: bool,
-    cleard: bool,
-}
+    if ui
+        .add_enabled(parsed.is_ok(), egui::Button::new("Add"))
+        .clicked()
+    {
+        let mut cheat = std::mem::take(new_cheat);
+        if cheat.description.trim().is_empty() {
+            cheat.description = format!("Cheat {}", cheats.len() + 1);
+        }
+        cheat.code = cheat.code.trim().to_string();
+        cheat.enabled = true;
+        cheats.push(cheat);
+    }
 
-#[derive(Clone, Debug)]
-enum FieldIndex {
-    Object(String),
-    Array(usize),
+    ui.label("Cheats are not applied while playing over netplay.");
+
+    if cheats.as_slice() != persistent_state.cheats(&emulator.game_hash) {
+        emulator.set_cheats(&cheats);
+        if cheats.is_empty() {
+            persistent_state.games.cheats.remove(&emulator.game_hash);
+        } else {
+            persistent_state
+                .games
+                .cheats
+                .insert(emulator.game_hash.clone(), cheats);
+        }
+        let fut = persistent_state.save();
+        spawn_local(async move { fut.await.unwrap() });
+    }
 }
 
-pub fn file_field(
+fn tab_goals(
     ui: &mut egui::Ui,
-    sender: &Sender<(PathBuf, Vec<u8>)>,
-    label: &str,
-    path: &mut Option<PathBuf>,
-    file_filter: &[(&str, &[&str])],
-    has_clear: bool,
-) -> FileFieldResult {
-    let mut file_sent = false;
-    let mut changed = false;
-
-    ui.horizontal(|ui| {
-        ui.label(label);
-        if ui.button("Change").clicked() {
-            file_sent = true;
+    emulator: &Emulator,
+    persistent_state: &mut PersistentState,
+    new_goal: &mut Goal,
+    keyboard: &mut OnScreenKeyboard,
+) {
+    ui.heading("Goals");
 
-            let cur_path = path.clone();
-            let file_filter = file_filter
-                .iter()
-                .map(|(name, exts)| {
-                    (
-                        name.to_string(),
-                        exts.iter().map(|ext| ext.to_string()).collect::<Vec<_>>(),
-                    )
-                })
-                .collect::<Vec<_>>();
+    if !emulator.core.supports_memory_access() {
+        ui.label("This core does not support goals");
+        return;
+    }
 
-            let sender = sender.clone();
-            spawn_local(async move {
-                let filter_keys = file_filter.iter().map(|(name, _)| name.as_str());
-                let filter_vals = file_filter
-                    .iter()
-                    .map(|(_, val)| val.iter().map(|r| r.as_str()).collect::<Vec<_>>())
-                    .collect::<Vec<_>>();
-                let filter_ref = filter_keys
-                    .into_iter()
-                    .zip(filter_vals.iter())
-                    .map(|(key, val)| (key, val.as_slice()))
-                    .collect::<Vec<_>>();
+    let mut goals = persistent_state.goals(&emulator.game_hash).to_vec();
+    let mut remove = None;
 
-                if let Some((path, data)) =
-                    file_dialog(cur_path.as_deref(), &filter_ref, file_filter.is_empty()).await
-                {
-                    sender.send((path, data)).await.unwrap();
+    egui::Grid::new("goals")
+        .num_columns(5)
+        .striped(true)
+        .show(ui, |ui| {
+            for (ix, goal) in goals.iter_mut().enumerate() {
+                ui.label(&goal.name);
+                ui.monospace(goal.condition.replace('\n', " + "));
+                if let Some(reached) = goal.reached {
+                    ui.label(format!("✔ {}", reached.format("%Y/%m/%d %H:%M")));
+                    if ui.small_button("Reset").clicked() {
+                        goal.reached = None;
+                    }
+                } else {
+                    ui.label("Not reached");
+                    ui.label("");
                 }
-            });
+                if ui.small_button("✖").on_hover_text("Remove").clicked() {
+                    remove = Some(ix);
+                }
+                ui.end_row();
+            }
+        });
+
+    if goals.is_empty() {
+        ui.label("No goals for this game");
+    } else {
+        let reached = goals.iter().filter(|goal| goal.reached.is_some()).count();
+        ui.label(format!("{reached} / {} reached", goals.len()));
+    }
+
+    if let Some(ix) = remove {
+        goals.remove(ix);
+    }
+
+    ui.separator();
+
+    egui::Grid::new("new_goal").num_columns(2).show(ui, |ui| {
+        ui.label("Name:");
+        keyboard.text_edit_singleline(ui, &mut new_goal.name);
+        ui.end_row();
+
+        ui.label("Condition:");
+        keyboard.text_edit(ui, &mut new_goal.condition, |text| {
+            egui::TextEdit::multiline(text).desired_rows(2)
+        });
+        ui.end_row();
+    });
+    ui.small("AAAA?CC: the byte at AAAA is CC. Use ?!, ?<, ?<=, ?> or ?>= for other comparisons. Join with + to require all");
+
+    let parsed = parse_conditions(&new_goal.condition);
+    if let Err(err) = &parsed {
+        if !new_goal.condition.trim().is_empty() {
+            ui.colored_label(egui::Color32::RED, err.to_string());
         }
-        if has_clear && ui.button("Clear").clicked() {
-            *path = None;
-            changed = true;
+    }
+
+    if ui
+        .add_enabled(parsed.is_ok(), egui::Button::new("Add"))
+        .clicked()
+    {
+        let mut goal = std::mem::take(new_goal);
+        if goal.name.trim().is_empty() {
+            goal.name = format!("Goal {}", goals.len() + 1);
+        }
+        goal.condition = goal.condition.trim().to_string();
+        goals.push(goal);
+    }
+
+    if goals.as_slice() != persistent_state.goals(&emulator.game_hash) {
+        if goals.is_empty() {
+            persistent_state.games.goals.remove(&emulator.game_hash);
+        } else {
+            persistent_state
+                .games
+                .goals
+                .insert(emulator.game_hash.clone(), goals);
+        }
+        let fut = persistent_state.save();
+        spawn_local(async move { fut.await.unwrap() });
+    }
+}
+
+fn tab_movies(
+    ui: &mut egui::Ui,
+    emulator: &Emulator,
+    movies: &mut Movies,
+    netplay_active: bool,
+    config: &Config,
+    app_state: &mut State<AppState>,
+) {
+    ui.heading("Movies");
+
+    if netplay_active {
+        ui.label("Movies are not available during netplay");
+        return;
+    }
+
+    ui.horizontal(|ui| {
+        if movies.is_recording() {
+            let (frames, _) = movies.progress();
+            ui.label(format!("Recording: {frames} frames"));
+            if ui.button("⏹ Stop recording").clicked() {
+                movies.stop(config);
+            }
+        } else if movies.is_playing() {
+            let (frame, len) = movies.progress();
+            ui.label(format!("Playing: {frame} / {} frames", len.unwrap_or(0)));
+            if ui.button("⏹ Stop playback").clicked() {
+                movies.stop(config);
+            }
+        } else if ui.button("⏺ Record from now").clicked() {
+            movies.start_recording(emulator, config);
+            app_state.set(AppState::Running).unwrap();
         }
-    });
-    ui.indent("", |ui| {
-        let s = path
-            .as_ref()
-            .map_or_else(|| "None".to_string(), |r| r.display().to_string());
-        ui.add(egui::TextEdit::singleline(&mut s.as_ref()));
     });
 
-    FileFieldResult {
-        file_sent,
-        cleard: changed,
+    ui.label("Frames advance one per screen update while recording or playing, without turbo.");
+
+    ui.separator();
+
+    let list = if let Some(list) = &movies.list {
+        list.clone()
+    } else {
+        movies.refresh(emulator, config);
+        movies.list = Some(vec![]);
+        vec![]
+    };
+
+    if list.is_empty() {
+        ui.label("No movies recorded for this game");
+        return;
     }
+
+    egui::Grid::new("movies")
+        .num_columns(3)
+        .striped(true)
+        .show(ui, |ui| {
+            for start in list {
+                ui.label(start.format("%Y/%m/%d %H:%M:%S").to_string());
+                if ui.button("▶ Play").clicked() {
+                    movies.play(emulator, Some(start), config);
+                    app_state.set(AppState::Running).unwrap();
+                }
+                if ui.small_button("✖").on_hover_text("Delete").clicked() {
+                    movies.delete(emulator, start, config);
+                }
+                ui.end_row();
+            }
+        });
 }
 
-fn core_config_ui(ui: &mut egui::Ui, abbrev: &str, config: Value, sender: &Sender<ConfigValue>) {
+fn tab_audio_pack(
+    ui: &mut egui::Ui,
+    emulator: &Emulator,
+    config: &mut Config,
+    keyboard: &mut OnScreenKeyboard,
+    menu_event: &Sender<MenuEvent>,
+) {
+    ui.heading("Audio Pack");
+
+    if cfg!(target_arch = "wasm32") {
+        ui.label("Audio packs are not supported on this platform");
+        return;
+    }
+    if !emulator.core.supports_memory_access() {
+        ui.label("This core does not support audio packs");
+        return;
+    }
+
+    let mut enabled = config
+        .audio_packs
+        .get(&emulator.game_hash)
+        .map_or(false, |pack| pack.enabled);
+    if ui
+        .checkbox(
+            &mut enabled,
+            format!("Play an audio pack for {}", emulator.game_name),
+        )
+        .changed()
+    {
+        config
+            .audio_packs
+            .entry(emulator.game_hash.clone())
+            .or_insert_with(|| AudioPack {
+                crossfade_ms: 1000,
+                ..Default::default()
+            })
+            .enabled = enabled;
+    }
+
+    let pack = if let Some(pack) = config.audio_packs.get_mut(&emulator.game_hash) {
+        pack
+    } else {
+        return;
+    };
+
+    ui.add_enabled_ui(pack.enabled, |ui| {
+        dir_field(
+            ui,
+            "Track directory:",
+            &pack.dir,
+            menu_event,
+            MenuEvent::SetAudioPackDir,
+        );
+
+        ui.checkbox(&mut pack.replace_core_audio, "Mute the game music while a track plays")
+            .on_hover_text("Otherwise tracks are mixed over the core audio");
+
+        ui.horizontal(|ui| {
+            ui.label("Crossfade:");
+            ui.add(egui::Slider::new(&mut pack.crossfade_ms, 0..=5000).suffix(" ms"));
+        });
+
+        ui.separator();
+
+        let mut remove = None;
+        egui::Grid::new("audio_pack_tracks")
+            .num_columns(3)
+            .striped(true)
+            .show(ui, |ui| {
+                ui.label("File");
+                ui.label("Condition");
+                ui.end_row();
+
+                for (ix, track) in pack.tracks.iter_mut().enumerate() {
+                    keyboard.text_edit(ui, &mut track.file, |text| {
+                        egui::TextEdit::singleline(text).hint_text("track.ogg")
+                    });
+
+                    let valid = parse_conditions(&track.condition).is_ok();
+                    keyboard.text_edit(ui, &mut track.condition, |text| {
+                        let edit = egui::TextEdit::singleline(text).hint_text("7E0100?01");
+                        if valid {
+                            edit
+                        } else {
+                            edit.text_color(egui::Color32::RED)
+                        }
+                    });
+
+                    if ui.small_button("✖").on_hover_text("Remove").clicked() {
+                        remove = Some(ix);
+                    }
+                    ui.end_row();
+                }
+            });
+
+        if let Some(ix) = remove {
+            pack.tracks.remove(ix);
+        }
+
+        if ui.button("Add track").clicked() {
+            pack.tracks.push(AudioPackTrack::default());
+        }
+
+        ui.label("OGG and FLAC files loop. The first track whose condition holds is played, and the game music is restored when none holds.");
+    });
+}
+
+fn tab_texture_pack(
+    ui: &mut egui::Ui,
+    emulator: &Emulator,
+    config: &mut Config,
+    menu_event: &Sender<MenuEvent>,
+) {
+    ui.heading("Texture Pack");
+    ui.label("Experimental: replaces the tiles of the game with high-resolution textures");
+
+    if !texture_pack::is_supported() {
+        ui.label("Texture packs are not supported on this platform");
+        return;
+    }
+    if !emulator.core.supports_tile_hashes() {
+        ui.label("This core does not support texture packs");
+        return;
+    }
+
+    let mut enabled = config
+        .texture_packs
+        .get(&emulator.game_hash)
+        .map_or(false, |pack| pack.enabled);
+    if ui
+        .checkbox(
+            &mut enabled,
+            format!("Use a texture pack for {}", emulator.game_name),
+        )
+        .changed()
+    {
+        config
+            .texture_packs
+            .entry(emulator.game_hash.clone())
+            .or_default()
+            .enabled = enabled;
+    }
+
+    let pack = if let Some(pack) = config.texture_packs.get_mut(&emulator.game_hash) {
+        pack
+    } else {
+        return;
+    };
+
+    ui.add_enabled_ui(pack.enabled, |ui| {
+        dir_field(
+            ui,
+            "Texture directory:",
+            &pack.dir,
+            menu_event,
+            MenuEvent::SetTexturePackDir,
+        );
+
+        ui.horizontal(|ui| {
+            ui.label("Scale:");
+            ui.add(
+                egui::Slider::new(&mut pack.scale, MIN_TEXTURE_SCALE..=MAX_TEXTURE_SCALE)
+                    .suffix("x"),
+            );
+        })
+        .response
+        .on_hover_text("Resolution of the screen. Textures of other sizes are resized to fit");
+
+        ui.checkbox(&mut pack.dump, "Dump tiles without textures")
+            .on_hover_text("Saves them into `dump` of the texture directory, for making packs");
+    });
+
+    ui.label("Textures are PNG files named by the hashes of the tiles, such as `0123456789abcdef.png`. Dumped tiles are captured from the screen, so overlapping graphics may need to be erased.");
+}
+
+fn tab_game_info(
+    ui: &mut egui::Ui,
+    emulator: &mut Emulator,
+    library_db: &mut LibraryDb,
+    new_tag: &mut String,
+    confirm_delete_backup: &mut bool,
+    keyboard: &mut OnScreenKeyboard,
+    blocker: Option<&str>,
+    menu_event: &Sender<MenuEvent>,
+) {
+    ui.heading("Game Info");
+
+    egui::Grid::new("key_config")
+        .num_columns(2)
+        .spacing([40.0, 4.0])
+        .striped(true)
+        .show(ui, |ui| {
+            if let Some(entry) = &emulator.archive_entry {
+                ui.label("Loaded from archive");
+                ui.label(entry);
+                ui.end_row();
+            }
+            for (key, value) in &emulator.game_info {
+                ui.label(key);
+                ui.label(value);
+                ui.end_row();
+            }
+        });
+
+    layer_toolbar(ui, emulator);
+
+    ui.separator();
+
+    let record = library_db
+        .game(&emulator.rom_path)
+        .cloned()
+        .unwrap_or_default();
+    let mut save_db = false;
+
+    ui.horizontal_wrapped(|ui| {
+        ui.label("Tags:");
+        for tag in &record.tags {
+            if ui
+                .small_button(format!("{tag} ✖"))
+                .on_hover_text("Remove tag")
+                .clicked()
+            {
+                library_db.update_game(&emulator.rom_path, |r| {
+                    r.tags.remove(tag);
+                });
+                save_db = true;
+            }
+        }
+
+        let resp = keyboard.text_edit(ui, new_tag, |text| {
+            egui::TextEdit::singleline(text).desired_width(100.0)
+        });
+        let tag = new_tag.trim().to_string();
+        let enter = resp.lost_focus() && ui.input().key_pressed(egui::Key::Enter);
+        if (ui
+            .add_enabled(!tag.is_empty(), egui::Button::new("Add"))
+            .clicked()
+            || enter)
+            && !tag.is_empty()
+        {
+            library_db.update_game(&emulator.rom_path, |r| {
+                r.tags.insert(tag);
+            });
+            new_tag.clear();
+            save_db = true;
+        }
+    });
+
+    ui.label("Notes:");
+    let mut notes = record.notes;
+    // Leaves room for the on-screen keyboard button
+    let width = ui.available_width() - 40.0;
+    let resp = keyboard.text_edit(ui, &mut notes, |text| {
+        egui::TextEdit::multiline(text)
+            .desired_rows(4)
+            .desired_width(width)
+    });
+    if resp.changed() {
+        library_db.update_game(&emulator.rom_path, |r| r.notes = notes);
+    }
+    // Save notes when editing finished, not on every keystroke
+    if resp.lost_focus() {
+        save_db = true;
+    }
+
+    if save_db {
+        let fut = library_db.save();
+        spawn_local(async move { fut.await.unwrap() });
+    }
+
+    ui.separator();
+
+    if emulator.core.supports_region_override() {
+        let region_str = |region: Option<Region>| match region {
+            None => "Auto",
+            Some(Region::Ntsc) => "NTSC (60Hz)",
+            Some(Region::Pal) => "PAL (50Hz)",
+        };
+
+        let cur = library_db.game(&emulator.rom_path).and_then(|r| r.region);
+        let mut region = cur;
+
+        ui.horizontal(|ui| {
+            ui.label("Region:");
+            egui::ComboBox::from_id_source("region")
+                .selected_text(region_str(region))
+                .show_ui(ui, |ui| {
+                    for r in [None, Some(Region::Ntsc), Some(Region::Pal)] {
+                        ui.selectable_value(&mut region, r, region_str(r));
+                    }
+                });
+        });
+
+        if region != cur {
+            emulator.core.set_region(region);
+            library_db.update_game(&emulator.rom_path, |r| r.region = region);
+
+            let fut = library_db.save();
+            spawn_local(async move { fut.await.unwrap() });
+        }
+
+        ui.separator();
+    }
+
+    let save_types = emulator.core.save_types();
+    if !save_types.is_empty() {
+        let cur = library_db
+            .game(&emulator.rom_path)
+            .and_then(|r| r.save_type.clone());
+        let mut save_type = cur.clone();
+
+        ui.horizontal(|ui| {
+            ui.label("Save type:");
+            egui::ComboBox::from_id_source("save_type")
+                .selected_text(save_type.as_deref().unwrap_or("Auto"))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut save_type, None, "Auto");
+                    for t in save_types {
+                        ui.selectable_value(&mut save_type, Some(t.to_string()), *t);
+                    }
+                });
+        })
+        .response
+        .on_hover_text("For games whose save type is detected wrongly. Applied when the game is opened next time.");
+
+        if save_type != cur {
+            library_db.update_game(&emulator.rom_path, |r| r.save_type = save_type);
+
+            let fut = library_db.save();
+            spawn_local(async move { fut.await.unwrap() });
+        }
+
+        ui.separator();
+    }
+
+    if !emulator.play_sessions.is_empty() {
+        ui.label("Play history");
+
+        egui::Grid::new("play_sessions")
+            .num_columns(3)
+            .spacing([40.0, 4.0])
+            .striped(true)
+            .show(ui, |ui| {
+                for session in emulator.play_sessions.iter().rev() {
+                    let minutes = (session.end - session.start).num_minutes();
+                    ui.label(format!(
+                        "{} - {}",
+                        session.start.format("%Y/%m/%d %H:%M"),
+                        session.end.format("%H:%M")
+                    ));
+                    ui.label(format!("{}h {:02}m", minutes / 60, minutes % 60));
+                    if ui
+                        .add_enabled(blocker.is_none(), egui::Button::new("Resume"))
+                        .on_hover_text("Load the state at the end of this session")
+                        .on_disabled_hover_text(format!(
+                            "Not available {}",
+                            blocker.unwrap_or_default()
+                        ))
+                        .clicked()
+                    {
+                        let menu_event = menu_event.clone();
+                        let fut = emulator.load_play_session(session);
+                        let end = session.end;
+                        spawn_local(async move {
+                            let data = fut.await;
+                            menu_event
+                                .send(MenuEvent::PlaySessionLoaded { end, data })
+                                .await
+                                .unwrap();
+                        });
+                    }
+                    ui.end_row();
+                }
+            });
+
+        ui.separator();
+    }
+
+    if emulator.backup_snapshots.iter().any(|r| r.is_some()) {
+        ui.label("Save data snapshots");
+
+        let mut snapshots = emulator
+            .backup_snapshots
+            .iter()
+            .enumerate()
+            .filter_map(|(i, date)| date.map(|date| (i, date)))
+            .collect::<Vec<_>>();
+        snapshots.sort_by(|a, b| b.1.cmp(&a.1));
+
+        egui::Grid::new("backup_snapshots")
+            .num_columns(2)
+            .spacing([40.0, 4.0])
+            .striped(true)
+            .show(ui, |ui| {
+                for (i, date) in snapshots {
+                    ui.label(date.format("%Y/%m/%d %H:%M:%S").to_string());
+                    let restore = ui
+                        .add_enabled(blocker.is_none(), egui::Button::new("Restore"))
+                        .on_disabled_hover_text(format!(
+                            "Not available {}",
+                            blocker.unwrap_or_default()
+                        ));
+                    if restore.clicked() {
+                        menu_event.try_send(MenuEvent::RestoreBackup(i)).unwrap();
+                    }
+                    ui.end_row();
+                }
+            });
+
+        ui.separator();
+    }
+
+    if ui.button("Delete save data for this game").clicked() {
+        *confirm_delete_backup = true;
+    }
+
+    if *confirm_delete_backup {
+        let mut open = true;
+        let mut close = false;
+
+        egui::Window::new("Delete save data")
+            .open(&mut open)
+            .collapsible(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ui.ctx(), |ui| {
+                ui.label(format!(
+                    "Delete save data of `{}`? This cannot be undone.",
+                    emulator.game_name
+                ));
+                ui.horizontal(|ui| {
+                    if ui.button("Delete").clicked() {
+                        menu_event.try_send(MenuEvent::DeleteBackup).unwrap();
+                        close = true;
+                    }
+                    if ui.button("Cancel").clicked() {
+                        close = true;
+                    }
+                });
+            });
+
+        if !open || close {
+            *confirm_delete_backup = false;
+        }
+    }
+}
+
+fn tab_general_setting(
+    ui: &mut egui::Ui,
+    config: &mut ResMut<Config>,
+    keyboard: &mut OnScreenKeyboard,
+    #[allow(unused_variables)] menu_event: &Sender<MenuEvent>,
+) {
+    ui.checkbox(&mut config.simple_mode, "Simple mode")
+        .on_hover_text("Hide advanced settings behind the \"Advanced\" toggle");
+
+    ui.horizontal(|ui| {
+        ui.label("Window title:");
+        keyboard
+            .text_edit_singleline(ui, &mut config.window_title)
+            .on_hover_text("`{game}`, `{system}` and `{fps}` are replaced while a game is loaded");
+        if ui.button("Reset").clicked() {
+            config.window_title = default_window_title();
+        }
+    });
+
+    ui.horizontal(|ui| {
+        let mut auto = config.ui_scale.is_none();
+        if ui
+            .checkbox(&mut auto, "Auto menu scale")
+            .on_hover_text("Follow the DPI of the monitor showing the window")
+            .changed()
+        {
+            config.ui_scale = if auto { None } else { Some(100) };
+        }
+
+        if let Some(percent) = &mut config.ui_scale {
+            ui.add(egui::Slider::new(percent, 50..=300).suffix("%"));
+        }
+    });
+
+    ui.horizontal(|ui| {
+        ui.label("Frame skip on turbo:");
+
+        ui.add(egui::Slider::new(&mut config.frame_skip_on_turbo, 1..=10));
+    });
+
+    ui.horizontal(|ui| {
+        ui.label("Turbo time budget per frame:");
+        ui.add(egui::Slider::new(&mut config.turbo_time_budget, 1..=16).suffix(" ms"))
+            .on_hover_text("Fewer frames are skipped when running them takes longer than this");
+    });
+
+    ui.horizontal(|ui| {
+        ui.label("Suspended games to keep:");
+        ui.add(egui::Slider::new(&mut config.max_sessions, 0..=8));
+    });
+
+    ui.horizontal(|ui| {
+        ui.label("Save data snapshots to keep:");
+        ui.add(egui::Slider::new(&mut config.backup_snapshots, 0..=20));
+    });
+
+    ui.horizontal(|ui| {
+        ui.label("Gamepad button labels:");
+
+        egui::ComboBox::from_id_source("button_label_style")
+            .selected_text(config.button_label_style.to_string())
+            .show_ui(ui, |ui| {
+                for style in all::<ButtonLabelStyle>() {
+                    ui.selectable_value(&mut config.button_label_style, style, style.to_string());
+                }
+            });
+    });
+
+    ui.checkbox(
+        &mut config.clear_backup_on_reload,
+        "Clear backup RAM when reloading ROM",
+    );
+
+    ui.checkbox(
+        &mut config.screenshot_scaled,
+        "Scale screenshots by the window scale",
+    )
+    .on_hover_text("Screen filters are not applied to screenshots");
+
+    ui.horizontal(|ui| {
+        ui.label("Clip length:");
+        ui.add(egui::Slider::new(&mut config.clip_length, 0..=60).suffix("s"));
+    })
+    .response
+    .on_hover_text("Keeps the last seconds of play to save as a GIF. 0 disables recording.");
+
+    ui.horizontal(|ui| {
+        ui.label("Clip scale:");
+        ui.add(egui::Slider::new(&mut config.clip_scale, 1..=4).suffix("x"));
+    });
+
+    ui.horizontal(|ui| {
+        ui.label("Save state storage:");
+
+        egui::ComboBox::from_id_source("state_storage")
+            .selected_text(config.state_storage.to_string())
+            .show_ui(ui, |ui| {
+                for storage in all::<StateStorage>() {
+                    ui.selectable_value(&mut config.state_storage, storage, storage.to_string());
+                }
+            });
+    })
+    .response
+    .on_hover_text("Existing states are moved to the new storage when overwritten");
+
+    ui.add_enabled(
+        cfg!(not(target_arch = "wasm32")),
+        egui::Checkbox::new(&mut config.compress_states, "Compress save states"),
+    )
+    .on_hover_text("Rewinding keeps more states in the same memory. Older states still load");
+
+    ui.checkbox(
+        &mut config.state_self_test,
+        "Test save states after loading a ROM",
+    )
+    .on_hover_text("Warns when the core does not restore states exactly");
+
+    ui.separator();
+
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        dir_field(
+            ui,
+            "Save file directory:",
+            &config.save_dir,
+            menu_event,
+            MenuEvent::SetSaveDir,
+        );
+        dir_field(
+            ui,
+            "State file directory:",
+            &config.state_dir,
+            menu_event,
+            MenuEvent::SetStateDir,
+        );
+        dir_field(
+            ui,
+            "Capture directory:",
+            &config.capture_dir,
+            menu_event,
+            MenuEvent::SetCaptureDir,
+        );
+
+        ui.checkbox(&mut config.minimize_to_tray, "Minimize to system tray")
+            .on_hover_text("Emulation is paused and muted while in the tray");
+
+        ui.add_enabled_ui(is_capture_protection_supported(), |ui| {
+            ui.checkbox(&mut config.capture_protection, "Hide window from screen capture")
+                .on_hover_text("The window appears blank or hidden in streams and recordings, such as while practicing");
+        });
+
+        ui.separator();
+
+        ui.horizontal(|ui| {
+            ui.label("SDL controller mapping file:");
+            if ui.button("Change").clicked() {
+                let menu_event = menu_event.clone();
+                spawn_local(async move {
+                    let fd = rfd::AsyncFileDialog::new()
+                        .add_filter("SDL_GameControllerDB", &["txt"])
+                        .add_filter("All files", &["*"]);
+                    if let Some(file) = fd.pick_file().await {
+                        menu_event
+                            .send(MenuEvent::SetControllerDb(Some(file.path().to_owned())))
+                            .await
+                            .unwrap();
+                    }
+                });
+            }
+            if config.controller_db.is_some() && ui.button("Clear").clicked() {
+                menu_event
+                    .try_send(MenuEvent::SetControllerDb(None))
+                    .unwrap();
+            }
+        });
+        ui.indent("", |ui| {
+            let s = config
+                .controller_db
+                .as_ref()
+                .map_or_else(|| "(none)".to_string(), |path| path.display().to_string());
+            ui.add(egui::TextEdit::singleline(&mut s.as_ref()));
+        });
+
+        ui.separator();
+
+        ui.label("ROM directories:");
+
+        let mut removed = None;
+        for (i, dir) in config.rom_dirs.iter().enumerate() {
+            ui.horizontal(|ui| {
+                if ui.button("Remove").clicked() {
+                    removed = Some(i);
+                }
+                ui.label(dir.display().to_string());
+            });
+        }
+        if let Some(i) = removed {
+            config.rom_dirs.remove(i);
+        }
+
+        if ui.button("Add").clicked() {
+            let menu_event = menu_event.clone();
+            spawn_local(async move {
+                if let Some(dir) = rfd::AsyncFileDialog::new().pick_folder().await {
+                    menu_event
+                        .send(MenuEvent::AddRomDir(dir.path().to_owned()))
+                        .await
+                        .unwrap();
+                }
+            });
+        }
+
+        ui.separator();
+    }
+
+    ui.label("Core priority:");
+    core_priority_setting(ui, config);
+
+    ui.separator();
+
+    ui.label("Rewinding:");
+
+    ui.horizontal(|ui| {
+        ui.label("Memory budget for rewinding:");
+        let mut rate_in_kb = config.auto_state_save_rate / 1024;
+        ui.add(
+            egui::Slider::new(&mut rate_in_kb, 0..=8192)
+                .logarithmic(true)
+                .suffix("KiB/s"),
+        );
+        config.auto_state_save_rate = rate_in_kb * 1024;
+    });
+
+    ui.horizontal(|ui| {
+        ui.label("Maximum memory amount for rewinding:");
+        let mut amount_in_mb = config.auto_state_save_limit / (1024 * 1024);
+        ui.add(
+            egui::Slider::new(&mut amount_in_mb, 0..=8192)
+                .logarithmic(true)
+                .suffix("MiB"),
+        );
+        config.auto_state_save_limit = amount_in_mb * 1024 * 1024;
+    });
+
+    ui.horizontal(|ui| {
+        ui.label("Minimum auto save span:");
+        ui.add(
+            egui::Slider::new(&mut config.minimum_auto_save_span, 1..=300)
+                .logarithmic(true)
+                .suffix("Frames"),
+        );
+    });
+
+    ui.horizontal(|ui| {
+        ui.label("Memory for rewinding while the hotkey is held:");
+        let mut amount_in_mb = config.hold_rewind_limit / (1024 * 1024);
+        ui.add(
+            egui::Slider::new(&mut amount_in_mb, 0..=1024)
+                .logarithmic(true)
+                .suffix("MiB"),
+        );
+        config.hold_rewind_limit = amount_in_mb * 1024 * 1024;
+    })
+    .response
+    .on_hover_text(
+        "States of every frame are kept, which costs some speed. 0 disables it.\n\
+         On turbo, one state is kept per displayed frame",
+    );
+
+    // FIXME: reset auto save timing state when changed rewinding setting
+}
+
+fn core_priority_setting(ui: &mut egui::Ui, config: &mut Config) {
+    let exts = Emulator::core_infos()
+        .into_iter()
+        .flat_map(|core_info| core_info.file_extensions.iter().copied())
+        .collect::<BTreeSet<_>>();
+
+    let mut shown = false;
+
+    for ext in exts {
+        let cores = EmulatorCores::for_extension(ext, config);
+        if cores.len() < 2 {
+            continue;
+        }
+        shown = true;
+
+        let mut abbrevs = cores
+            .iter()
+            .map(|core| core.core_info().abbrev.to_string())
+            .collect::<Vec<_>>();
+
+        let mut changed = false;
+        ui.horizontal(|ui| {
+            ui.label(format!(".{ext}:"));
+            for (i, core) in cores.iter().enumerate() {
+                if i > 0 && ui.small_button("◀").on_hover_text("Prefer").clicked() {
+                    abbrevs.swap(i - 1, i);
+                    changed = true;
+                }
+                ui.label(core.core_info().system_name);
+            }
+        });
+
+        if changed {
+            config.core_priority.insert(ext.to_string(), abbrevs);
+        }
+    }
+
+    if !shown {
+        ui.indent("", |ui| {
+            ui.label("No file type is supported by multiple cores");
+        });
+    }
+}
+
+fn tab_input_test(
+    ui: &mut egui::Ui,
+    selected_pad: &mut usize,
+    gamepads: &Gamepads,
+    gamepad_button_input: &Input<GamepadButton>,
+    gamepad_axis_input: &Axis<GamepadAxis>,
+    gamepad_layouts: &GamepadLayouts,
+) {
+    use meru_interface::{GamepadAxisType::*, GamepadButtonType::*};
+
+    const BUTTONS: &[meru_interface::GamepadButtonType] = &[
+        South,
+        East,
+        North,
+        West,
+        C,
+        Z,
+        LeftTrigger,
+        LeftTrigger2,
+        RightTrigger,
+        RightTrigger2,
+        Select,
+        Start,
+        Mode,
+        LeftThumb,
+        RightThumb,
+        DPadUp,
+        DPadDown,
+        DPadLeft,
+        DPadRight,
+    ];
+    const AXES: &[meru_interface::GamepadAxisType] = &[
+        LeftStickX,
+        LeftStickY,
+        LeftZ,
+        RightStickX,
+        RightStickY,
+        RightZ,
+    ];
+
+    let mut pads = gamepads.iter().map(|pad| pad.id).collect::<Vec<_>>();
+    pads.sort_unstable();
+
+    if pads.is_empty() {
+        ui.label("No gamepad connected");
+        return;
+    }
+
+    if !pads.contains(selected_pad) {
+        *selected_pad = pads[0];
+    }
+
+    ui.horizontal(|ui| {
+        for id in &pads {
+            ui.selectable_value(selected_pad, *id, format!("Pad{id}"));
+        }
+    });
+
+    let gamepad = meru_interface::Gamepad::new(*selected_pad);
+    let layout = gamepad_layouts.layout(*selected_pad);
+
+    ui.group(|ui| {
+        ui.label("Buttons");
+        ui.horizontal_wrapped(|ui| {
+            for button_type in BUTTONS {
+                let button = meru_interface::GamepadButton::new(gamepad, *button_type);
+                let pressed = gamepad_button_input.pressed(ConvertInput(button).into());
+                let _ = ui.selectable_label(pressed, layout.button_label(*button_type));
+            }
+        });
+    });
+
+    ui.group(|ui| {
+        ui.label("Axes");
+        egui::Grid::new("input_test_axes")
+            .num_columns(2)
+            .spacing([20.0, 4.0])
+            .show(ui, |ui| {
+                for axis_type in AXES {
+                    let axis = meru_interface::GamepadAxis::new(gamepad, *axis_type);
+                    let value = gamepad_axis_input
+                        .get(ConvertInput(axis).into())
+                        .unwrap_or(0.0);
+
+                    ui.label(axis_type.to_string());
+                    ui.add(
+                        egui::ProgressBar::new((value + 1.0) / 2.0)
+                            .desired_width(200.0)
+                            .text(format!("{value:+.2}")),
+                    );
+                    ui.end_row();
+                }
+            });
+    });
+}
+
+fn tab_messages(ui: &mut egui::Ui, message_log: &mut MessageLog) -> Option<MenuTab> {
+    let mut ret = None;
+
+    if ui.button("Clear").clicked() {
+        message_log.entries.clear();
+    }
+
+    ui.separator();
+
+    egui::ScrollArea::vertical()
+        .stick_to_bottom(true)
+        .show(ui, |ui| {
+            egui::Grid::new("messages")
+                .num_columns(4)
+                .spacing([20.0, 4.0])
+                .striped(true)
+                .show(ui, |ui| {
+                    for entry in &message_log.entries {
+                        ui.label(entry.time.format("%Y/%m/%d %H:%M:%S").to_string());
+                        match entry.level {
+                            MessageLevel::Info => ui.label("ℹ"),
+                            MessageLevel::Error => {
+                                ui.colored_label(egui::Color32::from_rgb(255, 96, 96), "❌")
+                            }
+                        };
+                        ui.label(&entry.text);
+                        if let Some(link) = &entry.link {
+                            if ui.small_button(format!("Open {}", link.name())).clicked() {
+                                ret = Some(link.clone());
+                            }
+                        } else {
+                            ui.label("");
+                        }
+                        ui.end_row();
+                    }
+                });
+        });
+
+    ret
+}
+
+fn tab_firmware(
+    ui: &mut egui::Ui,
+    config: &Config,
+    firmware: &mut Option<Vec<FirmwareEntry>>,
+    menu_event: &Sender<MenuEvent>,
+) {
+    let entries = if let Some(entries) = firmware {
+        entries
+    } else {
+        let firmware_dir = config.firmware_dir.clone();
+        let menu_event = menu_event.clone();
+        spawn_local(async move {
+            let result = check_firmwares(&firmware_dir).await;
+            menu_event
+                .send(MenuEvent::FirmwareChecked(result))
+                .await
+                .unwrap();
+        });
+        firmware.insert(vec![])
+    };
+
+    ui.label(format!(
+        "Firmware directory: {}",
+        config.firmware_dir.display()
+    ));
+
+    ui.separator();
+
+    if entries.is_empty() {
+        ui.label("No core uses firmware files");
+        return;
+    }
+
+    egui::Grid::new("firmware")
+        .num_columns(5)
+        .spacing([20.0, 4.0])
+        .striped(true)
+        .show(ui, |ui| {
+            ui.label("System");
+            ui.label("Firmware");
+            ui.label("Required");
+            ui.label("Status");
+            ui.end_row();
+
+            for entry in entries.iter() {
+                ui.label(entry.system_name);
+                ui.label(entry.info.name)
+                    .on_hover_text(entry.info.file_name);
+                ui.label(if entry.info.required {
+                    "Required"
+                } else {
+                    "Optional"
+                });
+                ui.label(entry.status.to_string());
+
+                if ui.button("Install...").clicked() {
+                    let abbrev = entry.abbrev;
+                    let info = entry.info;
+                    let firmware_dir = config.firmware_dir.clone();
+                    let menu_event = menu_event.clone();
+                    spawn_local(async move {
+                        if let Some((_, data)) =
+                            file_dialog(None, &[("All files", &["*"])], false).await
+                        {
+                            let result = install_firmware(abbrev, info, &data, &firmware_dir).await;
+                            menu_event
+                                .send(MenuEvent::FirmwareInstalled {
+                                    abbrev,
+                                    info,
+                                    data,
+                                    result,
+                                })
+                                .await
+                                .unwrap();
+                        }
+                    });
+                }
+                ui.end_row();
+            }
+        });
+}
+
+fn format_size(size: usize) -> String {
+    if size < 1024 * 1024 {
+        format!("{:.1} KiB", size as f64 / 1024.0)
+    } else {
+        format!("{:.1} MiB", size as f64 / 1024.0 / 1024.0)
+    }
+}
+
+/// State of the profile selector window
+#[derive(Default)]
+pub struct ProfileWindow {
+    pub open: bool,
+    selected: String,
+    passphrase: String,
+    new_name: String,
+    new_passphrase: String,
+    error: Option<String>,
+}
+
+impl ProfileWindow {
+    pub fn new(open: bool) -> Self {
+        Self {
+            open,
+            selected: current_profile(),
+            ..Default::default()
+        }
+    }
+
+    fn toggle(&mut self) {
+        *self = Self::new(!self.open);
+    }
+}
+
+fn profile_window_system(
+    mut egui_ctx: ResMut<EguiContext>,
+    mut window: ResMut<ProfileWindow>,
+    mut profiles: ResMut<Profiles>,
+    mut menu_state: ResMut<MenuState>,
+    menu_event: Res<Sender<MenuEvent>>,
+) {
+    if !window.open {
+        return;
+    }
+
+    let window = window.as_mut();
+    let profiles = profiles.as_mut();
+    let keyboard = &mut menu_state.keyboard;
+    let mut open = true;
+    let mut profiles_changed = false;
+
+    egui::Window::new("Profiles")
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+        .open(&mut open)
+        .show(egui_ctx.ctx_mut(), |ui| {
+            let current = current_profile();
+            let names = std::iter::once(DEFAULT_PROFILE.to_string())
+                .chain(profiles.profiles.iter().map(|p| p.name.clone()))
+                .collect::<Vec<_>>();
+
+            let mut remove = None;
+            egui::Grid::new("profiles").num_columns(3).show(ui, |ui| {
+                for name in &names {
+                    ui.radio_value(&mut window.selected, name.clone(), name);
+                    let encrypted = profiles.find(name).map_or(false, |p| p.is_encrypted());
+                    ui.label(if encrypted { "🔒" } else { "" });
+                    if name != DEFAULT_PROFILE
+                        && *name != current
+                        && ui
+                            .small_button("Remove")
+                            .on_hover_text("Files of the profile are kept")
+                            .clicked()
+                    {
+                        remove = Some(name.clone());
+                    }
+                    ui.end_row();
+                }
+            });
+            if let Some(name) = remove {
+                profiles.remove(&name);
+                profiles_changed = true;
+            }
+
+            let profile = profiles.find(&window.selected);
+            if profile.map_or(false, |p| p.is_encrypted()) {
+                ui.horizontal(|ui| {
+                    ui.label("Passphrase:");
+                    keyboard.text_edit(ui, &mut window.passphrase, |text| {
+                        egui::TextEdit::singleline(text).password(true)
+                    });
+                });
+            }
+
+            if ui
+                .add_enabled(window.selected != current, egui::Button::new("Switch"))
+                .clicked()
+            {
+                let unlocked = profile.map_or(Ok(()), |p| p.unlock(&window.passphrase));
+                if let Err(err) = unlocked {
+                    window.error = Some(err.to_string());
+                } else {
+                    set_current_profile(&window.selected);
+                    let menu_event = menu_event.clone();
+                    spawn_local(async move {
+                        let result = load_profile_data().await;
+                        menu_event
+                            .send(MenuEvent::ProfileLoaded {
+                                previous: current,
+                                result,
+                            })
+                            .await
+                            .unwrap();
+                    });
+                    window.open = false;
+                }
+                window.passphrase.clear();
+            }
+
+            ui.separator();
+
+            ui.label("New profile");
+            egui::Grid::new("new_profile")
+                .num_columns(2)
+                .show(ui, |ui| {
+                    ui.label("Name:");
+                    keyboard.text_edit_singleline(ui, &mut window.new_name);
+                    ui.end_row();
+
+                    ui.label("Passphrase:");
+                    keyboard
+                        .text_edit(ui, &mut window.new_passphrase, |text| {
+                            egui::TextEdit::singleline(text).password(true)
+                        })
+                        .on_hover_text(
+                            "Optional. Files of the profile are encrypted with it, except for \
+                             captures. A forgotten passphrase cannot be recovered. \
+                             The default profile cannot be encrypted",
+                        );
+                    ui.end_row();
+                });
+
+            if ui
+                .add_enabled(
+                    is_valid_profile_name(&window.new_name),
+                    egui::Button::new("Create"),
+                )
+                .clicked()
+            {
+                match Profile::new(&window.new_name, &window.new_passphrase).and_then(|profile| {
+                    profiles.add(profile.clone())?;
+                    profile.unlock(&window.new_passphrase)
+                }) {
+                    Ok(()) => {
+                        window.new_name.clear();
+                        window.new_passphrase.clear();
+                        window.error = None;
+                        profiles_changed = true;
+                    }
+                    Err(err) => window.error = Some(err.to_string()),
+                }
+            }
+
+            ui.separator();
+
+            profiles_changed |= ui
+                .checkbox(&mut profiles.ask_on_startup, "Choose a profile on startup")
+                .changed();
+
+            if let Some(error) = &window.error {
+                ui.colored_label(egui::Color32::RED, error);
+            }
+        });
+
+    if !open {
+        window.open = false;
+    }
+
+    if profiles_changed {
+        let fut = profiles.save();
+        spawn_local(async move { fut.await.unwrap() });
+    }
+}
+
+const DATA_ARCHIVE_NAME: &str = "meru-data.zip";
+
+/// Saves an archive of all data. Returns where it was saved
+async fn save_data_archive(data: Vec<u8>) -> anyhow::Result<Option<String>> {
+    cfg_if! {
+        if #[cfg(target_arch = "wasm32")] {
+            crate::utils::download(DATA_ARCHIVE_NAME, &data)?;
+            Ok(Some(DATA_ARCHIVE_NAME.to_string()))
+        } else {
+            let file = rfd::AsyncFileDialog::new()
+                .add_filter("Zip archive", &["zip"])
+                .set_file_name(DATA_ARCHIVE_NAME)
+                .save_file()
+                .await;
+            if let Some(file) = file {
+                std::fs::write(file.path(), data)?;
+                Ok(Some(file.path().display().to_string()))
+            } else {
+                Ok(None)
+            }
+        }
+    }
+}
+
+fn tab_obs(ui: &mut egui::Ui, obs: &mut Obs, config: &mut Config, keyboard: &mut OnScreenKeyboard) {
+    ui.label("OBS Studio");
+
+    if !obs::is_supported() {
+        ui.label("OBS integration is not supported on this platform");
+        return;
+    }
+
+    ui.checkbox(&mut config.obs.enabled, "Connect to OBS")
+        .on_hover_text(
+            "Enable the WebSocket server in Tools → WebSocket Server Settings of OBS 28 or later",
+        );
+
+    ui.add_enabled_ui(config.obs.enabled, |ui| {
+        egui::Grid::new("obs").num_columns(2).show(ui, |ui| {
+            ui.label("Address:");
+            keyboard.text_edit_singleline(ui, &mut config.obs.address);
+            ui.end_row();
+
+            ui.label("Password:");
+            keyboard.text_edit(ui, &mut config.obs.password, |text| {
+                egui::TextEdit::singleline(text).password(true)
+            });
+            ui.end_row();
+        });
+
+        ui.horizontal(|ui| {
+            let status = match obs.status() {
+                ObsStatus::Disconnected => "Disconnected".to_string(),
+                ObsStatus::Connecting => "Connecting...".to_string(),
+                ObsStatus::Connected => "Connected".to_string(),
+                ObsStatus::Failed(err) => format!("Error: {err}"),
+            };
+            ui.label(format!("Status: {status}"));
+            if ui
+                .button("Reconnect")
+                .on_hover_text("Apply changes of the address and password")
+                .clicked()
+            {
+                obs.reconnect();
+            }
+        });
+
+        ui.separator();
+
+        ui.checkbox(
+            &mut config.obs.record_on_game_start,
+            "Start recording when a game is opened",
+        );
+        ui.checkbox(
+            &mut config.obs.stop_on_game_close,
+            "Stop recording when the game is closed",
+        );
+
+        egui::Grid::new("obs_scenes").num_columns(2).show(ui, |ui| {
+            ui.label("Scene while playing:");
+            keyboard.text_edit_singleline(ui, &mut config.obs.running_scene);
+            ui.end_row();
+
+            ui.label("Scene in the menu:");
+            keyboard.text_edit_singleline(ui, &mut config.obs.menu_scene);
+            ui.end_row();
+        });
+        ui.small("Scenes are not switched when left empty");
+    });
+}
+
+fn tab_netplay(
+    ui: &mut egui::Ui,
+    netplay: &mut Netplay,
+    config: &mut Config,
+    emulator: Option<&Emulator>,
+    keyboard: &mut OnScreenKeyboard,
+) {
+    if cfg!(target_arch = "wasm32") {
+        ui.label("Netplay is not supported on this platform");
+        return;
+    }
+
+    ui.label(format!("Status: {}", netplay.status_text()));
+
+    if netplay.is_active() {
+        if ui.button("Disconnect").clicked() {
+            netplay.disconnect();
+        }
+        return;
+    }
+
+    if emulator.is_none() {
+        ui.label("Load the same game on both sides first");
+    }
+
+    ui.add_enabled_ui(emulator.is_some(), |ui| {
+        egui::Grid::new("netplay").num_columns(3).show(ui, |ui| {
+            ui.label("Port:");
+            ui.add(egui::DragValue::new(&mut config.netplay.port));
+            if ui.button("Host").clicked() {
+                if let Err(err) = netplay.host(config.netplay.port) {
+                    netplay.fail(&err);
+                }
+            }
+            ui.end_row();
+
+            ui.label("Host address:");
+            keyboard.text_edit(ui, &mut config.netplay.address, |text| {
+                egui::TextEdit::singleline(text).hint_text("host:port")
+            });
+            ui.horizontal(|ui| {
+                ui.set_enabled(!config.netplay.address.is_empty());
+                if ui.button("Join").clicked() {
+                    netplay.join(&config.netplay.address, false);
+                }
+                if ui
+                    .button("Watch")
+                    .on_hover_text("Watch the game without playing")
+                    .clicked()
+                {
+                    netplay.join(&config.netplay.address, true);
+                }
+            });
+            ui.end_row();
+        });
+
+        ui.separator();
+
+        egui::Grid::new("netplay_relay")
+            .num_columns(3)
+            .show(ui, |ui| {
+                ui.label("Relay server:");
+                keyboard.text_edit(ui, &mut config.netplay.relay_address, |text| {
+                    egui::TextEdit::singleline(text).hint_text("host:port")
+                });
+                if ui
+                    .add_enabled(
+                        !config.netplay.relay_address.is_empty(),
+                        egui::Button::new("Open room"),
+                    )
+                    .clicked()
+                {
+                    netplay.host_room(&config.netplay.relay_address);
+                }
+                ui.end_row();
+
+                ui.label("Room code:");
+                keyboard.text_edit_singleline(ui, &mut netplay.room_code);
+                if ui
+                    .add_enabled(
+                        !config.netplay.relay_address.is_empty() && !netplay.room_code.is_empty(),
+                        egui::Button::new("Join room"),
+                    )
+                    .clicked()
+                {
+                    let code = netplay.room_code.clone();
+                    netplay.join_room(&config.netplay.relay_address, &code);
+                }
+                ui.end_row();
+            });
+    });
+
+    ui.horizontal(|ui| {
+        ui.label("Input delay:");
+        ui.add(egui::Slider::new(&mut config.netplay.input_delay, 0..=8).suffix(" frames"));
+    })
+    .response
+    .on_hover_text("Larger delays make rollbacks less frequent on slow connections");
+
+    ui.horizontal(|ui| {
+        ui.label("Rollback window:");
+        ui.add(egui::Slider::new(&mut config.netplay.rollback_frames, 0..=30).suffix(" frames"));
+    })
+    .response
+    .on_hover_text(
+        "Frames to run ahead of the peer before waiting for it. 0 waits for the peer every frame.",
+    );
+
+    ui.label(
+        "The host is the first player, and others joining with Watch are spectators. Rooms on a relay server take the second player only. Resetting, loading states and rewinding are disabled while playing.",
+    );
+}
+
+fn tab_link_cable(
+    ui: &mut egui::Ui,
+    link_cable: &mut LinkCable,
+    config: &mut Config,
+    emulator: Option<&Emulator>,
+    keyboard: &mut OnScreenKeyboard,
+) {
+    if cfg!(target_arch = "wasm32") {
+        ui.label("The link cable is not supported on this platform");
+        return;
+    }
+
+    ui.label(format!("Status: {}", link_cable.status_text()));
+
+    if link_cable.is_active() {
+        if ui.button("Disconnect").clicked() {
+            link_cable.disconnect();
+        }
+        return;
+    }
+
+    let supported = emulator.map_or(false, |emulator| emulator.core.supports_link_cable());
+    if !supported {
+        ui.label("Load a game which uses the link cable first");
+    }
+
+    ui.add_enabled_ui(supported, |ui| {
+        egui::Grid::new("link_cable").num_columns(3).show(ui, |ui| {
+            ui.label("Port:");
+            ui.add(egui::DragValue::new(&mut config.link_cable.port));
+            if ui.button("Host").clicked() {
+                if let Err(err) = link_cable.host(config.link_cable.port) {
+                    link_cable.fail(&err);
+                }
+            }
+            ui.end_row();
+
+            ui.label("Host address:");
+            keyboard.text_edit(ui, &mut config.link_cable.address, |text| {
+                egui::TextEdit::singleline(text).hint_text("host:port")
+            });
+            if ui
+                .add_enabled(
+                    !config.link_cable.address.is_empty(),
+                    egui::Button::new("Connect"),
+                )
+                .clicked()
+            {
+                link_cable.join(&config.link_cable.address);
+            }
+            ui.end_row();
+        });
+    });
+
+    ui.label("Both sides may run different games of the same system, such as for trading.");
+}
+
+fn tab_storage(
+    ui: &mut egui::Ui,
+    config: &Config,
+    emulator: Option<&Emulator>,
+    storage: &mut Option<Vec<GameStorage>>,
+    menu_event: &Sender<MenuEvent>,
+) {
+    let games = if let Some(games) = storage {
+        games
+    } else {
+        let save_dir = config.save_dir.clone();
+        let state_dir = config.state_dir.clone();
+        let menu_event = menu_event.clone();
+        spawn_local(async move {
+            let abbrevs = Emulator::core_infos()
+                .into_iter()
+                .map(|info| info.abbrev)
+                .collect::<Vec<_>>();
+            let result = storage_usage(&abbrevs, &save_dir, &state_dir).await;
+            menu_event
+                .send(MenuEvent::StorageChecked(result))
+                .await
+                .unwrap();
+        });
+        storage.insert(vec![])
+    };
+
+    let total = games
+        .iter()
+        .map(|game| game.backup_size + game.state_size)
+        .sum::<usize>();
+
+    ui.horizontal(|ui| {
+        ui.label(format!("Total: {}", format_size(total)));
+        if ui.button("Refresh").clicked() {
+            *storage = None;
+        }
+
+        if ui.button("Export all...").clicked() {
+            let save_dir = config.save_dir.clone();
+            let state_dir = config.state_dir.clone();
+            let menu_event = menu_event.clone();
+            spawn_local(async move {
+                let result = async {
+                    let abbrevs = Emulator::core_infos()
+                        .into_iter()
+                        .map(|info| info.abbrev)
+                        .collect::<Vec<_>>();
+                    let data = export_all(&abbrevs, &config_dir()?, &save_dir, &state_dir).await?;
+                    save_data_archive(data).await
+                }
+                .await;
+                menu_event
+                    .send(MenuEvent::DataExported(result))
+                    .await
+                    .unwrap();
+            });
+        }
+
+        if ui.button("Import...").clicked() {
+            let save_dir = config.save_dir.clone();
+            let state_dir = config.state_dir.clone();
+            let menu_event = menu_event.clone();
+            spawn_local(async move {
+                if let Some((_, data)) =
+                    file_dialog(None, &[("Zip archive", &["zip"])], false).await
+                {
+                    let result = async {
+                        let files =
+                            import_all(&data, &config_dir()?, &save_dir, &state_dir).await?;
+                        Ok::<_, anyhow::Error>((files, load_profile_data().await?))
+                    }
+                    .await;
+                    menu_event
+                        .send(MenuEvent::DataImported(result))
+                        .await
+                        .unwrap();
+                }
+            });
+        }
+    });
+    ui.label("Exported archives contain the config, save data and states of all games.");
+
+    ui.separator();
+
+    if games.is_empty() {
+        ui.label("No save data");
+        return;
+    }
+
+    let delete = |files: Vec<PathBuf>| {
+        let menu_event = menu_event.clone();
+        spawn_local(async move {
+            let result = delete_files(&files).await;
+            menu_event
+                .send(MenuEvent::StorageDeleted(result))
+                .await
+                .unwrap();
+        });
+    };
+
+    egui::ScrollArea::vertical().show(ui, |ui| {
+        egui::Grid::new("storage")
+            .num_columns(5)
+            .spacing([20.0, 4.0])
+            .striped(true)
+            .show(ui, |ui| {
+                ui.label("System");
+                ui.label("Game");
+                ui.label("Save data");
+                ui.label("States");
+                ui.end_row();
+
+                for game in games.iter() {
+                    ui.label(&game.abbrev);
+                    ui.label(&game.name);
+                    ui.label(format_size(game.backup_size));
+                    ui.label(format_size(game.state_size));
+
+                    // The running game would write its files again
+                    let running = emulator.map_or(false, |emulator| {
+                        emulator.core.core_info().abbrev == game.abbrev
+                            && emulator.game_name == game.name
+                    });
+
+                    ui.add_enabled_ui(!running, |ui| {
+                        ui.horizontal(|ui| {
+                            if ui.button("Delete states").clicked() {
+                                delete(
+                                    game.files
+                                        .iter()
+                                        .filter(|path| {
+                                            !is_backup_file(
+                                                &path.file_name().unwrap().to_string_lossy(),
+                                            )
+                                        })
+                                        .cloned()
+                                        .collect(),
+                                );
+                            }
+                            if ui.button("Delete all").clicked() {
+                                delete(game.files.clone());
+                            }
+                        })
+                        .response
+                        .on_disabled_hover_text("Close the game to delete its data");
+                    });
+                    ui.end_row();
+                }
+            });
+    });
+}
+
+fn tab_audio_setting(
+    ui: &mut egui::Ui,
+    config: &mut Config,
+    audio_devices: &mut Option<Vec<String>>,
+) {
+    ui.horizontal(|ui| {
+        ui.label("Output device:");
+
+        let devices = audio_devices.get_or_insert_with(output_device_names);
+        egui::ComboBox::from_id_source("audio_device")
+            .selected_text(config.audio.device.as_deref().unwrap_or("Default"))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut config.audio.device, None, "Default");
+                for device in devices.iter() {
+                    ui.selectable_value(&mut config.audio.device, Some(device.clone()), device);
+                }
+            });
+
+        if ui.button("Refresh").clicked() {
+            *audio_devices = None;
+        }
+    });
+    ui.label("Output device change takes effect when the game is resumed.");
+
+    ui.horizontal(|ui| {
+        ui.label("Latency:");
+        ui.add(egui::Slider::new(&mut config.audio.latency, 1..=16).suffix(" frames"));
+    });
+
+    ui.separator();
+
+    ui.horizontal(|ui| {
+        ui.label("Volume:");
+        ui.add(egui::Slider::new(&mut config.audio.volume, 0..=MAX_VOLUME).suffix("%"));
+        ui.checkbox(&mut config.audio.muted, "Mute");
+    });
+
+    for core_info in Emulator::core_infos() {
+        ui.horizontal(|ui| {
+            ui.label(format!("{} volume:", core_info.system_name));
+            let mut volume = config.audio.core_volume(core_info.abbrev);
+            if ui
+                .add(egui::Slider::new(&mut volume, 0..=100).suffix("%"))
+                .changed()
+            {
+                config.audio.set_core_volume(core_info.abbrev, volume);
+            }
+        });
+    }
+
+    ui.horizontal(|ui| {
+        ui.label("Audio during turbo:");
+        egui::ComboBox::from_id_source("turbo_audio")
+            .selected_text(config.audio.turbo_audio.to_string())
+            .show_ui(ui, |ui| {
+                for turbo_audio in all::<TurboAudio>() {
+                    ui.selectable_value(
+                        &mut config.audio.turbo_audio,
+                        turbo_audio,
+                        turbo_audio.to_string(),
+                    );
+                }
+            });
+    });
+    ui.checkbox(
+        &mut config.audio.mute_in_background,
+        "Mute when the window is in background",
+    );
+
+    ui.separator();
+
+    ui.checkbox(&mut config.audio.mono, "Mono output");
+
+    ui.horizontal(|ui| {
+        ui.label("Balance:");
+        ui.add(egui::Slider::new(&mut config.audio.balance, -100..=100).text("L / R"));
+        if ui.button("Center").clicked() {
+            config.audio.balance = 0;
+        }
+    });
+
+    ui.checkbox(&mut config.audio.night_mode, "Night mode")
+        .on_hover_text(
+            "Compress dynamic range so that quiet sounds are louder and loud sounds are softer",
+        );
+
+    ui.checkbox(
+        &mut config.audio.dynamic_rate_control,
+        "Dynamic rate control",
+    )
+    .on_hover_text(
+        "Keep the video smooth by slightly adjusting the audio pitch, instead of skipping frames",
+    );
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn dir_field(
+    ui: &mut egui::Ui,
+    label: &str,
+    dir: &Path,
+    menu_event: &Sender<MenuEvent>,
+    event: fn(PathBuf) -> MenuEvent,
+) {
+    ui.horizontal(|ui| {
+        ui.label(label);
+        if ui.button("Change").clicked() {
+            let cur_dir = dir.to_owned();
+            let menu_event = menu_event.clone();
+            spawn_local(async move {
+                let fd = rfd::AsyncFileDialog::new().set_directory(&cur_dir);
+                if let Some(dir) = fd.pick_folder().await {
+                    menu_event.send(event(dir.path().to_owned())).await.unwrap();
+                }
+            });
+        }
+    });
+    ui.indent("", |ui| {
+        let s = dir.display().to_string();
+        ui.add(egui::TextEdit::singleline(&mut s.as_ref()));
+    });
+}
+
+pub struct FileFieldResult {
+    file_sent: bool,
+    cleard: bool,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+enum FieldIndex {
+    Object(String),
+    Array(usize),
+}
+
+/// Palette being edited in core settings, not applied yet
+struct PaletteEdit {
+    path: Vec<FieldIndex>,
+    applied: Vec<meru_interface::Color>,
+    candidate: Vec<meru_interface::Color>,
+}
+
+pub fn file_field(
+    ui: &mut egui::Ui,
+    sender: &Sender<(PathBuf, Vec<u8>)>,
+    label: &str,
+    path: &mut Option<PathBuf>,
+    file_filter: &[(&str, &[&str])],
+    has_clear: bool,
+) -> FileFieldResult {
+    let mut file_sent = false;
+    let mut changed = false;
+
+    ui.horizontal(|ui| {
+        ui.label(label);
+        if ui.button("Change").clicked() {
+            file_sent = true;
+
+            let cur_path = path.clone();
+            let file_filter = file_filter
+                .iter()
+                .map(|(name, exts)| {
+                    (
+                        name.to_string(),
+                        exts.iter().map(|ext| ext.to_string()).collect::<Vec<_>>(),
+                    )
+                })
+                .collect::<Vec<_>>();
+
+            let sender = sender.clone();
+            spawn_local(async move {
+                let filter_keys = file_filter.iter().map(|(name, _)| name.as_str());
+                let filter_vals = file_filter
+                    .iter()
+                    .map(|(_, val)| val.iter().map(|r| r.as_str()).collect::<Vec<_>>())
+                    .collect::<Vec<_>>();
+                let filter_ref = filter_keys
+                    .into_iter()
+                    .zip(filter_vals.iter())
+                    .map(|(key, val)| (key, val.as_slice()))
+                    .collect::<Vec<_>>();
+
+                if let Some((path, data)) =
+                    file_dialog(cur_path.as_deref(), &filter_ref, file_filter.is_empty()).await
+                {
+                    sender.send((path, data)).await.unwrap();
+                }
+            });
+        }
+        if has_clear && ui.button("Clear").clicked() {
+            *path = None;
+            changed = true;
+        }
+    });
+    ui.indent("", |ui| {
+        let s = path
+            .as_ref()
+            .map_or_else(|| "None".to_string(), |r| r.display().to_string());
+        ui.add(egui::TextEdit::singleline(&mut s.as_ref()));
+    });
+
+    FileFieldResult {
+        file_sent,
+        cleard: changed,
+    }
+}
+
+/// Returns the labels of options that are changed but not applied until the core is reset
+#[allow(clippy::too_many_arguments)]
+fn core_config_ui(
+    ui: &mut egui::Ui,
+    abbrev: &str,
+    config: Value,
+    boot_config: Option<Value>,
+    palette_edit: &mut Option<PaletteEdit>,
+    palette_presets: Vec<PalettePreset>,
+    menu_event: &Sender<MenuEvent>,
+    sender: &Sender<ConfigValue>,
+) -> Vec<String> {
     let mut schema = EMULATOR_CORES
         .iter()
         .find(|core| core.core_info().abbrev == abbrev)
@@ -1188,7 +4650,16 @@ fn core_config_ui(ui: &mut egui::Ui, abbrev: &str, config: Value, sender: &Sende
     let (s, r) = unbounded_channel::<(Vec<FieldIndex>, Value)>();
 
     let is_empty = config == json!({});
-    let mut visitor = ConfigVisitor::new(ui, &schema, config, s);
+    let mut visitor = ConfigVisitor::new(
+        ui,
+        &schema,
+        config,
+        boot_config,
+        palette_edit,
+        palette_presets,
+        menu_event.clone(),
+        s,
+    );
 
     if is_empty {
         visitor.ui().label("No config options");
@@ -1196,6 +4667,7 @@ fn core_config_ui(ui: &mut egui::Ui, abbrev: &str, config: Value, sender: &Sende
         visitor.visit_schema_object(&mut schema.schema);
     }
 
+    let pending_reset = std::mem::take(&mut visitor.pending_reset);
     let sender = sender.clone();
     let abbrev = abbrev.to_string();
 
@@ -1213,7 +4685,363 @@ fn core_config_ui(ui: &mut egui::Ui, abbrev: &str, config: Value, sender: &Sende
                 .await
                 .unwrap();
         }
+    });
+
+    pending_reset
+}
+
+fn palette_preview(
+    ui: &mut egui::Ui,
+    edit: &PaletteEdit,
+    frame_buffer: Option<&FrameBuffer>,
+    texture: &mut Option<egui::TextureHandle>,
+) {
+    ui.group(|ui| {
+        ui.label("Palette preview");
+
+        let frame_buffer = match frame_buffer {
+            Some(frame_buffer) if frame_buffer.width > 0 && frame_buffer.height > 0 => frame_buffer,
+            _ => {
+                ui.weak("Run a game of this system to preview the palette");
+                return;
+            }
+        };
+
+        let image = recolor_frame(frame_buffer, &edit.applied, &edit.candidate);
+        let size = egui::vec2(image.size[0] as f32, image.size[1] as f32) * 2.0;
+
+        if let Some(texture) = texture {
+            texture.set(image, egui::TextureFilter::Nearest);
+        } else {
+            *texture = Some(ui.ctx().load_texture(
+                "palette-preview",
+                image,
+                egui::TextureFilter::Nearest,
+            ));
+        }
+
+        ui.image(texture.as_ref().unwrap(), size);
+    });
+}
+
+fn core_frontend_setting(ui: &mut egui::Ui, config: &mut Config, abbrev: &str) {
+    let mut frame_skip = config.core_frame_skips_on_turbo.get(abbrev).copied();
+
+    ui.horizontal(|ui| {
+        let mut overridden = frame_skip.is_some();
+        if ui
+            .checkbox(&mut overridden, "Override frame skip on turbo:")
+            .changed()
+        {
+            frame_skip = overridden.then(|| config.frame_skip_on_turbo);
+        }
+
+        let mut value = frame_skip.unwrap_or(config.frame_skip_on_turbo);
+        ui.add_enabled_ui(overridden, |ui| {
+            ui.add(egui::Slider::new(&mut value, 1..=10));
+        });
+        if overridden {
+            frame_skip = Some(value);
+        }
+    });
+
+    config.set_core_frame_skip_on_turbo(abbrev, frame_skip);
+
+    let mut overscan = config.overscan(abbrev);
+    ui.horizontal(|ui| {
+        ui.label("Overscan crop:");
+        for (label, lines) in [
+            ("Top", &mut overscan.top),
+            ("Bottom", &mut overscan.bottom),
+            ("Left", &mut overscan.left),
+            ("Right", &mut overscan.right),
+        ] {
+            ui.label(label);
+            ui.add(egui::DragValue::new(lines).clamp_range(0..=32));
+        }
+        if ui.button("Default").clicked() {
+            overscan = Overscan::default_for(abbrev);
+        }
+    });
+    config.set_core_overscan(abbrev, overscan);
+}
+
+fn camera_setting(
+    ui: &mut egui::Ui,
+    config: &mut Config,
+    emulator: Option<&Emulator>,
+    menu_event: &Sender<MenuEvent>,
+) {
+    ui.label("Camera:");
+
+    ui.horizontal(|ui| {
+        ui.label("Source:");
+        egui::ComboBox::from_id_source("camera_source")
+            .selected_text(config.camera.source.to_string())
+            .show_ui(ui, |ui| {
+                for source in all::<CameraSource>() {
+                    if source == CameraSource::Webcam && !camera::is_webcam_supported() {
+                        continue;
+                    }
+                    ui.selectable_value(&mut config.camera.source, source, source.to_string());
+                }
+            });
     })
+    .response
+    .on_hover_text("Image seen by the Game Boy Camera");
+
+    match config.camera.source {
+        CameraSource::Off => {}
+        CameraSource::Image => {
+            ui.horizontal(|ui| {
+                ui.label("Image file:");
+                if ui.button("Change").clicked() {
+                    let menu_event = menu_event.clone();
+                    spawn_local(async move {
+                        let fd = rfd::AsyncFileDialog::new()
+                            .add_filter("PNG image", &["png"])
+                            .add_filter("All files", &["*"]);
+                        if let Some(file) = fd.pick_file().await {
+                            menu_event
+                                .send(MenuEvent::SetCameraImage(file.path().to_owned()))
+                                .await
+                                .unwrap();
+                        }
+                    });
+                }
+            });
+            ui.indent("", |ui| {
+                let s = config
+                    .camera
+                    .image
+                    .as_ref()
+                    .map_or(String::new(), |path| path.display().to_string());
+                ui.add(egui::TextEdit::singleline(&mut s.as_ref()));
+            });
+        }
+        CameraSource::Webcam => {
+            ui.horizontal(|ui| {
+                ui.label("Webcam number:");
+                ui.add(egui::DragValue::new(&mut config.camera.webcam_index).clamp_range(0..=9));
+            });
+        }
+    }
+
+    if let Some(emulator) = emulator.filter(|emulator| emulator.core.supports_camera()) {
+        if ui
+            .button("Export photos")
+            .on_hover_text("Saves the photos in the album as PNG files into the capture directory")
+            .clicked()
+        {
+            let fut = camera::export_photos(emulator, config);
+            let menu_event = menu_event.clone();
+            spawn_local(async move {
+                let result = fut.await;
+                menu_event
+                    .send(MenuEvent::CameraPhotosExported(result))
+                    .await
+                    .unwrap();
+            });
+        }
+    }
+}
+
+fn card_reader_setting(ui: &mut egui::Ui, menu_event: &Sender<MenuEvent>) {
+    ui.label("Card reader:");
+
+    if ui
+        .button("Scan card")
+        .on_hover_text("Scans an e-Reader card from a dot code dump or a scanned image")
+        .clicked()
+    {
+        let menu_event = menu_event.clone();
+        spawn_local(async move {
+            let fd = rfd::AsyncFileDialog::new()
+                .add_filter("e-Reader card", &["raw", "bin", "bmp"])
+                .add_filter("All files", &["*"]);
+            if let Some(file) = fd.pick_file().await {
+                let data = file.read().await;
+                menu_event
+                    .send(MenuEvent::ScanCard(file.file_name(), data))
+                    .await
+                    .unwrap();
+            }
+        });
+    }
+}
+
+fn gb_model_setting(
+    ui: &mut egui::Ui,
+    config: &mut Config,
+    emulator: Option<&mut Emulator>,
+    blocker: Option<&str>,
+) {
+    ui.checkbox(
+        &mut config.prompt_gb_model,
+        "Ask the model when loading games for both Game Boy and Game Boy Color",
+    );
+
+    let emulator = if let Some(emulator) = emulator.filter(|emulator| emulator.dual_mode_cart) {
+        emulator
+    } else {
+        return;
+    };
+
+    let mut model = config.gb_models.get(&emulator.game_hash).copied();
+    ui.add_enabled_ui(blocker.is_none(), |ui| {
+        ui.horizontal(|ui| {
+            ui.label("Model for this game:");
+            ui.selectable_value(&mut model, None, "Not remembered");
+            for m in all::<GbModel>() {
+                ui.selectable_value(&mut model, Some(m), m.to_string());
+            }
+        })
+        .response
+        .on_hover_text("Changing the model resets the game")
+        .on_disabled_hover_text(format!("Not available {}", blocker.unwrap_or_default()));
+    });
+
+    if model != config.gb_models.get(&emulator.game_hash).copied() {
+        if let Some(model) = model {
+            config.gb_models.insert(emulator.game_hash.clone(), model);
+            emulator.set_gb_model(Some(model));
+        } else {
+            // The running game keeps its model until it is loaded again
+            config.gb_models.remove(&emulator.game_hash);
+        }
+    }
+}
+
+fn reset_core_config_ui(
+    ui: &mut egui::Ui,
+    config: &Config,
+    abbrev: &str,
+    confirm_reset_cores: &mut Option<Vec<String>>,
+    sender: &Sender<ConfigValue>,
+) {
+    ui.horizontal(|ui| {
+        if ui.button("Reset this core's settings").clicked() {
+            *confirm_reset_cores = Some(vec![abbrev.to_string()]);
+        }
+        if ui.button("Reset all cores' settings").clicked() {
+            *confirm_reset_cores = Some(
+                Emulator::core_infos()
+                    .iter()
+                    .map(|core_info| core_info.abbrev.to_string())
+                    .collect(),
+            );
+        }
+    });
+
+    let abbrevs = if let Some(abbrevs) = confirm_reset_cores {
+        abbrevs
+    } else {
+        return;
+    };
+
+    let changes = abbrevs
+        .iter()
+        .map(|abbrev| {
+            let core = EmulatorCores::from_abbrev(abbrev).unwrap();
+            let default_config = core.default_config();
+            let mut diff = vec![];
+            config_diff("", &config.core_config(abbrev), &default_config, &mut diff);
+            (core.core_info().system_name, abbrev, default_config, diff)
+        })
+        .filter(|(_, _, _, diff)| !diff.is_empty())
+        .collect::<Vec<_>>();
+
+    let mut open = true;
+    let mut close = false;
+
+    egui::Window::new("Reset core settings")
+        .open(&mut open)
+        .collapsible(false)
+        .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+        .show(ui.ctx(), |ui| {
+            if changes.is_empty() {
+                ui.label("Settings are already default.");
+                if ui.button("OK").clicked() {
+                    close = true;
+                }
+                return;
+            }
+
+            ui.label("The following settings will be reset to default:");
+
+            egui::Grid::new("reset_core_config")
+                .num_columns(3)
+                .spacing([20.0, 4.0])
+                .striped(true)
+                .show(ui, |ui| {
+                    for (system_name, _, _, diff) in &changes {
+                        for (path, cur, default) in diff {
+                            ui.label(format!("{system_name}: {path}"));
+                            ui.label(cur);
+                            ui.label(format!("→ {default}"));
+                            ui.end_row();
+                        }
+                    }
+                });
+
+            ui.horizontal(|ui| {
+                if ui.button("Reset").clicked() {
+                    for (_, abbrev, default_config, _) in &changes {
+                        sender
+                            .try_send(ConfigValue {
+                                abbrev: abbrev.to_string(),
+                                value: default_config.clone(),
+                            })
+                            .unwrap();
+                    }
+                    close = true;
+                }
+                if ui.button("Cancel").clicked() {
+                    close = true;
+                }
+            });
+        });
+
+    if !open || close {
+        *confirm_reset_cores = None;
+    }
+}
+
+fn config_diff(path: &str, cur: &Value, default: &Value, diff: &mut Vec<(String, String, String)>) {
+    fn show(value: &Value) -> String {
+        const MAX_LEN: usize = 40;
+        let s = match value {
+            Value::String(s) => s.clone(),
+            Value::Null => "(none)".to_string(),
+            _ => value.to_string(),
+        };
+        if s.chars().count() > MAX_LEN {
+            format!("{}…", s.chars().take(MAX_LEN).collect::<String>())
+        } else {
+            s
+        }
+    }
+
+    match (cur, default) {
+        (Value::Object(cur), Value::Object(default)) => {
+            let keys = cur.keys().chain(default.keys()).collect::<BTreeSet<_>>();
+            for key in keys {
+                let path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{path}.{key}")
+                };
+                config_diff(
+                    &path,
+                    cur.get(key).unwrap_or(&Value::Null),
+                    default.get(key).unwrap_or(&Value::Null),
+                    diff,
+                );
+            }
+        }
+        _ if cur != default => diff.push((path.to_string(), show(cur), show(default))),
+        _ => {}
+    }
 }
 
 fn get_value_field<'a>(v: &'a mut Value, path: &'_ [FieldIndex]) -> &'a mut Value {
@@ -1240,14 +5068,27 @@ struct ConfigVisitor<'a> {
     new_val: Value,
     sender: Sender<(Vec<FieldIndex>, Value)>,
     changed: bool,
+    /// Config the running core was booted with, if it is running
+    boot_val: Option<Value>,
+    palette_edit: &'a mut Option<PaletteEdit>,
+    palette_presets: Vec<PalettePreset>,
+    menu_event: Sender<MenuEvent>,
+    /// `x-requires-reset` annotation of the nearest annotated ancestor
+    requires_reset: Option<bool>,
+    pending_reset: Vec<String>,
     defs: BTreeMap<String, Schema>,
 }
 
 impl<'a> ConfigVisitor<'a> {
+    #[allow(clippy::too_many_arguments)]
     fn new(
         ui: &'a mut egui::Ui,
         schema: &RootSchema,
         value: Value,
+        boot_value: Option<Value>,
+        palette_edit: &'a mut Option<PaletteEdit>,
+        palette_presets: Vec<PalettePreset>,
+        menu_event: Sender<MenuEvent>,
         sender: Sender<(Vec<FieldIndex>, Value)>,
     ) -> Self {
         Self {
@@ -1259,6 +5100,12 @@ impl<'a> ConfigVisitor<'a> {
             new_val: value,
             sender,
             changed: false,
+            boot_val: boot_value,
+            palette_edit,
+            palette_presets,
+            menu_event,
+            requires_reset: None,
+            pending_reset: vec![],
             defs: schema
                 .definitions
                 .iter()
@@ -1276,10 +5123,272 @@ impl ConfigVisitor<'_> {
     fn resolve(&self, name: &str) -> Schema {
         self.defs.get(name).unwrap().clone()
     }
+
+    fn is_color(&self, schema: &Schema) -> bool {
+        let schema = schema.clone().into_object();
+        if let Some(name) = &schema.reference {
+            self.is_color(&self.resolve(name))
+        } else {
+            schema.format.as_deref() == Some("color")
+        }
+    }
+
+    /// Color arrays are edited as palettes, which are applied after previewing them
+    fn palette_field(&mut self, label: &str) {
+        let applied = serde_json::from_value::<Vec<meru_interface::Color>>(
+            get_value_field(&mut self.cur_val, &self.path).clone(),
+        )
+        .unwrap();
+
+        let edit = self
+            .palette_edit
+            .as_ref()
+            .filter(|edit| edit.path == self.path);
+        let mut colors = edit.map_or_else(|| applied.clone(), |edit| edit.candidate.clone());
+
+        let mut edited = false;
+        let mut apply = false;
+        let mut revert = false;
+
+        self.ui.as_deref_mut().unwrap().horizontal(|ui| {
+            ui.label(label);
+
+            for color in colors.iter_mut() {
+                let mut rgb = [color.r, color.g, color.b];
+                if ui.color_edit_button_srgb(&mut rgb).changed() {
+                    *color = meru_interface::Color::new(rgb[0], rgb[1], rgb[2]);
+                    edited = true;
+                }
+            }
+
+            if colors != applied {
+                apply = ui.button("Apply").clicked();
+                revert = ui.button("Revert").clicked();
+            }
+        });
+
+        let presets = &self.palette_presets;
+        let menu_event = &self.menu_event;
+        self.ui.as_deref_mut().unwrap().indent("", |ui| {
+            ui.horizontal(|ui| {
+                egui::ComboBox::from_id_source(format!("palette-presets-{label}"))
+                    .selected_text("Presets")
+                    .show_ui(ui, |ui| {
+                        // Longer palettes are truncated, e.g. 16-color JASC files
+                        for preset in presets.iter().filter(|p| p.colors.len() >= colors.len()) {
+                            let text = match &preset.tag {
+                                Some(tag) => format!("{} [{tag}]", preset.name),
+                                None => preset.name.clone(),
+                            };
+                            if ui.selectable_label(false, text).clicked() {
+                                let len = colors.len();
+                                colors = preset.colors[..len].to_vec();
+                                edited = true;
+                            }
+                        }
+                    });
+
+                if ui.button("Import").clicked() {
+                    let menu_event = menu_event.clone();
+                    spawn_local(async move {
+                        let filter: &[(&str, &[&str])] =
+                            &[("Palette files", &["pal", "json"]), ("All files", &["*"])];
+                        if let Some((path, data)) = file_dialog(None, filter, false).await {
+                            let name = path
+                                .file_stem()
+                                .map_or_else(String::new, |s| s.to_string_lossy().to_string());
+                            menu_event
+                                .send(MenuEvent::PaletteImported(import_palette(&name, &data)))
+                                .await
+                                .unwrap();
+                        }
+                    });
+                }
+
+                #[cfg(not(target_arch = "wasm32"))]
+                if ui.button("Export").clicked() {
+                    let colors = colors.clone();
+                    let menu_event = menu_event.clone();
+                    spawn_local(async move {
+                        let file = rfd::AsyncFileDialog::new()
+                            .add_filter("Palette preset", &["json"])
+                            .set_file_name("palette.json")
+                            .save_file()
+                            .await;
+                        if let Some(file) = file {
+                            let path = file.path().to_owned();
+                            let name = path
+                                .file_stem()
+                                .map_or_else(String::new, |s| s.to_string_lossy().to_string());
+                            let data = export_palette(&PalettePreset {
+                                name,
+                                colors,
+                                tag: None,
+                            });
+                            let result = std::fs::write(&path, data)
+                                .map(|_| path)
+                                .map_err(Into::into);
+                            menu_event
+                                .send(MenuEvent::PaletteExported(result))
+                                .await
+                                .unwrap();
+                        }
+                    });
+                }
+
+                if ui.button("Copy as JSON").clicked() {
+                    let preset = PalettePreset {
+                        name: label.to_string(),
+                        colors: colors.clone(),
+                        tag: None,
+                    };
+                    ui.output().copied_text = String::from_utf8(export_palette(&preset)).unwrap();
+                }
+            });
+        });
+
+        if apply {
+            set_value_field(
+                &mut self.new_val,
+                &self.path,
+                serde_json::to_value(&colors).unwrap(),
+            );
+            self.changed = true;
+            *self.palette_edit = None;
+        } else if revert {
+            *self.palette_edit = None;
+        } else if edited {
+            *self.palette_edit = Some(PaletteEdit {
+                path: self.path.clone(),
+                applied,
+                candidate: colors,
+            });
+        } else if let Some(edit) = self.palette_edit.as_mut() {
+            if edit.path == self.path {
+                edit.applied = applied;
+            }
+        }
+    }
+
+    /// Numbers bounded on both sides by the schema are edited with a slider,
+    /// and others with a drag value. `multipleOf` is used as the step.
+    fn number_field(&mut self, schema: &SchemaObject, label: &str, nullable: bool) {
+        let integer = !schema.has_type(InstanceType::Number);
+        let validation = schema.number.as_deref().cloned().unwrap_or_default();
+        let (type_min, type_max) = integer_format_range(schema.format.as_deref());
+
+        // Exclusive bounds of integers are the next integers inside
+        let offset = if integer { 1.0 } else { 0.0 };
+        let min = validation
+            .minimum
+            .or_else(|| validation.exclusive_minimum.map(|min| min + offset));
+        let max = validation
+            .maximum
+            .or_else(|| validation.exclusive_maximum.map(|max| max - offset));
+        let step = validation.multiple_of.filter(|step| *step > 0.0);
+
+        let cur = get_value_field(&mut self.cur_val, &self.path).clone();
+        let mut enabled = !cur.is_null();
+        let mut value = cur.as_f64().unwrap_or_else(|| min.unwrap_or(0.0).max(0.0));
+
+        let mut changed = false;
+        self.ui().horizontal(|ui| {
+            if nullable {
+                changed |= ui.checkbox(&mut enabled, label).changed();
+            } else {
+                ui.label(label);
+            }
+
+            ui.add_enabled_ui(enabled, |ui| match (min, max) {
+                (Some(min), Some(max)) => {
+                    let mut slider = egui::Slider::new(&mut value, min..=max);
+                    if let Some(step) = step {
+                        slider = slider.step_by(step);
+                    }
+                    if integer {
+                        slider = slider.integer();
+                    }
+                    changed |= ui.add(slider).changed();
+                }
+                _ => {
+                    let min = min.or(type_min).unwrap_or(f64::MIN);
+                    let max = max.or(type_max).unwrap_or(f64::MAX);
+                    let mut drag = egui::DragValue::new(&mut value)
+                        .clamp_range(min..=max)
+                        .speed(step.unwrap_or(if integer { 1.0 } else { 0.01 }));
+                    if integer {
+                        drag = drag.fixed_decimals(0);
+                    }
+                    changed |= ui.add(drag).changed();
+                }
+            });
+        });
+
+        if !changed {
+            return;
+        }
+        self.changed = true;
+
+        if let Some(step) = step {
+            value = (value / step).round() * step;
+        }
+        let new_value = if !enabled {
+            Value::Null
+        } else if integer {
+            let value = value.round();
+            if value < 0.0 {
+                Value::from(value as i64)
+            } else {
+                Value::from(value as u64)
+            }
+        } else {
+            Value::from(value)
+        };
+        set_value_field(&mut self.new_val, &self.path, new_value);
+    }
+
+    /// Shows whether a change of the current field is waiting for a reset of the running core
+    fn reset_indicator(&mut self, label: &str) {
+        if self.requires_reset != Some(true) {
+            return;
+        }
+
+        let pending = if let Some(boot_val) = &mut self.boot_val {
+            let boot = get_value_field(boot_val, &self.path).clone();
+            &boot != get_value_field(&mut self.cur_val, &self.path)
+        } else {
+            false
+        };
+
+        if pending {
+            self.pending_reset.push(label.to_string());
+            self.ui()
+                .colored_label(egui::Color32::YELLOW, "⟳ Pending reset")
+                .on_hover_text("This change takes effect after the core is reset");
+        } else {
+            self.ui()
+                .weak("⟳ Applied on reset")
+                .on_hover_text("Changes of this option take effect after the core is reset");
+        }
+    }
 }
 
 impl Visitor for ConfigVisitor<'_> {
     fn visit_schema_object(&mut self, schema: &mut SchemaObject) {
+        // Outer annotations take precedence, so that fields can override the default of their types
+        let prev_requires_reset = self.requires_reset;
+        if prev_requires_reset.is_none() {
+            self.requires_reset = schema_requires_reset(schema);
+        }
+
+        self.visit_option(schema);
+
+        self.requires_reset = prev_requires_reset;
+    }
+}
+
+impl ConfigVisitor<'_> {
+    fn visit_option(&mut self, schema: &mut SchemaObject) {
         if schema.is_ref() {
             let name = schema.reference.as_ref().unwrap().clone();
             let mut schema = self.resolve(&name);
@@ -1404,6 +5513,12 @@ impl Visitor for ConfigVisitor<'_> {
                 return;
             };
 
+            if self.is_color(items) {
+                self.palette_field(&label);
+                self.reset_indicator(&label);
+                return;
+            }
+
             let mut parent_ui = self.ui.take();
 
             parent_ui.as_deref_mut().unwrap().horizontal(|ui| {
@@ -1429,18 +5544,13 @@ impl Visitor for ConfigVisitor<'_> {
                 .unwrap();
             self.changed |= self.ui().checkbox(&mut value, &label).changed();
             set_value_field(&mut self.new_val, &self.path, value.into());
+            self.reset_indicator(&label);
             return;
         }
 
-        if schema.has_type(InstanceType::Number) {
-            let msg = format!("TODO: {:?}: Number", self.path);
-            self.ui().label(msg);
-            return;
-        }
-
-        if schema.has_type(InstanceType::Integer) {
-            let msg = format!("TODO: {:?}: Integer", self.path);
-            self.ui().label(msg);
+        if schema.has_type(InstanceType::Number) || schema.has_type(InstanceType::Integer) {
+            self.number_field(schema, &label, nullable);
+            self.reset_indicator(&label);
             return;
         }
 
@@ -1457,7 +5567,7 @@ impl Visitor for ConfigVisitor<'_> {
                     .collect::<Vec<_>>();
                 let mut selected = alts.iter().position(|v| v == &value).unwrap();
 
-                self.changed |= egui::ComboBox::from_label(label)
+                self.changed |= egui::ComboBox::from_label(&label)
                     .width(300.0)
                     .selected_text(&value)
                     .show_index(self.ui.as_mut().unwrap(), &mut selected, alts.len(), |i| {
@@ -1548,10 +5658,28 @@ impl Visitor for ConfigVisitor<'_> {
                 let msg = format!("TODO: {:?}: String ({:?})", self.path, schema.format);
                 self.ui().label(msg);
             }
+
+            self.reset_indicator(&label);
         }
     }
 }
 
+/// Range of the integer types, for the formats generated by schemars
+fn integer_format_range(format: Option<&str>) -> (Option<f64>, Option<f64>) {
+    let (min, max) = match format {
+        Some("int8") => (i8::MIN as f64, i8::MAX as f64),
+        Some("uint8") => (0.0, u8::MAX as f64),
+        Some("int16") => (i16::MIN as f64, i16::MAX as f64),
+        Some("uint16") => (0.0, u16::MAX as f64),
+        Some("int32") => (i32::MIN as f64, i32::MAX as f64),
+        Some("uint32") => (0.0, u32::MAX as f64),
+        Some("int64") => (i64::MIN as f64, i64::MAX as f64),
+        Some("uint64" | "uint") => (0.0, u64::MAX as f64),
+        _ => return (None, None),
+    };
+    (Some(min), Some(max))
+}
+
 fn is_null(s: &Schema) -> bool {
     if let Some(SingleOrVec::Single(r)) = s.clone().into_object().instance_type {
         matches!(r.as_ref(), InstanceType::Null)
@@ -1559,3 +5687,99 @@ fn is_null(s: &Schema) -> bool {
         false
     }
 }
+
+/// Lists hotkeys triggered by core buttons, with buttons to reassign them to unused keys
+fn hotkey_conflict_setting(
+    ui: &mut egui::Ui,
+    config: &mut Config,
+    gamepad_layouts: &GamepadLayouts,
+    core: Option<&str>,
+) {
+    let conflicts = hotkey_conflicts(config, core);
+    if conflicts.is_empty() {
+        return;
+    }
+
+    ui.separator();
+    ui.colored_label(
+        egui::Color32::YELLOW,
+        "⚠ Hotkeys also assigned to game buttons:",
+    );
+
+    let mut applied = vec![];
+    egui::Grid::new("hotkey_conflicts")
+        .num_columns(3)
+        .spacing([40.0, 4.0])
+        .striped(true)
+        .show(ui, |ui| {
+            for (ix, conflict) in conflicts.iter().enumerate() {
+                ui.label(format!(
+                    "{}: {}",
+                    conflict.hotkey,
+                    gamepad_layouts.multi_key_label(&conflict.key)
+                ));
+                ui.label(format!(
+                    "{} {}",
+                    conflict.core.to_uppercase(),
+                    conflict.button
+                ));
+                if let Some(suggestion) = &conflict.suggestion {
+                    let label = format!("Use {}", gamepad_layouts.multi_key_label(suggestion));
+                    if ui.button(label).clicked() {
+                        applied.push(ix);
+                    }
+                } else {
+                    ui.label("No free key");
+                }
+                ui.end_row();
+            }
+        });
+
+    if conflicts.iter().filter(|c| c.suggestion.is_some()).count() > 1
+        && ui.button("Use all suggestions").clicked()
+    {
+        applied = (0..conflicts.len()).collect();
+    }
+
+    for ix in applied {
+        apply_suggestion(&mut config.hotkeys, &conflicts[ix]);
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn global_hotkey_setting(ui: &mut egui::Ui, config: &mut Config, keyboard: &mut OnScreenKeyboard) {
+    use crate::os_hotkey::check_os_hotkey;
+
+    ui.checkbox(
+        &mut config.global_hotkeys.enabled,
+        "Enable global hotkeys (work while another window is focused)",
+    )
+    .on_hover_text("These keys are taken from all other applications while MERU is running");
+
+    ui.add_enabled_ui(config.global_hotkeys.enabled, |ui| {
+        egui::Grid::new("global_hotkeys")
+            .num_columns(3)
+            .spacing([40.0, 4.0])
+            .striped(true)
+            .show(ui, |ui| {
+                for (hotkey, assign) in config.global_hotkeys.assignments_mut() {
+                    ui.label(hotkey.to_string());
+
+                    keyboard.text_edit_singleline(ui, assign);
+
+                    match check_os_hotkey(assign) {
+                        Ok(None) => ui.label(""),
+                        Ok(Some(warning)) => ui
+                            .colored_label(egui::Color32::YELLOW, "⚠ Conflict")
+                            .on_hover_text(warning),
+                        Err(err) => ui
+                            .colored_label(egui::Color32::RED, "✖ Invalid")
+                            .on_hover_text(err),
+                    };
+                    ui.end_row();
+                }
+            });
+    });
+
+    ui.label("Keys are written like `ctrl+alt+KeyS`, `shift+F5`");
+}
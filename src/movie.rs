@@ -0,0 +1,352 @@
+use anyhow::{anyhow, bail, Result};
+use bevy::prelude::*;
+use chrono::DateTime;
+use meru_interface::InputData;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app::ShowMessage,
+    config::Config,
+    core::Emulator,
+    file::{delete_movie, list_movies, load_movie, save_movie},
+    utils::{spawn_local, unbounded_channel, Receiver, Sender},
+};
+
+pub struct MoviePlugin;
+
+impl Plugin for MoviePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Movies>().add_system(movie_event_system);
+    }
+}
+
+/// Inputs of each frame from a state, which replay the same game deterministically
+#[derive(Serialize, Deserialize)]
+struct Movie {
+    game_hash: String,
+    start: DateTime<chrono::Local>,
+    /// State at the first frame
+    state: Vec<u8>,
+    /// Key names of each controller
+    keys: Vec<Vec<String>>,
+    /// Pressed keys of each controller as bit sets indexed by `keys`, for each frame
+    frames: Vec<Vec<u64>>,
+}
+
+const MAX_KEYS: usize = 64;
+
+impl Movie {
+    fn new(emulator: &Emulator) -> Self {
+        Self {
+            game_hash: emulator.game_hash.clone(),
+            start: chrono::Local::now(),
+            state: emulator.core.save_state(),
+            keys: vec![],
+            frames: vec![],
+        }
+    }
+
+    fn push(&mut self, input: &InputData) {
+        if self.keys.len() < input.controllers.len() {
+            self.keys.resize(input.controllers.len(), vec![]);
+        }
+
+        let mut frame = vec![0; input.controllers.len()];
+        for (keys, (controller, bits)) in self
+            .keys
+            .iter_mut()
+            .zip(input.controllers.iter().zip(frame.iter_mut()))
+        {
+            for (key, pressed) in controller {
+                let ix = match keys.iter().position(|k| k == key) {
+                    Some(ix) => ix,
+                    None if keys.len() < MAX_KEYS => {
+                        keys.push(key.clone());
+                        keys.len() - 1
+                    }
+                    None => continue,
+                };
+                if *pressed {
+                    *bits |= 1 << ix;
+                }
+            }
+        }
+
+        self.frames.push(frame);
+    }
+
+    fn input(&self, frame: usize) -> Option<InputData> {
+        let bits = self.frames.get(frame)?;
+
+        let controllers = self
+            .keys
+            .iter()
+            .enumerate()
+            .map(|(i, keys)| {
+                let bits = bits.get(i).copied().unwrap_or(0);
+                keys.iter()
+                    .enumerate()
+                    .map(|(ix, key)| (key.clone(), bits & (1 << ix) != 0))
+                    .collect()
+            })
+            .collect();
+
+        Some(InputData { controllers })
+    }
+}
+
+enum MovieState {
+    Idle,
+    Recording {
+        movie: Movie,
+        abbrev: String,
+        game_name: String,
+    },
+    Playing {
+        movie: Movie,
+        frame: usize,
+    },
+}
+
+enum MovieEvent {
+    Saved(Result<DateTime<chrono::Local>>),
+    Loaded(Result<Vec<u8>>),
+    Listed(Result<Vec<DateTime<chrono::Local>>>),
+    Deleted(Result<()>),
+}
+
+/// Recording and playback of input movies.
+/// Frames advance one per update while a movie is active, without turbo or catching up audio.
+pub struct Movies {
+    state: MovieState,
+    /// Start times of the movies of the current game, newest first. `None` until listed
+    pub list: Option<Vec<DateTime<chrono::Local>>>,
+    sender: Sender<MovieEvent>,
+    receiver: Receiver<MovieEvent>,
+}
+
+impl Default for Movies {
+    fn default() -> Self {
+        let (sender, receiver) = unbounded_channel();
+        Self {
+            state: MovieState::Idle,
+            list: None,
+            sender,
+            receiver,
+        }
+    }
+}
+
+impl Movies {
+    pub fn is_recording(&self) -> bool {
+        matches!(self.state, MovieState::Recording { .. })
+    }
+
+    pub fn is_playing(&self) -> bool {
+        matches!(self.state, MovieState::Playing { .. })
+    }
+
+    pub fn is_active(&self) -> bool {
+        !matches!(self.state, MovieState::Idle)
+    }
+
+    /// Frames recorded or played so far, and the length of the playing movie
+    pub fn progress(&self) -> (usize, Option<usize>) {
+        match &self.state {
+            MovieState::Idle => (0, None),
+            MovieState::Recording { movie, .. } => (movie.frames.len(), None),
+            MovieState::Playing { movie, frame } => (*frame, Some(movie.frames.len())),
+        }
+    }
+
+    pub fn start_recording(&mut self, emulator: &Emulator, config: &Config) {
+        self.stop(config);
+        self.state = MovieState::Recording {
+            movie: Movie::new(emulator),
+            abbrev: emulator.core.core_info().abbrev.to_string(),
+            game_name: emulator.game_name.clone(),
+        };
+    }
+
+    /// Stops recording or playback. A recorded movie is saved.
+    pub fn stop(&mut self, config: &Config) {
+        let state = std::mem::replace(&mut self.state, MovieState::Idle);
+
+        if let MovieState::Recording {
+            movie,
+            abbrev,
+            game_name,
+        } = state
+        {
+            let data = bincode::serialize(&movie).unwrap();
+            let state_dir = config.state_dir.clone();
+            let sender = self.sender.clone();
+            spawn_local(async move {
+                let result = save_movie(&abbrev, &game_name, &movie.start, &data, &state_dir)
+                    .await
+                    .map(|_| movie.start);
+                sender.send(MovieEvent::Saved(result)).await.unwrap();
+            });
+        }
+    }
+
+    /// Loads the movie recorded at `start`, or the latest one if `None`,
+    /// and plays it from its first frame
+    pub fn play(
+        &mut self,
+        emulator: &Emulator,
+        start: Option<DateTime<chrono::Local>>,
+        config: &Config,
+    ) {
+        self.stop(config);
+
+        let abbrev = emulator.core.core_info().abbrev;
+        let fut = load_movie_data(abbrev, &emulator.game_name, start, config);
+        let sender = self.sender.clone();
+        spawn_local(async move {
+            sender.send(MovieEvent::Loaded(fut.await)).await.unwrap();
+        });
+    }
+
+    pub fn delete(&mut self, emulator: &Emulator, start: DateTime<chrono::Local>, config: &Config) {
+        let abbrev = emulator.core.core_info().abbrev.to_string();
+        let game_name = emulator.game_name.clone();
+        let state_dir = config.state_dir.clone();
+        let sender = self.sender.clone();
+        spawn_local(async move {
+            let result = delete_movie(&abbrev, &game_name, &start, &state_dir).await;
+            sender.send(MovieEvent::Deleted(result)).await.unwrap();
+        });
+    }
+
+    /// Lists the movies of the current game into `list`
+    pub fn refresh(&mut self, emulator: &Emulator, config: &Config) {
+        let abbrev = emulator.core.core_info().abbrev.to_string();
+        let game_name = emulator.game_name.clone();
+        let state_dir = config.state_dir.clone();
+        let sender = self.sender.clone();
+        spawn_local(async move {
+            let result = list_movies(&abbrev, &game_name, &state_dir).await;
+            sender.send(MovieEvent::Listed(result)).await.unwrap();
+        });
+    }
+
+    /// Input of the next frame. Records `input` while recording,
+    /// or replaces it with the recorded one while playing.
+    /// Returns `None` when the playback reached the end.
+    pub fn next_input(&mut self, input: &InputData) -> Option<InputData> {
+        match &mut self.state {
+            MovieState::Idle => None,
+            MovieState::Recording { movie, .. } => {
+                movie.push(input);
+                movie.input(movie.frames.len() - 1)
+            }
+            MovieState::Playing { movie, frame } => {
+                let ret = movie.input(*frame);
+                *frame += 1;
+                if ret.is_none() {
+                    self.state = MovieState::Idle;
+                }
+                ret
+            }
+        }
+    }
+}
+
+fn load_movie_data(
+    abbrev: &str,
+    game_name: &str,
+    start: Option<DateTime<chrono::Local>>,
+    config: &Config,
+) -> impl std::future::Future<Output = Result<Vec<u8>>> {
+    let abbrev = abbrev.to_string();
+    let game_name = game_name.to_string();
+    let state_dir = config.state_dir.clone();
+    async move {
+        let start = match start {
+            Some(start) => start,
+            None => *list_movies(&abbrev, &game_name, &state_dir)
+                .await?
+                .first()
+                .ok_or_else(|| anyhow!("No movie recorded for this game"))?,
+        };
+        load_movie(&abbrev, &game_name, &start, &state_dir).await
+    }
+}
+
+fn start_playback(movies: &mut Movies, emulator: &mut Emulator, data: &[u8]) -> Result<()> {
+    let movie: Movie = bincode::deserialize(data)?;
+    if movie.game_hash != emulator.game_hash {
+        bail!("The movie was recorded with another ROM");
+    }
+    emulator.load_state_data(&movie.state)?;
+    movies.state = MovieState::Playing { movie, frame: 0 };
+    Ok(())
+}
+
+fn movie_event_system(
+    mut movies: ResMut<Movies>,
+    mut emulator: Option<ResMut<Emulator>>,
+    config: Res<Config>,
+    mut message_event: EventWriter<ShowMessage>,
+    mut last_game_hash: Local<Option<String>>,
+) {
+    while let Ok(event) = movies.receiver.try_recv() {
+        match event {
+            MovieEvent::Saved(Ok(start)) => {
+                movies.list = None;
+                message_event.send(ShowMessage::info(format!(
+                    "Movie saved: {}",
+                    start.format("%Y/%m/%d %H:%M:%S")
+                )));
+            }
+            MovieEvent::Saved(Err(err)) => {
+                message_event.send(ShowMessage::save_failed("Saving movie", &err));
+            }
+            MovieEvent::Loaded(data) => {
+                let result = data.and_then(|data| {
+                    let emulator = emulator
+                        .as_deref_mut()
+                        .ok_or_else(|| anyhow!("No emulator instance"))?;
+                    start_playback(&mut movies, emulator, &data)
+                });
+                match result {
+                    Ok(()) => message_event.send(ShowMessage::info("Movie playback started")),
+                    Err(err) => message_event
+                        .send(ShowMessage::error(format!("Failed to play movie: {err}"))),
+                }
+            }
+            MovieEvent::Listed(Ok(list)) => movies.list = Some(list),
+            MovieEvent::Listed(Err(err)) => {
+                movies.list = Some(vec![]);
+                message_event.send(ShowMessage::error(format!("Failed to list movies: {err}")));
+            }
+            MovieEvent::Deleted(result) => {
+                movies.list = None;
+                if let Err(err) = result {
+                    message_event
+                        .send(ShowMessage::error(format!("Failed to delete movie: {err}")));
+                }
+            }
+        }
+    }
+
+    // Another game was loaded
+    let game_hash = emulator
+        .as_deref()
+        .map(|emulator| emulator.game_hash.clone());
+    if *last_game_hash != game_hash {
+        movies.list = None;
+        *last_game_hash = game_hash.clone();
+    }
+
+    let game_changed = match &movies.state {
+        MovieState::Recording { movie, .. } | MovieState::Playing { movie, .. } => {
+            game_hash.as_ref() != Some(&movie.game_hash)
+        }
+        MovieState::Idle => false,
+    };
+    if game_changed {
+        movies.stop(&config);
+    }
+}
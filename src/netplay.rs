@@ -0,0 +1,976 @@
+use anyhow::{anyhow, bail, Result};
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext};
+use meru_interface::InputData;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    io::{ErrorKind, Read, Write},
+    net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+use crate::{
+    app::{AppState, ShowMessage},
+    audio::AudioSink,
+    config::{Config, NetplayConfig},
+    core::Emulator,
+    utils::{unbounded_channel, Receiver, Sender},
+};
+
+const PROTOCOL_VERSION: u32 = 2;
+
+// Confirmed frames a spectator keeps before running the extra ones without rendering
+const SPECTATOR_BUFFER_FRAMES: usize = 2;
+
+const MAX_MESSAGE_SIZE: usize = 64 * 1024 * 1024;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+const MAX_RELAY_LINE: usize = 256;
+
+pub struct NetplayPlugin;
+
+impl Plugin for NetplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Netplay>()
+            .add_system(netplay_connection_system)
+            .add_system_set(
+                SystemSet::on_update(AppState::Running).with_system(netplay_overlay_system),
+            );
+    }
+}
+
+enum Message {
+    Hello {
+        version: u32,
+        game_hash: String,
+    },
+    /// State of the host to start from
+    Start {
+        frame: u32,
+        state: Vec<u8>,
+    },
+    Input {
+        frame: u32,
+        buttons: u32,
+    },
+    Bye {
+        reason: String,
+    },
+    /// Sent instead of `Hello` to watch the game
+    Watch {
+        version: u32,
+        game_hash: String,
+    },
+    /// Inputs of both players for a frame which is never rolled back
+    Confirmed {
+        frame: u32,
+        buttons: [u32; 2],
+    },
+}
+
+impl Message {
+    /// Length prefixed message
+    fn encode(&self) -> Vec<u8> {
+        let mut body = vec![];
+        match self {
+            Message::Hello { version, game_hash } => {
+                body.push(0);
+                body.extend(version.to_le_bytes());
+                body.extend(game_hash.as_bytes());
+            }
+            Message::Start { frame, state } => {
+                body.push(1);
+                body.extend(frame.to_le_bytes());
+                body.extend(state);
+            }
+            Message::Input { frame, buttons } => {
+                body.push(2);
+                body.extend(frame.to_le_bytes());
+                body.extend(buttons.to_le_bytes());
+            }
+            Message::Bye { reason } => {
+                body.push(3);
+                body.extend(reason.as_bytes());
+            }
+            Message::Watch { version, game_hash } => {
+                body.push(4);
+                body.extend(version.to_le_bytes());
+                body.extend(game_hash.as_bytes());
+            }
+            Message::Confirmed { frame, buttons } => {
+                body.push(5);
+                body.extend(frame.to_le_bytes());
+                for buttons in buttons {
+                    body.extend(buttons.to_le_bytes());
+                }
+            }
+        }
+
+        let mut ret = (body.len() as u32).to_le_bytes().to_vec();
+        ret.extend(body);
+        ret
+    }
+
+    fn decode(body: &[u8]) -> Result<Self> {
+        let (&kind, payload) = body.split_first().ok_or_else(|| anyhow!("Empty message"))?;
+        let u32_at = |pos: usize| -> Result<u32> {
+            let bytes = payload
+                .get(pos..pos + 4)
+                .ok_or_else(|| anyhow!("Truncated message"))?;
+            Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+        };
+
+        Ok(match kind {
+            0 => Message::Hello {
+                version: u32_at(0)?,
+                game_hash: String::from_utf8(payload[4..].to_vec())?,
+            },
+            1 => Message::Start {
+                frame: u32_at(0)?,
+                state: payload[4..].to_vec(),
+            },
+            2 => Message::Input {
+                frame: u32_at(0)?,
+                buttons: u32_at(4)?,
+            },
+            3 => Message::Bye {
+                reason: String::from_utf8_lossy(payload).into_owned(),
+            },
+            4 => Message::Watch {
+                version: u32_at(0)?,
+                game_hash: String::from_utf8(payload[4..].to_vec())?,
+            },
+            5 => Message::Confirmed {
+                frame: u32_at(0)?,
+                buttons: [u32_at(4)?, u32_at(8)?],
+            },
+            _ => bail!("Unknown message: {kind}"),
+        })
+    }
+}
+
+struct Peer {
+    stream: TcpStream,
+    read_buf: Vec<u8>,
+    write_buf: Vec<u8>,
+    received: VecDeque<Message>,
+}
+
+impl Peer {
+    fn new(stream: TcpStream) -> Result<Self> {
+        stream.set_nonblocking(true)?;
+        stream.set_nodelay(true)?;
+        Ok(Self {
+            stream,
+            read_buf: vec![],
+            write_buf: vec![],
+            received: VecDeque::new(),
+        })
+    }
+
+    fn send(&mut self, msg: &Message) {
+        self.write_buf.extend(msg.encode());
+    }
+
+    /// Writes buffered messages and reads received ones into `received` without blocking
+    fn poll(&mut self) -> Result<()> {
+        while !self.write_buf.is_empty() {
+            match self.stream.write(&self.write_buf) {
+                Ok(0) => bail!("Connection closed"),
+                Ok(n) => {
+                    self.write_buf.drain(..n);
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        let mut buf = [0; 4096];
+        loop {
+            match self.stream.read(&mut buf) {
+                Ok(0) => bail!("Connection closed by the peer"),
+                Ok(n) => self.read_buf.extend_from_slice(&buf[..n]),
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        while self.read_buf.len() >= 4 {
+            let len = u32::from_le_bytes(self.read_buf[..4].try_into().unwrap()) as usize;
+            if len > MAX_MESSAGE_SIZE {
+                bail!("Message too large: {len} bytes");
+            }
+            if self.read_buf.len() < 4 + len {
+                break;
+            }
+            let msg = Message::decode(&self.read_buf[4..4 + len])?;
+            self.received.push_back(msg);
+            self.read_buf.drain(..4 + len);
+        }
+        Ok(())
+    }
+}
+
+fn connect(address: &str) -> Result<TcpStream> {
+    let addr = address
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| anyhow!("Invalid address: {address}"))?;
+    Ok(TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT)?)
+}
+
+// A relay server pairs two connections by a room code, and then forwards bytes between them,
+// so that neither player has to accept connections. Before forwarding, it speaks in lines:
+// the host sends `HOST` and receives `ROOM <code>`, and then `PAIRED` when a guest has joined.
+// The guest sends `JOIN <code>` and receives `PAIRED`. Failures are sent as `ERROR <reason>`.
+
+enum RelayEvent {
+    Room { code: String, stream: TcpStream },
+    Paired(TcpStream),
+}
+
+struct RelayRoom {
+    recv: Receiver<Result<RelayEvent>>,
+    code: Option<String>,
+    /// Clone of the connection to the relay, to stop waiting for a guest
+    stream: Option<TcpStream>,
+}
+
+/// Reads a line without reading ahead, since netplay messages follow it on the same stream
+fn read_relay_line(stream: &mut TcpStream) -> Result<String> {
+    let mut line = vec![];
+    let mut byte = [0];
+    while line.len() < MAX_RELAY_LINE {
+        if stream.read(&mut byte)? == 0 {
+            bail!("Connection closed by the relay server");
+        }
+        if byte[0] == b'\n' {
+            let line = String::from_utf8(line)?;
+            if let Some(reason) = line.strip_prefix("ERROR ") {
+                bail!("Relay server: {reason}");
+            }
+            return Ok(line);
+        }
+        line.push(byte[0]);
+    }
+    bail!("Invalid response from the relay server")
+}
+
+fn expect_paired(stream: &mut TcpStream) -> Result<()> {
+    if read_relay_line(stream)? != "PAIRED" {
+        bail!("Invalid response from the relay server");
+    }
+    Ok(())
+}
+
+fn host_room(relay: &str, s: &Sender<Result<RelayEvent>>) -> Result<()> {
+    let mut stream = connect(relay)?;
+    stream.write_all(b"HOST\n")?;
+    let line = read_relay_line(&mut stream)?;
+    let code = line
+        .strip_prefix("ROOM ")
+        .ok_or_else(|| anyhow!("Invalid response from the relay server"))?;
+    let _ = s.try_send(Ok(RelayEvent::Room {
+        code: code.to_string(),
+        stream: stream.try_clone()?,
+    }));
+    expect_paired(&mut stream)?;
+    let _ = s.try_send(Ok(RelayEvent::Paired(stream)));
+    Ok(())
+}
+
+fn join_room(relay: &str, code: &str) -> Result<TcpStream> {
+    let mut stream = connect(relay)?;
+    stream.write_all(format!("JOIN {code}\n").as_bytes())?;
+    expect_paired(&mut stream)?;
+    Ok(stream)
+}
+
+/// Two player netplay over TCP.
+/// Each side runs frames with the last known inputs of the peer,
+/// and rolls back with save states when the actual inputs differ.
+/// Other connections to the host watch the game with the confirmed inputs.
+#[derive(Default)]
+pub struct Netplay {
+    status: Status,
+    /// Accepts the player and spectators while hosting
+    listener: Option<TcpListener>,
+    /// Room on a relay server while hosting through it
+    relay: Option<RelayRoom>,
+    /// Accepted connections which have not told whether they play or watch
+    pending: Vec<(Peer, SocketAddr)>,
+    last_error: Option<String>,
+    pub show_overlay: bool,
+    /// Code of the room to join on the relay server
+    pub room_code: String,
+}
+
+#[derive(Default)]
+enum Status {
+    #[default]
+    Disconnected,
+    Listening,
+    Connecting {
+        recv: Receiver<Result<TcpStream>>,
+        spectate: bool,
+    },
+    Handshaking {
+        peer: Peer,
+        spectate: bool,
+    },
+    Playing(Session),
+    Spectating(Spectation),
+}
+
+struct Session {
+    peer: Peer,
+    /// Controller index of this side. The host is the first player.
+    local_player: usize,
+    input_delay: u32,
+    /// Frames that can be run with predicted inputs before waiting for the peer
+    rollback_frames: u32,
+    /// Next frame to run
+    frame: u32,
+    local_inputs: BTreeMap<u32, u32>,
+    remote_inputs: BTreeMap<u32, u32>,
+    /// Remote inputs used for frames run before the actual ones arrived
+    predictions: BTreeMap<u32, u32>,
+    /// States before each frame which may be rolled back
+    states: BTreeMap<u32, Vec<u8>>,
+    rollbacks: usize,
+    stalls: usize,
+    spectators: Vec<Peer>,
+    /// Next frame to send to spectators
+    spectated: u32,
+}
+
+impl Session {
+    fn new(mut peer: Peer, local_player: usize, config: &NetplayConfig) -> Self {
+        let input_delay = config.input_delay as u32;
+
+        // Frames before the delay have no inputs on both sides
+        let mut local_inputs = BTreeMap::new();
+        for frame in 0..input_delay {
+            local_inputs.insert(frame, 0);
+            peer.send(&Message::Input { frame, buttons: 0 });
+        }
+
+        Self {
+            peer,
+            local_player,
+            input_delay,
+            rollback_frames: config.rollback_frames as u32,
+            frame: 0,
+            local_inputs,
+            remote_inputs: BTreeMap::new(),
+            predictions: BTreeMap::new(),
+            states: BTreeMap::new(),
+            rollbacks: 0,
+            stalls: 0,
+            spectators: vec![],
+            spectated: 0,
+        }
+    }
+
+    /// Frames before this were run with the inputs of both sides
+    fn settled_frame(&self) -> u32 {
+        self.confirmed_frame().min(self.frame)
+    }
+
+    /// Starts `peer` watching from the first frame not sent to spectators yet
+    fn add_spectator(&mut self, mut peer: Peer, emulator: &Emulator) -> Result<()> {
+        let state = if self.spectated == self.frame {
+            emulator.core.save_state()
+        } else {
+            self.states
+                .get(&self.spectated)
+                .cloned()
+                .ok_or_else(|| anyhow!("No state for frame {}", self.spectated))?
+        };
+        peer.send(&Message::Start {
+            frame: self.spectated,
+            state,
+        });
+        self.spectators.push(peer);
+        Ok(())
+    }
+
+    /// Sends settled frames to spectators and drops the disconnected ones
+    fn update_spectators(&mut self) {
+        let settled = self.settled_frame();
+        for frame in self.spectated..settled {
+            let mut buttons = [0; 2];
+            buttons[self.local_player] = self.local_inputs.get(&frame).copied().unwrap_or(0);
+            buttons[1 - self.local_player] = self.remote_inputs.get(&frame).copied().unwrap_or(0);
+            for peer in &mut self.spectators {
+                peer.send(&Message::Confirmed { frame, buttons });
+            }
+        }
+        self.spectated = settled;
+
+        self.spectators.retain_mut(|peer| {
+            peer.received.clear();
+            peer.poll().is_ok()
+        });
+    }
+
+    /// Frames before this have inputs of the peer
+    fn confirmed_frame(&self) -> u32 {
+        self.remote_inputs
+            .keys()
+            .next_back()
+            .map_or(0, |&frame| frame + 1)
+    }
+
+    fn frame_input(&mut self, frame: u32, local: &InputData) -> InputData {
+        let local_buttons = self.local_inputs.get(&frame).copied().unwrap_or(0);
+        let remote_buttons = match self.remote_inputs.get(&frame) {
+            Some(&buttons) => buttons,
+            None => {
+                let buttons = self
+                    .remote_inputs
+                    .values()
+                    .next_back()
+                    .copied()
+                    .unwrap_or(0);
+                self.predictions.insert(frame, buttons);
+                buttons
+            }
+        };
+
+        let mut ret = local.clone();
+        unpack_buttons(&mut ret.controllers[self.local_player], local_buttons);
+        unpack_buttons(&mut ret.controllers[1 - self.local_player], remote_buttons);
+        ret
+    }
+
+    /// Runs the next frame unless waiting for the peer. Returns whether a frame was run.
+    fn exec_frame(
+        &mut self,
+        emulator: &mut Emulator,
+        input: &InputData,
+        audio_sink: &mut AudioSink,
+        config: &Config,
+    ) -> Result<bool> {
+        if input.controllers.len() < 2 {
+            bail!("The system has only one controller");
+        }
+
+        let mut rollback_from: Option<u32> = None;
+
+        self.peer.poll()?;
+        while let Some(msg) = self.peer.received.pop_front() {
+            match msg {
+                Message::Input { frame, buttons } => {
+                    if let Some(predicted) = self.predictions.remove(&frame) {
+                        if predicted != buttons {
+                            rollback_from = Some(rollback_from.map_or(frame, |f| f.min(frame)));
+                        }
+                    }
+                    self.remote_inputs.insert(frame, buttons);
+                }
+                Message::Bye { reason } => bail!(reason),
+                _ => bail!("Unexpected message"),
+            }
+        }
+
+        if let Some(from) = rollback_from {
+            let state = self
+                .states
+                .get(&from)
+                .ok_or_else(|| anyhow!("No state to roll back to frame {from}"))?;
+            emulator.core.load_state(state)?;
+
+            for frame in from..self.frame {
+                if frame != from {
+                    self.states.insert(frame, emulator.core.save_state());
+                }
+                let frame_input = self.frame_input(frame, input);
+                emulator.core.set_input(&frame_input);
+                emulator.core.exec_frame(false);
+            }
+            self.rollbacks += 1;
+        }
+
+        // Inputs are sent before waiting, since without a rollback window the peer waits for them
+        let frame = self.frame + self.input_delay;
+        if !self.local_inputs.contains_key(&frame) {
+            let local = &input.controllers[self.local_player];
+            let buttons = pack_buttons(input.controller(0), local);
+            self.local_inputs.insert(frame, buttons);
+            self.peer.send(&Message::Input { frame, buttons });
+            self.peer.poll()?;
+        }
+
+        if self.frame >= self.confirmed_frame() + self.rollback_frames {
+            self.stalls += 1;
+            self.update_spectators();
+            return Ok(false);
+        }
+
+        self.states.insert(self.frame, emulator.core.save_state());
+        let frame_input = self.frame_input(self.frame, input);
+        emulator.core.set_input(&frame_input);
+        emulator.core.exec_frame(true);
+        audio_sink.append(emulator.core.audio_buffer(), &config.audio);
+        self.frame += 1;
+
+        self.update_spectators();
+
+        // Settled frames are never rolled back.
+        // The last remote input is kept for predictions.
+        let settled = self.settled_frame();
+        self.states = self.states.split_off(&settled);
+        self.local_inputs = self.local_inputs.split_off(&settled);
+        self.remote_inputs = self.remote_inputs.split_off(&settled.saturating_sub(1));
+
+        Ok(true)
+    }
+}
+
+/// Watching a game by running the confirmed inputs sent by the host
+struct Spectation {
+    peer: Peer,
+    /// Next frame to run
+    frame: u32,
+    inputs: BTreeMap<u32, [u32; 2]>,
+}
+
+impl Spectation {
+    /// Runs the next frame if its inputs have arrived. Returns whether a frame was run.
+    fn exec_frame(
+        &mut self,
+        emulator: &mut Emulator,
+        input: &InputData,
+        audio_sink: &mut AudioSink,
+        config: &Config,
+    ) -> Result<bool> {
+        if input.controllers.len() < 2 {
+            bail!("The system has only one controller");
+        }
+
+        self.peer.poll()?;
+        while let Some(msg) = self.peer.received.pop_front() {
+            match msg {
+                Message::Confirmed { frame, buttons } => {
+                    self.inputs.insert(frame, buttons);
+                }
+                Message::Bye { reason } => bail!(reason),
+                _ => bail!("Unexpected message"),
+            }
+        }
+
+        // Catch up with the players when frames have piled up
+        while self.inputs.len() > SPECTATOR_BUFFER_FRAMES && self.run_frame(emulator, input, false)
+        {
+        }
+        if !self.run_frame(emulator, input, true) {
+            return Ok(false);
+        }
+        audio_sink.append(emulator.core.audio_buffer(), &config.audio);
+        Ok(true)
+    }
+
+    fn run_frame(&mut self, emulator: &mut Emulator, input: &InputData, render: bool) -> bool {
+        let buttons = if let Some(buttons) = self.inputs.remove(&self.frame) {
+            buttons
+        } else {
+            return false;
+        };
+
+        let mut frame_input = input.clone();
+        for (player, buttons) in buttons.into_iter().enumerate() {
+            unpack_buttons(&mut frame_input.controllers[player], buttons);
+        }
+        emulator.core.set_input(&frame_input);
+        emulator.core.exec_frame(render);
+        self.frame += 1;
+        true
+    }
+}
+
+/// Packs buttons of `source` in the order of `layout`.
+/// Buttons are matched by name, and by position when `source` lacks the name.
+fn pack_buttons(source: &[(String, bool)], layout: &[(String, bool)]) -> u32 {
+    layout
+        .iter()
+        .take(32)
+        .enumerate()
+        .filter(|(ix, (name, _))| {
+            source
+                .iter()
+                .find(|(key, _)| key == name)
+                .or_else(|| source.get(*ix))
+                .map_or(false, |(_, pressed)| *pressed)
+        })
+        .fold(0, |acc, (ix, _)| acc | 1 << ix)
+}
+
+fn unpack_buttons(controller: &mut [(String, bool)], buttons: u32) {
+    for (ix, (_, pressed)) in controller.iter_mut().take(32).enumerate() {
+        *pressed = buttons & (1 << ix) != 0;
+    }
+}
+
+impl Netplay {
+    pub fn host(&mut self, port: u16) -> Result<()> {
+        let listener = TcpListener::bind(("0.0.0.0", port))?;
+        listener.set_nonblocking(true)?;
+        self.listener = Some(listener);
+        self.status = Status::Listening;
+        self.last_error = None;
+        Ok(())
+    }
+
+    /// Opens a room on the relay server, and waits for a player to join with its code
+    pub fn host_room(&mut self, relay: &str) {
+        let relay = relay.to_string();
+        let (s, r) = unbounded_channel();
+
+        std::thread::spawn(move || {
+            if let Err(err) = host_room(&relay, &s) {
+                let _ = s.try_send(Err(err));
+            }
+        });
+
+        self.relay = Some(RelayRoom {
+            recv: r,
+            code: None,
+            stream: None,
+        });
+        self.status = Status::Listening;
+        self.last_error = None;
+    }
+
+    /// Connects to the host, as the second player or as a spectator
+    pub fn join(&mut self, address: &str, spectate: bool) {
+        let address = address.to_string();
+        let (s, r) = unbounded_channel();
+
+        std::thread::spawn(move || {
+            let _ = s.try_send(connect(&address));
+        });
+
+        self.status = Status::Connecting { recv: r, spectate };
+        self.last_error = None;
+    }
+
+    /// Joins the room with `code` on the relay server as the second player
+    pub fn join_room(&mut self, relay: &str, code: &str) {
+        let relay = relay.to_string();
+        let code = code.trim().to_uppercase();
+        let (s, r) = unbounded_channel();
+
+        std::thread::spawn(move || {
+            let _ = s.try_send(join_room(&relay, &code));
+        });
+
+        self.status = Status::Connecting {
+            recv: r,
+            spectate: false,
+        };
+        self.last_error = None;
+    }
+
+    pub fn disconnect(&mut self) {
+        self.close("Disconnected by the peer");
+    }
+
+    /// Disconnects and keeps `err` to show in the status
+    pub fn fail(&mut self, err: &anyhow::Error) {
+        self.close(&err.to_string());
+        self.last_error = Some(err.to_string());
+    }
+
+    /// Tells every connection why it is closed, and stops hosting
+    fn close(&mut self, reason: &str) {
+        let mut peers = self
+            .pending
+            .drain(..)
+            .map(|(peer, _)| peer)
+            .collect::<Vec<_>>();
+        match std::mem::take(&mut self.status) {
+            Status::Handshaking { peer, .. } => peers.push(peer),
+            Status::Playing(session) => {
+                peers.push(session.peer);
+                peers.extend(session.spectators);
+            }
+            Status::Spectating(spectation) => peers.push(spectation.peer),
+            _ => {}
+        }
+        for mut peer in peers {
+            peer.send(&Message::Bye {
+                reason: reason.into(),
+            });
+            let _ = peer.poll();
+        }
+        self.listener = None;
+        if let Some(stream) = self.relay.take().and_then(|relay| relay.stream) {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        !matches!(self.status, Status::Disconnected)
+    }
+
+    /// Whether frames are run by netplay, as a player or as a spectator
+    pub fn is_playing(&self) -> bool {
+        matches!(self.status, Status::Playing(_) | Status::Spectating(_))
+    }
+
+    pub fn status_text(&self) -> String {
+        match &self.status {
+            Status::Disconnected => match &self.last_error {
+                Some(err) => format!("Disconnected: {err}"),
+                None => "Disconnected".into(),
+            },
+            Status::Listening => match (&self.relay, &self.listener) {
+                (
+                    Some(RelayRoom {
+                        code: Some(code), ..
+                    }),
+                    _,
+                ) => {
+                    format!("Waiting for a player in room {code}")
+                }
+                (Some(_), _) => "Opening a room...".into(),
+                (None, Some(listener)) => match listener.local_addr() {
+                    Ok(addr) => format!("Waiting for a player on port {}", addr.port()),
+                    Err(_) => "Waiting for a player".into(),
+                },
+                (None, None) => "Waiting for a player".into(),
+            },
+            Status::Connecting { .. } => "Connecting...".into(),
+            Status::Handshaking { .. } => "Starting...".into(),
+            Status::Playing(session) => format!("Playing as player {}", session.local_player + 1),
+            Status::Spectating(_) => "Watching".into(),
+        }
+    }
+
+    fn status_lines(&self) -> Vec<String> {
+        let mut ret = vec![self.status_text()];
+        match &self.status {
+            Status::Playing(session) => {
+                ret.push(format!("Frame: {}", session.frame));
+                ret.push(format!(
+                    "Ahead of peer: {} frames",
+                    session.frame.saturating_sub(session.confirmed_frame())
+                ));
+                ret.push(format!("Input delay: {} frames", session.input_delay));
+                ret.push(format!(
+                    "Rollback window: {} frames",
+                    session.rollback_frames
+                ));
+                ret.push(format!("Rollbacks: {}", session.rollbacks));
+                ret.push(format!("Waits: {}", session.stalls));
+                ret.push(format!("Spectators: {}", session.spectators.len()));
+            }
+            Status::Spectating(spectation) => {
+                ret.push(format!("Frame: {}", spectation.frame));
+                ret.push(format!("Buffered: {} frames", spectation.inputs.len()));
+            }
+            _ => {}
+        }
+        ret
+    }
+
+    /// Runs the next netplay frame. Returns whether a frame was run.
+    pub fn exec_frame(
+        &mut self,
+        emulator: &mut Emulator,
+        input: &InputData,
+        audio_sink: &mut AudioSink,
+        config: &Config,
+    ) -> Result<bool> {
+        let result = match &mut self.status {
+            Status::Playing(session) => session.exec_frame(emulator, input, audio_sink, config),
+            Status::Spectating(spectation) => {
+                spectation.exec_frame(emulator, input, audio_sink, config)
+            }
+            _ => Ok(false),
+        };
+        if let Err(err) = &result {
+            self.fail(err);
+        }
+        result
+    }
+
+    /// Accepts new connections and answers the ones which have said hello
+    fn update_host(&mut self, emulator: &mut Emulator, config: &Config) -> Result<Vec<String>> {
+        let mut msgs = vec![];
+
+        if let Some(relay) = &mut self.relay {
+            if let Ok(event) = relay.recv.try_recv() {
+                match event? {
+                    RelayEvent::Room { code, stream } => {
+                        msgs.push(format!("Netplay: opened room {code}"));
+                        relay.code = Some(code);
+                        relay.stream = Some(stream);
+                    }
+                    RelayEvent::Paired(stream) => {
+                        let addr = stream.peer_addr()?;
+                        self.pending.push((Peer::new(stream)?, addr));
+                        self.relay = None;
+                    }
+                }
+            }
+        }
+
+        if let Some(listener) = &self.listener {
+            loop {
+                match listener.accept() {
+                    Ok((stream, addr)) => self.pending.push((Peer::new(stream)?, addr)),
+                    Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                    Err(err) => return Err(err.into()),
+                }
+            }
+        }
+
+        for (mut peer, addr) in std::mem::take(&mut self.pending) {
+            if peer.poll().is_err() {
+                continue;
+            }
+            let (version, game_hash, spectate) = match peer.received.pop_front() {
+                None => {
+                    self.pending.push((peer, addr));
+                    continue;
+                }
+                Some(Message::Hello { version, game_hash }) => (version, game_hash, false),
+                Some(Message::Watch { version, game_hash }) => (version, game_hash, true),
+                Some(_) => continue,
+            };
+
+            let reason = if version != PROTOCOL_VERSION {
+                Some("Incompatible version")
+            } else if game_hash != emulator.game_hash {
+                Some("Different game")
+            } else {
+                match (&self.status, spectate) {
+                    (Status::Listening, false) | (Status::Playing(_), true) => None,
+                    (_, false) => Some("The game already has two players"),
+                    (_, true) => Some("The game has not started yet"),
+                }
+            };
+            if let Some(reason) = reason {
+                peer.send(&Message::Bye {
+                    reason: reason.into(),
+                });
+                let _ = peer.poll();
+                msgs.push(format!("Netplay: {addr} was refused: {reason}"));
+                continue;
+            }
+
+            if let Status::Playing(session) = &mut self.status {
+                session.add_spectator(peer, emulator)?;
+                msgs.push(format!("Netplay: {addr} is watching"));
+            } else {
+                peer.send(&Message::Start {
+                    frame: 0,
+                    state: emulator.core.save_state(),
+                });
+                let session = Session::new(peer, 0, &config.netplay);
+                self.status = Status::Playing(session);
+                msgs.push(format!("Netplay: {addr} connected"));
+            }
+        }
+
+        Ok(msgs)
+    }
+
+    fn update(&mut self, emulator: Option<&mut Emulator>, config: &Config) -> Result<Vec<String>> {
+        let emulator = if let Some(emulator) = emulator {
+            emulator
+        } else {
+            if self.is_active() && !matches!(self.status, Status::Connecting { .. }) {
+                self.close("The game was closed");
+            }
+            return Ok(vec![]);
+        };
+
+        let mut msgs = self.update_host(emulator, config)?;
+
+        let status = match std::mem::take(&mut self.status) {
+            Status::Connecting { recv, spectate } => match recv.try_recv() {
+                Ok(result) => {
+                    let mut peer = Peer::new(result?)?;
+                    let version = PROTOCOL_VERSION;
+                    let game_hash = emulator.game_hash.clone();
+                    peer.send(&if spectate {
+                        Message::Watch { version, game_hash }
+                    } else {
+                        Message::Hello { version, game_hash }
+                    });
+                    Status::Handshaking { peer, spectate }
+                }
+                Err(_) => Status::Connecting { recv, spectate },
+            },
+            Status::Handshaking { mut peer, spectate } => {
+                peer.poll()?;
+
+                // Messages after the first one are left for the session
+                match peer.received.pop_front() {
+                    None => Status::Handshaking { peer, spectate },
+                    Some(Message::Start { frame, state }) => {
+                        emulator.core.load_state(&state)?;
+                        if spectate {
+                            msgs.push("Netplay: watching started".into());
+                            Status::Spectating(Spectation {
+                                peer,
+                                frame,
+                                inputs: BTreeMap::new(),
+                            })
+                        } else {
+                            msgs.push("Netplay started".into());
+                            Status::Playing(Session::new(peer, 1, &config.netplay))
+                        }
+                    }
+                    Some(Message::Bye { reason }) => bail!(reason),
+                    Some(_) => bail!("Unexpected message"),
+                }
+            }
+            status => status,
+        };
+
+        self.status = status;
+        Ok(msgs)
+    }
+}
+
+fn netplay_connection_system(
+    mut netplay: ResMut<Netplay>,
+    mut emulator: Option<ResMut<Emulator>>,
+    config: Res<Config>,
+    mut message_event: EventWriter<ShowMessage>,
+) {
+    match netplay.update(emulator.as_deref_mut(), &config) {
+        Ok(msgs) => {
+            for msg in msgs {
+                message_event.send(ShowMessage::info(msg));
+            }
+        }
+        Err(err) => {
+            netplay.fail(&err);
+            message_event.send(ShowMessage::error(format!("Netplay: {err}")));
+        }
+    }
+}
+
+fn netplay_overlay_system(mut egui_ctx: ResMut<EguiContext>, netplay: Res<Netplay>) {
+    if !netplay.show_overlay || !netplay.is_active() {
+        return;
+    }
+
+    egui::Area::new("netplay_overlay")
+        .anchor(egui::Align2::RIGHT_TOP, [-8.0, 8.0])
+        .show(egui_ctx.ctx_mut(), |ui| {
+            egui::Frame::popup(ui.style()).show(ui, |ui| {
+                for line in netplay.status_lines() {
+                    ui.label(line);
+                }
+            });
+        });
+}
@@ -0,0 +1,327 @@
+//! Remote control of OBS Studio through obs-websocket (protocol version 5),
+//! for recording or switching scenes along with the emulator.
+//!
+//! Requests are sent from a worker thread, since the socket is blocking.
+
+use bevy::prelude::*;
+
+#[cfg(not(target_arch = "wasm32"))]
+use {
+    anyhow::{anyhow, bail, Result},
+    serde_json::{json, Value},
+    sha2::{Digest, Sha256},
+    std::{net::TcpStream, time::Duration},
+    tungstenite::{stream::MaybeTlsStream, Message, WebSocket},
+};
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{
+    app::{AppState, ShowMessage},
+    config::{Config, ObsConfig},
+    core::Emulator,
+    utils::{unbounded_channel, Receiver, Sender},
+};
+
+pub struct ObsPlugin;
+
+impl Plugin for ObsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Obs>();
+
+        #[cfg(not(target_arch = "wasm32"))]
+        app.add_system(obs_system);
+    }
+}
+
+pub fn is_supported() -> bool {
+    cfg!(not(target_arch = "wasm32"))
+}
+
+#[derive(Clone, PartialEq, Eq)]
+pub enum ObsStatus {
+    Disconnected,
+    Connecting,
+    Connected,
+    Failed(String),
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+enum ObsCommand {
+    Connect { address: String, password: String },
+    Disconnect,
+    Request { request_type: String, data: Value },
+}
+
+pub struct Obs {
+    status: ObsStatus,
+    reconnect: bool,
+    #[cfg(not(target_arch = "wasm32"))]
+    worker: Option<(Sender<ObsCommand>, Receiver<ObsStatus>)>,
+}
+
+impl Default for Obs {
+    fn default() -> Self {
+        Self {
+            status: ObsStatus::Disconnected,
+            reconnect: false,
+            #[cfg(not(target_arch = "wasm32"))]
+            worker: None,
+        }
+    }
+}
+
+impl Obs {
+    pub fn status(&self) -> &ObsStatus {
+        &self.status
+    }
+
+    /// Connects again with the current config, e.g. after OBS was restarted
+    pub fn reconnect(&mut self) {
+        self.reconnect = true;
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Obs {
+    fn send(&mut self, command: ObsCommand) {
+        let (sender, _) = self.worker.get_or_insert_with(|| {
+            let (command_send, command_recv) = unbounded_channel();
+            let (status_send, status_recv) = unbounded_channel();
+            std::thread::spawn(move || worker(command_recv, status_send));
+            (command_send, status_recv)
+        });
+        sender.try_send(command).unwrap();
+    }
+
+    fn request(&mut self, request_type: &str, data: Value) {
+        if self.status == ObsStatus::Connected {
+            self.send(ObsCommand::Request {
+                request_type: request_type.to_string(),
+                data,
+            });
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+type Socket = WebSocket<MaybeTlsStream<TcpStream>>;
+
+#[cfg(not(target_arch = "wasm32"))]
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[cfg(not(target_arch = "wasm32"))]
+fn worker(commands: Receiver<ObsCommand>, status: Sender<ObsStatus>) {
+    let mut socket = None;
+    let mut request_id = 0_u64;
+
+    while let Ok(command) = async_std::task::block_on(commands.recv()) {
+        match command {
+            ObsCommand::Connect { address, password } => {
+                if let Some(mut socket) = socket.take() {
+                    let _ = socket.close(None);
+                }
+                let _ = status.try_send(ObsStatus::Connecting);
+                match connect(&address, &password) {
+                    Ok(s) => {
+                        socket = Some(s);
+                        let _ = status.try_send(ObsStatus::Connected);
+                    }
+                    Err(err) => {
+                        let _ = status.try_send(ObsStatus::Failed(err.to_string()));
+                    }
+                }
+            }
+            ObsCommand::Disconnect => {
+                if let Some(mut socket) = socket.take() {
+                    let _ = socket.close(None);
+                }
+                let _ = status.try_send(ObsStatus::Disconnected);
+            }
+            ObsCommand::Request { request_type, data } => {
+                if let Some(s) = &mut socket {
+                    request_id += 1;
+                    if let Err(err) = request(s, request_id, &request_type, data) {
+                        // The connection stays usable when OBS refused the request
+                        let closed = matches!(
+                            err.downcast_ref::<tungstenite::Error>(),
+                            Some(tungstenite::Error::ConnectionClosed)
+                                | Some(tungstenite::Error::AlreadyClosed)
+                                | Some(tungstenite::Error::Io(_))
+                        );
+                        if closed {
+                            socket = None;
+                        }
+                        let _ = status.try_send(ObsStatus::Failed(err.to_string()));
+                        if !closed {
+                            let _ = status.try_send(ObsStatus::Connected);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn connect(address: &str, password: &str) -> Result<Socket> {
+    let (mut socket, _) = tungstenite::connect(format!("ws://{address}"))?;
+    if let MaybeTlsStream::Plain(stream) = socket.get_mut() {
+        stream.set_read_timeout(Some(TIMEOUT))?;
+    }
+
+    let hello = read_message(&mut socket)?;
+    if hello["op"] != 0 {
+        bail!("Unexpected message from OBS");
+    }
+
+    let mut identify = json!({
+        "rpcVersion": 1,
+        // No events are needed
+        "eventSubscriptions": 0,
+    });
+    if let Some(auth) = hello["d"].get("authentication") {
+        let field = |name: &str| {
+            auth[name]
+                .as_str()
+                .ok_or_else(|| anyhow!("Invalid authentication request from OBS"))
+        };
+        let (salt, challenge) = (field("salt")?, field("challenge")?);
+        identify["authentication"] = json!(auth_string(password, salt, challenge));
+    }
+    write_message(&mut socket, json!({ "op": 1, "d": identify }))?;
+
+    // OBS closes the connection when authentication failed
+    match read_message(&mut socket) {
+        Ok(identified) if identified["op"] == 2 => Ok(socket),
+        Ok(_) => bail!("Unexpected message from OBS"),
+        Err(_) => bail!("Authentication failed. Check the password"),
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn auth_string(password: &str, salt: &str, challenge: &str) -> String {
+    let secret = base64::encode(Sha256::digest(format!("{password}{salt}")));
+    base64::encode(Sha256::digest(format!("{secret}{challenge}")))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn request(socket: &mut Socket, id: u64, request_type: &str, data: Value) -> Result<()> {
+    let id = id.to_string();
+    write_message(
+        socket,
+        json!({
+            "op": 6,
+            "d": {
+                "requestType": request_type,
+                "requestId": id,
+                "requestData": data,
+            },
+        }),
+    )?;
+
+    loop {
+        let response = read_message(socket)?;
+        if response["op"] != 7 || response["d"]["requestId"] != id.as_str() {
+            continue;
+        }
+
+        let status = &response["d"]["requestStatus"];
+        if status["result"].as_bool() == Some(true) {
+            return Ok(());
+        }
+        let comment = status["comment"].as_str().unwrap_or("Unknown error");
+        bail!("{request_type} failed: {comment}");
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn read_message(socket: &mut Socket) -> Result<Value> {
+    loop {
+        match socket.read_message()? {
+            Message::Text(text) => return Ok(serde_json::from_str(&text)?),
+            Message::Close(_) => return Err(tungstenite::Error::ConnectionClosed.into()),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn write_message(socket: &mut Socket, message: Value) -> Result<()> {
+    socket.write_message(Message::Text(message.to_string()))?;
+    Ok(())
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Default)]
+struct ObsTriggers {
+    enabled: bool,
+    game_hash: Option<String>,
+    running: bool,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn obs_system(
+    config: Res<Config>,
+    emulator: Option<Res<Emulator>>,
+    app_state: Res<State<AppState>>,
+    mut obs: ResMut<Obs>,
+    mut triggers: Local<ObsTriggers>,
+    mut message_event: EventWriter<ShowMessage>,
+) {
+    if let Some((_, receiver)) = &obs.worker {
+        let statuses = std::iter::from_fn(|| receiver.try_recv().ok()).collect::<Vec<_>>();
+        for status in statuses {
+            if let ObsStatus::Failed(err) = &status {
+                message_event.send(ShowMessage::error(format!("OBS: {err}")));
+            }
+            obs.status = status;
+        }
+    }
+
+    // Edits of the address and password are applied by reconnecting,
+    // rather than connecting on every keystroke
+    let ObsConfig {
+        enabled,
+        address,
+        password,
+        ..
+    } = &config.obs;
+    if *enabled != triggers.enabled || (*enabled && obs.reconnect) {
+        if *enabled {
+            obs.send(ObsCommand::Connect {
+                address: address.clone(),
+                password: password.clone(),
+            });
+        } else {
+            obs.send(ObsCommand::Disconnect);
+        }
+        triggers.enabled = *enabled;
+    }
+    obs.reconnect = false;
+
+    // Switching to another game keeps recording
+    let game_hash = emulator.map(|emulator| emulator.game_hash.clone());
+    match (&triggers.game_hash, &game_hash) {
+        (None, Some(_)) if config.obs.record_on_game_start => {
+            obs.request("StartRecord", json!({}));
+        }
+        (Some(_), None) if config.obs.stop_on_game_close => {
+            obs.request("StopRecord", json!({}));
+        }
+        _ => {}
+    }
+    triggers.game_hash = game_hash;
+
+    let running = app_state.current() == &AppState::Running;
+    if running != triggers.running {
+        let scene = if running {
+            &config.obs.running_scene
+        } else {
+            &config.obs.menu_scene
+        };
+        if !scene.is_empty() {
+            obs.request("SetCurrentProgramScene", json!({ "sceneName": scene }));
+        }
+        triggers.running = running;
+    }
+}
@@ -0,0 +1,154 @@
+use bevy::prelude::*;
+use global_hotkey::{hotkey::HotKey as OsHotKey, GlobalHotKeyEvent, GlobalHotKeyManager};
+use log::info;
+
+use crate::{
+    app::ShowMessage,
+    config::{Config, GlobalHotKeyConfig},
+    hotkey::{HotKey, TriggerHotKey},
+};
+
+pub struct OsHotKeyPlugin;
+
+impl Plugin for OsHotKeyPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_non_send_resource(OsHotKeys::default())
+            .add_system(os_hotkey_system);
+    }
+}
+
+#[derive(Default)]
+struct OsHotKeys {
+    manager: Option<GlobalHotKeyManager>,
+    registered: Vec<(HotKey, OsHotKey)>,
+    applied: Option<GlobalHotKeyConfig>,
+    /// Last seen config and when it was changed
+    pending: Option<(GlobalHotKeyConfig, f64)>,
+}
+
+// Wait for the user to finish typing before registering keys to the OS
+const APPLY_DELAY_SECS: f64 = 1.0;
+
+// Shortcuts commonly used by other applications, which would stop working there
+const COMMON_SHORTCUTS: &[&str] = &[
+    "ctrl+KeyA",
+    "ctrl+KeyC",
+    "ctrl+KeyF",
+    "ctrl+KeyL",
+    "ctrl+KeyN",
+    "ctrl+KeyP",
+    "ctrl+KeyR",
+    "ctrl+KeyS",
+    "ctrl+KeyT",
+    "ctrl+KeyV",
+    "ctrl+KeyW",
+    "ctrl+KeyX",
+    "ctrl+KeyZ",
+    "alt+F4",
+    "alt+Tab",
+];
+
+/// Returns an error for an invalid key, or a warning for keys likely to conflict with other applications
+pub fn check_os_hotkey(s: &str) -> Result<Option<&'static str>, String> {
+    let hotkey = s.parse::<OsHotKey>().map_err(|err| err.to_string())?;
+
+    Ok(if !s.contains('+') {
+        Some("Without modifier keys, this key cannot be typed in other applications")
+    } else if COMMON_SHORTCUTS
+        .iter()
+        .any(|r| r.parse::<OsHotKey>().ok() == Some(hotkey))
+    {
+        Some("This shortcut is commonly used by other applications")
+    } else {
+        None
+    })
+}
+
+impl OsHotKeys {
+    fn apply(&mut self, config: &GlobalHotKeyConfig, message_event: &mut EventWriter<ShowMessage>) {
+        if let Some(manager) = &self.manager {
+            for (_, os_hotkey) in self.registered.drain(..) {
+                let _ = manager.unregister(os_hotkey);
+            }
+        }
+
+        if !config.enabled {
+            return;
+        }
+
+        if self.manager.is_none() {
+            match GlobalHotKeyManager::new() {
+                Ok(manager) => self.manager = Some(manager),
+                Err(err) => {
+                    message_event.send(ShowMessage::error(format!(
+                        "Global hotkeys are not available: {err}"
+                    )));
+                    return;
+                }
+            }
+        }
+        let manager = self.manager.as_ref().unwrap();
+
+        for (hotkey, s) in config.assignments() {
+            let os_hotkey = match s.parse::<OsHotKey>() {
+                Ok(os_hotkey) => os_hotkey,
+                Err(err) => {
+                    message_event.send(ShowMessage::error(format!(
+                        "Invalid global hotkey `{s}` for {hotkey}: {err}"
+                    )));
+                    continue;
+                }
+            };
+
+            if self.registered.iter().any(|(_, r)| *r == os_hotkey) {
+                message_event.send(ShowMessage::error(format!(
+                    "Global hotkey `{s}` is assigned more than once"
+                )));
+                continue;
+            }
+
+            if let Err(err) = manager.register(os_hotkey) {
+                message_event.send(ShowMessage::error(format!(
+                    "Global hotkey `{s}` for {hotkey} is already used by another application: {err}"
+                )));
+                continue;
+            }
+
+            info!("Registered global hotkey `{s}` for {hotkey}");
+            self.registered.push((hotkey, os_hotkey));
+        }
+    }
+}
+
+fn os_hotkey_system(
+    mut os_hotkeys: NonSendMut<OsHotKeys>,
+    time: Res<Time>,
+    config: Res<Config>,
+    mut trigger: EventWriter<TriggerHotKey>,
+    mut message_event: EventWriter<ShowMessage>,
+) {
+    let now = time.seconds_since_startup();
+
+    if os_hotkeys.pending.as_ref().map(|(config, _)| config) != Some(&config.global_hotkeys) {
+        os_hotkeys.pending = Some((config.global_hotkeys.clone(), now));
+    }
+
+    // Apply immediately on startup
+    let (pending, changed_at) = os_hotkeys.pending.clone().unwrap();
+    let ready = os_hotkeys.applied.is_none() || now - changed_at >= APPLY_DELAY_SECS;
+
+    if ready && os_hotkeys.applied.as_ref() != Some(&pending) {
+        os_hotkeys.apply(&pending, &mut message_event);
+        os_hotkeys.applied = Some(pending);
+    }
+
+    while let Ok(event) = GlobalHotKeyEvent::receiver().try_recv() {
+        if let Some((hotkey, _)) = os_hotkeys
+            .registered
+            .iter()
+            .find(|(_, os_hotkey)| os_hotkey.id() == event.id)
+        {
+            trigger.send(TriggerHotKey(*hotkey));
+        }
+    }
+}
@@ -0,0 +1,275 @@
+use anyhow::{anyhow, bail, Result};
+use bevy_egui::egui;
+use meru_interface::{Color, FrameBuffer};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Palette shared as a small JSON file
+#[derive(PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct PalettePreset {
+    pub name: String,
+    pub colors: Vec<Color>,
+    /// Shown next to the name in the preset list
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+}
+
+/// Well-known 4-color palettes, from lightest to darkest.
+/// Tagged ones keep the shades apart for the color vision deficiency.
+pub const COMMUNITY_PALETTES: &[(&str, Option<&str>, [Color; 4])] = &[
+    (
+        "DMG Green",
+        None,
+        [
+            Color::new(0x9B, 0xBC, 0x0F),
+            Color::new(0x8B, 0xAC, 0x0F),
+            Color::new(0x30, 0x62, 0x30),
+            Color::new(0x0F, 0x38, 0x0F),
+        ],
+    ),
+    (
+        "Grayscale",
+        None,
+        [
+            Color::new(0xFF, 0xFF, 0xFF),
+            Color::new(0xAA, 0xAA, 0xAA),
+            Color::new(0x55, 0x55, 0x55),
+            Color::new(0x00, 0x00, 0x00),
+        ],
+    ),
+    (
+        "Nostalgia",
+        None,
+        [
+            Color::new(0xD0, 0xD0, 0x58),
+            Color::new(0xA0, 0xA8, 0x40),
+            Color::new(0x70, 0x80, 0x28),
+            Color::new(0x40, 0x50, 0x10),
+        ],
+    ),
+    (
+        "Kirokaze",
+        None,
+        [
+            Color::new(0xE2, 0xF3, 0xE4),
+            Color::new(0x94, 0xE3, 0x44),
+            Color::new(0x46, 0x87, 0x8F),
+            Color::new(0x33, 0x2C, 0x50),
+        ],
+    ),
+    (
+        "Ice Cream",
+        None,
+        [
+            Color::new(0xFF, 0xF6, 0xD3),
+            Color::new(0xF9, 0xA8, 0x75),
+            Color::new(0xEB, 0x6B, 0x6F),
+            Color::new(0x7C, 0x3F, 0x58),
+        ],
+    ),
+    (
+        "Mist",
+        None,
+        [
+            Color::new(0xC4, 0xF0, 0xC2),
+            Color::new(0x5A, 0xB9, 0xA8),
+            Color::new(0x1E, 0x60, 0x6E),
+            Color::new(0x2D, 0x1B, 0x00),
+        ],
+    ),
+    (
+        "Rustic",
+        None,
+        [
+            Color::new(0xED, 0xB4, 0xA1),
+            Color::new(0xA9, 0x68, 0x68),
+            Color::new(0x76, 0x44, 0x62),
+            Color::new(0x2C, 0x21, 0x37),
+        ],
+    ),
+    (
+        "2bit Demichrome",
+        None,
+        [
+            Color::new(0xE9, 0xEF, 0xEC),
+            Color::new(0xA0, 0xA0, 0x8B),
+            Color::new(0x55, 0x55, 0x68),
+            Color::new(0x21, 0x1E, 0x20),
+        ],
+    ),
+    (
+        "Blue Amber",
+        Some("Deuteranopia"),
+        [
+            Color::new(0xF0, 0xF0, 0xC8),
+            Color::new(0xE0, 0xA0, 0x30),
+            Color::new(0x38, 0x70, 0xB8),
+            Color::new(0x10, 0x18, 0x40),
+        ],
+    ),
+    (
+        "Sky Gold",
+        Some("Protanopia"),
+        [
+            Color::new(0xFF, 0xFF, 0xE0),
+            Color::new(0xF0, 0xC8, 0x50),
+            Color::new(0x50, 0x88, 0xD0),
+            Color::new(0x18, 0x28, 0x48),
+        ],
+    ),
+    (
+        "Rose Teal",
+        Some("Tritanopia"),
+        [
+            Color::new(0xF8, 0xE8, 0xE8),
+            Color::new(0xE0, 0x78, 0x78),
+            Color::new(0x1A, 0x68, 0x70),
+            Color::new(0x30, 0x18, 0x20),
+        ],
+    ),
+];
+
+pub fn community_presets() -> impl Iterator<Item = PalettePreset> {
+    COMMUNITY_PALETTES
+        .iter()
+        .map(|(name, tag, colors)| PalettePreset {
+            name: name.to_string(),
+            colors: colors.to_vec(),
+            tag: tag.map(str::to_string),
+        })
+}
+
+/// Reads a JSON preset, a JASC-PAL file, a RIFF palette or raw RGB triplets.
+/// `name` is used unless the file is a JSON preset.
+pub fn import_palette(name: &str, data: &[u8]) -> Result<PalettePreset> {
+    let name = if data.starts_with(b"{") {
+        serde_json::from_slice::<PalettePreset>(data)?.name
+    } else {
+        name.to_string()
+    };
+
+    let colors = if data.starts_with(b"{") {
+        serde_json::from_slice::<PalettePreset>(data)?.colors
+    } else if data.starts_with(b"JASC-PAL") {
+        parse_jasc_palette(data)?
+    } else if data.starts_with(b"RIFF") {
+        parse_riff_palette(data)?
+    } else if !data.is_empty() && data.len() % 3 == 0 {
+        data.chunks(3)
+            .map(|c| Color::new(c[0], c[1], c[2]))
+            .collect()
+    } else {
+        bail!("Unknown palette format");
+    };
+
+    if colors.is_empty() {
+        bail!("Palette has no colors");
+    }
+
+    Ok(PalettePreset {
+        name,
+        colors,
+        tag: None,
+    })
+}
+
+fn parse_jasc_palette(data: &[u8]) -> Result<Vec<Color>> {
+    let text = std::str::from_utf8(data)?;
+    let mut lines = text.lines().map(str::trim).filter(|line| !line.is_empty());
+
+    // Header and version
+    lines.next();
+    lines.next();
+
+    let count = lines
+        .next()
+        .ok_or_else(|| anyhow!("Missing color count"))?
+        .parse::<usize>()?;
+
+    lines
+        .take(count)
+        .map(|line| {
+            let rgb = line
+                .split_whitespace()
+                .map(|v| v.parse::<u8>())
+                .collect::<Result<Vec<_>, _>>()?;
+            if rgb.len() != 3 {
+                bail!("Invalid color: `{line}`");
+            }
+            Ok(Color::new(rgb[0], rgb[1], rgb[2]))
+        })
+        .collect()
+}
+
+fn parse_riff_palette(data: &[u8]) -> Result<Vec<Color>> {
+    if data.len() < 12 || &data[8..12] != b"PAL " {
+        bail!("Not a RIFF palette");
+    }
+
+    let mut pos = 12;
+    while pos + 8 <= data.len() {
+        let id = &data[pos..pos + 4];
+        let size = u32::from_le_bytes(data[pos + 4..pos + 8].try_into().unwrap()) as usize;
+        let body = data
+            .get(pos + 8..pos + 8 + size)
+            .ok_or_else(|| anyhow!("Truncated RIFF chunk"))?;
+
+        if id == b"data" {
+            // Version and number of entries, followed by RGBX entries
+            let count = u16::from_le_bytes(
+                body.get(2..4)
+                    .ok_or_else(|| anyhow!("Truncated RIFF chunk"))?
+                    .try_into()
+                    .unwrap(),
+            ) as usize;
+            return Ok(body[4..]
+                .chunks_exact(4)
+                .take(count)
+                .map(|c| Color::new(c[0], c[1], c[2]))
+                .collect());
+        }
+
+        // Chunks are padded to even sizes
+        pos += 8 + size + size % 2;
+    }
+
+    bail!("RIFF palette has no data chunk")
+}
+
+pub fn export_palette(preset: &PalettePreset) -> Vec<u8> {
+    serde_json::to_vec_pretty(preset).unwrap()
+}
+
+/// Recolors a frame rendered with the palette `from` into the palette `to`.
+/// Each pixel is mapped to the nearest color in `from`,
+/// so frames drawn with color correction are recolored as well.
+pub fn recolor_frame(frame_buffer: &FrameBuffer, from: &[Color], to: &[Color]) -> egui::ColorImage {
+    let mut cache = HashMap::new();
+
+    let pixels = frame_buffer
+        .buffer
+        .iter()
+        .map(|c| {
+            *cache.entry((c.r, c.g, c.b)).or_insert_with(|| {
+                let c = &to[nearest_color(from, c)];
+                egui::Color32::from_rgb(c.r, c.g, c.b)
+            })
+        })
+        .collect();
+
+    egui::ColorImage {
+        size: [frame_buffer.width, frame_buffer.height],
+        pixels,
+    }
+}
+
+fn nearest_color(palette: &[Color], c: &Color) -> usize {
+    let dist = |p: &Color| {
+        let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+        d(p.r, c.r) + d(p.g, c.g) + d(p.b, c.b)
+    };
+
+    (0..palette.len())
+        .min_by_key(|&i| dist(&palette[i]))
+        .unwrap_or(0)
+}
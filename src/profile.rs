@@ -0,0 +1,249 @@
+use anyhow::{anyhow, bail, Result};
+use chacha20poly1305::{aead::Aead, ChaCha20Poly1305, Key, KeyInit, Nonce};
+use hmac::Hmac;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    future::Future,
+    path::{Path, PathBuf},
+    sync::RwLock,
+};
+
+use crate::{
+    config::{base_config_dir, load_config, load_persistent_state, Config, PersistentState},
+    file::{read_to_string, write},
+    library::{load_library_db, LibraryDb},
+};
+
+/// The default profile uses the top level directories, as before profiles existed
+pub const DEFAULT_PROFILE: &str = "Default";
+
+const KEY_ROUNDS: u32 = 100_000;
+const NONCE_LEN: usize = 12;
+
+/// Header of encrypted files, followed by the nonce and the ciphertext
+const ENCRYPTED_MAGIC: &[u8] = b"MERUENC1";
+
+/// Encrypted into the profile list to tell a wrong passphrase
+const CHECK_TEXT: &[u8] = b"meru profile";
+
+static CURRENT_PROFILE: RwLock<Option<String>> = RwLock::new(None);
+
+/// Keys of the encrypted profiles unlocked in this session
+static PROFILE_KEYS: RwLock<BTreeMap<String, [u8; 32]>> = RwLock::new(BTreeMap::new());
+
+pub fn current_profile() -> String {
+    CURRENT_PROFILE
+        .read()
+        .unwrap()
+        .clone()
+        .unwrap_or_else(|| DEFAULT_PROFILE.to_string())
+}
+
+pub fn set_current_profile(name: &str) {
+    *CURRENT_PROFILE.write().unwrap() = (name != DEFAULT_PROFILE).then(|| name.to_string());
+}
+
+/// Directory of the current profile under `dir`
+pub fn profile_dir(dir: PathBuf) -> PathBuf {
+    match CURRENT_PROFILE.read().unwrap().as_ref() {
+        Some(name) => dir.join("profiles").join(name),
+        None => dir,
+    }
+}
+
+pub fn is_valid_profile_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 32
+        && name != DEFAULT_PROFILE
+        && name != "."
+        && name != ".."
+        && !name.contains(['/', '\\', ':'])
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Encryption {
+    salt: String,
+    check: String,
+}
+
+/// A user profile. The files of a profile with a passphrase are encrypted with
+/// a key derived from it, and cannot be read without it.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Profile {
+    pub name: String,
+    encryption: Option<Encryption>,
+}
+
+impl Profile {
+    /// The profile is not encrypted if `passphrase` is empty. It has to be
+    /// unlocked before its files are written.
+    pub fn new(name: &str, passphrase: &str) -> Result<Self> {
+        let encryption = if passphrase.is_empty() {
+            None
+        } else {
+            let mut salt = [0; 16];
+            getrandom::getrandom(&mut salt)?;
+            let key = derive_key(&salt, passphrase);
+            let check = encrypt(&key, CHECK_TEXT)?;
+            Some(Encryption {
+                salt: to_hex(&salt),
+                check: to_hex(&check),
+            })
+        };
+
+        Ok(Self {
+            name: name.to_string(),
+            encryption,
+        })
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.encryption.is_some()
+    }
+
+    /// Makes the files of the profile readable for the rest of the session
+    pub fn unlock(&self, passphrase: &str) -> Result<()> {
+        let encryption = match &self.encryption {
+            Some(encryption) => encryption,
+            None => return Ok(()),
+        };
+
+        let salt = from_hex(&encryption.salt).ok_or_else(|| anyhow!("Broken profile salt"))?;
+        let check = from_hex(&encryption.check).ok_or_else(|| anyhow!("Broken profile check"))?;
+        let key = derive_key(&salt, passphrase);
+        if decrypt(&key, &check).ok().as_deref() != Some(CHECK_TEXT) {
+            bail!("Wrong passphrase");
+        }
+        PROFILE_KEYS.write().unwrap().insert(self.name.clone(), key);
+        Ok(())
+    }
+}
+
+fn derive_key(salt: &[u8], passphrase: &str) -> [u8; 32] {
+    let mut key = [0; 32];
+    pbkdf2::pbkdf2::<Hmac<Sha256>>(passphrase.as_bytes(), salt, KEY_ROUNDS, &mut key);
+    key
+}
+
+fn encrypt(key: &[u8; 32], data: &[u8]) -> Result<Vec<u8>> {
+    let mut nonce = [0; NONCE_LEN];
+    getrandom::getrandom(&mut nonce)?;
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(key));
+    let encrypted = cipher
+        .encrypt(Nonce::from_slice(&nonce), data)
+        .map_err(|_| anyhow!("Failed to encrypt"))?;
+    Ok([&nonce[..], &encrypted[..]].concat())
+}
+
+fn decrypt(key: &[u8; 32], data: &[u8]) -> Result<Vec<u8>> {
+    if data.len() < NONCE_LEN {
+        bail!("Encrypted data is too short");
+    }
+    let (nonce, encrypted) = data.split_at(NONCE_LEN);
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(key));
+    cipher
+        .decrypt(Nonce::from_slice(nonce), encrypted)
+        .map_err(|_| anyhow!("Failed to decrypt: the file is broken"))
+}
+
+/// Key of the profile whose directory contains `path`. The profile is taken
+/// from the path rather than the current one, since files of the previous
+/// profile are still written while switching.
+fn profile_key(path: &Path) -> Option<[u8; 32]> {
+    let components = path.iter().collect::<Vec<_>>();
+    let name = components.windows(2).rev().find(|w| w[0] == "profiles")?[1].to_str()?;
+    PROFILE_KEYS.read().unwrap().get(name).copied()
+}
+
+/// Encrypts `data` if `path` is in the directory of an encrypted profile
+pub fn seal_file<'a>(path: &Path, data: &'a [u8]) -> Result<Cow<'a, [u8]>> {
+    Ok(match profile_key(path) {
+        Some(key) => Cow::Owned([ENCRYPTED_MAGIC, &encrypt(&key, data)?[..]].concat()),
+        None => Cow::Borrowed(data),
+    })
+}
+
+/// Decrypts `data` read from `path` if it is encrypted
+pub fn open_file(path: &Path, data: Vec<u8>) -> Result<Vec<u8>> {
+    let encrypted = match data.strip_prefix(ENCRYPTED_MAGIC) {
+        Some(encrypted) => encrypted,
+        None => return Ok(data),
+    };
+    let key = profile_key(path)
+        .ok_or_else(|| anyhow!("{} belongs to a locked profile", path.display()))?;
+    decrypt(&key, encrypted)
+}
+
+fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn from_hex(s: &str) -> Option<Vec<u8>> {
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Profiles other than the default one
+#[derive(Default, Serialize, Deserialize)]
+pub struct Profiles {
+    pub profiles: Vec<Profile>,
+    /// Show the profile selector when the app starts
+    #[serde(default)]
+    pub ask_on_startup: bool,
+}
+
+fn profiles_path() -> Result<PathBuf> {
+    Ok(base_config_dir()?.join("profiles.json"))
+}
+
+impl Profiles {
+    pub async fn load() -> Result<Self> {
+        Ok(if let Ok(s) = read_to_string(profiles_path()?).await {
+            serde_json::from_str(&s)?
+        } else {
+            Self::default()
+        })
+    }
+
+    pub fn save(&self) -> impl Future<Output = Result<()>> {
+        let s = serde_json::to_string_pretty(self).unwrap();
+        async move {
+            write(profiles_path()?, s).await?;
+            Ok(())
+        }
+    }
+
+    pub fn find(&self, name: &str) -> Option<&Profile> {
+        self.profiles.iter().find(|profile| profile.name == name)
+    }
+
+    pub fn add(&mut self, profile: Profile) -> Result<()> {
+        if !is_valid_profile_name(&profile.name) {
+            bail!("Invalid profile name: `{}`", profile.name);
+        }
+        if self.find(&profile.name).is_some() {
+            bail!("Profile `{}` already exists", profile.name);
+        }
+        self.profiles.push(profile);
+        Ok(())
+    }
+
+    /// Removes a profile from the list. Its files are kept.
+    pub fn remove(&mut self, name: &str) {
+        self.profiles.retain(|profile| profile.name != name);
+    }
+}
+
+/// Config, recent files and library of the current profile
+pub async fn load_profile_data() -> Result<(Config, PersistentState, LibraryDb)> {
+    Ok((
+        load_config().await?,
+        load_persistent_state().await?,
+        load_library_db().await?,
+    ))
+}
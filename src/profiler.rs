@@ -0,0 +1,48 @@
+//! Instrumentation for diagnosing performance issues.
+//!
+//! Build with `--features profile` and connect `puffin_viewer` to port 8585,
+//! or with `--features tracy` and connect the Tracy profiler.
+
+use bevy::prelude::*;
+
+/// Opens a profiling span lasting until the end of the enclosing block.
+/// Expands to nothing unless a profiler feature is enabled.
+macro_rules! profile_scope {
+    ($name:literal) => {
+        #[cfg(feature = "profile")]
+        puffin::profile_scope!($name);
+        #[cfg(feature = "tracy")]
+        let _span = bevy::utils::tracing::info_span!($name).entered();
+    };
+}
+
+pub(crate) use profile_scope;
+
+pub struct ProfilerPlugin;
+
+impl Plugin for ProfilerPlugin {
+    fn build(&self, app: &mut App) {
+        #[cfg(feature = "profile")]
+        {
+            puffin::set_scopes_on(true);
+
+            #[cfg(not(target_arch = "wasm32"))]
+            match puffin_http::Server::new("0.0.0.0:8585") {
+                Ok(server) => {
+                    app.insert_non_send_resource(server);
+                }
+                Err(err) => log::error!("Failed to start profiler server: {err}"),
+            }
+
+            app.add_system_to_stage(CoreStage::First, new_frame_system);
+        }
+
+        #[cfg(not(feature = "profile"))]
+        let _ = app;
+    }
+}
+
+#[cfg(feature = "profile")]
+fn new_frame_system() {
+    puffin::GlobalProfiler::lock().new_frame();
+}
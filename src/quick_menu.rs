@@ -0,0 +1,213 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext};
+use enum_iterator::{all, next_cycle, previous_cycle, Sequence};
+
+use crate::{
+    app::{AppState, UiState},
+    config::{Config, ScreenFilter, MAX_VOLUME, VOLUME_STEP},
+    core::Emulator,
+    utils::spawn_local,
+};
+
+/// Memory amounts for rewinding selectable from the quick menu, in MiB
+const REWIND_MEMORY_STEPS: &[usize] = &[0, 64, 128, 256, 512, 1024, 2048, 4096, 8192];
+
+pub struct QuickMenuPlugin;
+
+impl Plugin for QuickMenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<QuickMenu>()
+            .add_system_set(SystemSet::on_update(AppState::Running).with_system(quick_menu_system))
+            .add_system_set(
+                SystemSet::on_exit(AppState::Running).with_system(close_quick_menu_system),
+            );
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Sequence)]
+enum QuickItem {
+    Volume,
+    Filter,
+    TurboSpeed,
+    RewindMemory,
+}
+
+impl QuickItem {
+    fn label(&self) -> &'static str {
+        match self {
+            QuickItem::Volume => "Volume",
+            QuickItem::Filter => "Screen Filter",
+            QuickItem::TurboSpeed => "Turbo Speed",
+            QuickItem::RewindMemory => "Rewind Memory",
+        }
+    }
+
+    fn value(&self, config: &Config, emulator: &Emulator) -> String {
+        match self {
+            QuickItem::Volume => format!("{}%", config.audio.volume),
+            QuickItem::Filter => config
+                .video_setting(&emulator.game_hash)
+                .screen_filter
+                .to_string(),
+            QuickItem::TurboSpeed => {
+                format!(
+                    "x{}",
+                    config.frame_skip_on_turbo(emulator.core.core_info().abbrev)
+                )
+            }
+            QuickItem::RewindMemory => {
+                format!("{} MiB", config.auto_state_save_limit / (1024 * 1024))
+            }
+        }
+    }
+
+    fn adjust(&self, config: &mut Config, emulator: &Emulator, forward: bool) {
+        match self {
+            QuickItem::Volume => {
+                config.audio.volume = if forward {
+                    (config.audio.volume + VOLUME_STEP).min(MAX_VOLUME)
+                } else {
+                    config.audio.volume.saturating_sub(VOLUME_STEP)
+                };
+            }
+            QuickItem::Filter => {
+                let mut video = config.video_setting(&emulator.game_hash);
+                video.screen_filter = if forward {
+                    next_cycle(&video.screen_filter)
+                } else {
+                    previous_cycle(&video.screen_filter)
+                }
+                .unwrap_or(ScreenFilter::Nearest);
+                config.set_video_setting(&emulator.game_hash, video);
+            }
+            QuickItem::TurboSpeed => {
+                let abbrev = emulator.core.core_info().abbrev;
+                let frame_skip = config.frame_skip_on_turbo(abbrev);
+                let frame_skip = if forward {
+                    (frame_skip + 1).min(10)
+                } else {
+                    (frame_skip - 1).max(1)
+                };
+                if config.core_frame_skips_on_turbo.contains_key(abbrev) {
+                    config.set_core_frame_skip_on_turbo(abbrev, Some(frame_skip));
+                } else {
+                    config.frame_skip_on_turbo = frame_skip;
+                }
+            }
+            QuickItem::RewindMemory => {
+                let current = config.auto_state_save_limit / (1024 * 1024);
+                let amount = if forward {
+                    REWIND_MEMORY_STEPS.iter().find(|&&step| step > current)
+                } else {
+                    REWIND_MEMORY_STEPS
+                        .iter()
+                        .rev()
+                        .find(|&&step| step < current)
+                };
+                if let Some(amount) = amount {
+                    config.auto_state_save_limit = amount * 1024 * 1024;
+                }
+            }
+        }
+    }
+}
+
+/// Overlay with common settings adjustable with the D-pad.
+/// The game is paused while it is open.
+#[derive(Default)]
+pub struct QuickMenu {
+    open: bool,
+    selected: usize,
+    was_paused: bool,
+}
+
+impl QuickMenu {
+    pub fn toggle(&mut self, ui_state: &mut UiState) {
+        if self.open {
+            self.close(ui_state);
+        } else {
+            self.open = true;
+            self.was_paused = ui_state.paused;
+            ui_state.paused = true;
+        }
+    }
+
+    fn close(&mut self, ui_state: &mut UiState) {
+        if self.open {
+            self.open = false;
+            ui_state.paused = self.was_paused;
+        }
+    }
+}
+
+fn close_quick_menu_system(mut quick_menu: ResMut<QuickMenu>, mut ui_state: ResMut<UiState>) {
+    quick_menu.close(&mut ui_state);
+}
+
+fn quick_menu_system(
+    mut egui_ctx: ResMut<EguiContext>,
+    mut quick_menu: ResMut<QuickMenu>,
+    mut ui_state: ResMut<UiState>,
+    mut config: ResMut<Config>,
+    emulator: Res<Emulator>,
+    input_keycode: Res<Input<KeyCode>>,
+    input_gamepad_button: Res<Input<GamepadButton>>,
+) {
+    if !quick_menu.open {
+        return;
+    }
+
+    let pressed = |key: KeyCode, button: GamepadButtonType| {
+        input_keycode.just_pressed(key)
+            || input_gamepad_button
+                .get_just_pressed()
+                .any(|pressed| pressed.button_type == button)
+    };
+
+    let items = all::<QuickItem>().collect::<Vec<_>>();
+
+    if pressed(KeyCode::Up, GamepadButtonType::DPadUp) {
+        quick_menu.selected = (quick_menu.selected + items.len() - 1) % items.len();
+    }
+    if pressed(KeyCode::Down, GamepadButtonType::DPadDown) {
+        quick_menu.selected = (quick_menu.selected + 1) % items.len();
+    }
+
+    let old_config = config.clone();
+    let item = items[quick_menu.selected];
+    if pressed(KeyCode::Left, GamepadButtonType::DPadLeft) {
+        item.adjust(&mut config, &emulator, false);
+    }
+    if pressed(KeyCode::Right, GamepadButtonType::DPadRight) {
+        item.adjust(&mut config, &emulator, true);
+    }
+    if &old_config != config.as_ref() {
+        let config = config.clone();
+        spawn_local(async move { config.save().await.unwrap() });
+    }
+
+    if pressed(KeyCode::Return, GamepadButtonType::East) {
+        quick_menu.close(&mut ui_state);
+        return;
+    }
+
+    egui::Area::new("quick_menu")
+        .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+        .show(egui_ctx.ctx_mut(), |ui| {
+            egui::Frame::popup(ui.style()).show(ui, |ui| {
+                ui.heading("Quick Settings");
+
+                egui::Grid::new("quick_menu_items")
+                    .num_columns(2)
+                    .show(ui, |ui| {
+                        for (ix, item) in items.iter().enumerate() {
+                            ui.selectable_label(ix == quick_menu.selected, item.label());
+                            ui.label(format!("◀ {} ▶", item.value(&config, &emulator)));
+                            ui.end_row();
+                        }
+                    });
+
+                ui.small("Up/Down: select, Left/Right: change, Enter: close");
+            });
+        });
+}
@@ -0,0 +1,145 @@
+//! Ring buffer of the states of every frame for rewinding while a hotkey is held.
+//!
+//! Only the newest state is kept as is. Each older state is stored as the XOR of
+//! it and the next state, with runs of zero bytes compressed, so that stepping back
+//! one frame restores the previous state from the current one in a single pass.
+
+use std::collections::VecDeque;
+
+enum Delta {
+    /// Run-length encoded XOR of the state and the next one
+    Xor(Vec<u8>),
+    /// The state itself, when its size differs from the next one
+    Full(Vec<u8>),
+}
+
+impl Delta {
+    fn size(&self) -> usize {
+        match self {
+            Delta::Xor(data) | Delta::Full(data) => data.len(),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct RewindBuffer {
+    current: Option<Vec<u8>>,
+    deltas: VecDeque<Delta>,
+    size: usize,
+}
+
+impl RewindBuffer {
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Number of frames which can be rewound
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+
+    /// Adds the state of the next frame, dropping the oldest ones beyond `limit` bytes
+    pub fn push(&mut self, state: Vec<u8>, limit: usize) {
+        if let Some(prev) = self.current.take() {
+            let delta = if prev.len() == state.len() {
+                Delta::Xor(encode_xor(&prev, &state))
+            } else {
+                Delta::Full(prev)
+            };
+            self.size += delta.size();
+            self.deltas.push_back(delta);
+        }
+        self.current = Some(state);
+
+        while self.size > limit {
+            if let Some(delta) = self.deltas.pop_front() {
+                self.size -= delta.size();
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Steps back one frame. Returns the state of the previous frame,
+    /// or `None` if no older state is left.
+    pub fn pop(&mut self) -> Option<&[u8]> {
+        let delta = self.deltas.pop_back()?;
+        self.size -= delta.size();
+
+        let current = self.current.as_mut()?;
+        match delta {
+            Delta::Xor(data) => decode_xor(&data, current),
+            Delta::Full(data) => *current = data,
+        }
+        Some(current)
+    }
+}
+
+/// Encodes `a ^ b` as pairs of a zero run and a literal run,
+/// each prefixed by their lengths in LEB128
+fn encode_xor(a: &[u8], b: &[u8]) -> Vec<u8> {
+    let mut ret = vec![];
+    let mut xor = a.iter().zip(b.iter()).map(|(a, b)| a ^ b).peekable();
+
+    while xor.peek().is_some() {
+        let mut zeros = 0;
+        while xor.next_if_eq(&0).is_some() {
+            zeros += 1;
+        }
+        let mut literal = vec![];
+        while let Some(x) = xor.next_if(|x| *x != 0) {
+            literal.push(x);
+        }
+        write_len(&mut ret, zeros);
+        write_len(&mut ret, literal.len());
+        ret.extend_from_slice(&literal);
+    }
+    ret
+}
+
+/// Applies XOR encoded by `encode_xor` to `state`
+fn decode_xor(data: &[u8], state: &mut [u8]) {
+    let mut data = data;
+    let mut pos = 0;
+
+    while !data.is_empty() {
+        let zeros = read_len(&mut data);
+        let len = read_len(&mut data);
+        pos += zeros;
+        for (s, x) in state[pos..pos + len].iter_mut().zip(&data[..len]) {
+            *s ^= x;
+        }
+        pos += len;
+        data = &data[len..];
+    }
+}
+
+fn write_len(buf: &mut Vec<u8>, mut len: usize) {
+    loop {
+        let byte = (len & 0x7f) as u8;
+        len >>= 7;
+        if len == 0 {
+            buf.push(byte);
+            break;
+        }
+        buf.push(byte | 0x80);
+    }
+}
+
+fn read_len(data: &mut &[u8]) -> usize {
+    let mut len = 0;
+    let mut shift = 0;
+    while let Some((&byte, rest)) = data.split_first() {
+        *data = rest;
+        len |= ((byte & 0x7f) as usize) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+    len
+}
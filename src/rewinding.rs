@@ -1,19 +1,21 @@
-use bevy::prelude::*;
+use bevy::{prelude::*, render::render_resource::Extent3d};
 use bevy_easings::*;
-use std::time::Duration;
 
 use crate::{
-    app::{AppState, ScreenSprite},
+    app::{AppState, PixelFont, ScreenSprite},
     config::{self, SystemKey},
     core::Emulator,
+    file::decompress_state,
     hotkey::HotKey,
     input::InputState,
+    utils::ease_transform,
 };
 
 #[derive(Clone)]
 pub struct AutoSavedState {
     pub thumbnail: Image,
     pub data: Vec<u8>,
+    pub context: Option<String>,
 }
 
 impl AutoSavedState {
@@ -49,14 +51,54 @@ struct Preview;
 #[derive(Component)]
 struct Thumbnail(usize);
 
+#[derive(Component)]
+struct ContextText;
+
+fn context_text(state: &AutoSavedState) -> String {
+    state.context.clone().unwrap_or_default()
+}
+
+/// Thumbnails are upscaled by an integer factor with nearest neighbor beforehand,
+/// so that the linear sampler does not blur their pixels when drawn scaled
+fn thumbnail_image(state: &AutoSavedState, scale: usize) -> Image {
+    let src = &state.thumbnail;
+    if scale <= 1 {
+        return src.clone();
+    }
+
+    let size = src.size();
+    let (width, height) = (size.x as usize, size.y as usize);
+    let (scaled_width, scaled_height) = (width * scale, height * scale);
+
+    let mut data = vec![0; scaled_width * scaled_height * 4];
+    for y in 0..scaled_height {
+        for x in 0..scaled_width {
+            let src_ix = (y / scale * width + x / scale) * 4;
+            let ix = (y * scaled_width + x) * 4;
+            data[ix..ix + 4].copy_from_slice(&src.data[src_ix..src_ix + 4]);
+        }
+    }
+
+    let mut ret = src.clone();
+    ret.resize(Extent3d {
+        width: scaled_width as u32,
+        height: scaled_height as u32,
+        depth_or_array_layers: 1,
+    });
+    ret.data = data;
+    ret
+}
+
 fn enter_rewinding_system(
     mut commands: Commands,
     emulator: ResMut<Emulator>,
+    config: Res<config::Config>,
     mut images: ResMut<Assets<Image>>,
     mut screen_visibility: Query<&mut Visibility, With<ScreenSprite>>,
+    pixel_font: Query<&Handle<Font>, With<PixelFont>>,
 ) {
-    let screen_width = emulator.core.frame_buffer().width as f32;
-    let screen_height = emulator.core.frame_buffer().height as f32;
+    let (screen_width, screen_height) = emulator.screen_size();
+    let (screen_width, screen_height) = (screen_width as f32, screen_height as f32);
 
     for mut visibility in screen_visibility.iter_mut() {
         visibility.is_visible = false;
@@ -65,7 +107,13 @@ fn enter_rewinding_system(
     let state_num = emulator.auto_saved_states.len();
     assert!(state_num > 0);
 
-    let preview_image = images.add(emulator.auto_saved_states[state_num - 1].thumbnail.clone());
+    let scale = config.video_setting(&emulator.game_hash).scaling.max(1);
+    let screen_size = Some(Vec2::new(screen_width, screen_height));
+
+    let preview_image = images.add(thumbnail_image(
+        &emulator.auto_saved_states[state_num - 1],
+        scale,
+    ));
 
     commands
         .spawn_bundle(SpriteBundle {
@@ -79,36 +127,36 @@ fn enter_rewinding_system(
         })
         .insert(BgColor);
 
-    commands
-        .spawn_bundle(SpriteBundle {
-            texture: preview_image,
-            transform: Transform::from_xyz(0.0, 0.0, 1.0),
+    let mut preview = commands.spawn_bundle(SpriteBundle {
+        sprite: Sprite {
+            custom_size: screen_size,
             ..Default::default()
-        })
-        .insert(
-            Transform {
-                ..Default::default()
-            }
-            .ease_to(
-                Transform::from_xyz(0.0, screen_height / 6.0, 1.0)
-                    .with_scale(Vec3::splat(2.0 / 3.0)),
-                EaseFunction::CubicInOut,
-                EasingType::Once {
-                    duration: Duration::from_millis(200),
-                },
-            ),
-        )
-        .insert(Preview);
+        },
+        texture: preview_image,
+        transform: Transform::from_xyz(0.0, 0.0, 1.0),
+        ..Default::default()
+    });
+    ease_transform(
+        &mut preview,
+        Transform::default(),
+        Transform::from_xyz(0.0, screen_height / 6.0, 1.0).with_scale(Vec3::splat(2.0 / 3.0)),
+        200,
+        config.animations,
+    );
+    preview.insert(Preview);
 
     for i in 0..4 {
         if state_num > i {
-            let thumbnail = images.add(
-                emulator.auto_saved_states[state_num - 1 - i]
-                    .thumbnail
-                    .clone(),
-            );
+            let thumbnail = images.add(thumbnail_image(
+                &emulator.auto_saved_states[state_num - 1 - i],
+                scale,
+            ));
             commands
                 .spawn_bundle(SpriteBundle {
+                    sprite: Sprite {
+                        custom_size: screen_size,
+                        ..Default::default()
+                    },
                     texture: thumbnail,
                     transform: Transform::from_xyz(
                         -(i as f32) * screen_width / 4.0,
@@ -122,6 +170,22 @@ fn enter_rewinding_system(
         }
     }
 
+    commands
+        .spawn_bundle(Text2dBundle {
+            text: Text::from_section(
+                context_text(&emulator.auto_saved_states[state_num - 1]),
+                TextStyle {
+                    font: pixel_font.single().clone(),
+                    font_size: 16.0,
+                    color: Color::WHITE,
+                },
+            )
+            .with_alignment(TextAlignment::CENTER),
+            transform: Transform::from_xyz(0.0, screen_height / 2.0 - 10.0, 2.0),
+            ..Default::default()
+        })
+        .insert(ContextText);
+
     commands.insert_resource(RewindingState {
         pos: state_num - 1,
         load_pos: None,
@@ -143,21 +207,25 @@ fn rewinding_system(
     input_gamepad_button: Res<Input<GamepadButton>>,
     input_gamepad_axis: Res<Axis<GamepadAxis>>,
     easing: Query<&EasingComponent<Transform>>,
+    mut context: Query<&mut Text, With<ContextText>>,
 ) {
-    let screen_width = emulator.core.frame_buffer().width as f32;
-    let screen_height = emulator.core.frame_buffer().height as f32;
+    let (screen_width, screen_height) = emulator.screen_size();
+    let (screen_width, screen_height) = (screen_width as f32, screen_height as f32);
+    let scale = config.video_setting(&emulator.game_hash).scaling.max(1);
+    let screen_size = Some(Vec2::new(screen_width, screen_height));
 
     let input_state = InputState::new(&input_keycode, &input_gamepad_button, &input_gamepad_axis);
 
+    // remove invisible thumbnails
+    for (entity, transform) in thumbnails.iter() {
+        if transform.translation.x.abs() > screen_width {
+            commands.entity(entity).despawn();
+            // TODO: remove image from assets
+        }
+    }
+
     // wait for animation
     if easing.iter().next().is_some() {
-        // remove invisible thumbnails
-        for (entity, transform) in thumbnails.iter() {
-            if transform.translation.x.abs() > screen_width {
-                commands.entity(entity).despawn();
-                // TODO: remove image from assets
-            }
-        }
         return;
     }
 
@@ -173,15 +241,16 @@ fn rewinding_system(
         let state = emulator.auto_saved_states.back().unwrap().clone();
 
         let mut preview = preview.single_mut();
-        *preview.0 = images.add(state.thumbnail);
-        commands.entity(preview.2).insert(preview.1.ease_to(
+        *preview.0 = images.add(thumbnail_image(&state, scale));
+        ease_transform(
+            &mut commands.entity(preview.2),
+            *preview.1,
             Transform::from_xyz(0.0, 0.0, 1.0),
-            EaseFunction::CubicInOut,
-            EasingType::Once {
-                duration: Duration::from_millis(200),
-            },
-        ));
-        emulator.core.load_state(&state.data).unwrap();
+            200,
+            config.animations,
+        );
+        let data = decompress_state(&state.data).unwrap();
+        emulator.core.load_state(&data).unwrap();
         rewinding_state.exit = true;
         return;
     }
@@ -194,10 +263,14 @@ fn rewinding_system(
         if left && rewinding_state.pos > 0 {
             if rewinding_state.pos >= 4 {
                 let ix = rewinding_state.pos - 4;
-                let thumbnail = images.add(emulator.auto_saved_states[ix].thumbnail.clone());
+                let thumbnail = images.add(thumbnail_image(&emulator.auto_saved_states[ix], scale));
 
                 commands
                     .spawn_bundle(SpriteBundle {
+                        sprite: Sprite {
+                            custom_size: screen_size,
+                            ..Default::default()
+                        },
                         texture: thumbnail,
                         transform: Transform::from_xyz(
                             -3.0 * screen_width / 4.0,
@@ -216,10 +289,14 @@ fn rewinding_system(
         if right && rewinding_state.pos < emulator.auto_saved_states.len() - 1 {
             if rewinding_state.pos + 4 < emulator.auto_saved_states.len() {
                 let ix = rewinding_state.pos + 4;
-                let thumbnail = images.add(emulator.auto_saved_states[ix].thumbnail.clone());
+                let thumbnail = images.add(thumbnail_image(&emulator.auto_saved_states[ix], scale));
 
                 commands
                     .spawn_bundle(SpriteBundle {
+                        sprite: Sprite {
+                            custom_size: screen_size,
+                            ..Default::default()
+                        },
                         texture: thumbnail,
                         transform: Transform::from_xyz(
                             3.0 * screen_width / 4.0,
@@ -239,20 +316,23 @@ fn rewinding_system(
         if do_move {
             let dx = if left { 1.0 } else { -1.0 } * screen_width / 4.0;
             for (entity, trans) in thumbnails.iter() {
-                commands.entity(entity).insert(trans.ease_to(
+                ease_transform(
+                    &mut commands.entity(entity),
+                    *trans,
                     Transform::from_xyz(dx, 0.0, 0.0) * *trans,
-                    EaseFunction::CubicInOut,
-                    EasingType::Once {
-                        duration: Duration::from_millis(100),
-                    },
-                ));
+                    100,
+                    config.animations,
+                );
             }
 
-            *preview.single_mut().0 = images.add(
-                emulator.auto_saved_states[rewinding_state.pos]
-                    .thumbnail
-                    .clone(),
-            );
+            *preview.single_mut().0 = images.add(thumbnail_image(
+                &emulator.auto_saved_states[rewinding_state.pos],
+                scale,
+            ));
+            for mut text in context.iter_mut() {
+                text.sections[0].value =
+                    context_text(&emulator.auto_saved_states[rewinding_state.pos]);
+            }
         }
     }
 
@@ -275,6 +355,7 @@ fn exit_rewinding_system(
     bg_color: Query<Entity, With<BgColor>>,
     preview: Query<Entity, With<Preview>>,
     thumbnails: Query<Entity, With<Thumbnail>>,
+    context: Query<Entity, With<ContextText>>,
     mut screen_visibility: Query<&mut Visibility, With<ScreenSprite>>,
 ) {
     for mut visibility in screen_visibility.iter_mut() {
@@ -285,6 +366,7 @@ fn exit_rewinding_system(
         .iter()
         .chain(preview.iter())
         .chain(thumbnails.iter())
+        .chain(context.iter())
     {
         commands.entity(entity).despawn();
     }
@@ -0,0 +1,34 @@
+use std::{collections::VecDeque, path::Path};
+
+use crate::core::Emulator;
+
+/// Games suspended in the background, most recently used first.
+/// Suspended emulators keep their whole state in memory, so switching back resumes instantly.
+#[derive(Default)]
+pub struct Sessions {
+    pub games: VecDeque<Emulator>,
+}
+
+impl Sessions {
+    /// Suspends `emulator`. The oldest sessions are closed when exceeding `max_sessions`.
+    pub fn suspend(&mut self, emulator: Emulator, max_sessions: usize) {
+        self.games.push_front(emulator);
+        // Dropping an emulator saves its backup
+        self.games.truncate(max_sessions);
+    }
+
+    pub fn find(&self, rom_path: &Path) -> Option<usize> {
+        self.games.iter().position(|game| game.rom_path == rom_path)
+    }
+
+    /// Swaps the running emulator with the `index`-th suspended one
+    pub fn switch(&mut self, index: usize, current: &mut Emulator) {
+        let mut game = self.games.remove(index).unwrap();
+        std::mem::swap(&mut game, current);
+        self.games.push_front(game);
+    }
+
+    pub fn close(&mut self, index: usize) {
+        self.games.remove(index);
+    }
+}
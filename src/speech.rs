@@ -0,0 +1,106 @@
+//! Text-to-speech of menu items and messages for low-vision users.
+//!
+//! Uses the speech API of the platform through the `tts` crate,
+//! which is enabled by the `speech` feature.
+
+use bevy::prelude::*;
+#[cfg(feature = "speech")]
+use bevy_egui::{egui, EguiContext, EguiSystem};
+
+#[cfg(feature = "speech")]
+use crate::{app::ShowMessage, config::Config};
+
+pub struct SpeechPlugin;
+
+impl Plugin for SpeechPlugin {
+    fn build(&self, app: &mut App) {
+        #[cfg(feature = "speech")]
+        app.init_non_send_resource::<Speech>()
+            .add_system(speak_message_system)
+            // Widget events are taken when egui ends the frame
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                speak_menu_system.before(EguiSystem::ProcessOutput),
+            );
+
+        #[cfg(not(feature = "speech"))]
+        let _ = app;
+    }
+}
+
+pub fn is_supported() -> bool {
+    cfg!(feature = "speech")
+}
+
+/// Speech engine, initialized when first used
+#[cfg(feature = "speech")]
+#[derive(Default)]
+struct Speech {
+    tts: Option<tts::Tts>,
+    failed: bool,
+}
+
+#[cfg(feature = "speech")]
+impl Speech {
+    fn speak(&mut self, text: &str, interrupt: bool) {
+        if self.failed {
+            return;
+        }
+
+        if self.tts.is_none() {
+            match tts::Tts::default() {
+                Ok(tts) => self.tts = Some(tts),
+                Err(err) => {
+                    log::error!("Failed to initialize text-to-speech: {err}");
+                    self.failed = true;
+                    return;
+                }
+            }
+        }
+
+        if let Err(err) = self.tts.as_mut().unwrap().speak(text, interrupt) {
+            log::warn!("Failed to speak: {err}");
+        }
+    }
+}
+
+#[cfg(feature = "speech")]
+fn speak_message_system(
+    config: Res<Config>,
+    mut speech: NonSendMut<Speech>,
+    mut event: EventReader<ShowMessage>,
+) {
+    for msg in event.iter() {
+        if config.text_to_speech {
+            speech.speak(&msg.text, false);
+        }
+    }
+}
+
+#[cfg(feature = "speech")]
+fn speak_menu_system(
+    config: Res<Config>,
+    mut speech: NonSendMut<Speech>,
+    mut egui_ctx: ResMut<EguiContext>,
+) {
+    if !config.text_to_speech {
+        return;
+    }
+
+    // Only the latest one matters when focus moves quickly
+    let text = egui_ctx
+        .ctx_mut()
+        .output()
+        .events
+        .iter()
+        .filter_map(|event| match event {
+            egui::output::OutputEvent::FocusGained(info)
+            | egui::output::OutputEvent::ValueChanged(info) => Some(info.description()),
+            _ => None,
+        })
+        .last();
+
+    if let Some(text) = text {
+        speech.speak(&text, true);
+    }
+}
@@ -0,0 +1,163 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext};
+
+use crate::{
+    app::{AppState, UiState},
+    core::Emulator,
+    hotkey::{HotKey, TriggerHotKey},
+    menu::{state_thumbnail, StateThumbnails},
+};
+
+/// Slots shown on each side of the selected one
+const VISIBLE_NEIGHBORS: usize = 2;
+
+pub struct StateCarouselPlugin;
+
+impl Plugin for StateCarouselPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<StateCarousel>()
+            .add_system_set(
+                SystemSet::on_update(AppState::Running).with_system(state_carousel_system),
+            )
+            .add_system_set(
+                SystemSet::on_exit(AppState::Running).with_system(close_state_carousel_system),
+            );
+    }
+}
+
+/// Overlay of the state slots with their thumbnails, to pick one to load
+/// without opening the menu. The game is paused while it is open.
+#[derive(Default)]
+pub struct StateCarousel {
+    open: bool,
+    selected: usize,
+    was_paused: bool,
+    thumbnails: StateThumbnails,
+}
+
+impl StateCarousel {
+    pub fn toggle(&mut self, ui_state: &mut UiState, slots: usize) {
+        if self.open {
+            self.close(ui_state);
+        } else {
+            self.open = true;
+            self.selected = ui_state.state_save_slot.min(slots.saturating_sub(1));
+            self.was_paused = ui_state.paused;
+            ui_state.paused = true;
+        }
+    }
+
+    fn close(&mut self, ui_state: &mut UiState) {
+        if self.open {
+            self.open = false;
+            ui_state.paused = self.was_paused;
+        }
+    }
+}
+
+fn close_state_carousel_system(mut carousel: ResMut<StateCarousel>, mut ui_state: ResMut<UiState>) {
+    carousel.close(&mut ui_state);
+}
+
+fn state_carousel_system(
+    mut egui_ctx: ResMut<EguiContext>,
+    mut carousel: ResMut<StateCarousel>,
+    mut ui_state: ResMut<UiState>,
+    emulator: Res<Emulator>,
+    input_keycode: Res<Input<KeyCode>>,
+    input_gamepad_button: Res<Input<GamepadButton>>,
+    mut trigger: EventWriter<TriggerHotKey>,
+) {
+    let slots = emulator.state_files.len();
+    if !carousel.open || slots == 0 {
+        return;
+    }
+
+    let pressed = |key: KeyCode, button: GamepadButtonType| {
+        input_keycode.just_pressed(key)
+            || input_gamepad_button
+                .get_just_pressed()
+                .any(|pressed| pressed.button_type == button)
+    };
+
+    if pressed(KeyCode::Left, GamepadButtonType::DPadLeft) {
+        carousel.selected = (carousel.selected + slots - 1) % slots;
+    }
+    if pressed(KeyCode::Right, GamepadButtonType::DPadRight) {
+        carousel.selected = (carousel.selected + 1) % slots;
+    }
+    // Keys other than the hotkey to close it would trigger other hotkeys
+    let cancelled = input_gamepad_button
+        .get_just_pressed()
+        .any(|pressed| pressed.button_type == GamepadButtonType::South);
+    if cancelled {
+        carousel.close(&mut ui_state);
+        return;
+    }
+    if pressed(KeyCode::Return, GamepadButtonType::East) {
+        // Loading goes through the hotkey, which refuses it during netplay or movies
+        ui_state.state_save_slot = carousel.selected;
+        carousel.close(&mut ui_state);
+        if emulator.state_files[ui_state.state_save_slot].is_some() {
+            trigger.send(TriggerHotKey(HotKey::StateLoad));
+        }
+        return;
+    }
+
+    let carousel = &mut *carousel;
+    let visible = (VISIBLE_NEIGHBORS * 2 + 1).min(slots);
+    let first = carousel.selected + slots - visible / 2;
+
+    egui::Area::new("state_carousel")
+        .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+        .show(egui_ctx.ctx_mut(), |ui| {
+            egui::Frame::popup(ui.style()).show(ui, |ui| {
+                ui.heading("State Slots");
+
+                ui.horizontal(|ui| {
+                    for i in 0..visible {
+                        let slot = (first + i) % slots;
+                        let state_file = emulator.state_files[slot].as_ref();
+                        let is_selected = slot == carousel.selected;
+
+                        let stroke = if is_selected {
+                            ui.visuals().selection.stroke
+                        } else {
+                            egui::Stroke::none()
+                        };
+                        egui::Frame::group(ui.style())
+                            .stroke(stroke)
+                            .show(ui, |ui| {
+                                ui.vertical_centered(|ui| {
+                                    ui.label(format!("#{slot}"));
+
+                                    let thumbnail = state_thumbnail(
+                                        ui.ctx(),
+                                        &mut carousel.thumbnails,
+                                        slot,
+                                        state_file,
+                                    );
+                                    let scale = if is_selected { 2.0 } else { 4.0 };
+                                    if let Some(texture) = thumbnail {
+                                        ui.image(&texture, texture.size_vec2() / scale);
+                                    } else if state_file.is_some() {
+                                        ui.label("No preview");
+                                    } else {
+                                        ui.label("Empty");
+                                    }
+
+                                    ui.small(state_file.map_or_else(
+                                        || "---".to_string(),
+                                        |state_file| {
+                                            state_file.modified.format("%Y/%m/%d %H:%M").to_string()
+                                        },
+                                    ));
+                                });
+                            });
+                    }
+                });
+
+                ui.small("Left/Right: select, Enter: load");
+            });
+        });
+}
@@ -0,0 +1,337 @@
+//! Replacement of tiles drawn by the core with high-resolution textures.
+//!
+//! A texture pack is a directory of PNG files named by the hashes of the tiles
+//! they replace, such as `0123456789abcdef.png`. The screen is rendered at the scale
+//! of the pack, and each tile reported by `tile_draws` is drawn over it with its texture.
+
+use anyhow::Result;
+use bevy::prelude::*;
+use meru_interface::{FrameBuffer, TileDraw};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    app::ShowMessage,
+    config::{Config, Overscan, TexturePack, MAX_TEXTURE_SCALE, MIN_TEXTURE_SCALE},
+    core::Emulator,
+    utils::{spawn_local, unbounded_channel, Receiver, Sender},
+};
+
+pub struct TexturePackPlugin;
+
+impl Plugin for TexturePackPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TextureReplacer>()
+            .add_system(texture_pack_system.after("emulator"));
+    }
+}
+
+pub fn is_supported() -> bool {
+    cfg!(not(target_arch = "wasm32"))
+}
+
+const DUMP_DIR: &str = "dump";
+
+struct LoadedTextures {
+    textures: HashMap<u64, image::RgbaImage>,
+    /// Hashes of the tiles already in the dump directory
+    dumped: HashSet<u64>,
+}
+
+struct TexturesLoaded {
+    game_hash: String,
+    dir: PathBuf,
+    result: Result<LoadedTextures>,
+}
+
+/// Textures of the pack of the current game
+pub struct TextureReplacer {
+    /// Game hash and pack setting which `textures` were loaded for
+    pack: Option<(String, TexturePack)>,
+    textures: Option<HashMap<u64, image::RgbaImage>>,
+    dumped: HashSet<u64>,
+    sender: Sender<TexturesLoaded>,
+    receiver: Receiver<TexturesLoaded>,
+}
+
+impl Default for TextureReplacer {
+    fn default() -> Self {
+        let (sender, receiver) = unbounded_channel();
+        Self {
+            pack: None,
+            textures: None,
+            dumped: HashSet::new(),
+            sender,
+            receiver,
+        }
+    }
+}
+
+impl TextureReplacer {
+    /// Scale of the screen while textures are replaced
+    pub fn scale(&self) -> Option<usize> {
+        self.textures.as_ref()?;
+        let (_, pack) = self.pack.as_ref()?;
+        Some(pack.scale.clamp(MIN_TEXTURE_SCALE, MAX_TEXTURE_SCALE))
+    }
+
+    /// Draws the textures of `draws` over `image`,
+    /// which holds the frame rendered at `scale`
+    pub fn draw_tiles(
+        &self,
+        image: &mut Image,
+        frame_buffer: &FrameBuffer,
+        overscan: &Overscan,
+        draws: &[TileDraw],
+    ) {
+        let (textures, scale) = match (&self.textures, self.scale()) {
+            (Some(textures), Some(scale)) => (textures, scale as i64),
+            _ => return,
+        };
+
+        let (left, top, width, height) =
+            overscan.crop_rect(frame_buffer.width, frame_buffer.height);
+        let (width, height) = (width as i64 * scale, height as i64 * scale);
+        let image_size = image.size();
+        if (image_size[0] as i64, image_size[1] as i64) != (width, height) {
+            return;
+        }
+
+        let data = &mut image.data;
+
+        for draw in draws {
+            let texture = if let Some(texture) = textures.get(&draw.hash) {
+                texture
+            } else {
+                continue;
+            };
+
+            let (tex_width, tex_height) = (texture.width() as i64, texture.height() as i64);
+            let (draw_width, draw_height) = (draw.width as i64 * scale, draw.height as i64 * scale);
+            let x0 = (draw.x as i64 - left as i64) * scale;
+            let y0 = (draw.y as i64 - top as i64) * scale;
+
+            for dy in 0..draw_height {
+                let y = y0 + dy;
+                if y < 0 || y >= height {
+                    continue;
+                }
+                let mut ty = dy * tex_height / draw_height;
+                if draw.flip_v {
+                    ty = tex_height - 1 - ty;
+                }
+
+                for dx in 0..draw_width {
+                    let x = x0 + dx;
+                    if x < 0 || x >= width {
+                        continue;
+                    }
+                    let mut tx = dx * tex_width / draw_width;
+                    if draw.flip_h {
+                        tx = tex_width - 1 - tx;
+                    }
+
+                    let src = texture.get_pixel(tx as u32, ty as u32).0;
+                    let ix = (y * width + x) as usize * 4;
+                    let dest = &mut data[ix..ix + 3];
+                    match src[3] {
+                        0 => {}
+                        0xff => dest.copy_from_slice(&src[..3]),
+                        alpha => {
+                            let (a, b) = (alpha as u32, 255 - alpha as u32);
+                            for (d, s) in dest.iter_mut().zip(src.iter()) {
+                                *d = ((*s as u32 * a + *d as u32 * b) / 255) as u8;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn set_pack(&mut self, game_hash: &str, pack: Option<&TexturePack>) {
+        let same_textures = match (&self.pack, pack) {
+            (Some((cur_hash, cur)), Some(pack)) => cur_hash == game_hash && cur.dir == pack.dir,
+            _ => false,
+        };
+
+        self.pack = pack.map(|pack| (game_hash.to_string(), pack.clone()));
+        if same_textures {
+            return;
+        }
+
+        self.textures = None;
+        self.dumped.clear();
+
+        if let Some(pack) = pack {
+            let game_hash = game_hash.to_string();
+            let dir = pack.dir.clone();
+            let sender = self.sender.clone();
+            spawn_local(async move {
+                let result = load_textures(&dir);
+                let loaded = TexturesLoaded {
+                    game_hash,
+                    dir,
+                    result,
+                };
+                sender.send(loaded).await.unwrap();
+            });
+        }
+    }
+
+    /// Saves the tiles of `draws` that have no texture and are not dumped yet.
+    /// Tiles are captured from the screen, so overlapping graphics are included.
+    fn dump_tiles(&mut self, frame_buffer: &FrameBuffer, draws: &[TileDraw]) {
+        let (textures, dir) = match (&self.textures, &self.pack) {
+            (Some(textures), Some((_, pack))) => (textures, pack.dir.join(DUMP_DIR)),
+            _ => return,
+        };
+
+        let mut tiles = vec![];
+        for draw in draws {
+            if textures.contains_key(&draw.hash) || self.dumped.contains(&draw.hash) {
+                continue;
+            }
+            // Partly hidden tiles are dumped when they are fully on screen later
+            if draw.x < 0
+                || draw.y < 0
+                || draw.x as usize + draw.width as usize > frame_buffer.width
+                || draw.y as usize + draw.height as usize > frame_buffer.height
+            {
+                continue;
+            }
+
+            let tile = image::RgbaImage::from_fn(draw.width, draw.height, |x, y| {
+                let x = if draw.flip_h { draw.width - 1 - x } else { x };
+                let y = if draw.flip_v { draw.height - 1 - y } else { y };
+                let c =
+                    frame_buffer.pixel(draw.x as usize + x as usize, draw.y as usize + y as usize);
+                image::Rgba([c.r, c.g, c.b, 0xff])
+            });
+            self.dumped.insert(draw.hash);
+            tiles.push((draw.hash, tile));
+        }
+
+        if tiles.is_empty() {
+            return;
+        }
+
+        spawn_local(async move {
+            if let Err(err) = fs::create_dir_all(&dir) {
+                log::error!("Failed to create `{}`: {err}", dir.display());
+                return;
+            }
+            for (hash, tile) in tiles {
+                let path = dir.join(texture_file_name(hash));
+                if let Err(err) = tile.save(&path) {
+                    log::error!("Failed to dump `{}`: {err}", path.display());
+                }
+            }
+        });
+    }
+}
+
+fn texture_file_name(hash: u64) -> String {
+    format!("{hash:016x}.png")
+}
+
+fn parse_texture_file_name(path: &Path) -> Option<u64> {
+    if path.extension()? != "png" {
+        return None;
+    }
+    u64::from_str_radix(path.file_stem()?.to_str()?, 16).ok()
+}
+
+fn load_textures(dir: &Path) -> Result<LoadedTextures> {
+    let mut textures = HashMap::new();
+    for entry in fs::read_dir(dir)?.flatten() {
+        let path = entry.path();
+        if let Some(hash) = parse_texture_file_name(&path) {
+            match image::open(&path) {
+                Ok(texture) => {
+                    textures.insert(hash, texture.into_rgba8());
+                }
+                Err(err) => log::warn!("Ignoring texture `{}`: {err}", path.display()),
+            }
+        }
+    }
+
+    let mut dumped = HashSet::new();
+    if let Ok(entries) = fs::read_dir(dir.join(DUMP_DIR)) {
+        for entry in entries.flatten() {
+            if let Some(hash) = parse_texture_file_name(&entry.path()) {
+                dumped.insert(hash);
+            }
+        }
+    }
+
+    Ok(LoadedTextures { textures, dumped })
+}
+
+fn texture_pack_system(
+    config: Res<Config>,
+    emulator: Option<ResMut<Emulator>>,
+    mut replacer: ResMut<TextureReplacer>,
+    mut message_event: EventWriter<ShowMessage>,
+) {
+    while let Ok(loaded) = replacer.receiver.try_recv() {
+        let current = replacer.pack.as_ref().map_or(false, |(game_hash, pack)| {
+            game_hash == &loaded.game_hash && pack.dir == loaded.dir
+        });
+        if !current {
+            continue;
+        }
+
+        match loaded.result {
+            Ok(LoadedTextures { textures, dumped }) => {
+                message_event.send(ShowMessage::info(format!(
+                    "Loaded {} HD textures",
+                    textures.len()
+                )));
+                replacer.textures = Some(textures);
+                replacer.dumped = dumped;
+            }
+            Err(err) => {
+                message_event.send(ShowMessage::error(format!(
+                    "Failed to load texture pack: {err}"
+                )));
+            }
+        }
+    }
+
+    let mut emulator = if let Some(emulator) = emulator {
+        emulator
+    } else {
+        if replacer.pack.is_some() {
+            replacer.set_pack("", None);
+        }
+        return;
+    };
+
+    let pack = config
+        .texture_packs
+        .get(&emulator.game_hash)
+        .filter(|pack| pack.enabled && is_supported() && emulator.core.supports_tile_hashes());
+
+    let changed = replacer
+        .pack
+        .as_ref()
+        .map(|(game_hash, pack)| (game_hash, pack))
+        != pack.map(|pack| (&emulator.game_hash, pack));
+    if changed {
+        replacer.set_pack(&emulator.game_hash, pack);
+    }
+
+    // A reloaded core starts without hashing
+    if changed || emulator.is_added() {
+        let enabled = pack.is_some();
+        emulator.core.set_tile_hashing(enabled);
+    }
+
+    if pack.map_or(false, |pack| pack.dump) {
+        replacer.dump_tiles(emulator.core.frame_buffer(), emulator.core.tile_draws());
+    }
+}
@@ -0,0 +1,148 @@
+use bevy::{
+    app::AppExit,
+    prelude::*,
+    window::{WindowId, WindowResized},
+    winit::WinitWindows,
+};
+use log::error;
+use tray_item::{IconSource, TrayItem};
+
+use crate::{
+    app::{ShowMessage, UiState, WindowControlEvent},
+    config::Config,
+    core::Emulator,
+    utils::{spawn_local, unbounded_channel, Receiver, Sender},
+};
+
+#[cfg(target_os = "windows")]
+const TRAY_ICON: &str = "meru-icon";
+#[cfg(not(target_os = "windows"))]
+const TRAY_ICON: &str = "applications-games";
+
+pub struct TrayPlugin;
+
+impl Plugin for TrayPlugin {
+    fn build(&self, app: &mut App) {
+        let (s, r) = unbounded_channel::<TrayCommand>();
+        app.insert_resource(s)
+            .insert_resource(r)
+            .insert_non_send_resource(Tray::default())
+            .add_system(tray_system);
+    }
+}
+
+#[derive(Clone, Copy)]
+enum TrayCommand {
+    Resume,
+    SaveState,
+    Quit,
+}
+
+/// The tray icon exists only while the window is hidden in the tray
+#[derive(Default)]
+struct Tray {
+    item: Option<TrayItem>,
+    was_paused: bool,
+}
+
+fn create_tray_item(send: &Sender<TrayCommand>) -> anyhow::Result<TrayItem> {
+    let mut item = TrayItem::new("MERU", IconSource::Resource(TRAY_ICON))?;
+
+    for (label, command) in [
+        ("Resume", TrayCommand::Resume),
+        ("Save State", TrayCommand::SaveState),
+        ("Quit", TrayCommand::Quit),
+    ] {
+        let send = send.clone();
+        item.add_menu_item(label, move || {
+            send.try_send(command).unwrap();
+        })?;
+    }
+
+    Ok(item)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn tray_system(
+    mut tray: NonSendMut<Tray>,
+    winit_windows: NonSend<WinitWindows>,
+    config: Res<Config>,
+    mut emulator: Option<ResMut<Emulator>>,
+    mut ui_state: ResMut<UiState>,
+    (mut window_control_event, mut window_resized): (
+        EventReader<WindowControlEvent>,
+        EventReader<WindowResized>,
+    ),
+    (send, recv): (Res<Sender<TrayCommand>>, Res<Receiver<TrayCommand>>),
+    (mut message_event, message_send): (EventWriter<ShowMessage>, Res<Sender<ShowMessage>>),
+    mut app_exit: EventWriter<AppExit>,
+) {
+    let window = if let Some(window) = winit_windows.get_window(WindowId::primary()) {
+        window
+    } else {
+        return;
+    };
+
+    let requested = window_control_event
+        .iter()
+        .filter(|event| matches!(event, WindowControlEvent::MinimizeToTray))
+        .count()
+        > 0;
+    // Minimized windows are resized to zero on some platforms
+    let minimized = window_resized
+        .iter()
+        .filter(|event| event.width == 0.0 || event.height == 0.0)
+        .count()
+        > 0;
+
+    if config.minimize_to_tray && (requested || minimized) && tray.item.is_none() {
+        match create_tray_item(&send) {
+            Ok(item) => {
+                tray.item = Some(item);
+                tray.was_paused = ui_state.paused;
+                ui_state.paused = true;
+                ui_state.in_tray = true;
+                window.set_visible(false);
+            }
+            Err(err) => error!("Failed to create tray icon: {err}"),
+        }
+    }
+
+    while let Ok(command) = recv.try_recv() {
+        match command {
+            TrayCommand::Resume => {
+                if tray.item.take().is_some() {
+                    ui_state.paused = tray.was_paused;
+                    ui_state.in_tray = false;
+                    window.set_visible(true);
+                    window.set_minimized(false);
+                    window.focus_window();
+                }
+            }
+            TrayCommand::SaveState => {
+                if let Some(emulator) = &mut emulator {
+                    let slot = ui_state.state_save_slot;
+                    let fut = emulator.save_state_slot(slot, config.as_ref());
+                    let message_send = message_send.clone();
+                    spawn_local(async move {
+                        if let Err(err) = fut.await {
+                            let msg = ShowMessage::save_failed("Saving state", &err);
+                            message_send.send(msg).await.unwrap();
+                        }
+                    });
+
+                    let state_file = emulator.current_state_file();
+
+                    if let Some(slot_file) = emulator.state_files.get_mut(slot) {
+                        *slot_file = Some(state_file);
+                    }
+
+                    message_event.send(ShowMessage::info(format!("State saved: #{slot}")));
+                }
+            }
+            TrayCommand::Quit => {
+                app_exit.send(AppExit);
+            }
+        }
+    }
+}
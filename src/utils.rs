@@ -1,5 +1,8 @@
+use bevy::{ecs::system::EntityCommands, prelude::*};
+use bevy_easings::{Ease, EaseFunction, EasingType};
 use std::future::Future;
 use std::ops::Deref;
+use std::time::Duration;
 
 pub fn unbounded_channel<T>() -> (Sender<T>, Receiver<T>) {
     let (s, r) = async_channel::unbounded();
@@ -54,3 +57,50 @@ pub fn spawn_local(f: impl Future<Output = ()> + 'static) {
 pub fn spawn_local(f: impl Future<Output = ()> + Send + 'static) {
     async_std::task::spawn(f);
 }
+
+/// Lets the browser download `data` as a file
+#[cfg(target_arch = "wasm32")]
+pub fn download(file_name: &str, data: &[u8]) -> anyhow::Result<()> {
+    use wasm_bindgen::JsCast;
+
+    let js_err = |err: wasm_bindgen::JsValue| anyhow::anyhow!("{err:?}");
+
+    let parts = js_sys::Array::of1(&js_sys::Uint8Array::from(data));
+    let blob = web_sys::Blob::new_with_u8_array_sequence(&parts).map_err(js_err)?;
+    let url = web_sys::Url::create_object_url_with_blob(&blob).map_err(js_err)?;
+
+    let document = web_sys::window().unwrap().document().unwrap();
+    let anchor = document
+        .create_element("a")
+        .map_err(js_err)?
+        .dyn_into::<web_sys::HtmlAnchorElement>()
+        .map_err(|_| anyhow::anyhow!("Failed to create a download link"))?;
+    anchor.set_href(&url);
+    anchor.set_download(file_name);
+    anchor.click();
+
+    web_sys::Url::revoke_object_url(&url).map_err(js_err)?;
+    Ok(())
+}
+
+/// Moves an entity from `from` to `to`.
+/// Jumps to `to` immediately when animations are disabled.
+pub fn ease_transform(
+    entity: &mut EntityCommands,
+    from: Transform,
+    to: Transform,
+    millis: u64,
+    animations: bool,
+) {
+    if animations {
+        entity.insert(from.ease_to(
+            to,
+            EaseFunction::CubicInOut,
+            EasingType::Once {
+                duration: Duration::from_millis(millis),
+            },
+        ));
+    } else {
+        entity.insert(to);
+    }
+}